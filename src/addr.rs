@@ -1,19 +1,42 @@
-use libc::{sockaddr_ll, AF_PACKET};
+use libc::{if_indextoname, sockaddr_ll, AF_PACKET, ARPHRD_ETHER, IF_NAMESIZE, PACKET_OUTGOING};
 use macaddr::MacAddr6;
-use std::ffi::c_int;
+use std::collections::HashMap;
+use std::ffi::{c_int, CStr};
+use std::io::{Error, ErrorKind};
 use std::mem::zeroed;
+use std::sync::Mutex;
 
 /// Struct to build a [`sockaddr_ll`].
+///
+/// The address for each peer is cached since the exploit stages send a lot of frames to the same
+/// peer.
 pub struct AddrBuilder {
     interface: c_int,
+    cache: Mutex<HashMap<(u16, MacAddr6), sockaddr_ll>>,
 }
 
 impl AddrBuilder {
     pub fn new(interface: c_int) -> Self {
-        Self { interface }
+        Self {
+            interface,
+            cache: Mutex::default(),
+        }
     }
 
+    /// Build an address for `proto`. Specify `addr` for outgoing frames or [`None`] for binding.
     pub fn build(&self, proto: u16, addr: Option<MacAddr6>) -> sockaddr_ll {
+        match addr {
+            Some(addr) => *self
+                .cache
+                .lock()
+                .unwrap()
+                .entry((proto, addr))
+                .or_insert_with(|| self.create(proto, Some(addr))),
+            None => self.create(proto, None),
+        }
+    }
+
+    fn create(&self, proto: u16, addr: Option<MacAddr6>) -> sockaddr_ll {
         let mut v: sockaddr_ll = unsafe { zeroed() };
 
         v.sll_family = AF_PACKET as _;
@@ -25,8 +48,43 @@ impl AddrBuilder {
 
             v.sll_addr[..addr.len()].copy_from_slice(addr);
             v.sll_halen = addr.len().try_into().unwrap();
+            v.sll_hatype = ARPHRD_ETHER;
+            v.sll_pkttype = PACKET_OUTGOING;
         }
 
         v
     }
 }
+
+/// Get the name of interface `index`.
+pub fn interface_name(interface: c_int) -> Result<String, Error> {
+    let mut buf = [0; IF_NAMESIZE];
+
+    if unsafe { if_indextoname(interface as _, buf.as_mut_ptr()).is_null() } {
+        return Err(Error::last_os_error());
+    }
+
+    let name = unsafe { CStr::from_ptr(buf.as_ptr()) };
+
+    Ok(name.to_string_lossy().into_owned())
+}
+
+/// Get the MAC address of interface `index`.
+pub fn interface_mac(interface: c_int) -> Result<MacAddr6, Error> {
+    let name = interface_name(interface)?;
+    let addr = std::fs::read_to_string(format!("/sys/class/net/{name}/address"))?;
+
+    addr.trim()
+        .parse()
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "not an Ethernet interface"))
+}
+
+/// Returns `true` if the link on interface `index` is up.
+///
+/// The console is connected directly so the carrier is lost whenever it is powered off.
+pub fn interface_carrier(interface: c_int) -> Result<bool, Error> {
+    let name = interface_name(interface)?;
+    let carrier = std::fs::read_to_string(format!("/sys/class/net/{name}/carrier"))?;
+
+    Ok(carrier.trim() == "1")
+}
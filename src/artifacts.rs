@@ -0,0 +1,152 @@
+use crate::attempt::Attempt;
+use crate::log::{self, Recorder};
+use crate::pcap::Capture;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+
+/// Directory to keep the artifacts of each attempt for auditing.
+///
+/// Each attempt get its own directory under the root that is named after the time it was started,
+/// its ID and the console. The directory contains:
+///
+/// - `log.txt`: all messages while the attempt was active.
+/// - `capture.pcapng`: the frames from and to the console during the attempt.
+/// - `report.txt`: the result of the attempt.
+/// - `payloads.txt`: SHA-256 of the payloads that the attempt used.
+pub struct Artifacts {
+    root: PathBuf,
+    capture: Arc<Capture>,
+    recorders: Mutex<HashMap<u64, Recorder>>,
+}
+
+impl Artifacts {
+    /// `capture` must record all frames on the interface.
+    pub fn new(root: PathBuf, capture: Arc<Capture>) -> Result<Self, std::io::Error> {
+        std::fs::create_dir_all(&root)?;
+
+        Ok(Self {
+            root,
+            capture,
+            recorders: Mutex::default(),
+        })
+    }
+
+    /// Start collecting the messages for `attempt`. This must be called from
+    /// [`crate::attempt::Attempts::on_started()`].
+    pub fn started(&self, attempt: &Attempt) {
+        self.recorders
+            .lock()
+            .unwrap()
+            .insert(attempt.id(), log::record());
+    }
+
+    /// Write the artifacts of `attempt` and returns the directory that contains it.
+    pub fn finished(&self, attempt: &Attempt) -> Result<PathBuf, (PathBuf, std::io::Error)> {
+        let recorder = self.recorders.lock().unwrap().remove(&attempt.id());
+        let started = SystemTime::now() - attempt.started().elapsed();
+        let dir = self.root.join(format!(
+            "{}-attempt{}-{}",
+            timestamp(started),
+            attempt.id(),
+            attempt.peer().to_string().to_lowercase().replace(':', "-")
+        ));
+
+        std::fs::create_dir_all(&dir).map_err(|e| (dir.clone(), e))?;
+
+        // Write the files.
+        let write = |name: &str, data: &[u8]| {
+            let path = dir.join(name);
+
+            std::fs::write(&path, data).map_err(|e| (path, e))
+        };
+
+        let log = recorder.map(|r| r.lines()).unwrap_or_default();
+
+        write("log.txt", (log.join("\n") + "\n").as_bytes())?;
+        write("report.txt", self.report(attempt, started).as_bytes())?;
+        let payloads = attempt
+            .payloads()
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| "No payloads.\n".into());
+
+        write("payloads.txt", payloads.as_bytes())?;
+
+        // Write the frames.
+        let path = dir.join("capture.pcapng");
+        let file = File::create(&path).map_err(|e| (path.clone(), e))?;
+
+        self.capture
+            .trim(file, attempt.peer(), attempt.started(), Instant::now())
+            .map_err(|e| (path, e))?;
+
+        Ok(dir)
+    }
+
+    fn report(&self, attempt: &Attempt, started: SystemTime) -> String {
+        let mut out = String::new();
+
+        writeln!(out, "Console: {}", attempt.peer()).unwrap();
+        writeln!(out, "Started: {} UTC", timestamp(started)).unwrap();
+
+        if let Some(v) = attempt.vulnerability() {
+            writeln!(out, "Result: {v}").unwrap();
+        }
+
+        if let Some(v) = attempt.close_reason() {
+            writeln!(out, "Session closed: {v}").unwrap();
+        }
+
+        out.push('\n');
+        out.push_str(&attempt.summary());
+        out
+    }
+}
+
+/// Returns `time` in UTC as `YYYYMMDD-HHMMSS`.
+fn timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let days = (secs / 86400) as i64;
+    let secs = secs % 86400;
+
+    // Convert the days to the civil date (https://howardhinnant.github.io/date_algorithms.html).
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn time() {
+        let t = SystemTime::UNIX_EPOCH + Duration::from_secs(1709210096);
+
+        assert_eq!(timestamp(SystemTime::UNIX_EPOCH), "19700101-000000");
+        assert_eq!(timestamp(t), "20240229-123456");
+    }
+}
@@ -0,0 +1,735 @@
+use crate::failure::{Failure, Vulnerability};
+use crate::log::info;
+use crate::rt;
+use crate::session::CloseReason;
+use crate::socket::TxCallback;
+use crate::telemetry::SendTiming;
+use macaddr::MacAddr6;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Write};
+use std::future::Future;
+use std::num::NonZeroU64;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// Exploit attempts on all consoles.
+///
+/// Each console can have only one active attempt at a time. The attempt become inactive once
+/// [`Attempts::finish()`] is called and will be moved to the history.
+#[derive(Default)]
+pub struct Attempts {
+    state: Mutex<State>,
+    started: Mutex<Vec<StartCallback>>,
+    callbacks: Mutex<Vec<FinishCallback>>,
+    limit: Option<NonZeroU64>,
+    finished: Notify,
+}
+
+impl Attempts {
+    /// No more than `limit` attempts will be started if it is not [`None`].
+    pub fn new(limit: Option<NonZeroU64>) -> Self {
+        Self {
+            limit,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the active attempt for `peer` or start a new one if there are no active attempt.
+    ///
+    /// Returns [`None`] if a new attempt is required but the limit has been reached.
+    pub fn get_or_start(&self, peer: MacAddr6) -> Option<Arc<Attempt>> {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(v) = state.active.get(&peer) {
+            return Some(v.clone());
+        }
+
+        if self.limit.is_some_and(|n| state.next >= n.get()) {
+            return None;
+        }
+
+        // Start a new attempt. The console reconnect if it has a finished attempt.
+        let now = Instant::now();
+        let mut previous = state.history.iter().filter(|a| a.peer == peer);
+        let reconnect = previous.next_back().map(|a| Reconnect {
+            count: previous.count() as u64 + 1,
+            previous: a.id,
+            after: now.saturating_duration_since(a.state.lock().unwrap().finished.unwrap()),
+        });
+
+        state.next += 1;
+
+        let attempt = Arc::new(Attempt {
+            id: state.next,
+            peer,
+            started: now,
+            reconnect,
+            timings: Default::default(),
+            state: Mutex::default(),
+        });
+
+        state.active.insert(peer, attempt.clone());
+
+        drop(state);
+
+        for f in self.started.lock().unwrap().iter() {
+            f(&attempt);
+        }
+
+        match self.limit {
+            Some(n) => info!("Attempt #{} of {} started for {}.", attempt.id, n, peer),
+            None => info!("Attempt #{} started for {}.", attempt.id, peer),
+        }
+
+        if let Some(r) = &attempt.reconnect {
+            info!("Attempt #{}: {} {}.", attempt.id, peer, r);
+            attempt.record(Stage::Discovery, format!("Console {r}"));
+        }
+
+        Some(attempt)
+    }
+
+    /// Returns [`None`] if there is no active attempt for `peer`.
+    pub fn active(&self, peer: MacAddr6) -> Option<Arc<Attempt>> {
+        self.state.lock().unwrap().active.get(&peer).cloned()
+    }
+
+    /// Wait until all attempts allowed by the limit has been finished. This never resolve if
+    /// there is no limit.
+    pub async fn exhausted(&self) {
+        let limit = match self.limit {
+            Some(v) => v.get(),
+            None => return std::future::pending().await,
+        };
+
+        loop {
+            // Register the waiter before checking so we don't miss the notification.
+            let finished = self.finished.notified();
+            tokio::pin!(finished);
+            finished.as_mut().enable();
+
+            if self.state.lock().unwrap().history.len() as u64 >= limit {
+                break;
+            }
+
+            finished.await;
+        }
+    }
+
+    /// Register a callback to be invoked when an attempt has been started.
+    ///
+    /// The callback is invoked by [`Attempts::get_or_start()`] before anything is logged for the
+    /// attempt so it must not block.
+    pub fn on_started(&self, f: impl Fn(&Arc<Attempt>) + Send + Sync + 'static) {
+        self.started.lock().unwrap().push(Box::new(f));
+    }
+
+    /// Register a callback to be invoked when an attempt has been finished.
+    ///
+    /// Each invocation will be run on a new task so it will not block the caller of
+    /// [`Attempts::finish()`].
+    pub fn on_finished<F, R>(&self, f: F)
+    where
+        F: Fn(Arc<Attempt>) -> R + Send + Sync + 'static,
+        R: Future<Output = ()> + Send + 'static,
+    {
+        self.callbacks
+            .lock()
+            .unwrap()
+            .push(Box::new(move |a| Box::pin(f(a))));
+    }
+
+    /// Mark `attempt` as finished with `result`.
+    pub fn finish(&self, attempt: &Arc<Attempt>, result: Result<(), Failure>) {
+        let mut state = self.state.lock().unwrap();
+
+        match state.active.get(&attempt.peer) {
+            Some(v) if Arc::ptr_eq(v, attempt) => {}
+            _ => return,
+        }
+
+        state.active.remove(&attempt.peer);
+        let mut s = attempt.state.lock().unwrap();
+
+        s.result = Some(result);
+        s.finished = Some(Instant::now());
+
+        drop(s);
+        state.history.push(attempt.clone());
+
+        drop(state);
+
+        // Print summary.
+        for l in attempt.summary().lines() {
+            info!("{l}");
+        }
+
+        for f in self.callbacks.lock().unwrap().iter() {
+            rt::spawn(f(attempt.clone()));
+        }
+
+        self.finished.notify_waiters();
+    }
+
+    /// Write the active attempts to `out`.
+    pub fn dump(&self, out: &mut String) {
+        let state = self.state.lock().unwrap();
+        let mut active: Vec<_> = state.active.values().collect();
+
+        active.sort_unstable_by_key(|a| a.id);
+
+        writeln!(out, "Attempts:").unwrap();
+        writeln!(out, "  Started: {}", state.next).unwrap();
+
+        match self.limit {
+            Some(v) => writeln!(out, "  Limit: {v}").unwrap(),
+            None => writeln!(out, "  Limit: none").unwrap(),
+        }
+
+        writeln!(out, "  Finished: {}", state.history.len()).unwrap();
+
+        for a in active {
+            writeln!(
+                out,
+                "  #{} on {} (active for {:.1} seconds)",
+                a.id,
+                a.peer,
+                a.started.elapsed().as_secs_f64()
+            )
+            .unwrap();
+
+            for e in a.timeline() {
+                writeln!(
+                    out,
+                    "    +{:.3}s [{}] {}",
+                    e.time.as_secs_f64(),
+                    e.stage,
+                    e.text
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    /// Returns all active attempts in the order they were started.
+    pub fn actives(&self) -> Vec<Arc<Attempt>> {
+        let mut active: Vec<_> = self
+            .state
+            .lock()
+            .unwrap()
+            .active
+            .values()
+            .cloned()
+            .collect();
+
+        active.sort_unstable_by_key(|a| a.id);
+        active
+    }
+
+    /// Returns all finished attempts in the order they were finished.
+    pub fn history(&self) -> Vec<Arc<Attempt>> {
+        self.state.lock().unwrap().history.clone()
+    }
+
+    /// Returns a table of the finished attempts side by side with the duration of each stage, the
+    /// frames that was dropped by the kernel and the outcome, which is useful to see which change
+    /// between the attempts made a difference. Returns [`None`] if there is nothing to compare.
+    pub fn comparison(&self) -> Option<String> {
+        let history = self.history();
+
+        if history.len() < 2 {
+            return None;
+        }
+
+        // Build the cells.
+        let mut rows = vec![["#", "Console"]
+            .into_iter()
+            .map(String::from)
+            .chain(Stage::ALL.map(|s| s.to_string()))
+            .chain(["total", "exploit gap p99", "drops", "result"].map(String::from))
+            .collect::<Vec<_>>()];
+        let secs = |v: Option<Duration>| match v {
+            Some(v) => format!("{:.3}s", v.as_secs_f64()),
+            None => "-".into(),
+        };
+
+        for a in history {
+            let gaps = a.timing(Stage::Exploit).gaps();
+            let mut row = vec![a.id.to_string(), a.peer.to_string()];
+
+            row.extend(a.stage_durations().map(secs));
+            row.push(secs(Some(a.duration())));
+            row.push(match gaps.count() {
+                0 => "-".into(),
+                _ => format!("{}us", gaps.percentile(99).as_micros()),
+            });
+            row.push(a.drops().map_or("-".into(), |v| v.to_string()));
+            row.push(match a.result().unwrap() {
+                Ok(_) => "OK".into(),
+                Err(e) => e.id().into(),
+            });
+
+            rows.push(row);
+        }
+
+        // Align the columns.
+        let mut widths = vec![0; rows[0].len()];
+        let mut out = String::new();
+
+        for row in &rows {
+            for (w, c) in widths.iter_mut().zip(row) {
+                *w = (*w).max(c.len());
+            }
+        }
+
+        for row in rows {
+            let line: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(c, &w)| format!("{c:w$}"))
+                .collect();
+
+            writeln!(out, "{}", line.join("  ").trim_end()).unwrap();
+        }
+
+        Some(out)
+    }
+
+    /// Returns how long each console took to reconnect after each of its finished attempt, in the
+    /// order of the first attempt of each console.
+    pub fn reconnects(&self) -> Vec<(MacAddr6, Vec<Duration>)> {
+        let state = self.state.lock().unwrap();
+        let mut all: Vec<&Arc<Attempt>> =
+            state.history.iter().chain(state.active.values()).collect();
+        let mut out: Vec<(MacAddr6, Vec<Duration>)> = Vec::new();
+
+        all.sort_unstable_by_key(|a| a.id);
+
+        for a in all {
+            let i = match out.iter().position(|(p, _)| *p == a.peer) {
+                Some(v) => v,
+                None => {
+                    out.push((a.peer, Vec::new()));
+                    out.len() - 1
+                }
+            };
+
+            if let Some(r) = &a.reconnect {
+                out[i].1.push(r.after);
+            }
+        }
+
+        out
+    }
+}
+
+/// A single exploit attempt on a console.
+pub struct Attempt {
+    id: u64,
+    peer: MacAddr6,
+    started: Instant,
+    reconnect: Option<Reconnect>,
+    timings: [SendTiming; Stage::ALL.len()],
+    state: Mutex<AttemptState>,
+}
+
+impl Attempt {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn peer(&self) -> MacAddr6 {
+        self.peer
+    }
+
+    /// Record an event on the timeline.
+    pub fn record(&self, stage: Stage, event: impl Into<String>) {
+        let event = Event {
+            time: self.started.elapsed(),
+            stage,
+            text: event.into(),
+        };
+
+        self.state.lock().unwrap().timeline.push(event);
+    }
+
+    /// Returns a callback for [`crate::socket::PacketSocket::send_confirmed()`] that record when
+    /// `frame` left the NIC on the timeline.
+    pub fn tx_recorder(self: &Arc<Self>, stage: Stage, frame: &'static str) -> TxCallback {
+        let attempt = self.clone();
+
+        Box::new(move |delay| match delay {
+            Some(v) => attempt.record(
+                stage,
+                format!("{frame} left the NIC {}us after it was sent", v.as_micros()),
+            ),
+            None => attempt.record(stage, format!("{frame} was not confirmed to leave the NIC")),
+        })
+    }
+
+    /// Move this attempt to `milestone` and print it. This does nothing if the attempt already
+    /// passed `milestone`.
+    pub fn advance(&self, milestone: Milestone) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.milestone.is_some_and(|v| v >= milestone) {
+            return;
+        }
+
+        state.milestone = Some(milestone);
+
+        drop(state);
+
+        info!("Attempt #{}: {}.", self.id, milestone);
+    }
+
+    /// Returns [`None`] if no milestones has been reached.
+    pub fn milestone(&self) -> Option<Milestone> {
+        self.state.lock().unwrap().milestone
+    }
+
+    /// Returns the timing of the packets that was sent during `stage`.
+    pub fn timing(&self, stage: Stage) -> &SendTiming {
+        &self.timings[stage as usize]
+    }
+
+    /// Record the result of `stage`.
+    pub fn complete(&self, stage: Stage, result: Result<(), Failure>) {
+        let mut state = self.state.lock().unwrap();
+
+        state.stages.push((stage, result));
+    }
+
+    pub fn timeline(&self) -> Vec<Event> {
+        self.state.lock().unwrap().timeline.clone()
+    }
+
+    /// Returns the time of the first event of `stage` on the timeline.
+    pub fn stage_started(&self, stage: Stage) -> Option<Instant> {
+        let state = self.state.lock().unwrap();
+        let e = state.timeline.iter().find(|e| e.stage == stage)?;
+
+        Some(self.started + e.time)
+    }
+
+    /// Returns [`None`] if this attempt is still active or it was succeeded.
+    ///
+    /// The last stage on the timeline is returned if the failure is not from a completed stage
+    /// (e.g. the session was closed).
+    pub fn failed_stage(&self) -> Option<Stage> {
+        let state = self.state.lock().unwrap();
+
+        state.result?.err()?;
+
+        match state.stages.iter().rev().find(|(_, r)| r.is_err()) {
+            Some(&(s, _)) => Some(s),
+            None => state.timeline.last().map(|e| e.stage),
+        }
+    }
+
+    /// Returns how long each stage took, in the order of [`Stage::ALL`]. A stage that is not on the
+    /// timeline is [`None`]. Each stage last until the next one started or the attempt finished.
+    pub fn stage_durations(&self) -> [Option<Duration>; Stage::ALL.len()] {
+        let timeline = self.timeline();
+        let end = self.duration();
+        let starts = Stage::ALL.map(|s| timeline.iter().find(|e| e.stage == s).map(|e| e.time));
+
+        starts.map(|start| {
+            let start = start?;
+            let next = starts.iter().flatten().filter(|&&v| v > start).min();
+
+            Some(next.copied().unwrap_or(end).saturating_sub(start))
+        })
+    }
+
+    /// Record the total number of frames that the kernel has dropped on our sockets. This is
+    /// called when the attempt is started and finished so [`Self::drops()`] can tell how many was
+    /// dropped in between.
+    pub fn record_drops(&self, total: u64) {
+        let mut state = self.state.lock().unwrap();
+
+        state.drops = match state.drops {
+            Some((start, _)) => Some((start, total)),
+            None => Some((total, total)),
+        };
+    }
+
+    /// Returns the frames that was dropped by the kernel while this attempt was active, which
+    /// also include the frames of the other consoles. Returns [`None`] if it was not recorded.
+    pub fn drops(&self) -> Option<u64> {
+        self.state
+            .lock()
+            .unwrap()
+            .drops
+            .map(|(start, end)| end.saturating_sub(start))
+    }
+
+    /// Returns the result of each completed stage.
+    pub fn stages(&self) -> Vec<(Stage, Result<(), Failure>)> {
+        self.state.lock().unwrap().stages.clone()
+    }
+
+    /// Record why the session of this attempt was closed.
+    pub fn set_close_reason(&self, reason: CloseReason) {
+        self.state.lock().unwrap().close_reason = Some(reason);
+    }
+
+    /// Returns [`None`] if the session has not been closed or it was never started.
+    pub fn close_reason(&self) -> Option<CloseReason> {
+        self.state.lock().unwrap().close_reason.clone()
+    }
+
+    /// Set SHA-256 of the payloads that this attempt use in the format of `sha256sum`.
+    pub fn set_payloads(&self, hashes: impl Into<String>) {
+        self.state.lock().unwrap().payloads = Some(hashes.into());
+    }
+
+    /// Returns [`None`] if this attempt did not use any payloads (e.g. the exploit is disabled).
+    pub fn payloads(&self) -> Option<String> {
+        self.state.lock().unwrap().payloads.clone()
+    }
+
+    /// Returns [`None`] if this attempt is still active.
+    pub fn result(&self) -> Option<Result<(), Failure>> {
+        self.state.lock().unwrap().result
+    }
+
+    /// Returns the result, timeline, stages and timings of this finished attempt.
+    ///
+    /// # Panics
+    /// If this attempt is still active.
+    pub fn summary(&self) -> String {
+        let mut out = String::new();
+        let elapsed = self.duration().as_secs_f64();
+
+        match self.result().unwrap() {
+            Ok(_) => writeln!(
+                out,
+                "Attempt #{} succeeded after {:.1} seconds.",
+                self.id, elapsed
+            ),
+            Err(e) => writeln!(
+                out,
+                "Attempt #{} failed after {:.1} seconds: {}.",
+                self.id, elapsed, e
+            ),
+        }
+        .unwrap();
+
+        if let Some(v) = self.milestone() {
+            writeln!(out, "  Last milestone: {v}").unwrap();
+        }
+
+        for e in self.timeline() {
+            writeln!(
+                out,
+                "  +{:.3}s [{}] {}",
+                e.time.as_secs_f64(),
+                e.stage,
+                e.text
+            )
+            .unwrap();
+        }
+
+        for (s, r) in self.stages() {
+            match r {
+                Ok(_) => writeln!(out, "  {s}: OK").unwrap(),
+                Err(e) => writeln!(out, "  {}: FAILED with {}", s, e.id()).unwrap(),
+            }
+        }
+
+        for s in Stage::ALL {
+            let latencies = self.timing(s).latencies();
+
+            if latencies.count() == 0 {
+                continue;
+            }
+
+            writeln!(out, "  Sent {} packets during {}:", latencies.count(), s).unwrap();
+            writeln!(out, "    Gap: {}", self.timing(s).gaps()).unwrap();
+            writeln!(out, "    Latency: {latencies}").unwrap();
+        }
+
+        out
+    }
+
+    /// Returns the time when this attempt was started.
+    pub fn started(&self) -> Instant {
+        self.started
+    }
+
+    /// Returns [`None`] if this is the first attempt of the console.
+    pub fn reconnect(&self) -> Option<&Reconnect> {
+        self.reconnect.as_ref()
+    }
+
+    /// Returns how long this attempt took or has been active.
+    pub fn duration(&self) -> Duration {
+        let finished = self.state.lock().unwrap().finished;
+
+        finished.unwrap_or_else(Instant::now) - self.started
+    }
+
+    /// Returns [`None`] if this attempt is still active.
+    ///
+    /// The console is vulnerable if the corruption was found even if the failure itself does not
+    /// tell.
+    pub fn vulnerability(&self) -> Option<Vulnerability> {
+        let state = self.state.lock().unwrap();
+        let v = match state.result? {
+            Ok(_) => Vulnerability::Vulnerable,
+            Err(_) if state.milestone >= Some(Milestone::Corrupted) => Vulnerability::Vulnerable,
+            Err(e) => e.vulnerability(),
+        };
+
+        Some(v)
+    }
+}
+
+/// A new discovery from the console after its previous attempt was finished.
+pub struct Reconnect {
+    /// Number of reconnects of the console, including this one.
+    pub count: u64,
+    /// ID of the previous attempt of the console.
+    pub previous: u64,
+    /// Time from the end of the previous attempt to the start of this one.
+    pub after: Duration,
+}
+
+impl Display for Reconnect {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "reconnected {:.1} seconds after attempt #{} (reconnect #{})",
+            self.after.as_secs_f64(),
+            self.previous,
+            self.count
+        )
+    }
+}
+
+/// Stage of an [`Attempt`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Discovery,
+    Session,
+    Exploit,
+}
+
+impl Stage {
+    pub const ALL: [Self; 3] = [Self::Discovery, Self::Session, Self::Exploit];
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let v = match self {
+            Self::Discovery => "discovery",
+            Self::Session => "session",
+            Self::Exploit => "exploit",
+        };
+
+        f.write_str(v)
+    }
+}
+
+/// Milestone of an [`Attempt`] in the order they are reached.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(not(feature = "exploit"), allow(dead_code))]
+pub enum Milestone {
+    PadiReceived,
+    SessionStarted,
+    LinkUp,
+    HeapGroomed,
+    CorruptionSent,
+    Corrupted,
+    KaslrDefeated,
+}
+
+impl Display for Milestone {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let v = match self {
+            Self::PadiReceived => "PADI received",
+            Self::SessionStarted => "Session started",
+            Self::LinkUp => "LCP and IPCP up",
+            Self::HeapGroomed => "Heap groomed",
+            Self::CorruptionSent => "Corruption sent",
+            Self::Corrupted => "Kernel heap corrupted",
+            Self::KaslrDefeated => "KASLR defeated",
+        };
+
+        f.write_str(v)
+    }
+}
+
+/// An event on the timeline of [`Attempt`].
+#[derive(Clone)]
+pub struct Event {
+    pub time: Duration,
+    pub stage: Stage,
+    pub text: String,
+}
+
+type StartCallback = Box<dyn Fn(&Arc<Attempt>) + Send + Sync>;
+
+type FinishCallback =
+    Box<dyn Fn(Arc<Attempt>) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+#[derive(Default)]
+struct State {
+    next: u64,
+    active: HashMap<MacAddr6, Arc<Attempt>>,
+    history: Vec<Arc<Attempt>>,
+}
+
+#[derive(Default)]
+struct AttemptState {
+    timeline: Vec<Event>,
+    stages: Vec<(Stage, Result<(), Failure>)>,
+    milestone: Option<Milestone>,
+    result: Option<Result<(), Failure>>,
+    finished: Option<Instant>,
+    close_reason: Option<CloseReason>,
+    drops: Option<(u64, u64)>,
+    payloads: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comparison() {
+        let attempts = Attempts::new(None);
+        let console = MacAddr6::new(2, 0, 0, 0, 0, 1);
+        let a = attempts.get_or_start(console).unwrap();
+
+        assert!(attempts.comparison().is_none());
+
+        a.record(Stage::Discovery, "PADI");
+        a.record(Stage::Session, "LCP");
+        a.record_drops(10);
+        a.record_drops(13);
+        attempts.finish(&a, Ok(()));
+
+        let b = attempts.get_or_start(console).unwrap();
+
+        b.record(Stage::Discovery, "PADI");
+        attempts.finish(&b, Err(Failure::LcpTimeout));
+
+        let table = attempts.comparison().unwrap();
+        let rows: Vec<Vec<&str>> = table
+            .lines()
+            .map(|l| l.split_whitespace().collect())
+            .collect();
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0][..2], ["#", "Console"]);
+        assert_eq!(rows[1][..2], ["1", "02:00:00:00:00:01"]);
+        assert_eq!(rows[1][4], "-");
+        assert_eq!(rows[1][7..], ["3", "OK"]);
+        assert_eq!(rows[2][3..5], ["-", "-"]);
+        assert_eq!(rows[2][7..], ["-", "LCP_TIMEOUT"]);
+    }
+}
@@ -0,0 +1,135 @@
+use crate::random::random_bytes;
+use erdp::ErrorDisplay;
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// How AC-Name and Service-Name vary between attempts.
+///
+/// This is for testing whether the console caches the identity of the concentrator between the
+/// retries.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Banner {
+    /// Always send the configured values.
+    Fixed,
+    /// Append ` #N` where `N` goes from 1 to the specified number then start over.
+    Cycle(u64),
+    /// Append ` #` and 8 hex digits derived from the specified seed and the attempt ID.
+    Random(u64),
+}
+
+impl Banner {
+    /// Returns the value of `base` to send on attempt `id`.
+    pub fn apply(self, base: &str, id: u64) -> Cow<'_, str> {
+        match self {
+            Self::Fixed => Cow::Borrowed(base),
+            Self::Cycle(n) => Cow::Owned(format!("{} #{}", base, (id.saturating_sub(1) % n) + 1)),
+            Self::Random(seed) => Cow::Owned(format!("{} #{:08x}", base, mix(seed ^ id) as u32)),
+        }
+    }
+
+    /// Returns `true` if `value` is `base` on any attempt.
+    pub fn matches(self, base: &str, value: &str) -> bool {
+        if value == base {
+            return true;
+        }
+
+        let suffix = match value.strip_prefix(base).and_then(|v| v.strip_prefix(" #")) {
+            Some(v) => v,
+            None => return false,
+        };
+
+        match self {
+            Self::Fixed => false,
+            Self::Cycle(n) => suffix.parse().is_ok_and(|v: u64| (1..=n).contains(&v)),
+            Self::Random(_) => suffix.len() == 8 && u32::from_str_radix(suffix, 16).is_ok(),
+        }
+    }
+}
+
+impl FromStr for Banner {
+    type Err = String;
+
+    /// Parse `fixed`, `cycle:N`, `random` or `random:SEED`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mode, arg) = match s.split_once(':') {
+            Some((m, a)) => (m, Some(a)),
+            None => (s, None),
+        };
+
+        let v = match (mode, arg) {
+            ("fixed", None) => Self::Fixed,
+            ("cycle", Some(v)) => match v.parse() {
+                Ok(v) if v != 0 => Self::Cycle(v),
+                _ => return Err(format!("invalid number of banners '{v}'")),
+            },
+            ("random", None) => Self::Random(
+                seed().map_err(|e| format!("failed to generate random seed: {}", e.display()))?,
+            ),
+            ("random", Some(v)) => Self::Random(
+                v.parse()
+                    .map_err(|_| format!("invalid random seed '{v}'"))?,
+            ),
+            _ => return Err(format!("expect fixed, cycle:N or random[:SEED], got '{s}'")),
+        };
+
+        Ok(v)
+    }
+}
+
+impl Display for Banner {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fixed => f.write_str("fixed"),
+            Self::Cycle(n) => write!(f, "cycle:{n}"),
+            Self::Random(seed) => write!(f, "random:{seed}"),
+        }
+    }
+}
+
+fn seed() -> Result<u64, std::io::Error> {
+    let mut v = [0u8; 8];
+
+    random_bytes(&mut v)?;
+
+    Ok(u64::from_ne_bytes(v))
+}
+
+/// SplitMix64 finalizer.
+fn mix(mut v: u64) -> u64 {
+    v = v.wrapping_add(0x9e3779b97f4a7c15);
+    v = (v ^ (v >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    v = (v ^ (v >> 27)).wrapping_mul(0x94d049bb133111eb);
+    v ^ (v >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycle() {
+        let b: Banner = "cycle:2".parse().unwrap();
+
+        assert_eq!(b.apply("AC", 1), "AC #1");
+        assert_eq!(b.apply("AC", 2), "AC #2");
+        assert_eq!(b.apply("AC", 3), "AC #1");
+        assert!(b.matches("AC", "AC"));
+        assert!(b.matches("AC", "AC #2"));
+        assert!(!b.matches("AC", "AC #3"));
+        assert!("cycle:0".parse::<Banner>().is_err());
+        assert!("cycle".parse::<Banner>().is_err());
+    }
+
+    #[test]
+    fn random() {
+        let b: Banner = "random:7".parse().unwrap();
+        let v = b.apply("AC", 1);
+
+        assert_eq!(v, b.apply("AC", 1));
+        assert_ne!(v, b.apply("AC", 2));
+        assert!(b.matches("AC", &v));
+        assert!(!b.matches("AC", "AC #xyz"));
+        assert_eq!(Banner::Fixed.apply("AC", 1), "AC");
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Receive and send buffers of a socket. The zero sizes keep the kernel default.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Buffers {
+    pub recv: usize,
+    pub send: usize,
+    /// Number of frames in `PACKET_RX_RING`. Zero to receive with `recvmsg` instead.
+    pub ring: usize,
+}
+
+impl Display for Buffers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let size = |f: &mut Formatter<'_>, v: usize| match v {
+            0 => f.write_str("default"),
+            v => write!(f, "{v} bytes"),
+        };
+
+        f.write_str("receive ")?;
+        size(f, self.recv)?;
+        f.write_str(", send ")?;
+        size(f, self.send)?;
+
+        if self.ring != 0 {
+            write!(f, ", ring {} frames", self.ring)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// [`Buffers`] of each socket.
+///
+/// The defaults follow the traffic of each stage. Discovery is a few frames per attempt so it keep
+/// the kernel default. The session socket need to absorb the LCP burst after the heap is corrupted
+/// and the IPv6 socket receive a reply for every probe during the scan and the leak, which
+/// overflow the default buffer on a small router.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BufferConfig {
+    pub discovery: Buffers,
+    pub session: Buffers,
+    pub ipv6: Buffers,
+}
+
+impl Default for BufferConfig {
+    fn default() -> Self {
+        Self {
+            discovery: Buffers::default(),
+            session: Buffers {
+                recv: 1024 * 1024,
+                send: 0,
+                ring: 0,
+            },
+            ipv6: Buffers {
+                recv: 4 * 1024 * 1024,
+                send: 1024 * 1024,
+                ring: 0,
+            },
+        }
+    }
+}
+
+impl BufferConfig {
+    /// Receive with `PACKET_RX_RING` of `frames` frames on all sockets.
+    pub fn set_ring(&mut self, frames: usize) {
+        for b in [&mut self.discovery, &mut self.session, &mut self.ipv6] {
+            b.ring = frames;
+        }
+    }
+}
+
+impl FromStr for BufferConfig {
+    type Err = String;
+
+    /// Parse a comma-separated list of `[SOCKET-]rx=SIZE`, `[SOCKET-]tx=SIZE` or
+    /// `[SOCKET-]ring=FRAMES` on top of the defaults. `SOCKET` is `discovery`, `session` or `ipv6`
+    /// and all of them without it. `SIZE` is bytes with optional `K` or `M` suffix.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Self::default();
+
+        for item in s.split(',') {
+            let (k, v) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expect KEY=SIZE, got '{item}'"))?;
+
+            // Get which socket to apply.
+            let (socks, k) = match k.split_once('-') {
+                Some(("discovery", k)) => (vec![&mut config.discovery], k),
+                Some(("session", k)) => (vec![&mut config.session], k),
+                Some(("ipv6", k)) => (vec![&mut config.ipv6], k),
+                Some(_) => return Err(format!("unknown key '{k}'")),
+                None => (
+                    vec![&mut config.discovery, &mut config.session, &mut config.ipv6],
+                    k,
+                ),
+            };
+
+            let size = parse_size(v).ok_or_else(|| format!("invalid size '{v}' for {k}"))?;
+
+            for b in socks {
+                match k {
+                    "rx" => b.recv = size,
+                    "tx" => b.send = size,
+                    "ring" => b.ring = size,
+                    _ => return Err(format!("unknown key '{k}'")),
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_size(v: &str) -> Option<usize> {
+    let (v, unit) = match v.as_bytes().last()? {
+        b'K' | b'k' => (&v[..(v.len() - 1)], 1024),
+        b'M' | b'm' => (&v[..(v.len() - 1)], 1024 * 1024),
+        _ => (v, 1),
+    };
+
+    v.parse::<usize>().ok()?.checked_mul(unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let c: BufferConfig = "rx=256K,ipv6-tx=0,session-rx=8M".parse().unwrap();
+
+        assert_eq!(c.discovery.recv, 256 * 1024);
+        assert_eq!(c.session.recv, 8 * 1024 * 1024);
+        assert_eq!(c.ipv6.recv, 256 * 1024);
+        assert_eq!(c.ipv6.send, 0);
+        assert_eq!(c.session.send, BufferConfig::default().session.send);
+        assert_eq!(c.ipv6.ring, 0);
+        assert_eq!(
+            "ipv6-ring=96".parse::<BufferConfig>().unwrap().ipv6.ring,
+            96
+        );
+        assert!("rx".parse::<BufferConfig>().is_err());
+        assert!("rx=1G".parse::<BufferConfig>().is_err());
+        assert!("pads-rx=1".parse::<BufferConfig>().is_err());
+        assert!("session-mtu=1".parse::<BufferConfig>().is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use libc::{chmod, geteuid, setxattr};
+use std::ffi::CString;
+use std::io::Error;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+/// Path of the udev rule that is written by [`install_udev_rule()`].
+pub const UDEV_RULE: &str = "/etc/udev/rules.d/70-jailbreak-11.rules";
+
+const CAP_NET_ADMIN: u32 = 12;
+const CAP_NET_RAW: u32 = 13;
+const VFS_CAP_REVISION_2: u32 = 0x02000000;
+const VFS_CAP_FLAGS_EFFECTIVE: u32 = 0x000001;
+
+/// Content of [`UDEV_RULE`].
+const RULE: &str = "\
+# Written by jailbreak-11 install-caps. The kernel require CAP_NET_ADMIN to use the device so this
+# does not grant anything else.
+KERNEL==\"ppp\", MODE=\"0666\"
+";
+
+/// Returns `true` if we are running as root.
+pub fn is_root() -> bool {
+    unsafe { geteuid() == 0 }
+}
+
+/// Give `CAP_NET_RAW` and `CAP_NET_ADMIN` to the executable at `path` so it can open the packet
+/// sockets, the TUN devices and `/dev/ppp` without root. This is the same as
+/// `setcap cap_net_raw,cap_net_admin+ep` and requires root.
+///
+/// The kernel drops the capabilities when the file is replaced (e.g. rebuilt) and ignores them on
+/// a filesystem that is mounted with `nosuid`.
+pub fn set_file_caps(path: &Path) -> Result<(), Error> {
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(Error::other)?;
+    let data = vfs_cap_data((1 << CAP_NET_ADMIN) | (1 << CAP_NET_RAW));
+
+    if unsafe {
+        setxattr(
+            path.as_ptr(),
+            c"security.capability".as_ptr(),
+            data.as_ptr().cast(),
+            data.len(),
+            0,
+        )
+    } < 0
+    {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Write [`UDEV_RULE`] to let the unprivileged users open `/dev/ppp` and apply it to the current
+/// device, if any.
+pub fn install_udev_rule() -> Result<(), Error> {
+    let path = Path::new(UDEV_RULE);
+
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(path, RULE)?;
+
+    // The rule only applies when the device is created.
+    if unsafe { chmod(c"/dev/ppp".as_ptr(), 0o666) } < 0 {
+        let e = Error::last_os_error();
+
+        if e.kind() != std::io::ErrorKind::NotFound {
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns `struct vfs_cap_data` with `caps` on the permitted and effective set.
+fn vfs_cap_data(caps: u64) -> [u8; 20] {
+    let mut data = [0; 20];
+
+    data[..4].copy_from_slice(&(VFS_CAP_REVISION_2 | VFS_CAP_FLAGS_EFFECTIVE).to_le_bytes());
+    data[4..8].copy_from_slice(&(caps as u32).to_le_bytes());
+    data[12..16].copy_from_slice(&((caps >> 32) as u32).to_le_bytes());
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cap_data() {
+        // The attribute that `setcap cap_net_raw,cap_net_admin+ep` write.
+        assert_eq!(
+            vfs_cap_data((1 << CAP_NET_ADMIN) | (1 << CAP_NET_RAW)),
+            [1, 0, 0, 2, 0, 0x30, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+}
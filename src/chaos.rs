@@ -0,0 +1,312 @@
+use crate::log::error;
+use crate::random::random_bytes;
+use erdp::ErrorDisplay;
+use libc::{sendto, sockaddr_ll};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt::{Display, Formatter};
+use std::mem::size_of_val;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::str::FromStr;
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+
+/// Additional delay for a frame to be reordered so the next frame will overtake it.
+pub const REORDER_DELAY: Duration = Duration::from_millis(5);
+
+/// Impair the frames on all sockets to test how the jailbreak behave on a bad link.
+///
+/// This is for testing only. The exploit is very sensitive to the timing so don't expect it to
+/// work with this enabled.
+pub struct Chaos {
+    config: ChaosConfig,
+    rng: Mutex<u64>,
+    queue: Mutex<Sender<Delayed>>,
+}
+
+impl Chaos {
+    pub fn new(config: ChaosConfig) -> Result<Self, std::io::Error> {
+        let seed = match config.seed {
+            Some(v) => v,
+            None => {
+                let mut v = [0u8; 8];
+
+                random_bytes(&mut v)?;
+
+                u64::from_ne_bytes(v)
+            }
+        };
+
+        // Start a thread to send the delayed frames.
+        let (tx, rx) = channel();
+
+        spawn(move || {
+            let mut pending = BinaryHeap::<Reverse<Delayed>>::new();
+
+            loop {
+                let timeout = pending
+                    .peek()
+                    .map(|v| v.0.due.saturating_duration_since(Instant::now()));
+                let r = match timeout {
+                    Some(v) => rx.recv_timeout(v),
+                    None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+
+                match r {
+                    Ok(v) => pending.push(Reverse(v)),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+
+                while pending.peek().is_some_and(|v| v.0.due <= Instant::now()) {
+                    pending.pop().unwrap().0.send();
+                }
+            }
+        });
+
+        Ok(Self {
+            config,
+            // Zero is a fixed point of xorshift.
+            rng: Mutex::new(seed | 1),
+            queue: Mutex::new(tx),
+        })
+    }
+
+    pub fn config(&self) -> &ChaosConfig {
+        &self.config
+    }
+
+    /// Returns what to do with a received frame.
+    pub fn inbound(&self) -> Verdict {
+        self.verdict(&self.config.rx)
+    }
+
+    /// Returns what to do with a frame to be sent.
+    pub fn outbound(&self) -> Verdict {
+        self.verdict(&self.config.tx)
+    }
+
+    /// Send `data` to `addr` via `fd` after `delay`.
+    pub fn send_later(&self, fd: Arc<OwnedFd>, addr: sockaddr_ll, data: &[u8], delay: Duration) {
+        let frame = Delayed {
+            due: Instant::now() + delay,
+            fd,
+            addr,
+            data: data.to_vec(),
+        };
+
+        self.queue.lock().unwrap().send(frame).unwrap();
+    }
+
+    fn verdict(&self, imp: &Impairment) -> Verdict {
+        if self.random() < imp.drop {
+            return Verdict::Drop;
+        }
+
+        let delay = imp.delay.mul_f64(self.random());
+
+        if self.random() < imp.reorder {
+            Verdict::Reorder(delay)
+        } else if delay.is_zero() {
+            Verdict::Pass
+        } else {
+            Verdict::Delay(delay)
+        }
+    }
+
+    /// Returns a number in `[0, 1)` with xorshift64*.
+    fn random(&self) -> f64 {
+        let mut s = self.rng.lock().unwrap();
+
+        *s ^= *s >> 12;
+        *s ^= *s << 25;
+        *s ^= *s >> 27;
+
+        let v = s.wrapping_mul(0x2545f4914f6cdd1d);
+
+        (v >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Action for a frame.
+pub enum Verdict {
+    Pass,
+    Drop,
+    Delay(Duration),
+    /// Same as [`Verdict::Delay`] but the next frame should overtake this one.
+    Reorder(Duration),
+}
+
+/// Configuration of [`Chaos`].
+#[derive(Clone)]
+pub struct ChaosConfig {
+    /// Impairment of the received frames.
+    pub rx: Impairment,
+    /// Impairment of the frames to be sent.
+    pub tx: Impairment,
+    /// Seed to reproduce the same decisions. [`None`] to use a random one.
+    pub seed: Option<u64>,
+}
+
+impl FromStr for ChaosConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Self {
+            rx: Impairment::default(),
+            tx: Impairment::default(),
+            seed: None,
+        };
+
+        for item in s.split(',') {
+            let (k, v) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expect KEY=VALUE, got '{item}'"))?;
+
+            if k == "seed" {
+                config.seed = Some(v.parse().map_err(|_| format!("invalid seed '{v}'"))?);
+                continue;
+            }
+
+            // Get which direction to apply.
+            let (dirs, k) = match k.split_once('-') {
+                Some(("rx", k)) => (vec![&mut config.rx], k),
+                Some(("tx", k)) => (vec![&mut config.tx], k),
+                Some(_) => return Err(format!("unknown key '{k}'")),
+                None => (vec![&mut config.rx, &mut config.tx], k),
+            };
+
+            let fraction = || match v.parse::<f64>() {
+                Ok(v) if (0.0..=1.0).contains(&v) => Ok(v),
+                _ => Err(format!("invalid fraction '{v}' for {k}")),
+            };
+
+            for imp in dirs {
+                match k {
+                    "drop" => imp.drop = fraction()?,
+                    "reorder" => imp.reorder = fraction()?,
+                    "delay" => {
+                        imp.delay = Duration::from_millis(
+                            v.parse().map_err(|_| format!("invalid delay '{v}'"))?,
+                        )
+                    }
+                    _ => return Err(format!("unknown key '{k}'")),
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+impl Display for ChaosConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rx: {}, tx: {}", self.rx, self.tx)?;
+
+        if let Some(v) = self.seed {
+            write!(f, ", seed {v}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Impairment of a single direction.
+#[derive(Clone, Default)]
+pub struct Impairment {
+    /// Fraction of the frames to drop.
+    pub drop: f64,
+    /// Maximum delay of each frame. The actual delay is random up to this.
+    pub delay: Duration,
+    /// Fraction of the frames to be overtook by the next frame.
+    pub reorder: f64,
+}
+
+impl Display for Impairment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.1}% dropped, up to {} ms delayed, {:.1}% reordered",
+            self.drop * 100.0,
+            self.delay.as_millis(),
+            self.reorder * 100.0
+        )
+    }
+}
+
+/// Frame to be sent by the thread of [`Chaos`].
+struct Delayed {
+    due: Instant,
+    fd: Arc<OwnedFd>,
+    addr: sockaddr_ll,
+    data: Vec<u8>,
+}
+
+impl Delayed {
+    fn send(self) {
+        let sent = unsafe {
+            sendto(
+                self.fd.as_raw_fd(),
+                self.data.as_ptr().cast(),
+                self.data.len(),
+                0,
+                &self.addr as *const sockaddr_ll as _,
+                size_of_val(&self.addr).try_into().unwrap(),
+            )
+        };
+
+        if sent < 0 {
+            error!(
+                "Failed to send a delayed frame: {}.",
+                std::io::Error::last_os_error().display()
+            );
+        }
+    }
+}
+
+impl PartialEq for Delayed {
+    fn eq(&self, other: &Self) -> bool {
+        self.due == other.due
+    }
+}
+
+impl Eq for Delayed {}
+
+impl PartialOrd for Delayed {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delayed {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.due.cmp(&other.due)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let c: ChaosConfig = "drop=0.1,tx-delay=20,rx-reorder=0.5,seed=7"
+            .parse()
+            .unwrap();
+
+        assert_eq!(c.rx.drop, 0.1);
+        assert_eq!(c.tx.drop, 0.1);
+        assert_eq!(c.rx.delay, Duration::ZERO);
+        assert_eq!(c.tx.delay, Duration::from_millis(20));
+        assert_eq!(c.rx.reorder, 0.5);
+        assert_eq!(c.tx.reorder, 0.0);
+        assert_eq!(c.seed, Some(7));
+
+        assert!("drop=2".parse::<ChaosConfig>().is_err());
+        assert!("xx-drop=0.1".parse::<ChaosConfig>().is_err());
+        assert!("loss=0.1".parse::<ChaosConfig>().is_err());
+        assert!("drop".parse::<ChaosConfig>().is_err());
+    }
+}
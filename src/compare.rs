@@ -0,0 +1,582 @@
+use crate::passive::{
+    control_code_name, discovery_code_name, protocol_name, tag_name, write_value,
+};
+use crate::pcap::Frame;
+use crate::proto::pppoe::{Code, EthernetPayload, Tags};
+use macaddr::MacAddr6;
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter, Write};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+/// Maximum number of differences to print for each stage.
+const MAX_DIFFS: usize = 20;
+
+/// Maximum number of characters to print for a single value.
+const MAX_VALUE: usize = 64;
+
+/// Fields that are expected to be different on every run.
+///
+/// AC-Cookie is keyed with a random key generated on startup and Magic-Number is random by design.
+const VOLATILE: [&str; 2] = ["AC-Cookie", "Magic-Number"];
+
+/// Align two captures by the stages of the attempt and find the differences between them.
+///
+/// The frames of each stage are paired in the order they appear in each direction so a missing
+/// frame only affects the frames after it within the same stage and direction.
+pub fn compare(a: &[Frame], b: &[Frame]) -> Report {
+    let a = Run::new(a);
+    let b = Run::new(b);
+    let mut report = Report {
+        lines: Vec::new(),
+        diffs: 0,
+    };
+
+    for stage in Stage::ALL {
+        let fa = a.stage(stage);
+        let fb = b.stage(stage);
+
+        if fa.is_empty() && fb.is_empty() {
+            continue;
+        }
+
+        report.lines.push(format!(
+            "{}: {} in A{}, {} in B{}.",
+            stage,
+            count(fa.len()),
+            a.offset(&fa),
+            count(fb.len()),
+            b.offset(&fb)
+        ));
+
+        let mut lines = Vec::new();
+
+        for dir in [Some(Dir::FromConsole), Some(Dir::ToConsole), None] {
+            let fa: Vec<&Decoded> = fa.iter().copied().filter(|f| f.dir == dir).collect();
+            let fb: Vec<&Decoded> = fb.iter().copied().filter(|f| f.dir == dir).collect();
+
+            // Compare the frames that exist on both sides.
+            for (x, y) in fa.iter().zip(&fb) {
+                for (name, va, vb) in x.diff(y) {
+                    lines.push(format!(
+                        "  A#{} B#{} {} {}: {} = {} vs {}",
+                        x.index,
+                        y.index,
+                        x.summary,
+                        dir_name(dir),
+                        name,
+                        truncate(va),
+                        truncate(vb)
+                    ));
+                }
+            }
+
+            // Report the extra frames.
+            for (side, extra) in [("A", &fa), ("B", &fb)] {
+                let n = fa.len().min(fb.len());
+
+                if let Some(f) = extra.get(n) {
+                    lines.push(format!(
+                        "  {side} has {} more {} {}, starting with {side}#{} {}",
+                        extra.len() - n,
+                        noun(extra.len() - n),
+                        dir_name(dir),
+                        f.index,
+                        f.summary
+                    ));
+                }
+            }
+        }
+
+        report.diffs += lines.len();
+
+        if lines.len() > MAX_DIFFS {
+            let more = lines.len() - MAX_DIFFS;
+
+            lines.truncate(MAX_DIFFS);
+            lines.push(format!("  ... and {more} more differences"));
+        }
+
+        report.lines.extend(lines);
+    }
+
+    report
+}
+
+fn count(n: usize) -> String {
+    format!("{n} {}", noun(n))
+}
+
+fn noun(n: usize) -> &'static str {
+    if n == 1 {
+        "frame"
+    } else {
+        "frames"
+    }
+}
+
+fn dir_name(dir: Option<Dir>) -> &'static str {
+    match dir {
+        Some(Dir::FromConsole) => "from PS4",
+        Some(Dir::ToConsole) => "to PS4",
+        None => "from unknown host",
+    }
+}
+
+fn truncate(v: &str) -> Cow<'_, str> {
+    match v.char_indices().nth(MAX_VALUE) {
+        Some((i, _)) => Cow::Owned(format!("{}... ({} chars)", &v[..i], v.chars().count())),
+        None => Cow::Borrowed(v),
+    }
+}
+
+/// Result of [`compare()`].
+pub struct Report {
+    lines: Vec<String>,
+    diffs: usize,
+}
+
+impl Report {
+    /// Returns the number of differences including the ones that were not printed.
+    pub fn differences(&self) -> usize {
+        self.diffs
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for l in &self.lines {
+            writeln!(f, "{l}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Decoded frames of a single capture.
+struct Run {
+    frames: Vec<Decoded>,
+    start: Option<Duration>,
+}
+
+impl Run {
+    fn new(frames: &[Frame]) -> Self {
+        // The PS4 is the sender of PADI or the receiver of PADO.
+        let console = frames.iter().find_map(|f| {
+            let code = match f.data.get(12..16)? {
+                [0x88, 0x63, _, c] => *c,
+                _ => return None,
+            };
+
+            match Code::try_from(code) {
+                Ok(Code::Padi | Code::Padr) => mac(&f.data[6..12]),
+                Ok(Code::Pado | Code::Pads) => mac(&f.data[..6]),
+                _ => None,
+            }
+        });
+
+        let frames: Vec<Decoded> = frames
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                let dir = console.and_then(|c| {
+                    if mac(f.data.get(6..12)?)? == c {
+                        Some(Dir::FromConsole)
+                    } else if mac(&f.data[..6])? == c {
+                        Some(Dir::ToConsole)
+                    } else {
+                        None
+                    }
+                });
+
+                Decoded::new(i + 1, dir, f)
+            })
+            .collect();
+
+        Self {
+            start: frames.first().map(|f| f.time),
+            frames,
+        }
+    }
+
+    fn stage(&self, stage: Stage) -> Vec<&Decoded> {
+        self.frames.iter().filter(|f| f.stage == stage).collect()
+    }
+
+    fn offset(&self, frames: &[&Decoded]) -> String {
+        match (self.start, frames.first()) {
+            (Some(s), Some(f)) => format!(" at +{:.3}s", f.time.saturating_sub(s).as_secs_f64()),
+            _ => String::new(),
+        }
+    }
+}
+
+fn mac(data: &[u8]) -> Option<MacAddr6> {
+    let v: [u8; 6] = data.try_into().ok()?;
+
+    Some(v.into())
+}
+
+/// Stage of an attempt.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Discovery,
+    Lcp,
+    Ipv4,
+    Ipv6,
+    Teardown,
+    Other,
+}
+
+impl Stage {
+    const ALL: [Self; 6] = [
+        Self::Discovery,
+        Self::Lcp,
+        Self::Ipv4,
+        Self::Ipv6,
+        Self::Teardown,
+        Self::Other,
+    ];
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let v = match self {
+            Self::Discovery => "Discovery",
+            Self::Lcp => "LCP",
+            Self::Ipv4 => "IPCP and IPv4",
+            Self::Ipv6 => "IPV6CP and IPv6",
+            Self::Teardown => "Teardown",
+            Self::Other => "Other",
+        };
+
+        f.write_str(v)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Dir {
+    FromConsole,
+    ToConsole,
+}
+
+/// A frame decoded into the fields to compare.
+struct Decoded {
+    index: usize,
+    time: Duration,
+    dir: Option<Dir>,
+    stage: Stage,
+    summary: String,
+    fields: Vec<(String, String)>,
+}
+
+impl Decoded {
+    fn new(index: usize, dir: Option<Dir>, frame: &Frame) -> Self {
+        let mut d = Self {
+            index,
+            time: frame.time,
+            dir,
+            stage: Stage::Other,
+            summary: String::new(),
+            fields: Vec::new(),
+        };
+
+        match frame.data.get(12..14) {
+            Some([0x88, 0x63]) => d.discovery(&frame.data[14..]),
+            Some([0x88, 0x64]) => d.session(&frame.data[14..]),
+            Some(&[h, l]) => d.summary = format!("EtherType {:#06x}", u16::from_be_bytes([h, l])),
+            _ => d.summary = "truncated frame".into(),
+        }
+
+        d
+    }
+
+    fn discovery(&mut self, data: &[u8]) {
+        let data = match EthernetPayload::<Tags>::deserialize(data) {
+            Some(v) => v,
+            None => {
+                self.summary = "invalid PPPoE discovery frame".into();
+                return;
+            }
+        };
+
+        self.stage = if data.code() == Code::Padt.into() {
+            Stage::Teardown
+        } else {
+            Stage::Discovery
+        };
+
+        self.summary = discovery_code_name(data.code()).into();
+        self.push("CODE", format!("{:#04x}", data.code()));
+        self.push("SESSION_ID", data.session_id().to_string());
+
+        for (t, v) in data.payload() {
+            let mut value = String::new();
+
+            write_value(&mut value, v);
+
+            match tag_name(*t) {
+                Some(n) => self.push(n, value),
+                None => self.push(&format!("tag {t:#06x}"), value),
+            }
+        }
+    }
+
+    fn session(&mut self, data: &[u8]) {
+        let data = match EthernetPayload::<Cow<[u8]>>::deserialize(data) {
+            Some(v) if v.code() == 0 => v,
+            _ => {
+                self.summary = "invalid PPPoE session frame".into();
+                return;
+            }
+        };
+
+        self.push("SESSION_ID", data.session_id().to_string());
+
+        // Get PPP protocol.
+        let ppp = data.payload();
+        let proto = match ppp.get(..2) {
+            Some(v) => u16::from_be_bytes(v.try_into().unwrap()),
+            None => {
+                self.summary = "truncated PPP frame".into();
+                return;
+            }
+        };
+
+        self.stage = match proto {
+            0xc021 => Stage::Lcp,
+            0x0021 | 0x8021 => Stage::Ipv4,
+            0x0057 | 0x8057 => Stage::Ipv6,
+            _ => Stage::Other,
+        };
+
+        self.summary = protocol_name(proto).into();
+        self.push("Protocol", format!("{proto:#06x}"));
+
+        match proto {
+            0x0021 => self.ipv4(&ppp[2..]),
+            0x0057 => self.ipv6(&ppp[2..]),
+            _ if proto & 0x8000 != 0 => self.control(proto, &ppp[2..]),
+            _ => self.push("Length", (ppp.len() - 2).to_string()),
+        }
+    }
+
+    fn control(&mut self, proto: u16, data: &[u8]) {
+        let (code, id, len) = match data {
+            &[code, id, h, l, ..] => (code, id, u16::from_be_bytes([h, l])),
+            _ => {
+                self.summary.push_str(" truncated control packet");
+                return;
+            }
+        };
+
+        write!(self.summary, " {}", control_code_name(proto, code)).unwrap();
+
+        self.push("Code", code.to_string());
+        self.push("Identifier", id.to_string());
+        self.push("Length", len.to_string());
+
+        let data = data.get(4..usize::from(len)).unwrap_or(&data[4..]);
+
+        // Decode options of Configure-* packets.
+        if matches!(code, 1..=4) && !matches!(proto, 0xc023 | 0xc223) {
+            let mut opts = data;
+
+            while let [ty, len, ..] = *opts {
+                let len = usize::from(len);
+                let v = match opts.get(2..len) {
+                    Some(v) if len >= 2 => v,
+                    _ => break,
+                };
+
+                match (proto, ty) {
+                    (0xc021, 5) => self.push("Magic-Number", hex(v)),
+                    _ => self.push(&format!("Option {ty}"), hex(v)),
+                }
+
+                opts = &opts[len..];
+            }
+
+            if !opts.is_empty() {
+                self.push("Invalid options", hex(opts));
+            }
+        } else if proto == 0xc021 && matches!(code, 9..=11) && data.len() >= 4 {
+            self.push("Magic-Number", hex(&data[..4]));
+            self.push("Data", hex(&data[4..]));
+        } else if !data.is_empty() {
+            self.push("Data", hex(data));
+        }
+    }
+
+    fn ipv4(&mut self, data: &[u8]) {
+        if data.len() < 20 {
+            self.summary.push_str(" truncated packet");
+            return;
+        }
+
+        self.push("Protocol", data[9].to_string());
+        self.push(
+            "Source",
+            Ipv4Addr::from(<[u8; 4]>::try_from(&data[12..16]).unwrap()).to_string(),
+        );
+        self.push(
+            "Destination",
+            Ipv4Addr::from(<[u8; 4]>::try_from(&data[16..20]).unwrap()).to_string(),
+        );
+        self.push("Length", data.len().to_string());
+    }
+
+    fn ipv6(&mut self, data: &[u8]) {
+        if data.len() < 40 {
+            self.summary.push_str(" truncated packet");
+            return;
+        }
+
+        let next = data[6];
+        let addr = |v: &[u8]| Ipv6Addr::from(<[u8; 16]>::try_from(v).unwrap()).to_string();
+
+        self.push("Next Header", next.to_string());
+        self.push("Hop Limit", data[7].to_string());
+        self.push("Source", addr(&data[8..24]));
+        self.push("Destination", addr(&data[24..40]));
+
+        // Decode ICMPv6.
+        let payload = &data[40..];
+
+        match (next, payload) {
+            (58, &[ty, code, ..]) => {
+                write!(self.summary, " ICMPv6 type {ty}").unwrap();
+
+                self.push("ICMPv6 Type", ty.to_string());
+                self.push("ICMPv6 Code", code.to_string());
+                self.push("ICMPv6 Body", hex(payload.get(4..).unwrap_or_default()));
+            }
+            _ => self.push("Payload", hex(payload)),
+        }
+    }
+
+    fn push(&mut self, name: &str, value: String) {
+        // Make the name unique if the field is repeated.
+        let repeated = format!("{name} #");
+        let n = self
+            .fields
+            .iter()
+            .filter(|(f, _)| f == name || f.starts_with(&repeated))
+            .count();
+        let name = if n == 0 {
+            name.to_string()
+        } else {
+            format!("{name} #{}", n + 1)
+        };
+
+        self.fields.push((name, value));
+    }
+
+    /// Returns the fields that are different between `self` and `other`.
+    fn diff<'a>(&'a self, other: &'a Self) -> Vec<(&'a str, &'a str, &'a str)> {
+        let mut diffs = Vec::new();
+        let get = |fields: &'a [(String, String)], name: &str| {
+            fields
+                .iter()
+                .find(|(n, _)| n == name)
+                .map_or("(none)", |(_, v)| v.as_str())
+        };
+
+        for (name, _) in self.fields.iter().chain(&other.fields) {
+            if VOLATILE.contains(&name.as_str()) || diffs.iter().any(|(n, _, _)| n == name) {
+                continue;
+            }
+
+            let a = get(&self.fields, name);
+            let b = get(&other.fields, name);
+
+            if a != b {
+                diffs.push((name.as_str(), a, b));
+            }
+        }
+
+        diffs
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|v| format!("{v:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::synthetic_captures;
+    use crate::log::Direction;
+
+    /// Convert the fixture to Ethernet frames.
+    fn frames(name: &str) -> Vec<Frame> {
+        let capture = synthetic_captures()
+            .into_iter()
+            .find(|c| c.name == name)
+            .unwrap();
+        let console = [2, 0, 0, 0, 0, 2];
+        let server = [2, 0, 0, 0, 0, 1];
+
+        capture
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                let mut data = Vec::new();
+                let (dst, src) = match f.dir {
+                    Direction::Received => (server, console),
+                    Direction::Sent => (console, server),
+                };
+
+                data.extend_from_slice(&dst);
+                data.extend_from_slice(&src);
+                data.extend_from_slice(if f.is_discovery() {
+                    &[0x88, 0x63]
+                } else {
+                    &[0x88, 0x64]
+                });
+                data.extend_from_slice(&f.data);
+
+                Frame {
+                    time: Duration::from_millis(i as u64),
+                    data,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn identical() {
+        let a = frames("ipcp.log");
+        let report = compare(&a, &a);
+
+        assert_eq!(report.differences(), 0);
+        assert!(report.to_string().starts_with("Discovery: 4 frames in A"));
+    }
+
+    #[test]
+    fn different() {
+        let a = frames("ipcp.log");
+        let mut b = frames("ipcp.log");
+
+        // Change Identifier of the first LCP Configure-Request and remove the last frame.
+        let i = b
+            .iter()
+            .position(|f| f.data[12..14] == [0x88, 0x64])
+            .unwrap();
+
+        b[i].data[23] ^= 0xff;
+        b.pop();
+
+        let report = compare(&a, &b);
+        let text = report.to_string();
+
+        assert!(report.differences() >= 2, "{text}");
+        assert!(
+            text.contains("LCP Configure-Request to PS4: Identifier"),
+            "{text}"
+        );
+        assert!(text.contains("A has 1 more frame"), "{text}");
+    }
+}
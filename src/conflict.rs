@@ -0,0 +1,189 @@
+use crate::addr::interface_name;
+use crate::proto::pppoe::{Code, EtherTypes, EthernetPayload, Tags};
+use macaddr::MacAddr6;
+use std::ffi::c_int;
+use std::fmt::{Display, Formatter};
+use std::fs::{read_dir, read_link, read_to_string};
+use std::io::{Error, ErrorKind};
+
+/// Another PPPoE listener that is active on the same interface.
+pub enum Conflict {
+    /// Kernel PPPoE session on the interface.
+    KernelSession { id: u16, peer: String },
+    /// Packet socket that is bound to PPPoE EtherType on the interface. `stage` is either
+    /// `discovery` or `session`.
+    PacketSocket {
+        stage: &'static str,
+        owner: Option<(u32, String)>,
+    },
+    /// Another access concentrator that answered on the segment, usually a modem that bridges the
+    /// ISP line to the LAN or a router with a PPPoE server.
+    AccessConcentrator { mac: MacAddr6, name: Option<String> },
+}
+
+impl Conflict {
+    /// Returns how to resolve this conflict.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Self::KernelSession { .. } | Self::PacketSocket { .. } => {
+                "Stop it or use --ignore-conflicts to start anyway."
+            }
+            Self::AccessConcentrator { .. } => {
+                "Connect the console directly to this computer since it may pick the other PADO."
+            }
+        }
+    }
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::KernelSession { id, peer } => {
+                write!(f, "kernel PPPoE session {id:#06x} with {peer}")
+            }
+            Self::PacketSocket { stage, owner } => {
+                write!(f, "PPPoE {stage} socket")?;
+
+                match owner {
+                    Some((pid, name)) => write!(f, " owned by {name} (PID {pid})"),
+                    None => write!(f, " owned by an unknown process"),
+                }
+            }
+            Self::AccessConcentrator { mac, name } => {
+                write!(f, "access concentrator {mac}")?;
+
+                match name {
+                    Some(v) => write!(f, " (AC-Name = '{v}')"),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// Find other PPPoE listeners on `interface`.
+///
+/// This must be called before we create our own sockets otherwise they will be reported too. Only
+/// `/proc` is checked. The packet sockets are not queried over netlink (`NETLINK_SOCK_DIAG`) since
+/// it report the same sockets as `/proc/net/packet` and require `af_packet_diag` module.
+pub fn find_conflicts(interface: c_int, ethertypes: EtherTypes) -> Result<Vec<Conflict>, Error> {
+    let name = interface_name(interface)?;
+    let mut conflicts = Vec::new();
+
+    // Check kernel PPPoE sessions. This file only exists when pppoe module is loaded.
+    match read_to_string("/proc/net/pppoe") {
+        Ok(v) => {
+            for l in v.lines().skip(1) {
+                let mut f = l.split_whitespace();
+                let (id, peer, dev) = match (f.next(), f.next(), f.next()) {
+                    (Some(i), Some(p), Some(d)) => (i, p, d),
+                    _ => continue,
+                };
+
+                if dev != name {
+                    continue;
+                }
+
+                // The kernel print the ID as-is in network byte order.
+                let id = match u32::from_str_radix(id, 16).map(u16::try_from) {
+                    Ok(Ok(v)) => u16::from_be(v),
+                    _ => continue,
+                };
+
+                conflicts.push(Conflict::KernelSession {
+                    id,
+                    peer: peer.to_owned(),
+                });
+            }
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    // Check packet sockets (e.g. rp-pppoe or pppd with rp-pppoe plugin).
+    let packet = read_to_string("/proc/net/packet")?;
+    let mut inodes = Vec::new();
+
+    for l in packet.lines().skip(1) {
+        // sk RefCnt Type Proto Iface R Rmem User Inode
+        let f: Vec<&str> = l.split_whitespace().collect();
+
+        if f.len() < 9 {
+            continue;
+        }
+
+        let proto = match u16::from_str_radix(f[3], 16) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+
+        let stage = if proto == ethertypes.discovery {
+            "discovery"
+        } else if proto == ethertypes.session {
+            "session"
+        } else {
+            continue;
+        };
+
+        match f[4].parse::<c_int>() {
+            Ok(v) if v == 0 || v == interface => {}
+            _ => continue,
+        }
+
+        inodes.push((stage, f[8].to_owned()));
+    }
+
+    for (stage, inode) in inodes {
+        conflicts.push(Conflict::PacketSocket {
+            stage,
+            owner: find_socket_owner(&inode),
+        });
+    }
+
+    Ok(conflicts)
+}
+
+/// Returns the access concentrator that sent `data` from `peer` if it is a PADO or PADS.
+///
+/// We never receive our own PADO and PADS so any of them is from a competing access concentrator.
+pub fn competing_ac(peer: MacAddr6, data: &EthernetPayload<Tags>) -> Option<Conflict> {
+    match Code::try_from(data.code()) {
+        Ok(Code::Pado | Code::Pads) => Some(Conflict::AccessConcentrator {
+            mac: peer,
+            name: data
+                .ac_name()
+                .map(|v| String::from_utf8_lossy(v).into_owned()),
+        }),
+        _ => None,
+    }
+}
+
+fn find_socket_owner(inode: &str) -> Option<(u32, String)> {
+    let target = format!("socket:[{inode}]");
+
+    for e in read_dir("/proc").ok()?.flatten() {
+        let pid: u32 = match e.file_name().to_str().and_then(|v| v.parse().ok()) {
+            Some(v) => v,
+            None => continue,
+        };
+
+        // We may not have permission to read file descriptors of some processes.
+        let fds = match read_dir(e.path().join("fd")) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+
+        for fd in fds.flatten() {
+            match read_link(fd.path()) {
+                Ok(v) if v.as_os_str() == target.as_str() => {}
+                _ => continue,
+            }
+
+            let name = read_to_string(e.path().join("comm")).unwrap_or_default();
+
+            return Some((pid, name.trim_end().to_owned()));
+        }
+    }
+
+    None
+}
@@ -0,0 +1,195 @@
+use crate::log::warning;
+use crate::proto::lcp::DISCARD_REQUEST;
+use crate::proto::pap::AUTHENTICATE_NAK;
+use crate::proto::ppp::{
+    parse_packet, Options, CODE_REJECT, CONFIGURE_REJECT, CONFIGURE_REQUEST, PROTO_IPCP,
+    PROTO_IPV6CP, PROTO_LCP, PROTO_PAP,
+};
+use crate::proto::pppoe::{Code, EthernetPayload, Tag, Tags, MAX_PPP_MAX_PAYLOAD};
+use libc::sockaddr_ll;
+use macaddr::MacAddr6;
+use std::borrow::Cow;
+
+/// Check an outgoing PPPoE frame without Ethernet header against the structural rules of RFC 2516
+/// and RFC 1661.
+///
+/// This only run on the debug builds. A non-conforming frame is a panic in the tests and a warning
+/// otherwise. The exploit send its malformed frames without going through this.
+pub fn verify(addr: &sockaddr_ll, frame: &[u8]) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+
+    let e = match check(frame) {
+        Ok(_) => return,
+        Err(e) => e,
+    };
+
+    let peer = MacAddr6::from(TryInto::<[u8; 6]>::try_into(&addr.sll_addr[..6]).unwrap());
+
+    if cfg!(test) {
+        panic!("Non-conforming frame to {peer}: {e}.");
+    }
+
+    warning!("Sending a non-conforming frame to {peer}: {e}.");
+}
+
+/// Returns the rule that `frame` violated, if any.
+pub fn check(frame: &[u8]) -> Result<(), String> {
+    if frame.get(6..).is_none() {
+        return Err("frame is shorter than PPPoE header".into());
+    }
+
+    let len: usize = u16::from_be_bytes([frame[4], frame[5]]).into();
+
+    if frame[0] != 0x11 {
+        return Err(format!("invalid VER and TYPE {:#04x}", frame[0]));
+    } else if 6 + len != frame.len() {
+        return Err(format!(
+            "LENGTH is {} but the payload is {} bytes",
+            len,
+            frame.len() - 6
+        ));
+    }
+
+    match Code::try_from(frame[1]) {
+        Ok(Code::Session) => session(frame),
+        Ok(v) => discovery(v, frame),
+        Err(v) => Err(format!("unknown CODE {v:#04x}")),
+    }
+}
+
+fn discovery(code: Code, frame: &[u8]) -> Result<(), String> {
+    let data = EthernetPayload::<Tags>::deserialize(frame).ok_or("a tag exceed the payload")?;
+    let tags = data.payload();
+    let id = data.session_id();
+    let count = |t: Tag| tags.iter().filter(|(v, _)| *v == t.into()).count();
+    let errors =
+        count(Tag::ServiceNameError) + count(Tag::AcSystemError) + count(Tag::GenericError);
+
+    // End-Of-List is optional but nothing can follow it.
+    let eol = tags.iter().position(|(t, _)| *t == Tag::EndOfList.into());
+
+    if eol.is_some_and(|i| i != tags.len() - 1) {
+        return Err("a tag follow End-Of-List".into());
+    }
+
+    // Check SESSION_ID.
+    match code {
+        Code::Pads if id == 0 && errors == 0 => {
+            return Err("PADS with SESSION_ID 0 has no error tag".into())
+        }
+        Code::Pads | Code::Padt if id == 0xffff => {
+            return Err("SESSION_ID 0xffff is reserved".into())
+        }
+        Code::Padt if id == 0 => return Err("PADT has SESSION_ID 0".into()),
+        Code::Pads | Code::Padt => {}
+        _ if id != 0 => return Err(format!("{} has SESSION_ID {:#06x}", code.name(), id)),
+        _ => {}
+    }
+
+    // Check the required tags.
+    let sn = count(Tag::ServiceName);
+
+    match code {
+        Code::Pado if count(Tag::AcName) != 1 => Err("PADO must have one AC-Name".into()),
+        Code::Pado if sn == 0 => Err("PADO has no Service-Name".into()),
+        Code::Padi | Code::Padr | Code::Pads if sn != 1 => {
+            Err(format!("{} must have one Service-Name", code.name()))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn session(frame: &[u8]) -> Result<(), String> {
+    let data = EthernetPayload::<Cow<[u8]>>::deserialize(frame).unwrap();
+    let ppp = data.payload();
+
+    match data.session_id() {
+        0 => return Err("session frame has SESSION_ID 0".into()),
+        0xffff => return Err("SESSION_ID 0xffff is reserved".into()),
+        _ => {}
+    }
+
+    if ppp.len() < 2 {
+        return Err("no PPP protocol".into());
+    } else if ppp.len() - 2 > MAX_PPP_MAX_PAYLOAD.into() {
+        // Whether PPP-Max-Payload (RFC 4638) raised the MRU above 1492 is not known here.
+        return Err(format!("{} bytes is larger than any MRU", ppp.len() - 2));
+    }
+
+    // Check control packet.
+    let proto = u16::from_be_bytes([ppp[0], ppp[1]]);
+    let packet = &ppp[2..];
+    let max = match proto {
+        PROTO_LCP => DISCARD_REQUEST,
+        PROTO_IPCP | PROTO_IPV6CP => CODE_REJECT,
+        PROTO_PAP => AUTHENTICATE_NAK,
+        _ => return Ok(()),
+    };
+
+    let (code, _, data) = parse_packet(packet).ok_or("control packet is truncated")?;
+
+    if 4 + data.len() != packet.len() {
+        return Err(format!(
+            "control packet LENGTH is {} but the packet is {} bytes",
+            4 + data.len(),
+            packet.len()
+        ));
+    } else if code == 0 || code > max {
+        return Err(format!("unknown code {code} for protocol {proto:#06x}"));
+    }
+
+    // Check options. PAP has the same codes with different contents.
+    if proto != PROTO_PAP && (CONFIGURE_REQUEST..=CONFIGURE_REJECT).contains(&code) {
+        let len: usize = Options::new(data).map(|(_, _, raw)| raw.len()).sum();
+
+        if len != data.len() {
+            return Err(format!("malformed option at offset {len}"));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::ppp::packet;
+
+    fn frame(code: Code, id: u16, tags: Tags) -> Vec<u8> {
+        EthernetPayload::new(code.into(), id, tags).serialize()
+    }
+
+    fn ppp(proto: u16, packet: &[u8]) -> Vec<u8> {
+        let data = [&proto.to_be_bytes()[..], packet].concat();
+
+        EthernetPayload::new(Code::Session.into(), 1, Cow::Owned(data)).serialize()
+    }
+
+    #[test]
+    fn rules() {
+        let sn = (Tag::ServiceName.into(), Cow::Borrowed(&b""[..]));
+        let ac = (Tag::AcName.into(), Cow::Borrowed(&b"AC"[..]));
+        let mut truncated = frame(Code::Pado, 0, vec![ac.clone(), sn.clone()]);
+
+        truncated.truncate(truncated.len() - 1);
+
+        assert!(check(&frame(Code::Pado, 0, vec![ac.clone(), sn.clone()])).is_ok());
+        assert!(check(&frame(Code::Pado, 0, vec![sn.clone()])).is_err());
+        assert!(check(&frame(Code::Pado, 1, vec![ac, sn.clone()])).is_err());
+        assert!(check(&truncated).is_err());
+        assert!(check(&frame(Code::Pads, 0, vec![sn.clone()])).is_err());
+        assert!(check(&frame(Code::Pads, 1, vec![sn])).is_ok());
+        assert!(check(&frame(Code::Padt, 0, Vec::new())).is_err());
+
+        // Session stage.
+        let opts = [1, 4, 0x05, 0xd4];
+
+        assert!(check(&ppp(PROTO_LCP, &packet(CONFIGURE_REQUEST, 1, &opts))).is_ok());
+        assert!(check(&ppp(PROTO_LCP, &packet(CONFIGURE_REQUEST, 1, &opts[..3]))).is_err());
+        assert!(check(&ppp(PROTO_LCP, &packet(0x41, 1, &[]))).is_err());
+        assert!(check(&ppp(PROTO_LCP, &[1, 1, 0, 8])).is_err());
+        assert!(check(&ppp(0x0021, &[0x45])).is_ok());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::addr::interface_carrier;
+use crate::discovery::DiscoveryServer;
+use crate::log::{error, info};
+use crate::rt;
+use crate::session::Sessions;
+use erdp::ErrorDisplay;
+use std::ffi::c_int;
+use std::fmt::{Display, Formatter, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::select;
+use tokio_util::sync::CancellationToken;
+
+/// Interval to re-evaluate the state.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The console usually start sending PADI within this duration after the link is up if it was
+/// configured with PPPoE.
+const BOOT_TIME: Duration = Duration::from_secs(60);
+
+/// The console send PADI every few seconds while the connection test is running.
+const PADI_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The console keep sending frames while the exploit is running so a silent session most likely
+/// mean the kernel has been crashed.
+const SILENCE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Infer the state of the console from the traffic and the carrier of the interface.
+///
+/// This is only a guess to tell the user what to do next.
+pub struct ConsoleMonitor {
+    interface: c_int,
+    discovery: Arc<DiscoveryServer>,
+    sessions: Arc<Sessions>,
+    state: Mutex<Current>,
+}
+
+impl ConsoleMonitor {
+    pub fn new(interface: c_int, discovery: Arc<DiscoveryServer>, sessions: Arc<Sessions>) -> Self {
+        Self {
+            interface,
+            discovery,
+            sessions,
+            state: Mutex::new(Current {
+                state: ConsoleState::Unknown,
+                since: Instant::now(),
+            }),
+        }
+    }
+
+    /// Returns the current state and how long it has been in that state.
+    pub fn state(&self) -> (ConsoleState, Duration) {
+        let cur = self.state.lock().unwrap();
+
+        (cur.state, cur.since.elapsed())
+    }
+
+    /// Write the current state to `out`.
+    pub fn dump(&self, out: &mut String) {
+        let cur = self.state.lock().unwrap();
+
+        writeln!(out, "Console:").unwrap();
+        writeln!(
+            out,
+            "  State: {} for {:.1} seconds",
+            cur.state,
+            cur.since.elapsed().as_secs_f64()
+        )
+        .unwrap();
+    }
+
+    /// Re-evaluate the state periodically until `running` is cancelled.
+    pub async fn run(&self, running: CancellationToken) {
+        // The carrier is not available on some interfaces (e.g. when the interface is down) so we
+        // only report the first error.
+        let mut reported = false;
+
+        loop {
+            let carrier = match interface_carrier(self.interface) {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    if !reported {
+                        error!(
+                            "Failed to get carrier of interface {}: {}.",
+                            self.interface,
+                            e.display()
+                        );
+
+                        reported = true;
+                    }
+
+                    None
+                }
+            };
+
+            self.update(carrier);
+
+            select! {
+                _ = running.cancelled() => break,
+                _ = rt::sleep(POLL_INTERVAL) => {}
+            }
+        }
+    }
+
+    fn update(&self, carrier: Option<bool>) {
+        let mut cur = self.state.lock().unwrap();
+        let now = Instant::now();
+        let state = cur.state;
+        let padi = self.discovery.last_padi();
+        let next = match carrier {
+            Some(false) => match state {
+                ConsoleState::Connected | ConsoleState::Crashed | ConsoleState::Rebooting => {
+                    ConsoleState::Rebooting
+                }
+                _ => ConsoleState::Off,
+            },
+            _ if matches!(state, ConsoleState::Off | ConsoleState::Rebooting) => {
+                ConsoleState::Booting
+            }
+            _ => match self.sessions.idle() {
+                Some(v) if v >= SILENCE_TIMEOUT => ConsoleState::Crashed,
+                Some(_) => ConsoleState::Connected,
+                None if padi.is_some_and(|v| now - v < PADI_TIMEOUT) => ConsoleState::NetworkTest,
+                None => match state {
+                    ConsoleState::Unknown if carrier.is_some() => ConsoleState::Idle,
+                    ConsoleState::Booting if now - cur.since < BOOT_TIME => state,
+                    ConsoleState::Booting | ConsoleState::NetworkTest | ConsoleState::Connected => {
+                        ConsoleState::Idle
+                    }
+                    v => v,
+                },
+            },
+        };
+
+        cur.set(next, now);
+    }
+}
+
+/// Inferred state of the console.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleState {
+    /// Nothing is known about the console yet.
+    Unknown,
+    /// The link is down.
+    Off,
+    /// The link has been up recently but no PADI yet.
+    Booting,
+    /// The console is up but is not trying to connect.
+    Idle,
+    /// The console is sending PADIs.
+    NetworkTest,
+    /// The console has an active session.
+    Connected,
+    /// The console has an active session but stopped responding.
+    Crashed,
+    /// The link was lost after the console has been connected.
+    Rebooting,
+}
+
+impl ConsoleState {
+    /// Returns the stable identifier of this state.
+    pub fn id(self) -> &'static str {
+        match self {
+            Self::Unknown => "UNKNOWN",
+            Self::Off => "OFF",
+            Self::Booting => "BOOTING",
+            Self::Idle => "IDLE",
+            Self::NetworkTest => "NETWORK_TEST",
+            Self::Connected => "CONNECTED",
+            Self::Crashed => "CRASHED",
+            Self::Rebooting => "REBOOTING",
+        }
+    }
+
+    /// Returns what the user should do in this state.
+    pub fn hint(self) -> Option<&'static str> {
+        match self {
+            Self::Unknown | Self::NetworkTest | Self::Connected => None,
+            Self::Off => Some("Check the cable and power on the PS4."),
+            Self::Booting | Self::Rebooting => {
+                Some("Wait for the PS4 to boot then start the connection test.")
+            }
+            Self::Idle => Some(
+                "Go to Settings > Network > Set Up Internet Connection on the PS4 to start the \
+                connection test.",
+            ),
+            Self::Crashed => {
+                Some("The PS4 may have crashed. Power-cycle it if it does not reboot by itself.")
+            }
+        }
+    }
+}
+
+impl Display for ConsoleState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let v = match self {
+            Self::Unknown => "unknown",
+            Self::Off => "powered off or disconnected",
+            Self::Booting => "booting",
+            Self::Idle => "idle",
+            Self::NetworkTest => "running the connection test",
+            Self::Connected => "connected",
+            Self::Crashed => "not responding",
+            Self::Rebooting => "rebooting",
+        };
+
+        f.write_str(v)
+    }
+}
+
+/// Current state with the time when it was entered.
+struct Current {
+    state: ConsoleState,
+    since: Instant,
+}
+
+impl Current {
+    fn set(&mut self, state: ConsoleState, now: Instant) {
+        if state == self.state {
+            return;
+        }
+
+        self.state = state;
+        self.since = now;
+
+        match state.hint() {
+            Some(h) => info!("The PS4 seems to be {state}. {h}"),
+            None => info!("The PS4 seems to be {state}."),
+        }
+    }
+}
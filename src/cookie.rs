@@ -0,0 +1,57 @@
+use crate::digest::hmac_sha256;
+use crate::random::random_bytes;
+use macaddr::MacAddr6;
+
+/// Length of AC-Cookie. HMAC-SHA256 is truncated to this length.
+pub const COOKIE_LEN: usize = 16;
+
+/// Generate and verify AC-Cookie tag (RFC 2516).
+///
+/// The cookie is HMAC-SHA256 of the peer address with a random key so a PADR with a cookie that
+/// was not issued to its sender can be rejected without keeping any state.
+pub struct CookieJar {
+    key: [u8; 32],
+}
+
+impl CookieJar {
+    pub fn new() -> Result<Self, std::io::Error> {
+        let mut key = [0; 32];
+
+        random_bytes(&mut key)?;
+
+        Ok(Self { key })
+    }
+
+    /// Returns AC-Cookie for `peer`.
+    pub fn issue(&self, peer: MacAddr6) -> [u8; COOKIE_LEN] {
+        hmac_sha256(&self.key, peer.as_bytes())[..COOKIE_LEN]
+            .try_into()
+            .unwrap()
+    }
+
+    /// Returns `true` if `cookie` was issued to `peer`.
+    pub fn verify(&self, peer: MacAddr6, cookie: &[u8]) -> bool {
+        let expected = self.issue(peer);
+
+        // Don't leak the position of the first mismatch.
+        cookie.len() == expected.len()
+            && cookie.iter().zip(expected).fold(0, |r, (a, b)| r | (a ^ b)) == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify() {
+        let jar = CookieJar::new().unwrap();
+        let peer = MacAddr6::new(2, 0, 0, 0, 0, 2);
+        let cookie = jar.issue(peer);
+
+        assert!(jar.verify(peer, &cookie));
+        assert!(!jar.verify(MacAddr6::new(2, 0, 0, 0, 0, 3), &cookie));
+        assert!(!jar.verify(peer, &cookie[1..]));
+        assert!(!CookieJar::new().unwrap().verify(peer, &cookie));
+    }
+}
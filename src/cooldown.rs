@@ -0,0 +1,202 @@
+use crate::log::info;
+use crate::rt;
+use macaddr::MacAddr6;
+use std::collections::HashMap;
+use std::io::stdin;
+use std::sync::{Arc, Mutex};
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+
+/// Cooldown period after a failed attempt.
+///
+/// The PS4 need to be restarted after a failed attempt since the kernel memory is already
+/// corrupted. Any PADI during this period will be ignored so we don't start a new attempt on the
+/// console that is about to crash. Each console has its own cooldown so the other consoles on the
+/// same link are not blocked.
+pub struct Cooldown {
+    duration: Duration,
+    guidance: &'static str,
+    wait_padi: bool,
+    until: Mutex<HashMap<MacAddr6, Instant>>,
+}
+
+impl Cooldown {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            guidance: "Power-cycle the PS4 then retry the connection test.",
+            wait_padi: false,
+            until: Mutex::default(),
+        }
+    }
+
+    /// Set what the user need to do with the console after a failed attempt, which depends on its
+    /// firmware.
+    pub fn with_guidance(mut self, v: &'static str) -> Self {
+        self.guidance = v;
+        self
+    }
+
+    /// Restart the cooldown on each PADI during it so it only ends once the console has been quiet
+    /// for the whole duration (e.g. it crashed or was turned off). The next PADI is then from the
+    /// restarted console.
+    pub fn with_padi_wait(mut self) -> Self {
+        self.wait_padi = true;
+        self
+    }
+
+    /// Start the cooldown of `peer` and print the guidance for the user.
+    pub fn start(self: &Arc<Self>, peer: MacAddr6) {
+        info!("The attempt on {} was failed. {}", peer, self.guidance);
+
+        if self.duration.is_zero() {
+            return;
+        }
+
+        if self.wait_padi {
+            info!(
+                "New attempts from {} will be accepted on the first PADI after it has been quiet for {} seconds or when Enter is pressed.",
+                peer,
+                self.duration.as_secs()
+            );
+        } else {
+            info!(
+                "New attempts from {} will be accepted after {} seconds or when Enter is pressed.",
+                peer,
+                self.duration.as_secs()
+            );
+        }
+
+        self.restart(&mut self.until.lock().unwrap(), peer, Instant::now());
+    }
+
+    /// Start the timer of `peer` on `list`, which is the locked [`Cooldown::until`].
+    fn restart(
+        self: &Arc<Self>,
+        list: &mut HashMap<MacAddr6, Instant>,
+        peer: MacAddr6,
+        now: Instant,
+    ) {
+        let until = now + self.duration;
+        let cooldown = self.clone();
+
+        list.insert(peer, until);
+
+        rt::spawn(async move {
+            rt::sleep(cooldown.duration).await;
+
+            // Check if the cooldown was skipped or restarted.
+            let mut current = cooldown.until.lock().unwrap();
+
+            if current.get(&peer) != Some(&until) {
+                return;
+            }
+
+            current.remove(&peer);
+
+            if cooldown.wait_padi {
+                info!("Cooldown of {peer} is over, waiting for a new PADI.");
+            } else {
+                info!("Cooldown of {peer} is over, ready for a new attempt.");
+            }
+        });
+    }
+
+    /// End the cooldown of all consoles immediately.
+    pub fn skip(&self) {
+        let mut until = self.until.lock().unwrap();
+
+        if !until.is_empty() {
+            until.clear();
+            info!("Cooldown was skipped, ready for a new attempt.");
+        }
+    }
+
+    /// Returns the longest remaining cooldown of all consoles or [`None`] if no cooldown is active.
+    pub fn remaining(&self) -> Option<Duration> {
+        let now = Instant::now();
+
+        self.until
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|v| v.checked_duration_since(now))
+            .max()
+    }
+
+    /// Must be called on each PADI from `peer`. Returns `true` if the PADI must be ignored due to
+    /// the cooldown.
+    pub fn padi(self: &Arc<Self>, peer: MacAddr6) -> bool {
+        let mut until = self.until.lock().unwrap();
+        let now = Instant::now();
+
+        match until.get(&peer) {
+            Some(&v) if now < v => {
+                // The console is still alive so wait until it become quiet.
+                if self.wait_padi {
+                    self.restart(&mut until, peer, now);
+                }
+
+                true
+            }
+            Some(_) => {
+                until.remove(&peer);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Skip the cooldown each time Enter is pressed.
+    ///
+    /// This use a dedicated thread since reading stdin is blocking on all runtimes.
+    pub fn watch_stdin(self: Arc<Self>) {
+        spawn(move || {
+            for l in stdin().lines() {
+                if l.is_err() {
+                    break;
+                }
+
+                self.skip();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::runtime;
+
+    #[test]
+    fn padi_wait() {
+        runtime().block_on(async {
+            let peer = MacAddr6::new(2, 0, 0, 0, 0, 1);
+            let timer = Arc::new(Cooldown::new(Duration::from_millis(100)));
+            let wait = Arc::new(Cooldown::new(Duration::from_millis(100)).with_padi_wait());
+
+            timer.start(peer);
+            wait.start(peer);
+
+            assert!(timer.padi(peer));
+            assert!(wait.padi(peer));
+
+            // Only the PADI wait restart the cooldown.
+            rt::sleep(Duration::from_millis(60)).await;
+
+            assert!(timer.padi(peer));
+            assert!(wait.padi(peer));
+
+            rt::sleep(Duration::from_millis(60)).await;
+
+            assert!(!timer.padi(peer));
+            assert!(wait.padi(peer));
+
+            // The first PADI after the console is quiet end it.
+            rt::sleep(Duration::from_millis(150)).await;
+
+            assert!(!wait.padi(peer));
+            assert!(!wait.padi(peer));
+        });
+    }
+}
@@ -1,46 +1,245 @@
 use crate::addr::AddrBuilder;
-use crate::payload::EthernetPayload;
-use crate::session::Sessions;
-use crate::socket::PacketSocket;
+use crate::attempt::{Attempt, Attempts, Milestone, Stage};
+use crate::banner::Banner;
+use crate::conflict::competing_ac;
+use crate::cookie::CookieJar;
+use crate::cooldown::Cooldown;
+#[cfg(feature = "exploit")]
+use crate::exploit::Exploit;
+use crate::failure::Failure;
+use crate::kppp::KernelPpp;
+use crate::log::{self, error, info, warning};
+use crate::policy::{AnswerPolicy, Answerer};
+use crate::proto::lcp::MAX_MRU;
+use crate::proto::pppoe::{Code, EthernetPayload, Tag, Tags};
+use crate::recovery::Recovery;
+use crate::rt;
+use crate::session::{Session, SessionMsg, Sessions};
+use crate::socket::{PacketSocket, SocketStats, Transport};
 use erdp::ErrorDisplay;
-use libc::ETH_P_PPP_DISC;
 use macaddr::MacAddr6;
 use std::borrow::Cow;
-use std::io::Write;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter, Write as _};
+use std::num::{NonZeroU16, NonZeroUsize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::select;
 use tokio_util::sync::CancellationToken;
 
 /// Server for PPPoE Discovery Stage.
-pub struct DiscoveryServer {
-    sock: PacketSocket,
+pub struct DiscoveryServer<T = PacketSocket> {
+    sock: T,
     ab: Arc<AddrBuilder>,
     sessions: Arc<Sessions>,
+    attempts: Arc<Attempts>,
+    cooldown: Arc<Cooldown>,
+    ethertype: u16,
+    #[cfg(feature = "exploit")]
+    exploit: Option<Arc<Exploit>>,
+    #[cfg(feature = "exploit")]
+    attempt_timeout: Option<Duration>,
+    kernel_ppp: Option<String>,
+    padi_window: Duration,
+    answerer: Answerer,
+    pads_zero_id: bool,
+    ac_name: String,
+    service_name: Option<String>,
+    banner: Banner,
+    inactivity: Option<Duration>,
+    inactivity_padt: bool,
+    strict: bool,
+    max_consoles: Option<NonZeroUsize>,
+    max_payload: Option<u16>,
+    cookies: CookieJar,
+    padis: Mutex<HashMap<MacAddr6, LastPadi>>,
+    last_padi: Mutex<Option<Instant>>,
+    offers: Mutex<HashMap<MacAddr6, Option<Vec<u8>>>>,
+    competitors: Mutex<HashSet<MacAddr6>>,
+    answered: AtomicBool,
+    stop: CancellationToken,
+    accepting: CancellationToken,
 }
 
-impl DiscoveryServer {
-    pub fn new(sock: PacketSocket, ab: Arc<AddrBuilder>, sessions: Arc<Sessions>) -> Self {
-        Self { sock, ab, sessions }
+impl<T: Transport> DiscoveryServer<T> {
+    pub fn new(
+        sock: T,
+        ab: Arc<AddrBuilder>,
+        sessions: Arc<Sessions>,
+        attempts: Arc<Attempts>,
+        cooldown: Arc<Cooldown>,
+        config: DiscoveryConfig,
+    ) -> Result<Self, std::io::Error> {
+        let stop = CancellationToken::new();
+
+        Ok(Self {
+            sock,
+            ab,
+            sessions,
+            attempts,
+            cooldown,
+            ethertype: config.ethertype,
+            #[cfg(feature = "exploit")]
+            exploit: config.exploit,
+            #[cfg(feature = "exploit")]
+            attempt_timeout: config.attempt_timeout,
+            kernel_ppp: config.kernel_ppp,
+            padi_window: config.padi_window,
+            answerer: Answerer::new(config.policy),
+            pads_zero_id: config.pads_zero_id,
+            ac_name: config.ac_name,
+            service_name: config.service_name,
+            banner: config.banner,
+            inactivity: config.inactivity,
+            inactivity_padt: config.inactivity_padt,
+            strict: config.strict,
+            max_consoles: config.max_consoles,
+            max_payload: config.max_payload,
+            cookies: CookieJar::new()?,
+            padis: Mutex::default(),
+            last_padi: Mutex::default(),
+            offers: Mutex::default(),
+            competitors: Mutex::default(),
+            answered: AtomicBool::new(false),
+            accepting: stop.child_token(),
+            stop,
+        })
     }
 
-    pub async fn run(self, running: CancellationToken) {
+    /// Stop accepting new sessions then close all active sessions with PADT.
+    ///
+    /// This will resolve once all sessions has been closed.
+    pub async fn shutdown(&self) {
+        self.stop.cancel();
+
+        for (id, peer) in self.sessions.peers() {
+            self.send_padt(id, peer);
+        }
+
+        self.sessions.close_all();
+        self.sessions.wait_empty().await;
+    }
+
+    /// Stop accepting new sessions then close all active sessions without notifying the peers.
+    ///
+    /// This will resolve once all sessions has been closed.
+    pub async fn abort(&self) {
+        self.stop.cancel();
+        self.sessions.close_all();
+        self.sessions.wait_empty().await;
+    }
+
+    /// Returns the token to stop accepting new consoles.
+    ///
+    /// Once it has been cancelled the discovery from the consoles without an active attempt is
+    /// ignored while the active attempts keep running until they are finished. This is a child of
+    /// the token that [`Self::shutdown()`] and [`Self::abort()`] cancel, and independent from the
+    /// token passed to [`Self::run()`].
+    pub fn accepting(&self) -> CancellationToken {
+        self.accepting.clone()
+    }
+
+    /// Returns the kernel counters of the discovery socket.
+    pub fn statistics(&self) -> Result<SocketStats, std::io::Error> {
+        self.sock.statistics()
+    }
+
+    /// Returns the time of the most recent PADI from any console, including the ignored ones.
+    pub fn last_padi(&self) -> Option<Instant> {
+        *self.last_padi.lock().unwrap()
+    }
+
+    /// Returns the other access concentrators that have been answering on the segment.
+    pub fn competitors(&self) -> Vec<MacAddr6> {
+        let mut v: Vec<MacAddr6> = self.competitors.lock().unwrap().iter().copied().collect();
+        v.sort_unstable();
+        v
+    }
+
+    /// Write the state of this server to `out`.
+    pub fn dump(&self, out: &mut String) {
+        writeln!(out, "Discovery server:").unwrap();
+        writeln!(out, "  Stopped: {}", self.stop.is_cancelled()).unwrap();
+        writeln!(out, "  Accepting: {}", !self.accepting.is_cancelled()).unwrap();
+        writeln!(out, "  Answer policy: {}", self.answerer.policy()).unwrap();
+        writeln!(out, "  AC-Name: {}", self.ac_name).unwrap();
+        writeln!(
+            out,
+            "  Service-Name: {}",
+            self.service_name.as_deref().unwrap_or("*")
+        )
+        .unwrap();
+        writeln!(out, "  Banner: {}", self.banner).unwrap();
+
+        match self.max_consoles {
+            Some(v) => writeln!(out, "  Max consoles: {v}").unwrap(),
+            None => writeln!(out, "  Max consoles: unlimited").unwrap(),
+        }
+
+        match self.max_payload {
+            Some(v) => writeln!(out, "  PPP-Max-Payload: up to {v}").unwrap(),
+            None => writeln!(out, "  PPP-Max-Payload: ignored").unwrap(),
+        }
+
+        match self.inactivity {
+            Some(v) => writeln!(
+                out,
+                "  Inactivity timeout: {} seconds{}",
+                v.as_secs(),
+                if self.inactivity_padt {
+                    " with PADT"
+                } else {
+                    ""
+                }
+            )
+            .unwrap(),
+            None => writeln!(out, "  Inactivity timeout: disabled").unwrap(),
+        }
+
+        match self.cooldown.remaining() {
+            Some(v) => writeln!(out, "  Cooldown: {:.1} seconds left", v.as_secs_f64()).unwrap(),
+            None => writeln!(out, "  Cooldown: inactive").unwrap(),
+        }
+
+        for (addr, p) in self.padis.lock().unwrap().iter() {
+            writeln!(
+                out,
+                "  Last PADI from {}: {:.1} seconds ago, {} suppressed",
+                addr,
+                p.time.elapsed().as_secs_f64(),
+                p.suppressed
+            )
+            .unwrap();
+        }
+
+        self.sock.dump(out);
+    }
+
+    pub async fn run(&self, running: CancellationToken) {
         let mut buf = [0; 1500];
+        let mut recovery = Recovery::new("PPPoE discovery socket");
 
         loop {
             // Wait for PPPoE discovery packet.
             let (len, addr) = select! {
                 _ = running.cancelled() => break,
+                _ = self.stop.cancelled() => break,
+                _ = self.inactivity_timer() => {
+                    self.expire_sessions();
+                    continue;
+                }
+                _ = self.padi_timer() => {
+                    self.report_padis();
+                    continue;
+                }
                 v = self.sock.recv(&mut buf) => match v {
-                    Ok(v) => v,
-                    Err(e) => {
-                        eprintln!(
-                            "Failed to receive a packet from PPPoE discovery socket: {}.",
-                            e.display()
-                        );
-
-                        running.cancel();
-                        return;
+                    Ok(v) => {
+                        recovery.succeeded();
+                        v
                     }
+                    Err(e) if recovery.failed(&e, &running).await => continue,
+                    Err(_) => return,
                 }
             };
 
@@ -55,61 +254,160 @@ impl DiscoveryServer {
             let data = match Payload::deserialize(&buf[..len]) {
                 Some(v) => v,
                 None => {
-                    eprintln!("Unexpected PPPoE discovery packet from {addr}.");
+                    warning!("Unexpected PPPoE discovery packet from {addr}.");
+                    self.violation(addr, "malformed PPPoE discovery frame");
                     continue;
                 }
             };
 
+            // A PADO or PADS from the other host means a real access concentrator is also answering
+            // the console, which is not the fault of the console.
+            if let Some(c) = competing_ac(addr, &data) {
+                if self.competitors.lock().unwrap().insert(addr) {
+                    warning!("WARNING: found {c} on the segment. {}", c.hint());
+                }
+
+                continue;
+            }
+
             // Process the payload.
             match ty {
-                0 => match data.code() {
-                    0x19 => self.parse_padr(addr, data),
-                    _ => eprintln!(
-                        "Unexpected PPPoE discovery unicast packet {} from {}.",
-                        data.code(),
-                        addr
-                    ),
+                0 => match Code::try_from(data.code()) {
+                    Ok(Code::Padr) => self.parse_padr(addr, data),
+                    Ok(Code::Padt) => self.parse_padt(addr, data),
+                    _ => {
+                        warning!(
+                            "Unexpected PPPoE discovery unicast packet {} from {}.",
+                            data.code(),
+                            addr
+                        );
+
+                        self.violation(
+                            addr,
+                            &format!(
+                                "unicast PPPoE discovery frame with code {:#04x}",
+                                data.code()
+                            ),
+                        );
+                    }
                 },
-                1 => match data.code() {
-                    0x09 => self.parse_padi(addr, data),
-                    _ => eprintln!(
-                        "Unexpected PPPoE discovery broadcast packet {} from {}.",
-                        data.code(),
-                        addr
-                    ),
+                1 => match Code::try_from(data.code()) {
+                    Ok(Code::Padi) => self.parse_padi(addr, data),
+                    _ => {
+                        warning!(
+                            "Unexpected PPPoE discovery broadcast packet {} from {}.",
+                            data.code(),
+                            addr
+                        );
+
+                        self.violation(
+                            addr,
+                            &format!(
+                                "broadcast PPPoE discovery frame with code {:#04x}",
+                                data.code()
+                            ),
+                        );
+                    }
                 },
-                _ => eprintln!("Unexpected sll_pkttype for PPPoE discovery packet from {addr}."),
+                _ => {
+                    warning!("Unexpected sll_pkttype for PPPoE discovery packet from {addr}.");
+                    self.violation(
+                        addr,
+                        "PPPoE discovery frame that is not unicast or broadcast",
+                    );
+                }
             }
         }
     }
 
     fn parse_padi(&self, addr: MacAddr6, data: Payload) {
+        // Some consoles fall back to DHCP if the first PADO is late so the messages are written
+        // after it has been sent.
+        let held = (!self.answered.load(Ordering::Relaxed)).then(log::hold);
+
         if data.session_id() != 0x0000 {
-            eprintln!("Unexpected PPPoE SESSION_ID from {addr}.");
+            warning!("Unexpected PPPoE SESSION_ID from {addr}.");
+            self.violation(addr, "PADI with non-zero SESSION_ID");
+            return;
+        }
+
+        // Some consoles send PADI in a burst so we only respond to the first one. The duplicates
+        // still count as a sign of life for the cooldown.
+        let raw = data.serialize();
+        let now = Instant::now();
+        let duplicate = match self.padis.lock().unwrap().get_mut(&addr) {
+            Some(v) if v.data == raw && now.duration_since(v.time) < self.padi_window => {
+                v.suppressed += 1;
+                true
+            }
+            _ => false,
+        };
+
+        *self.last_padi.lock().unwrap() = Some(now);
+
+        if duplicate {
+            self.cooldown.padi(addr);
             return;
         }
 
+        self.answer_padi(addr, &data);
+
+        // Start a new burst once the PADO is on the wire.
+        let mut padis = self.padis.lock().unwrap();
+
+        if let Some(v) = padis.get(&addr) {
+            if v.suppressed != 0 {
+                info!(
+                    "Ignored {} identical PADIs from {} after the previous one.",
+                    v.suppressed, addr
+                );
+            }
+        }
+
+        padis.insert(
+            addr,
+            LastPadi {
+                data: raw,
+                time: now,
+                suppressed: 0,
+            },
+        );
+
+        drop(padis);
+        drop(held);
+    }
+
+    /// Send a PADO for the PADI from `addr` if it is acceptable. Only the checks that decide
+    /// whether to answer are done before the PADO is sent and the rest is recorded after that.
+    fn answer_padi(&self, addr: MacAddr6, data: &Payload) {
         // Process tags.
         let mut sn = None; // Service-Name
         let mut hu = None; // Host-Uniq
 
         for (t, v) in data.payload() {
-            match t {
-                0x0101 => {
+            match Tag::try_from(*t) {
+                Ok(Tag::ServiceName) => {
                     if sn.is_some() {
-                        eprintln!("Multiple Service-Name tags on PADI packet from {addr}.");
+                        warning!("Multiple Service-Name tags on PADI packet from {addr}.");
+                        self.violation(addr, "multiple Service-Name tags on PADI");
                         return;
                     }
 
                     match std::str::from_utf8(v.as_ref()) {
                         Ok(v) => sn = Some(v),
                         Err(_) => {
-                            eprintln!("Invalid Service-Name tag on PADI packet from {addr}.");
+                            warning!("Invalid Service-Name tag on PADI packet from {addr}.");
+                            self.violation(addr, "invalid Service-Name tag on PADI");
                             return;
                         }
                     }
                 }
-                0x0103 => hu = Some(v.as_ref()),
+                Ok(Tag::HostUniq) => hu = Some(v.as_ref()),
+                Err(_) if self.strict => {
+                    warning!("Unknown tag {t:#06x} on PADI packet from {addr}.");
+                    self.violation(addr, &format!("unknown tag {t:#06x} on PADI"));
+                    return;
+                }
                 _ => {}
             }
         }
@@ -118,62 +416,174 @@ impl DiscoveryServer {
         let sn = match sn {
             Some(v) => v,
             None => {
-                eprintln!("No Service-Name tag on PADI packet from {addr}.");
+                warning!("No Service-Name tag on PADI packet from {addr}.");
+                self.violation(addr, "no Service-Name tag on PADI");
+                return;
+            }
+        };
+
+        info!(
+            "PADI from {}: Service-Name = '{}', Host-Uniq = {}.",
+            addr,
+            sn,
+            HostUniq(hu)
+        );
+
+        let sn = match self.offer(sn) {
+            Some(v) => v,
+            None => {
+                info!("Ignored PADI from {addr} due to unsupported Service-Name.");
                 return;
             }
         };
 
-        println!("PADI: Service-Name = '{sn}', Host-Uniq = {hu:?}");
+        if !self.answerer.accept(addr) {
+            info!("Ignored PADI from {addr} due to the answer policy.");
+            return;
+        }
+
+        if self.cooldown.padi(addr) {
+            info!("Ignored PADI from {addr} due to cooldown.");
+            return;
+        }
+
+        if self.accepting.is_cancelled() && self.attempts.active(addr).is_none() {
+            info!("Ignored PADI from {addr} since new consoles are not accepted.");
+            return;
+        }
+
+        if !self.admit(addr) {
+            info!("Ignored PADI from {addr} since the other consoles are being served.");
+            return;
+        }
+
+        // The console start a new discovery once it gave up on the current session (e.g. the
+        // exploit was failed without crashing it) so close the session and start a new attempt.
+        if let Some(a) = self.attempts.active(addr) {
+            if a.milestone() >= Some(Milestone::SessionStarted) && self.sessions.restart(addr) != 0
+            {
+                info!("Attempt #{}: {} started a new discovery.", a.id(), addr);
+                a.record(Stage::Discovery, "PADI received during the session");
+                self.attempts.finish(&a, Err(Failure::Aborted));
+            }
+        }
+
+        let attempt = match self.attempts.get_or_start(addr) {
+            Some(v) => v,
+            None => {
+                info!("Ignored PADI from {addr} since all attempts have been used.");
+                return;
+            }
+        };
+
+        // The PADI is recorded once the PADO has been sent (or failed).
+        let received = || {
+            match hu {
+                Some(_) => attempt.record(
+                    Stage::Discovery,
+                    format!("PADI received with Host-Uniq {}", HostUniq(hu)),
+                ),
+                None => attempt.record(Stage::Discovery, "PADI received"),
+            }
+            attempt.advance(Milestone::PadiReceived);
+        };
 
         // Send PPPoE Active Discovery Offer (PADO) packet.
+        let ac_name = self.banner.apply(&self.ac_name, attempt.id());
+        let sn = self.banner_service(sn, &attempt);
         let mut pado = Payload::new(
-            0x07,
+            Code::Pado.into(),
             0x0000,
             vec![
-                (0x0102, Cow::Borrowed("OBHQ Jailbreak 11.00".as_bytes())),
-                (0x0101, Cow::Borrowed(sn.as_bytes())),
+                (Tag::AcName.into(), Cow::Borrowed(ac_name.as_bytes())),
+                (Tag::ServiceName.into(), Cow::Borrowed(sn.as_bytes())),
             ],
         );
 
         if let Some(hu) = hu {
-            pado.payload_mut().push((0x0103, Cow::Borrowed(hu)));
+            pado.payload_mut()
+                .push((Tag::HostUniq.into(), Cow::Borrowed(hu)));
+        }
+
+        if let Some(v) = self.agree_max_payload(data) {
+            pado.payload_mut().push((
+                Tag::PppMaxPayload.into(),
+                Cow::Owned(v.to_be_bytes().into()),
+            ));
         }
 
-        if let Err(e) = self.sock.send(
-            self.ab.build(ETH_P_PPP_DISC as _, Some(addr)),
-            pado.serialize(),
+        pado.payload_mut().push((
+            Tag::AcCookie.into(),
+            Cow::Owned(self.cookies.issue(addr).into()),
+        ));
+
+        if let Err(e) = self.sock.send_confirmed(
+            self.ab.build(self.ethertype, Some(addr)),
+            &pado.serialize(),
+            attempt.timing(Stage::Discovery),
+            attempt.tx_recorder(Stage::Discovery, "PADO"),
         ) {
-            eprintln!("Failed to send PADO packet to {}: {}.", addr, e.display());
+            error!("Failed to send PADO packet to {}: {}.", addr, e.display());
+            received();
+            return;
+        }
+
+        self.answered.store(true, Ordering::Relaxed);
+
+        // Keep Host-Uniq to verify the PADR.
+        self.offers
+            .lock()
+            .unwrap()
+            .insert(addr, hu.map(|v| v.to_vec()));
+
+        received();
+
+        match self.banner {
+            Banner::Fixed => attempt.record(Stage::Discovery, "PADO sent"),
+            _ => attempt.record(
+                Stage::Discovery,
+                format!("PADO sent with AC-Name '{ac_name}' and Service-Name '{sn}'"),
+            ),
         }
     }
 
     fn parse_padr(&self, addr: MacAddr6, data: Payload) {
         if data.session_id() != 0x0000 {
-            eprintln!("Unexpected PPPoE SESSION_ID from {addr}.");
+            warning!("Unexpected PPPoE SESSION_ID from {addr}.");
+            self.violation(addr, "PADR with non-zero SESSION_ID");
             return;
         }
 
         // Process tags.
         let mut sn = None; // Service-Name
         let mut hu = None; // Host-Uniq
+        let mut cookie = None; // AC-Cookie
 
         for (t, v) in data.payload() {
-            match t {
-                0x0101 => {
+            match Tag::try_from(*t) {
+                Ok(Tag::ServiceName) => {
                     if sn.is_some() {
-                        eprintln!("Multiple Service-Name tags on PADR packet from {addr}.");
+                        warning!("Multiple Service-Name tags on PADR packet from {addr}.");
+                        self.violation(addr, "multiple Service-Name tags on PADR");
                         return;
                     }
 
                     match std::str::from_utf8(v.as_ref()) {
                         Ok(v) => sn = Some(v),
                         Err(_) => {
-                            eprintln!("Invalid Service-Name tag on PADR packet from {addr}.");
+                            warning!("Invalid Service-Name tag on PADR packet from {addr}.");
+                            self.violation(addr, "invalid Service-Name tag on PADR");
                             return;
                         }
                     }
                 }
-                0x0103 => hu = Some(v.as_ref()),
+                Ok(Tag::HostUniq) => hu = Some(v.as_ref()),
+                Ok(Tag::AcCookie) => cookie = Some(v.as_ref()),
+                Err(_) if self.strict => {
+                    warning!("Unknown tag {t:#06x} on PADR packet from {addr}.");
+                    self.violation(addr, &format!("unknown tag {t:#06x} on PADR"));
+                    return;
+                }
                 _ => {}
             }
         }
@@ -182,72 +592,1073 @@ impl DiscoveryServer {
         let sn = match sn {
             Some(v) => v,
             None => {
-                eprintln!("No Service-Name tag on PADR packet from {addr}.");
+                warning!("No Service-Name tag on PADR packet from {addr}.");
+                self.violation(addr, "no Service-Name tag on PADR");
+                return;
+            }
+        };
+
+        info!(
+            "PADR from {}: Service-Name = '{}', Host-Uniq = {}.",
+            addr,
+            sn,
+            HostUniq(hu)
+        );
+
+        // RFC 2516 requires AC to send PADS with Service-Name-Error if it can't serve the request.
+        if self.offer(sn).is_none() {
+            info!("Rejected PADR from {addr} due to unsupported Service-Name.");
+            self.send_pads_error(addr, sn, hu, Tag::ServiceNameError, "Unsupported service");
+            return;
+        }
+
+        // Check AC-Cookie. The console must echo the one from our PADO.
+        if !cookie.is_some_and(|v| self.cookies.verify(addr, v)) {
+            warning!("Invalid or missing AC-Cookie on PADR packet from {addr}.");
+            self.violation(addr, "invalid or missing AC-Cookie on PADR");
+            return;
+        }
+
+        // Check Host-Uniq. It must be the same as the PADI that we answered otherwise this PADR
+        // belongs to another discovery on the same console (e.g. another PPPoE client on it).
+        let offered = self.offers.lock().unwrap().get(&addr).cloned();
+
+        match offered {
+            Some(v) if v.as_deref() == hu => {}
+            Some(v) => {
+                warning!(
+                    "Host-Uniq {} on PADR packet from {} does not match {} on its PADI.",
+                    HostUniq(hu),
+                    addr,
+                    HostUniq(v.as_deref())
+                );
+
+                self.violation(addr, "Host-Uniq on PADR does not match PADI");
+                return;
+            }
+            None => {
+                warning!("Unexpected PADR from {addr} without PADI.");
+                self.violation(addr, "PADR without PADI");
+                return;
+            }
+        }
+
+        if !self.answerer.accept(addr) {
+            info!("Ignored PADR from {addr} due to the answer policy.");
+            return;
+        }
+
+        if self.accepting.is_cancelled() && self.attempts.active(addr).is_none() {
+            info!("Ignored PADR from {addr} since new consoles are not accepted.");
+            return;
+        }
+
+        if !self.admit(addr) {
+            info!("Ignored PADR from {addr} since the other consoles are being served.");
+            return;
+        }
+
+        let attempt = match self.attempts.get_or_start(addr) {
+            Some(v) => v,
+            None => {
+                info!("Ignored PADR from {addr} since all attempts have been used.");
                 return;
             }
         };
 
-        println!("PADR: Service-Name = '{sn}', Host-Uniq = {hu:?}");
+        match hu {
+            Some(_) => attempt.record(
+                Stage::Discovery,
+                format!("PADR received with Host-Uniq {}", HostUniq(hu)),
+            ),
+            None => attempt.record(Stage::Discovery, "PADR received"),
+        }
 
         // Spawn a session.
-        let session = match self.sessions.spawn() {
+        let max_payload = self.agree_max_payload(&data);
+        let session = match self.sessions.spawn(addr, max_payload) {
             Some(v) => v,
-            None => todo!(),
+            None => {
+                error!("Failed to allocate a session for {addr}: all session IDs are in use.");
+                warning!("Close the other sessions or restart the jailbreak.");
+                self.send_pads_error(addr, sn, hu, Tag::AcSystemError, "No free session");
+                attempt.record(Stage::Discovery, "PADS sent with AC-System-Error");
+                attempt.complete(Stage::Discovery, Err(Failure::NoFreeSession));
+                self.attempts.finish(&attempt, Err(Failure::NoFreeSession));
+                return;
+            }
+        };
+
+        // Attach the session to the kernel before the console knows about it so a failure can still
+        // be reported with PADS.
+        let ppp = match &self.kernel_ppp {
+            Some(v) => match KernelPpp::attach(v, session.id().get(), addr) {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    error!(
+                        "Failed to hand off session {} to kernel PPP: {}.",
+                        session.id(),
+                        e.display()
+                    );
+
+                    self.send_pads_error(addr, sn, hu, Tag::AcSystemError, "Kernel PPP failed");
+                    attempt.record(Stage::Discovery, "PADS sent with AC-System-Error");
+                    attempt.complete(Stage::Discovery, Err(Failure::KernelPppFailed));
+                    self.attempts
+                        .finish(&attempt, Err(Failure::KernelPppFailed));
+                    return;
+                }
+            },
+            None => None,
         };
 
         // Send PPPoE Active Discovery Session-confirmation (PADS) packet.
+        let sn = self.banner_service(sn, &attempt);
+        let id = if self.pads_zero_id {
+            0
+        } else {
+            session.id().get()
+        };
+
         let mut pads = Payload::new(
-            0x65,
-            session.id().get(),
-            vec![(0x0101, Cow::Borrowed(sn.as_bytes()))],
+            Code::Pads.into(),
+            id,
+            vec![(Tag::ServiceName.into(), Cow::Borrowed(sn.as_bytes()))],
         );
 
         if let Some(hu) = hu {
-            pads.payload_mut().push((0x0103, Cow::Borrowed(hu)));
+            pads.payload_mut()
+                .push((Tag::HostUniq.into(), Cow::Borrowed(hu)));
         }
 
-        if let Err(e) = self.sock.send(
-            self.ab.build(ETH_P_PPP_DISC as _, Some(addr)),
-            pads.serialize(),
+        if let Some(v) = max_payload {
+            pads.payload_mut().push((
+                Tag::PppMaxPayload.into(),
+                Cow::Owned(v.to_be_bytes().into()),
+            ));
+        }
+
+        if let Err(e) = self.sock.send_confirmed(
+            self.ab.build(self.ethertype, Some(addr)),
+            &pads.serialize(),
+            attempt.timing(Stage::Discovery),
+            attempt.tx_recorder(Stage::Discovery, "PADS"),
         ) {
-            eprintln!("Failed to send PADS packet to {}: {}.", addr, e.display());
+            error!("Failed to send PADS packet to {}: {}.", addr, e.display());
+            attempt.complete(Stage::Discovery, Err(Failure::SendFailed));
+            self.attempts.finish(&attempt, Err(Failure::SendFailed));
             return;
         }
 
+        attempt.record(Stage::Discovery, format!("PADS sent with SESSION_ID {id}"));
+
+        if let Some(v) = max_payload {
+            info!("Agreed PPP-Max-Payload {v} with {addr}.");
+            attempt.record(Stage::Discovery, format!("PPP-Max-Payload {v} agreed"));
+        }
+
+        attempt.complete(Stage::Discovery, Ok(()));
+
         // Spawn a task to handle the session.
-        tokio::spawn(session.run());
+        match ppp {
+            Some(v) => self.spawn_kernel(session, addr, attempt, v),
+            None => {
+                let attempts = self.attempts.clone();
+                let cooldown = self.cooldown.clone();
+                #[cfg(feature = "exploit")]
+                let exploit = self.exploit.clone();
+                #[cfg(feature = "exploit")]
+                let timeout = self.attempt_timeout;
+
+                rt::spawn(async move {
+                    #[cfg(feature = "exploit")]
+                    let result = match &exploit {
+                        Some(v) => session.run(&attempt, v, timeout).await,
+                        None => session.serve(&attempt).await,
+                    };
+                    #[cfg(not(feature = "exploit"))]
+                    let result = session.serve(&attempt).await;
+
+                    attempts.finish(&attempt, result);
+
+                    if result.is_err_and(|e| e.requires_reboot()) {
+                        cooldown.start(attempt.peer());
+                    }
+                });
+            }
+        }
+    }
+
+    fn parse_padt(&self, addr: MacAddr6, data: Payload) {
+        // The console may terminate the session at any time (e.g. when the connection test was
+        // cancelled) so close it otherwise the ID will never be freed.
+        let id = match NonZeroU16::new(data.session_id()) {
+            Some(v) => v,
+            None => {
+                warning!("Unexpected PADT with SESSION_ID 0 from {addr}.");
+                self.violation(addr, "PADT with SESSION_ID 0");
+                return;
+            }
+        };
+
+        if self.sessions.peer(id) == Some(addr) && self.sessions.send(id, SessionMsg::Padt) {
+            info!("Session {id} was terminated by {addr}.");
+        } else {
+            warning!("Unexpected PADT for unknown session {id} from {addr}.");
+            self.violation(addr, &format!("PADT for unknown session {id}"));
+        }
+    }
+
+    /// Returns `false` if `addr` has no active attempt and [`DiscoveryConfig::max_consoles`] has
+    /// been reached by the other consoles.
+    fn admit(&self, addr: MacAddr6) -> bool {
+        let max = match self.max_consoles {
+            Some(v) => v.get(),
+            None => return true,
+        };
+
+        let active = self.attempts.actives();
+
+        active.len() < max || active.iter().any(|a| a.peer() == addr)
+    }
+
+    /// Returns PPP-Max-Payload (RFC 4638) to echo for the one on `data`, which is the smaller of it
+    /// and [`DiscoveryConfig::max_payload`]. Returns [`None`] if there is nothing to agree above
+    /// [`MAX_MRU`].
+    fn agree_max_payload(&self, data: &Payload) -> Option<u16> {
+        let v = data.ppp_max_payload()?.min(self.max_payload?);
+
+        (v > MAX_MRU).then_some(v)
+    }
+
+    /// Fail the active attempt of `addr` with [`Failure::ProtocolViolation`] if we are in strict
+    /// mode. The session of the attempt is closed with `reason` if it has been started.
+    fn violation(&self, addr: MacAddr6, reason: &str) {
+        if !self.strict {
+            return;
+        }
+
+        let attempt = match self.attempts.active(addr) {
+            Some(v) => v,
+            None => return,
+        };
+
+        if self.sessions.violate(addr, reason) == 0 {
+            attempt.record(Stage::Discovery, format!("Protocol violation: {reason}"));
+            attempt.complete(Stage::Discovery, Err(Failure::ProtocolViolation));
+            self.attempts
+                .finish(&attempt, Err(Failure::ProtocolViolation));
+        }
+    }
+
+    /// Wait until any session may have been idle for [`Self::inactivity`].
+    async fn inactivity_timer(&self) {
+        let deadline = self.inactivity.and_then(|v| self.sessions.next_expiry(v));
+
+        match deadline {
+            Some(v) => rt::sleep(v.saturating_duration_since(Instant::now())).await,
+            None => std::future::pending().await,
+        }
     }
-}
 
-impl<'a> crate::payload::Payload<'a> for Vec<(u16, Cow<'a, [u8]>)> {
-    fn deserialize(mut data: &'a [u8]) -> Option<Self> {
-        let mut tags = Vec::new();
+    /// Wait until the window of any PADI has been closed.
+    async fn padi_timer(&self) {
+        let deadline = self
+            .padis
+            .lock()
+            .unwrap()
+            .values()
+            .map(|p| p.time + self.padi_window)
+            .min();
 
-        while !data.is_empty() {
-            if data.len() < 4 {
-                return None;
+        match deadline {
+            Some(v) => rt::sleep(v.saturating_duration_since(Instant::now())).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Report the number of suppressed PADIs of each burst that has ended and forget it. Otherwise
+    /// it would only be reported when the console send another PADI, which may never happen.
+    fn report_padis(&self) {
+        let now = Instant::now();
+
+        self.padis.lock().unwrap().retain(|addr, p| {
+            if now.duration_since(p.time) < self.padi_window {
+                return true;
             }
 
-            let ty = u16::from_be_bytes(data[..2].try_into().unwrap());
-            let length: usize = u16::from_be_bytes(data[2..4].try_into().unwrap()).into();
-            let value = data[4..].get(..length)?;
+            if p.suppressed != 0 {
+                info!(
+                    "Ignored {} identical PADIs from {} after the previous one.",
+                    p.suppressed, addr
+                );
+            }
 
-            tags.push((ty, Cow::Borrowed(value)));
-            data = &data[(4 + length)..];
+            false
+        });
+    }
+
+    /// Close the sessions that has been idle for [`Self::inactivity`]. A console that was turned
+    /// off or unplugged never tell us so its session would stay forever.
+    fn expire_sessions(&self) {
+        let timeout = self.inactivity.unwrap();
+
+        for (id, peer) in self.sessions.expire(timeout) {
+            info!(
+                "Session {} with {}: no traffic for {} seconds.",
+                id,
+                peer,
+                timeout.as_secs()
+            );
+
+            if self.inactivity_padt {
+                self.send_padt(id, peer);
+            }
         }
+    }
 
-        Some(tags)
+    fn send_padt(&self, id: NonZeroU16, peer: MacAddr6) {
+        let padt = Payload::new(Code::Padt.into(), id.get(), Vec::new());
+
+        if let Err(e) = self
+            .sock
+            .send(self.ab.build(self.ethertype, Some(peer)), &padt.serialize())
+        {
+            error!("Failed to send PADT packet to {}: {}.", peer, e.display());
+        }
     }
 
-    fn serialize(&self, buf: &mut Vec<u8>) {
-        for (t, v) in self {
-            let l: u16 = v.len().try_into().unwrap();
+    /// Returns the Service-Name to offer for `requested` or [`None`] if it is not supported.
+    ///
+    /// An empty Service-Name means any service (RFC 2516).
+    fn offer<'a>(&'a self, requested: &'a str) -> Option<&'a str> {
+        match &self.service_name {
+            None => Some(requested),
+            Some(v) if requested.is_empty() || self.banner.matches(v, requested) => Some(v),
+            Some(_) => None,
+        }
+    }
 
-            buf.write_all(&t.to_be_bytes()).unwrap();
-            buf.write_all(&l.to_be_bytes()).unwrap();
-            buf.write_all(v).unwrap();
+    /// Returns the Service-Name to send on `attempt` for `offered`, which is the result of
+    /// [`Self::offer()`]. The Service-Name requested by the console is never changed.
+    fn banner_service<'a>(&self, offered: &'a str, attempt: &Attempt) -> Cow<'a, str> {
+        match self.service_name {
+            Some(_) => self.banner.apply(offered, attempt.id()),
+            None => Cow::Borrowed(offered),
         }
     }
+
+    /// Send PADS with SESSION_ID 0 and `error` tag (e.g. AC-System-Error) as RFC 2516 required
+    /// when we are not able to create a session.
+    fn send_pads_error(
+        &self,
+        addr: MacAddr6,
+        sn: &str,
+        hu: Option<&[u8]>,
+        error: Tag,
+        reason: &str,
+    ) {
+        let mut pads = Payload::new(
+            Code::Pads.into(),
+            0x0000,
+            vec![
+                (Tag::ServiceName.into(), Cow::Borrowed(sn.as_bytes())),
+                (error.into(), Cow::Borrowed(reason.as_bytes())),
+            ],
+        );
+
+        if let Some(hu) = hu {
+            pads.payload_mut()
+                .push((Tag::HostUniq.into(), Cow::Borrowed(hu)));
+        }
+
+        if let Err(e) = self
+            .sock
+            .send(self.ab.build(self.ethertype, Some(addr)), &pads.serialize())
+        {
+            error!("Failed to send PADS packet to {}: {}.", addr, e.display());
+        }
+    }
+
+    fn spawn_kernel(
+        &self,
+        session: Session,
+        addr: MacAddr6,
+        attempt: Arc<Attempt>,
+        ppp: KernelPpp,
+    ) {
+        info!(
+            "Session {} with {} is attached to ppp{}.",
+            session.id(),
+            addr,
+            ppp.index()
+        );
+
+        attempt.record(Stage::Session, format!("Attached to ppp{}", ppp.index()));
+        attempt.advance(Milestone::SessionStarted);
+
+        let attempts = self.attempts.clone();
+
+        rt::spawn(async move {
+            session.run_kernel(&attempt, ppp).await;
+            attempts.finish(&attempt, Err(Failure::Aborted));
+        });
+    }
+}
+
+/// Configurations for [`DiscoveryServer`].
+pub struct DiscoveryConfig {
+    /// EtherType of the frames to send.
+    pub ethertype: u16,
+    /// Exploit to run on each session. [`None`] to keep the sessions up without exploiting it.
+    #[cfg(feature = "exploit")]
+    pub exploit: Option<Arc<Exploit>>,
+    /// Fail the exploit if it does not finish within this duration. [`None`] to wait forever.
+    #[cfg(feature = "exploit")]
+    pub attempt_timeout: Option<Duration>,
+    /// If not [`None`] the sessions will be handed off to the kernel PPP subsystem on the
+    /// specified interface.
+    pub kernel_ppp: Option<String>,
+    /// Identical PADIs from the same console within this duration will be ignored.
+    pub padi_window: Duration,
+    pub policy: AnswerPolicy,
+    /// Send PADS with SESSION_ID 0 instead of the allocated one. The session will still be
+    /// allocated but the frames from the console will not match it.
+    pub pads_zero_id: bool,
+    /// Value of AC-Name tag.
+    pub ac_name: String,
+    /// The only Service-Name to offer. [`None`] to accept any Service-Name.
+    pub service_name: Option<String>,
+    /// How to vary AC-Name and Service-Name between attempts.
+    pub banner: Banner,
+    /// Close the sessions that has not received any frames within this duration. [`None`] to keep
+    /// the idle sessions forever.
+    pub inactivity: Option<Duration>,
+    /// Send PADT to the peer when its session is closed by [`DiscoveryConfig::inactivity`].
+    pub inactivity_padt: bool,
+    /// Fail the attempt of the console that violate the protocol instead of ignoring the frame.
+    pub strict: bool,
+    /// Maximum number of consoles to serve at the same time. [`None`] for no limit.
+    pub max_consoles: Option<NonZeroUsize>,
+    /// Largest PPP-Max-Payload (RFC 4638) to agree when the console ask for a MRU above 1492. The
+    /// agreed value is also the largest MRU of LCP on the session. [`None`] to ignore the tag. This
+    /// has no effect on the sessions that are handed off to the kernel.
+    pub max_payload: Option<u16>,
 }
 
-type Payload<'a> = EthernetPayload<Vec<(u16, Cow<'a, [u8]>)>>;
+/// The last PADI that we responded while its window is open.
+struct LastPadi {
+    data: Vec<u8>,
+    time: Instant,
+    suppressed: usize,
+}
+
+/// Display Host-Uniq as hex so the lines of the same discovery can be found easily.
+struct HostUniq<'a>(Option<&'a [u8]>);
+
+impl Display for HostUniq<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let v = match self.0 {
+            Some(v) => v,
+            None => return f.write_str("none"),
+        };
+
+        for b in v {
+            write!(f, "{b:02x}")?;
+        }
+
+        Ok(())
+    }
+}
+
+type Payload<'a> = EthernetPayload<Tags<'a>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{peer_addr, runtime, synthetic_captures, transport};
+    use crate::proto::pppoe::Padding;
+    use crate::session::CloseReason;
+    use crate::socket::{MockPeer, MockTransport};
+    use libc::{PACKET_BROADCAST, PACKET_HOST, PACKET_OTHERHOST};
+    use std::num::NonZeroU64;
+
+    #[test]
+    fn parse_synthetic_captures() {
+        for c in synthetic_captures() {
+            for (i, f) in c.frames.iter().enumerate() {
+                if !f.is_discovery() {
+                    continue;
+                }
+
+                let data = Padding::Trim.trim(&f.data).unwrap();
+                let p = Payload::deserialize(data).unwrap_or_else(|| {
+                    panic!("{}: invalid PPPoE discovery on frame #{}", c.name, i)
+                });
+
+                assert_eq!(p.serialize(), data, "{}: frame #{}", c.name, i);
+            }
+        }
+    }
+
+    fn config() -> DiscoveryConfig {
+        DiscoveryConfig {
+            ethertype: 0x8863,
+            #[cfg(feature = "exploit")]
+            exploit: None,
+            #[cfg(feature = "exploit")]
+            attempt_timeout: None,
+            kernel_ppp: None,
+            padi_window: Duration::ZERO,
+            policy: AnswerPolicy::All,
+            pads_zero_id: false,
+            ac_name: "AC".into(),
+            service_name: None,
+            banner: Banner::Fixed,
+            inactivity: None,
+            inactivity_padt: true,
+            strict: false,
+            max_consoles: None,
+            max_payload: None,
+        }
+    }
+
+    fn server(
+        sock: MockTransport,
+        sessions: Arc<Sessions>,
+        attempts: Arc<Attempts>,
+        config: DiscoveryConfig,
+    ) -> Arc<DiscoveryServer<MockTransport>> {
+        Arc::new(
+            DiscoveryServer::new(
+                sock,
+                Arc::new(AddrBuilder::new(0)),
+                sessions,
+                attempts,
+                Arc::new(Cooldown::new(Duration::ZERO)),
+                config,
+            )
+            .unwrap(),
+        )
+    }
+
+    /// Send a PADI with an empty Service-Name from `console` and returns the AC-Cookie on the PADO.
+    async fn offer(peer: &mut MockPeer, console: MacAddr6) -> Vec<u8> {
+        let sn = (Tag::ServiceName.into(), Cow::Borrowed(&b""[..]));
+        let padi = Payload::new(Code::Padi.into(), 0, vec![sn]);
+
+        peer.send(&padi.serialize(), peer_addr(console, PACKET_BROADCAST));
+
+        let (data, _) = peer.recv().await.unwrap();
+
+        Payload::deserialize(&data)
+            .unwrap()
+            .ac_cookie()
+            .unwrap()
+            .into()
+    }
+
+    /// Run the discovery of `console` and returns the session ID on the PADS.
+    async fn handshake(peer: &mut MockPeer, console: MacAddr6) -> u16 {
+        let cookie = offer(peer, console).await;
+        let sn = (Tag::ServiceName.into(), Cow::Borrowed(&b""[..]));
+        let padr = Payload::new(
+            Code::Padr.into(),
+            0,
+            vec![sn, (Tag::AcCookie.into(), Cow::Owned(cookie))],
+        );
+
+        peer.send(&padr.serialize(), peer_addr(console, PACKET_HOST));
+
+        let (data, _) = peer.recv().await.unwrap();
+
+        Payload::deserialize(&data).unwrap().session_id()
+    }
+
+    #[test]
+    fn padi_padr() {
+        runtime().block_on(async {
+            let (sock, mut peer) = transport();
+            let sessions = Arc::new(Sessions::new(false));
+            let server = server(sock, sessions.clone(), Arc::default(), config());
+            let running = CancellationToken::new();
+            let console = MacAddr6::new(2, 0, 0, 0, 0, 1);
+            let hu = Cow::Borrowed(&[1u8, 2, 3, 4][..]);
+            let sn = Cow::Borrowed(&b""[..]);
+
+            rt::spawn({
+                let server = server.clone();
+                let running = running.clone();
+
+                async move { server.run(running).await }
+            });
+
+            // PADI.
+            let padi = Payload::new(
+                Code::Padi.into(),
+                0,
+                vec![
+                    (Tag::ServiceName.into(), sn.clone()),
+                    (Tag::HostUniq.into(), hu.clone()),
+                ],
+            );
+
+            peer.send(&padi.serialize(), peer_addr(console, PACKET_BROADCAST));
+
+            let (data, addr) = peer.recv().await.unwrap();
+            let pado = Payload::deserialize(&data).unwrap();
+            let cookie = pado.ac_cookie().unwrap();
+
+            assert_eq!(pado.code(), Code::Pado.into());
+            assert_eq!(addr.sll_addr[..6], *console.as_bytes());
+            assert_eq!(pado.ac_name(), Some(&b"AC"[..]));
+            assert_eq!(pado.host_uniq(), Some(&*hu));
+
+            // PADR with a wrong cookie must be ignored.
+            let padr = |c: &[u8]| {
+                Payload::new(
+                    Code::Padr.into(),
+                    0,
+                    vec![
+                        (Tag::ServiceName.into(), sn.clone()),
+                        (Tag::HostUniq.into(), hu.clone()),
+                        (Tag::AcCookie.into(), Cow::Owned(c.to_vec())),
+                    ],
+                )
+                .serialize()
+            };
+
+            peer.send(&padr(&[0; 16]), peer_addr(console, PACKET_HOST));
+
+            // PADR with a different Host-Uniq must be ignored too.
+            let other = Payload::new(
+                Code::Padr.into(),
+                0,
+                vec![
+                    (Tag::ServiceName.into(), sn.clone()),
+                    (Tag::HostUniq.into(), Cow::Borrowed(&[5u8, 6, 7, 8][..])),
+                    (Tag::AcCookie.into(), Cow::Borrowed(cookie)),
+                ],
+            );
+
+            peer.send(&other.serialize(), peer_addr(console, PACKET_HOST));
+            peer.send(&padr(cookie), peer_addr(console, PACKET_HOST));
+
+            let (data, _) = peer.recv().await.unwrap();
+            let pads = Payload::deserialize(&data).unwrap();
+            let id = NonZeroU16::new(pads.session_id()).unwrap();
+
+            assert_eq!(pads.code(), Code::Pads.into());
+            assert_eq!(pads.host_uniq(), Some(&*hu));
+            assert_eq!(sessions.peer(id), Some(console));
+
+            // Shutdown must close the session with PADT.
+            running.cancel();
+            server.shutdown().await;
+
+            let (data, _) = peer.recv().await.unwrap();
+            let padt = Payload::deserialize(&data).unwrap();
+
+            assert_eq!(
+                (padt.code(), padt.session_id()),
+                (Code::Padt.into(), id.get())
+            );
+        });
+    }
+
+    #[test]
+    fn retry() {
+        runtime().block_on(async {
+            let (sock, mut peer) = transport();
+            let sessions = Arc::new(Sessions::new(false));
+            let attempts = Arc::new(Attempts::new(NonZeroU64::new(2)));
+            let server = server(sock, sessions.clone(), attempts.clone(), config());
+            let running = CancellationToken::new();
+            let console = MacAddr6::new(2, 0, 0, 0, 0, 1);
+            let sn = (Tag::ServiceName.into(), Cow::Borrowed(&b""[..]));
+            let padi = Payload::new(Code::Padi.into(), 0, vec![sn]).serialize();
+
+            rt::spawn({
+                let server = server.clone();
+                let running = running.clone();
+
+                async move { server.run(running).await }
+            });
+
+            // Establish a session then restart the discovery on it.
+            for _ in 0..2 {
+                handshake(&mut peer, console).await;
+                rt::sleep(Duration::from_millis(50)).await;
+            }
+
+            peer.send(&padi, peer_addr(console, PACKET_BROADCAST));
+            attempts.exhausted().await;
+            rt::sleep(Duration::from_millis(50)).await;
+
+            // No more attempts must be started after the limit.
+            let history = attempts.history();
+
+            assert!(peer.try_recv().is_none());
+            assert_eq!(history.len(), 2);
+            assert!(history
+                .iter()
+                .all(|a| a.result() == Some(Err(Failure::Aborted))));
+            assert!(matches!(sessions.closed()[0].2, CloseReason::Restarted));
+
+            // The second attempt is a reconnect of the first one.
+            let reconnects = attempts.reconnects();
+
+            assert!(history[0].reconnect().is_none());
+            assert!(history[1]
+                .reconnect()
+                .is_some_and(|r| r.count == 1 && r.previous == history[0].id()));
+            assert_eq!(reconnects.len(), 1);
+            assert_eq!(reconnects[0].0, console);
+            assert_eq!(reconnects[0].1.len(), 1);
+
+            running.cancel();
+            server.abort().await;
+        });
+    }
+
+    #[test]
+    fn inactivity() {
+        runtime().block_on(async {
+            let (sock, mut peer) = transport();
+            let sessions = Arc::new(Sessions::new(false));
+            let config = DiscoveryConfig {
+                inactivity: Some(Duration::from_millis(100)),
+                ..config()
+            };
+            let server = server(sock, sessions.clone(), Arc::default(), config);
+            let running = CancellationToken::new();
+            let console = MacAddr6::new(2, 0, 0, 0, 0, 1);
+
+            rt::spawn({
+                let server = server.clone();
+                let running = running.clone();
+
+                async move { server.run(running).await }
+            });
+
+            // Establish a session then go silent.
+            let id = handshake(&mut peer, console).await;
+
+            // The session must be closed with PADT.
+            let (data, _) = peer.recv().await.unwrap();
+            let padt = Payload::deserialize(&data).unwrap();
+
+            assert_eq!((padt.code(), padt.session_id()), (Code::Padt.into(), id));
+
+            sessions.wait_empty().await;
+
+            assert!(matches!(sessions.closed()[0].2, CloseReason::Inactive));
+
+            running.cancel();
+            server.abort().await;
+        });
+    }
+
+    #[test]
+    fn strict() {
+        runtime().block_on(async {
+            let (sock, mut peer) = transport();
+            let sessions = Arc::new(Sessions::new(false));
+            let attempts = Arc::new(Attempts::default());
+            let config = DiscoveryConfig {
+                strict: true,
+                ..config()
+            };
+            let server = server(sock, sessions.clone(), attempts.clone(), config);
+            let running = CancellationToken::new();
+            let console = MacAddr6::new(2, 0, 0, 0, 0, 1);
+            let sn = (Tag::ServiceName.into(), Cow::Borrowed(&b""[..]));
+
+            rt::spawn({
+                let server = server.clone();
+                let running = running.clone();
+
+                async move { server.run(running).await }
+            });
+
+            // A PADR with an unknown tag must fail the attempt instead of starting a session.
+            let cookie = (
+                Tag::AcCookie.into(),
+                Cow::Owned(offer(&mut peer, console).await),
+            );
+            let unknown = (0x0777, Cow::Borrowed(&b""[..]));
+            let padr = Payload::new(Code::Padr.into(), 0, vec![sn, cookie, unknown]);
+
+            peer.send(&padr.serialize(), peer_addr(console, PACKET_HOST));
+            rt::sleep(Duration::from_millis(50)).await;
+
+            let history = attempts.history();
+
+            assert!(peer.try_recv().is_none());
+            assert_eq!(history.len(), 1);
+            assert!(history[0].result() == Some(Err(Failure::ProtocolViolation)));
+            assert!(history[0]
+                .timeline()
+                .iter()
+                .any(|e| e.text == "Protocol violation: unknown tag 0x0777 on PADR"));
+
+            running.cancel();
+            server.abort().await;
+        });
+    }
+
+    #[test]
+    fn competitor() {
+        runtime().block_on(async {
+            let (sock, mut peer) = transport();
+            let attempts = Arc::new(Attempts::default());
+            let config = DiscoveryConfig {
+                strict: true,
+                ..config()
+            };
+            let server = server(sock, Arc::default(), attempts.clone(), config);
+            let running = CancellationToken::new();
+            let modem = MacAddr6::new(2, 0, 0, 0, 0, 2);
+            let name = (Tag::AcName.into(), Cow::Borrowed(&b"ISP"[..]));
+            let pado = Payload::new(Code::Pado.into(), 0, vec![name]);
+
+            rt::spawn({
+                let server = server.clone();
+                let running = running.clone();
+
+                async move { server.run(running).await }
+            });
+
+            // A PADO from the other host must be reported instead of treated as a violation.
+            peer.send(&pado.serialize(), peer_addr(modem, PACKET_OTHERHOST));
+            peer.send(&pado.serialize(), peer_addr(modem, PACKET_OTHERHOST));
+            rt::sleep(Duration::from_millis(50)).await;
+
+            assert!(peer.try_recv().is_none());
+            assert!(attempts.history().is_empty());
+            assert_eq!(server.competitors(), [modem]);
+
+            running.cancel();
+            server.abort().await;
+        });
+    }
+
+    #[test]
+    fn max_consoles() {
+        runtime().block_on(async {
+            let (sock, mut peer) = transport();
+            let attempts = Arc::new(Attempts::default());
+            let config = DiscoveryConfig {
+                max_consoles: NonZeroUsize::new(1),
+                ..config()
+            };
+            let server = server(sock, Arc::default(), attempts.clone(), config);
+            let running = CancellationToken::new();
+            let first = MacAddr6::new(2, 0, 0, 0, 0, 1);
+            let second = MacAddr6::new(2, 0, 0, 0, 0, 2);
+            let sn = (Tag::ServiceName.into(), Cow::Borrowed(&b""[..]));
+            let padi = Payload::new(Code::Padi.into(), 0, vec![sn]).serialize();
+
+            rt::spawn({
+                let server = server.clone();
+                let running = running.clone();
+
+                async move { server.run(running).await }
+            });
+
+            // The second console must be ignored while the first one is being served.
+            peer.send(&padi, peer_addr(first, PACKET_BROADCAST));
+            peer.send(&padi, peer_addr(second, PACKET_BROADCAST));
+
+            let (_, addr) = peer.recv().await.unwrap();
+
+            assert_eq!(addr.sll_addr[..6], *first.as_bytes());
+
+            rt::sleep(Duration::from_millis(50)).await;
+
+            assert!(peer.try_recv().is_none());
+
+            // The second console must be served once the first one is finished.
+            let active = attempts.active(first).unwrap();
+
+            attempts.finish(&active, Err(Failure::Aborted));
+            peer.send(&padi, peer_addr(second, PACKET_BROADCAST));
+
+            let (_, addr) = peer.recv().await.unwrap();
+
+            assert_eq!(addr.sll_addr[..6], *second.as_bytes());
+
+            running.cancel();
+            server.abort().await;
+        });
+    }
+
+    #[test]
+    fn accepting() {
+        runtime().block_on(async {
+            let (sock, mut peer) = transport();
+            let server = server(sock, Arc::default(), Arc::default(), config());
+            let running = CancellationToken::new();
+            let first = MacAddr6::new(2, 0, 0, 0, 0, 1);
+            let second = MacAddr6::new(2, 0, 0, 0, 0, 2);
+            let sn = (Tag::ServiceName.into(), Cow::Borrowed(&b""[..]));
+            let padi = Payload::new(Code::Padi.into(), 0, vec![sn]).serialize();
+
+            rt::spawn({
+                let server = server.clone();
+                let running = running.clone();
+
+                async move { server.run(running).await }
+            });
+
+            peer.send(&padi, peer_addr(first, PACKET_BROADCAST));
+            peer.recv().await.unwrap();
+
+            // The new console must be ignored while the active attempt is still served.
+            server.accepting().cancel();
+            peer.send(&padi, peer_addr(second, PACKET_BROADCAST));
+            peer.send(&padi, peer_addr(first, PACKET_BROADCAST));
+
+            let (_, addr) = peer.recv().await.unwrap();
+
+            assert_eq!(addr.sll_addr[..6], *first.as_bytes());
+
+            rt::sleep(Duration::from_millis(50)).await;
+
+            assert!(peer.try_recv().is_none());
+
+            running.cancel();
+            server.abort().await;
+        });
+    }
+
+    #[test]
+    fn max_payload() {
+        runtime().block_on(async {
+            let (sock, mut peer) = transport();
+            let sessions = Arc::new(Sessions::new(false));
+            let config = DiscoveryConfig {
+                max_payload: Some(1800),
+                ..config()
+            };
+            let server = server(sock, sessions.clone(), Arc::default(), config);
+            let running = CancellationToken::new();
+            let console = MacAddr6::new(2, 0, 0, 0, 0, 1);
+            let discovery = |code: Code, max: u16, cookie: Option<&[u8]>| {
+                let mut p = Payload::new(
+                    code.into(),
+                    0,
+                    vec![
+                        (Tag::ServiceName.into(), Cow::Borrowed(&b""[..])),
+                        (
+                            Tag::PppMaxPayload.into(),
+                            Cow::Owned(max.to_be_bytes().into()),
+                        ),
+                    ],
+                );
+
+                if let Some(v) = cookie {
+                    p.payload_mut()
+                        .push((Tag::AcCookie.into(), Cow::Owned(v.to_vec())));
+                }
+
+                p.serialize()
+            };
+
+            rt::spawn({
+                let server = server.clone();
+                let running = running.clone();
+
+                async move { server.run(running).await }
+            });
+
+            // Nothing to agree at the default MRU.
+            peer.send(
+                &discovery(Code::Padi, 1492, None),
+                peer_addr(console, PACKET_BROADCAST),
+            );
+
+            let (data, _) = peer.recv().await.unwrap();
+
+            assert_eq!(Payload::deserialize(&data).unwrap().ppp_max_payload(), None);
+
+            // The larger one must be reduced to our limit.
+            peer.send(
+                &discovery(Code::Padi, 2000, None),
+                peer_addr(console, PACKET_BROADCAST),
+            );
+
+            let (data, _) = peer.recv().await.unwrap();
+            let pado = Payload::deserialize(&data).unwrap();
+
+            assert_eq!(pado.ppp_max_payload(), Some(1800));
+
+            peer.send(
+                &discovery(Code::Padr, 1700, pado.ac_cookie()),
+                peer_addr(console, PACKET_HOST),
+            );
+
+            let (data, _) = peer.recv().await.unwrap();
+            let pads = Payload::deserialize(&data).unwrap();
+            let id = NonZeroU16::new(pads.session_id()).unwrap();
+
+            assert_eq!(pads.code(), Code::Pads.into());
+            assert_eq!(pads.ppp_max_payload(), Some(1700));
+            assert_eq!(sessions.max_payload(id), Some(1700));
+
+            running.cancel();
+            server.abort().await;
+        });
+    }
+
+    #[test]
+    fn padi_burst() {
+        crate::log::init(crate::log::LogFilter::default());
+
+        runtime().block_on(async {
+            let (sock, mut peer) = transport();
+            let sessions = Arc::new(Sessions::new(false));
+            let attempts = Arc::new(Attempts::default());
+            let config = DiscoveryConfig {
+                padi_window: Duration::from_millis(100),
+                ..config()
+            };
+            let server = server(sock, sessions, attempts, config);
+            let running = CancellationToken::new();
+            let console = MacAddr6::new(2, 0, 0, 0, 0, 3);
+            let sn = (Tag::ServiceName.into(), Cow::Borrowed(&b""[..]));
+            let padi = Payload::new(Code::Padi.into(), 0, vec![sn]).serialize();
+            let log = crate::log::record();
+            let reported = || {
+                let line = format!("Ignored 2 identical PADIs from {console}");
+
+                log.lines().iter().any(|l| l.contains(&line))
+            };
+
+            rt::spawn({
+                let server = server.clone();
+                let running = running.clone();
+
+                async move { server.run(running).await }
+            });
+
+            // Only the first one is answered.
+            for _ in 0..3 {
+                peer.send(&padi, peer_addr(console, PACKET_BROADCAST));
+            }
+
+            peer.recv().await.unwrap();
+            rt::sleep(Duration::from_millis(20)).await;
+
+            assert!(!reported());
+
+            // The duplicates must be reported once the window is closed without another PADI.
+            rt::sleep(Duration::from_millis(150)).await;
+
+            assert!(reported());
+            assert!(server.padis.lock().unwrap().is_empty());
+
+            running.cancel();
+        });
+    }
+}
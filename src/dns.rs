@@ -0,0 +1,306 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Default domains for [`DnsBlocklist`], which are where the PS4 download the firmware updates.
+pub const DEFAULT_BLOCKLIST: &str = "update.playstation.net";
+
+const PROTO_UDP: u8 = 17;
+const PORT_DNS: u16 = 53;
+const RCODE_NXDOMAIN: u8 = 3;
+
+/// Domains to answer with NXDOMAIN instead of forwarding the DNS query upstream.
+///
+/// A domain also blocks all of its subdomains (e.g. `update.playstation.net` blocks
+/// `fjp01.ps4.update.playstation.net`).
+#[derive(Clone)]
+pub struct DnsBlocklist {
+    domains: Vec<String>,
+}
+
+impl DnsBlocklist {
+    /// Returns `true` if `name` or any of its parent is in the list.
+    pub fn blocks(&self, name: &str) -> bool {
+        let name = name.trim_end_matches('.');
+
+        self.domains.iter().any(|d| {
+            name.eq_ignore_ascii_case(d)
+                || name.len() > d.len()
+                    && name.as_bytes()[name.len() - d.len() - 1] == b'.'
+                    && name[(name.len() - d.len())..].eq_ignore_ascii_case(d)
+        })
+    }
+
+    /// If `packet` is an IPv4 or IPv6 packet with a DNS query for a blocked domain, returns the
+    /// queried name and the IP packet of the NXDOMAIN response to the sender.
+    pub fn intercept(&self, packet: &[u8]) -> Option<(String, Vec<u8>)> {
+        let ip = IpPacket::parse(packet)?;
+        let udp = ip.udp?;
+
+        if u16::from_be_bytes([udp[2], udp[3]]) != PORT_DNS {
+            return None;
+        }
+
+        // Check if it is a standard query.
+        let query = &udp[8..];
+
+        if query.len() < 12 || query[2] & 0xf8 != 0 || query[4..6] == [0, 0] {
+            return None;
+        }
+
+        // Get the first question. The name in a query is never compressed.
+        let mut name = String::new();
+        let mut off = 12;
+
+        loop {
+            let len = *query.get(off)? as usize;
+
+            off += 1;
+
+            if len == 0 {
+                break;
+            } else if len > 63 {
+                return None;
+            }
+
+            let label = query.get(off..(off + len))?;
+
+            if !name.is_empty() {
+                name.push('.');
+            }
+
+            name.push_str(&String::from_utf8_lossy(label));
+            off += len;
+        }
+
+        let question = query.get(12..(off + 4))?;
+
+        if !self.blocks(&name) {
+            return None;
+        }
+
+        // Build the response.
+        let mut dns = Vec::with_capacity(12 + question.len());
+
+        dns.extend_from_slice(&query[..2]);
+        dns.push(0x80 | (query[2] & 0x01));
+        dns.push(0x80 | RCODE_NXDOMAIN);
+        dns.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+        dns.extend_from_slice(question);
+
+        Some((name, ip.reply(&udp[..4], &dns)))
+    }
+}
+
+impl FromStr for DnsBlocklist {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut domains = Vec::new();
+
+        for d in s.split(',') {
+            let d = d.trim().trim_end_matches('.');
+
+            if d.is_empty() || d.split('.').any(|l| l.is_empty() || l.len() > 63) {
+                return Err(format!("invalid domain '{d}'"));
+            }
+
+            domains.push(d.to_ascii_lowercase());
+        }
+
+        Ok(Self { domains })
+    }
+}
+
+impl Display for DnsBlocklist {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.domains.join(","))
+    }
+}
+
+/// IP packet that carry a DNS query.
+struct IpPacket<'a> {
+    src: &'a [u8],
+    dst: &'a [u8],
+    udp: Option<&'a [u8]>,
+}
+
+impl<'a> IpPacket<'a> {
+    fn parse(packet: &'a [u8]) -> Option<Self> {
+        let (src, dst, proto, payload) = match packet.first()? >> 4 {
+            4 => {
+                let hl = usize::from(packet[0] & 0xf) * 4;
+                let len = usize::from(u16::from_be_bytes(packet.get(2..4)?.try_into().unwrap()));
+
+                // Fragments are never DNS queries we can answer.
+                if hl < 20
+                    || u16::from_be_bytes(packet.get(6..8)?.try_into().unwrap()) & 0x3fff != 0
+                {
+                    return None;
+                }
+
+                (
+                    packet.get(12..16)?,
+                    packet.get(16..20)?,
+                    packet[9],
+                    packet.get(hl..len)?,
+                )
+            }
+            6 => {
+                let len = usize::from(u16::from_be_bytes(packet.get(4..6)?.try_into().unwrap()));
+
+                (
+                    packet.get(8..24)?,
+                    packet.get(24..40)?,
+                    packet[6],
+                    packet.get(40..(40 + len))?,
+                )
+            }
+            _ => return None,
+        };
+
+        let udp = match proto {
+            PROTO_UDP if payload.len() >= 8 => Some(payload),
+            _ => None,
+        };
+
+        Some(Self { src, dst, udp })
+    }
+
+    /// Returns a UDP packet with `data` from the destination to the source. `ports` is the source
+    /// and destination port of the packet to reply.
+    fn reply(&self, ports: &[u8], data: &[u8]) -> Vec<u8> {
+        let len = 8 + data.len();
+        let mut udp = Vec::with_capacity(len);
+
+        udp.extend_from_slice(&ports[2..4]);
+        udp.extend_from_slice(&ports[..2]);
+        udp.extend_from_slice(&(len as u16).to_be_bytes());
+        udp.extend_from_slice(&[0, 0]);
+        udp.extend_from_slice(data);
+
+        // Compute UDP checksum with the pseudo-header.
+        let mut pseudo = Vec::with_capacity(40);
+
+        pseudo.extend_from_slice(self.dst);
+        pseudo.extend_from_slice(self.src);
+        pseudo.extend_from_slice(&(len as u32).to_be_bytes());
+        pseudo.extend_from_slice(&[0, 0, 0, PROTO_UDP]);
+
+        let sum = match checksum(&[&pseudo, &udp]) {
+            0 => 0xffff,
+            v => v,
+        };
+
+        udp[6..8].copy_from_slice(&sum.to_be_bytes());
+
+        // Build IP header.
+        let mut packet = Vec::with_capacity(40 + len);
+
+        if self.src.len() == 4 {
+            packet.extend_from_slice(&[0x45, 0]);
+            packet.extend_from_slice(&((20 + len) as u16).to_be_bytes());
+            packet.extend_from_slice(&[0, 0, 0x40, 0, 64, PROTO_UDP, 0, 0]);
+            packet.extend_from_slice(self.dst);
+            packet.extend_from_slice(self.src);
+
+            let sum = checksum(&[&packet]);
+
+            packet[10..12].copy_from_slice(&sum.to_be_bytes());
+        } else {
+            packet.extend_from_slice(&[0x60, 0, 0, 0]);
+            packet.extend_from_slice(&(len as u16).to_be_bytes());
+            packet.extend_from_slice(&[PROTO_UDP, 64]);
+            packet.extend_from_slice(self.dst);
+            packet.extend_from_slice(self.src);
+        }
+
+        packet.extend_from_slice(&udp);
+        packet
+    }
+}
+
+/// Returns the Internet checksum (RFC 1071) of the concatenation of `data`. Each item except the
+/// last one must have even length.
+fn checksum(data: &[&[u8]]) -> u16 {
+    let mut sum = 0u32;
+
+    for d in data {
+        for w in d.chunks(2) {
+            let w = match *w {
+                [h, l] => u16::from_be_bytes([h, l]),
+                [h] => u16::from_be_bytes([h, 0]),
+                _ => unreachable!(),
+            };
+
+            sum += u32::from(w);
+        }
+    }
+
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an IPv4 packet with a DNS query for `name` from 10.0.0.2 to 8.8.8.8.
+    fn query(name: &str) -> Vec<u8> {
+        let mut dns = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 1];
+
+        for l in name.split('.') {
+            dns.push(l.len() as u8);
+            dns.extend_from_slice(l.as_bytes());
+        }
+
+        dns.extend_from_slice(&[0, 0, 1, 0, 1]);
+
+        // EDNS OPT record, which is not included in the response.
+        dns.extend_from_slice(&[0, 0, 41, 0x10, 0, 0, 0, 0, 0, 0, 0]);
+
+        let ip = IpPacket {
+            src: &[8, 8, 8, 8],
+            dst: &[10, 0, 0, 2],
+            udp: None,
+        };
+
+        ip.reply(&[0, 53, 0xc0, 0x01], &dns)
+    }
+
+    #[test]
+    fn nxdomain() {
+        let list: DnsBlocklist = "update.playstation.net,Example.COM.".parse().unwrap();
+
+        assert!(list.blocks("fjp01.ps4.update.playstation.net"));
+        assert!(list.blocks("update.playstation.net."));
+        assert!(list.blocks("www.example.com"));
+        assert!(!list.blocks("notupdate.playstation.net"));
+        assert!(!list.blocks("playstation.net"));
+        assert_eq!(list.to_string(), "update.playstation.net,example.com");
+        assert!(list.intercept(&query("store.playstation.net")).is_none());
+
+        let (name, resp) = list
+            .intercept(&query("ps4.update.playstation.net"))
+            .unwrap();
+
+        assert_eq!(name, "ps4.update.playstation.net");
+        assert_eq!(checksum(&[&resp[..20]]), 0);
+        assert_eq!(resp[12..20], [8, 8, 8, 8, 10, 0, 0, 2]);
+
+        let udp = &resp[20..];
+
+        assert_eq!(udp[..4], [0, 53, 0xc0, 0x01]);
+        assert_eq!(usize::from(u16::from_be_bytes([udp[4], udp[5]])), udp.len());
+        assert_eq!(checksum(&[&resp[12..20], &[0, 17], &udp[4..6], udp]), 0);
+
+        let dns = &udp[8..];
+
+        assert_eq!(dns[..12], [0x12, 0x34, 0x81, 0x83, 0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(dns.len(), 12 + 28 + 4);
+        assert!(list.intercept(&resp).is_none());
+        assert!("a..b".parse::<DnsBlocklist>().is_err());
+    }
+}
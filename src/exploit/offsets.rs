@@ -0,0 +1,141 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Supported firmwares. The offsets are taken from TheFloW proof-of-concept.
+const FIRMWARES: [(&str, Offsets); 12] = [
+    ("9.00", FW_900),
+    ("9.03", FW_903),
+    ("9.04", FW_903),
+    ("9.50", FW_950),
+    ("9.51", FW_950),
+    ("9.60", FW_950),
+    ("10.00", FW_1000),
+    ("10.01", FW_1000),
+    ("10.50", FW_1050),
+    ("10.70", FW_1050),
+    ("10.71", FW_1050),
+    ("11.00", FW_1100),
+];
+
+/// Version of [`FIRMWARES`]. Bump this on each change to it so the outdated binaries can be detected
+/// with the published manifest.
+pub const OFFSETS_VERSION: u32 = 1;
+
+/// Lowest address of the kernel.
+const KERNEL_BASE: u64 = 0xffffffff80000000;
+
+const FW_900: Offsets = Offsets {
+    pppoe_softc_list: 0xffffffff843ed9f8,
+};
+
+const FW_903: Offsets = Offsets {
+    pppoe_softc_list: 0xffffffff843e99f8,
+};
+
+const FW_950: Offsets = Offsets {
+    pppoe_softc_list: 0xffffffff8434c0a8,
+};
+
+const FW_1000: Offsets = Offsets {
+    pppoe_softc_list: 0xffffffff844514b8,
+};
+
+const FW_1050: Offsets = Offsets {
+    pppoe_softc_list: 0xffffffff844514d8,
+};
+
+const FW_1100: Offsets = Offsets {
+    pppoe_softc_list: 0xffffffff844e2578,
+};
+
+/// Firmware version of the console.
+#[derive(Clone, Copy)]
+pub struct Firmware {
+    version: &'static str,
+    offsets: Offsets,
+}
+
+impl Firmware {
+    pub fn offsets(&self) -> &Offsets {
+        &self.offsets
+    }
+
+    /// Returns what the user need to do with the console after a failed attempt.
+    pub fn guidance(&self) -> &'static str {
+        // The corrupted kernel panic and reboot by itself on 9.xx while the newer firmwares tend to
+        // freeze with the connection test still spinning.
+        if self.version.starts_with("9.") {
+            "The PS4 should restart by itself, wait until it boots then retry the connection test."
+        } else {
+            "The PS4 may freeze instead of restarting, hold the power button until it turns off then turn it on and retry the connection test."
+        }
+    }
+}
+
+impl FromStr for Firmware {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (version, offsets) = FIRMWARES.iter().find(|(v, _)| *v == s).ok_or_else(|| {
+            let supported: Vec<&str> = FIRMWARES.iter().map(|(v, _)| *v).collect();
+
+            format!(
+                "unsupported firmware '{s}' (expect {})",
+                supported.join(", ")
+            )
+        })?;
+
+        Ok(Self {
+            version,
+            offsets: *offsets,
+        })
+    }
+}
+
+impl Display for Firmware {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.version)
+    }
+}
+
+/// Check that the offsets of all firmwares are plausible kernel addresses.
+pub fn check() -> Result<(), String> {
+    for (i, (v, o)) in FIRMWARES.iter().enumerate() {
+        if FIRMWARES[..i].iter().any(|(p, _)| p == v) {
+            return Err(format!("firmware {v} is listed twice"));
+        }
+
+        let addr = o.pppoe_softc_list;
+
+        if addr < KERNEL_BASE || !addr.is_multiple_of(8) {
+            return Err(format!("invalid pppoe_softc_list {addr:#x} for {v}"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Kernel addresses of a firmware without KASLR.
+#[derive(Clone, Copy)]
+pub struct Offsets {
+    pub pppoe_softc_list: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let fw: Firmware = "11.00".parse().unwrap();
+
+        assert_eq!(fw.to_string(), "11.00");
+        assert_eq!(fw.offsets().pppoe_softc_list, 0xffffffff844e2578);
+
+        let fw: Firmware = "9.04".parse().unwrap();
+
+        assert_eq!(fw.offsets().pppoe_softc_list, 0xffffffff843e99f8);
+        assert!("11.02".parse::<Firmware>().is_err());
+        assert!("11".parse::<Firmware>().is_err());
+    }
+}
@@ -0,0 +1,276 @@
+use crate::digest::sha256;
+use crate::fetch::{fetch, is_url};
+use crate::update::parse_hash;
+use std::error::Error;
+use std::fmt::Write;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Maximum size of stage1. It is sent with the ROP chain in a single PPPoE frame.
+pub const STAGE1_MAX: usize = 1024;
+
+/// Maximum size of stage2. This is only a sanity check to catch the wrong file.
+pub const STAGE2_MAX: usize = 16 * 1024 * 1024;
+
+/// Maximum duration to download a payload.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Load the kernel shellcode from `src`, which is either a local path or an HTTP(S) URL. It must
+/// be a flat binary and match `checksum` if it is not [`None`], which is required for a URL.
+pub fn load_stage1(src: &str, checksum: Option<Checksum>) -> Result<Vec<u8>, StageError> {
+    let data = read(src, checksum)?;
+
+    check_stage1(&data)?;
+
+    Ok(data)
+}
+
+/// Load the payload to be loaded by stage1 from `src`, which is either a local path or an HTTP(S)
+/// URL. It can be either a flat binary (e.g. `payload.bin`) or an x86-64 ELF and must match
+/// `checksum` if it is not [`None`], which is required for a URL.
+pub fn load_stage2(src: &str, checksum: Option<Checksum>) -> Result<Vec<u8>, StageError> {
+    let data = read(src, checksum)?;
+
+    check_stage2(&data)?;
+
+    Ok(data)
+}
+
+fn read(src: &str, checksum: Option<Checksum>) -> Result<Vec<u8>, StageError> {
+    // The downloaded code run in the kernel of the console so anyone on the path must not be able
+    // to replace it.
+    if checksum.is_none() && is_url(src) {
+        return Err(StageError::UnverifiedUrl);
+    }
+
+    let data = match fetch(src, DOWNLOAD_TIMEOUT) {
+        Ok(v) => v,
+        Err(e) if is_url(src) => return Err(StageError::Download(e)),
+        Err(e) => return Err(StageError::Read(e)),
+    };
+
+    // Check the content before anything else since a mismatch means it is not the payload.
+    if checksum.is_some_and(|v| v.0 != sha256(&data)) {
+        return Err(StageError::ChecksumMismatch);
+    }
+
+    Ok(data)
+}
+
+pub(super) fn check_stage1(data: &[u8]) -> Result<(), StageError> {
+    if data.is_empty() {
+        return Err(StageError::Empty);
+    }
+
+    if data.len() > STAGE1_MAX {
+        return Err(StageError::TooLarge(STAGE1_MAX));
+    }
+
+    if data.starts_with(b"\x7fELF") {
+        return Err(StageError::UnexpectedElf);
+    }
+
+    Ok(())
+}
+
+pub(super) fn check_stage2(data: &[u8]) -> Result<(), StageError> {
+    if data.is_empty() {
+        return Err(StageError::Empty);
+    }
+
+    if data.len() > STAGE2_MAX {
+        return Err(StageError::TooLarge(STAGE2_MAX));
+    }
+
+    // Check ELF class, data encoding and machine.
+    if data.starts_with(b"\x7fELF") {
+        match data.get(..20) {
+            Some([_, _, _, _, 2, 1, .., 0x3e, 0]) => {}
+            _ => return Err(StageError::UnsupportedElf),
+        }
+    }
+
+    Ok(())
+}
+
+/// Payloads that an attempt use from start to finish.
+///
+/// [`super::Exploit`] replaces the whole snapshot when a payload is replaced so the active attempts
+/// keep the one they started with.
+pub struct Payloads {
+    stage1: Option<Vec<u8>>,
+    stage2: Option<Vec<u8>>,
+    hashes: String,
+}
+
+impl Payloads {
+    /// The payloads must be checked with [`check_stage1()`] and [`check_stage2()`] first.
+    pub(super) fn new(stage1: Option<Vec<u8>>, stage2: Option<Vec<u8>>) -> Self {
+        // Hash once instead of on each attempt since they rarely change.
+        let mut hashes = String::new();
+
+        for (name, data) in [("stage1", &stage1), ("stage2", &stage2)] {
+            let data = match data {
+                Some(v) => v,
+                None => continue,
+            };
+
+            let hash: String = sha256(data).iter().map(|b| format!("{b:02x}")).collect();
+
+            writeln!(hashes, "{}  {} ({} bytes)", hash, name, data.len()).unwrap();
+        }
+
+        Self {
+            stage1,
+            stage2,
+            hashes,
+        }
+    }
+
+    pub fn stage1(&self) -> Option<&[u8]> {
+        self.stage1.as_deref()
+    }
+
+    pub fn stage2(&self) -> Option<&[u8]> {
+        self.stage2.as_deref()
+    }
+
+    /// Returns SHA-256 of each payload in the format of `sha256sum`, which is empty if there are no
+    /// payloads.
+    pub fn hashes(&self) -> &str {
+        &self.hashes
+    }
+}
+
+/// SHA-256 that a payload must match, which is parsed from 64 hex digits.
+#[derive(Clone, Copy)]
+pub struct Checksum([u8; 32]);
+
+impl FromStr for Checksum {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hash(s)
+            .map(Self)
+            .ok_or_else(|| format!("expect SHA-256 in hex, got '{s}'"))
+    }
+}
+
+/// Represents an error when [`load_stage1()`] or [`load_stage2()`] fails or a payload is
+/// rejected by [`super::Exploit::set_stage1()`] or [`super::Exploit::set_stage2()`].
+#[derive(Debug)]
+pub enum StageError {
+    Read(std::io::Error),
+    Download(std::io::Error),
+    UnverifiedUrl,
+    ChecksumMismatch,
+    Empty,
+    TooLarge(usize),
+    UnexpectedElf,
+    UnsupportedElf,
+}
+
+impl Error for StageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Read(e) | Self::Download(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for StageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read(_) => f.write_str("couldn't read the file"),
+            Self::Download(_) => f.write_str("couldn't download the file"),
+            Self::UnverifiedUrl => f.write_str("a URL requires its SHA-256 with --sha256"),
+            Self::ChecksumMismatch => f.write_str("SHA-256 of the file does not match"),
+            Self::Empty => f.write_str("the file is empty"),
+            Self::TooLarge(v) => write!(f, "the file is larger than {v} bytes"),
+            Self::UnexpectedElf => f.write_str("expect a flat binary, got an ELF"),
+            Self::UnsupportedElf => f.write_str("not a 64-bit little-endian x86-64 ELF"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let mut elf = vec![0; 64];
+
+        elf[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        elf[18] = 0x3e;
+
+        assert!(check_stage1(&[0x90, 0xc3]).is_ok());
+        assert!(matches!(check_stage1(&[]), Err(StageError::Empty)));
+        assert!(matches!(
+            check_stage1(&[0; STAGE1_MAX + 1]),
+            Err(StageError::TooLarge(_))
+        ));
+        assert!(matches!(check_stage1(&elf), Err(StageError::UnexpectedElf)));
+        assert!(check_stage2(&[0x90, 0xc3]).is_ok());
+        assert!(check_stage2(&elf).is_ok());
+
+        elf[18] = 0x28;
+
+        assert!(matches!(
+            check_stage2(&elf),
+            Err(StageError::UnsupportedElf)
+        ));
+        assert!(matches!(
+            check_stage2(b"\x7fELF"),
+            Err(StageError::UnsupportedElf)
+        ));
+    }
+
+    #[test]
+    fn unverified_url() {
+        for src in [
+            "http://192.168.1.1/stage1.bin",
+            "https://example.com/stage1.bin",
+        ] {
+            assert!(matches!(
+                load_stage1(src, None),
+                Err(StageError::UnverifiedUrl)
+            ));
+        }
+    }
+
+    #[test]
+    fn payloads() {
+        let payloads = Payloads::new(Some(b"abc".to_vec()), None);
+
+        assert_eq!(payloads.stage1(), Some(&b"abc"[..]));
+        assert!(payloads.stage2().is_none());
+        assert_eq!(
+            payloads.hashes(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  stage1 (3 bytes)\n"
+        );
+        assert!(Payloads::new(None, None).hashes().is_empty());
+    }
+
+    #[test]
+    fn checksum() {
+        let path = std::env::temp_dir().join(format!("jailbreak11-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        let abc: Checksum = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+            .parse()
+            .unwrap();
+
+        std::fs::write(path, b"abc").unwrap();
+
+        let stage1 = load_stage1(path, Some(abc));
+        let stage2 = load_stage2(path, Some("0".repeat(64).parse().unwrap()));
+
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(stage1.unwrap(), b"abc");
+        assert!(matches!(stage2, Err(StageError::ChecksumMismatch)));
+        assert!("abc".parse::<Checksum>().is_err());
+    }
+}
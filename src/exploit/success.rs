@@ -0,0 +1,243 @@
+use crate::log::error;
+use crate::rt;
+use erdp::ErrorDisplay;
+use macaddr::MacAddr6;
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::io::Read;
+use std::net::{Ipv4Addr, SocketAddr, TcpStream};
+use std::pin::Pin;
+use std::process::Command;
+use std::str::FromStr;
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+use tokio::select;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::oneshot;
+
+/// Maximum duration for each detector to wait for the payload.
+const DETECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Port of the kernel log server on the console.
+const KLOG_PORT: u16 = 3232;
+
+/// Console that the exploit was run on.
+pub struct Target<'a> {
+    pub peer: MacAddr6,
+    pub ip: Ipv4Addr,
+    /// PPP frames of the session.
+    pub frames: &'a mut UnboundedReceiver<Vec<u8>>,
+}
+
+/// Check if the payload has been run successfully.
+///
+/// Each payload signal its success differently so the user choose which detectors to use.
+pub trait SuccessDetector: Send + Sync {
+    /// Returns `true` if the payload signaled success.
+    fn detect<'a>(
+        &'a self,
+        target: &'a mut Target,
+    ) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>>;
+}
+
+/// Configuration of a `SuccessDetector`.
+#[derive(Clone)]
+pub enum DetectorConfig {
+    /// The console accept a TCP connection on the port.
+    Port(u16),
+    /// The kernel log of the console contains the text.
+    Klog(String),
+    /// The console does not send any LCP packet for the duration.
+    Silence(Duration),
+    /// The command exit with zero.
+    Script(String),
+}
+
+impl DetectorConfig {
+    pub fn build(self) -> Box<dyn SuccessDetector> {
+        match self {
+            Self::Port(v) => Box::new(PortProbe(v)),
+            Self::Klog(v) => Box::new(KlogBanner(v)),
+            Self::Silence(v) => Box::new(LcpSilence(v)),
+            Self::Script(v) => Box::new(Script(v)),
+        }
+    }
+}
+
+impl FromStr for DetectorConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (k, v) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expect KIND=VALUE, got '{s}'"))?;
+        let v = match k {
+            "port" => Self::Port(v.parse().map_err(|_| format!("invalid port '{v}'"))?),
+            "klog" if v.is_empty() => return Err("empty text for klog".into()),
+            "klog" => Self::Klog(v.into()),
+            "silence" => Self::Silence(Duration::from_secs(
+                v.parse().map_err(|_| format!("invalid duration '{v}'"))?,
+            )),
+            "script" => Self::Script(v.into()),
+            v => return Err(format!("unknown detector '{v}'")),
+        };
+
+        Ok(v)
+    }
+}
+
+impl Display for DetectorConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Port(v) => write!(f, "port={v}"),
+            Self::Klog(v) => write!(f, "klog={v}"),
+            Self::Silence(v) => write!(f, "silence={}", v.as_secs()),
+            Self::Script(v) => write!(f, "script={v}"),
+        }
+    }
+}
+
+/// Implementation of [`DetectorConfig::Port`].
+struct PortProbe(u16);
+
+impl SuccessDetector for PortProbe {
+    fn detect<'a>(
+        &'a self,
+        target: &'a mut Target,
+    ) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>> {
+        let addr = SocketAddr::from((target.ip, self.0));
+
+        Box::pin(blocking(move || connect(addr).is_some()))
+    }
+}
+
+/// Implementation of [`DetectorConfig::Klog`].
+struct KlogBanner(String);
+
+impl SuccessDetector for KlogBanner {
+    fn detect<'a>(
+        &'a self,
+        target: &'a mut Target,
+    ) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>> {
+        let addr = SocketAddr::from((target.ip, KLOG_PORT));
+        let text = self.0.clone();
+
+        Box::pin(blocking(move || {
+            let deadline = Instant::now() + DETECT_TIMEOUT;
+            let mut sock = match connect(addr) {
+                Some(v) => v,
+                None => return false,
+            };
+
+            // Read until the text is found. Keep only the tail that can be a prefix of the text.
+            let mut log = Vec::new();
+            let mut buf = [0; 1024];
+
+            while let Some(v) = deadline.checked_duration_since(Instant::now()) {
+                if v.is_zero() || sock.set_read_timeout(Some(v)).is_err() {
+                    break;
+                }
+
+                let len = match sock.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(v) => v,
+                };
+
+                log.extend_from_slice(&buf[..len]);
+
+                if log.windows(text.len()).any(|w| w == text.as_bytes()) {
+                    return true;
+                }
+
+                log.drain(..log.len().saturating_sub(text.len()));
+            }
+
+            false
+        }))
+    }
+}
+
+/// Implementation of [`DetectorConfig::Silence`].
+struct LcpSilence(Duration);
+
+impl SuccessDetector for LcpSilence {
+    fn detect<'a>(
+        &'a self,
+        target: &'a mut Target,
+    ) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>> {
+        Box::pin(async move {
+            let deadline = Instant::now() + self.0;
+
+            loop {
+                let frame = select! {
+                    _ = rt::sleep(deadline.saturating_duration_since(Instant::now())) => break true,
+                    v = target.frames.recv() => match v {
+                        Some(v) => v,
+                        None => break false,
+                    },
+                };
+
+                if frame.get(..2) == Some(&[0xc0, 0x21]) {
+                    break false;
+                }
+            }
+        })
+    }
+}
+
+/// Implementation of [`DetectorConfig::Script`].
+///
+/// The command is run with `sh -c` and the address of the console in `PS4_IP` and `PS4_MAC`.
+struct Script(String);
+
+impl SuccessDetector for Script {
+    fn detect<'a>(
+        &'a self,
+        target: &'a mut Target,
+    ) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>> {
+        let mut cmd = Command::new("sh");
+        let script = self.0.clone();
+
+        cmd.arg("-c")
+            .arg(&script)
+            .env("PS4_IP", target.ip.to_string())
+            .env("PS4_MAC", target.peer.to_string());
+
+        Box::pin(blocking(move || match cmd.status() {
+            Ok(v) => v.success(),
+            Err(e) => {
+                error!("Failed to run '{}': {}.", script, e.display());
+                false
+            }
+        }))
+    }
+}
+
+/// Connect to `addr` until success or [`DETECT_TIMEOUT`].
+fn connect(addr: SocketAddr) -> Option<TcpStream> {
+    let deadline = Instant::now() + DETECT_TIMEOUT;
+
+    loop {
+        let start = Instant::now();
+
+        if let Ok(v) = TcpStream::connect_timeout(&addr, Duration::from_secs(1)) {
+            return Some(v);
+        }
+
+        if Instant::now() >= deadline {
+            return None;
+        }
+
+        // The connection may be refused immediately.
+        std::thread::sleep(Duration::from_secs(1).saturating_sub(start.elapsed()));
+    }
+}
+
+/// Run `f` on a dedicated thread since it is blocking on all runtimes.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (tx, rx) = oneshot::channel();
+
+    spawn(move || tx.send(f()).ok());
+
+    rx.await.unwrap()
+}
@@ -0,0 +1,179 @@
+use std::fmt::{Display, Formatter};
+
+/// Cause of a failed attempt.
+///
+/// The identifier and exit code of each variant are part of the public interface since the
+/// troubleshooting guides and scripts refer to them. Do not change or reuse them. The exit codes
+/// are grouped by [`Vulnerability`]: 10-19 and 30-39 for [`Vulnerability::Unknown`], 20-29 for
+/// [`Vulnerability::Vulnerable`] and 40-49 for [`Vulnerability::NotVulnerable`]. Exit codes 22, 23
+/// and 30 are reserved for the stages after the KASLR leak.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(feature = "exploit"), allow(dead_code))]
+pub enum Failure {
+    /// No PADI was received from the console.
+    NoPadi,
+    /// All session IDs are in use.
+    NoFreeSession,
+    /// Failed to send a PPPoE frame to the console.
+    SendFailed,
+    /// Failed to hand off the session to the kernel PPP subsystem.
+    KernelPppFailed,
+    /// The console did not respond over IPv6.
+    Ipv6Timeout,
+    /// The exploit did not finish within the time limit of the attempt.
+    AttemptTimeout,
+    /// The console violated the protocol in strict mode.
+    ProtocolViolation,
+    /// LCP was not opened.
+    LcpTimeout,
+    /// The corrupted neighbor cache entry was not found.
+    CorruptionNotFound,
+    /// The leaked kernel pointer does not look like one.
+    LeakInvalid,
+    /// The detectors did not agree that the payload was run successfully.
+    SuccessNotDetected,
+    /// KASLR was defeated but no payload was run and confirmed by the detectors.
+    KaslrOnly,
+    /// The experimental stage failed.
+    #[cfg_attr(not(feature = "experimental"), allow(dead_code))]
+    ExperimentalFailed,
+    /// The session was closed before the exploit finished.
+    Aborted,
+    /// All neighbor cache entries are intact after the malicious LCP Configure-Request.
+    NotVulnerable,
+}
+
+impl Failure {
+    /// Returns the stable identifier of this failure.
+    pub fn id(self) -> &'static str {
+        match self {
+            Self::NoPadi => "NO_PADI",
+            Self::NoFreeSession => "NO_FREE_SESSION",
+            Self::SendFailed => "SEND_FAILED",
+            Self::KernelPppFailed => "KERNEL_PPP_FAILED",
+            Self::Ipv6Timeout => "IPV6_TIMEOUT",
+            Self::AttemptTimeout => "ATTEMPT_TIMEOUT",
+            Self::ProtocolViolation => "PROTOCOL_VIOLATION",
+            Self::LcpTimeout => "LCP_TIMEOUT",
+            Self::CorruptionNotFound => "CORRUPTION_NOT_FOUND",
+            Self::LeakInvalid => "LEAK_INVALID",
+            Self::SuccessNotDetected => "SUCCESS_NOT_DETECTED",
+            Self::KaslrOnly => "KASLR_ONLY",
+            Self::ExperimentalFailed => "EXPERIMENTAL_FAILED",
+            Self::Aborted => "ABORTED",
+            Self::NotVulnerable => "NOT_VULNERABLE",
+        }
+    }
+
+    /// Returns the process exit code for this failure.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::NoPadi => 10,
+            Self::NoFreeSession => 11,
+            Self::SendFailed => 12,
+            Self::KernelPppFailed => 13,
+            Self::Ipv6Timeout => 14,
+            Self::AttemptTimeout => 15,
+            Self::ProtocolViolation => 16,
+            Self::LcpTimeout => 20,
+            Self::LeakInvalid => 21,
+            Self::CorruptionNotFound => 24,
+            Self::SuccessNotDetected => 25,
+            Self::ExperimentalFailed => 26,
+            Self::KaslrOnly => 27,
+            Self::Aborted => 31,
+            Self::NotVulnerable => 40,
+        }
+    }
+
+    /// Returns `true` if the console need to be power-cycled before the next attempt.
+    ///
+    /// This is the case when the kernel memory of the console may already be corrupted.
+    pub fn requires_reboot(self) -> bool {
+        match self {
+            Self::NoPadi
+            | Self::NoFreeSession
+            | Self::SendFailed
+            | Self::KernelPppFailed
+            | Self::Ipv6Timeout
+            | Self::AttemptTimeout
+            | Self::ProtocolViolation
+            | Self::LcpTimeout
+            | Self::Aborted
+            | Self::NotVulnerable => false,
+            Self::CorruptionNotFound
+            | Self::LeakInvalid
+            | Self::SuccessNotDetected
+            | Self::KaslrOnly
+            | Self::ExperimentalFailed => true,
+        }
+    }
+
+    /// Returns what this failure tell about the console.
+    pub fn vulnerability(self) -> Vulnerability {
+        match self {
+            Self::NoPadi
+            | Self::NoFreeSession
+            | Self::SendFailed
+            | Self::KernelPppFailed
+            | Self::Ipv6Timeout
+            | Self::AttemptTimeout
+            | Self::ProtocolViolation
+            | Self::Aborted => Vulnerability::Unknown,
+            Self::LcpTimeout
+            | Self::CorruptionNotFound
+            | Self::LeakInvalid
+            | Self::SuccessNotDetected
+            | Self::KaslrOnly
+            | Self::ExperimentalFailed => Vulnerability::Vulnerable,
+            Self::NotVulnerable => Vulnerability::NotVulnerable,
+        }
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let v = match self {
+            Self::NoPadi => "no PADI from the console",
+            Self::NoFreeSession => "all session IDs are in use",
+            Self::SendFailed => "failed to send a PPPoE frame",
+            Self::KernelPppFailed => "failed to hand off the session to kernel PPP",
+            Self::Ipv6Timeout => "the console did not respond over IPv6",
+            Self::AttemptTimeout => "the attempt took too long",
+            Self::ProtocolViolation => "the console violated the protocol",
+            Self::LcpTimeout => "LCP was not opened",
+            Self::CorruptionNotFound => "the corrupted object was not found",
+            Self::LeakInvalid => "the leaked kernel address is not valid",
+            Self::SuccessNotDetected => "the payload did not signal success",
+            Self::KaslrOnly => "the exploit stopped after the KASLR leak",
+            Self::ExperimentalFailed => "the experimental stage failed",
+            Self::Aborted => "the session was closed before the exploit finished",
+            Self::NotVulnerable => "the console is not vulnerable",
+        };
+
+        write!(f, "{} ({})", self.id(), v)
+    }
+}
+
+/// Whether the console is vulnerable according to a [`Failure`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Vulnerability {
+    /// The exploit did not reach the vulnerable code.
+    Unknown,
+    /// The console reacted to the corruption but the exploit failed after that.
+    Vulnerable,
+    /// The console is most likely patched.
+    NotVulnerable,
+}
+
+impl Display for Vulnerability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let v = match self {
+            Self::Unknown => "it is unknown if the PS4 is vulnerable",
+            Self::Vulnerable => "the PS4 is vulnerable but the exploit failed",
+            Self::NotVulnerable => "the PS4 is not vulnerable",
+        };
+
+        f.write_str(v)
+    }
+}
@@ -0,0 +1,44 @@
+use std::io::Error;
+use std::process::Command;
+use std::time::Duration;
+
+/// Returns the content of `src`, which is either an `http://` or `https://` URL or a local path.
+///
+/// The URLs are downloaded by running `curl`, which must be installed and in `PATH`, so this blocks
+/// the current thread for up to `timeout`. The content is not authenticated beyond what `curl`
+/// does for HTTPS so the caller must verify it if it matters.
+pub fn fetch(src: &str, timeout: Duration) -> Result<Vec<u8>, Error> {
+    if !is_url(src) {
+        return std::fs::read(src);
+    }
+
+    let output = Command::new("curl")
+        .args([
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--location",
+            "--max-time",
+        ])
+        .arg(timeout.as_secs().max(1).to_string())
+        .arg("--")
+        .arg(src)
+        .output()?;
+
+    if !output.status.success() {
+        let msg = String::from_utf8_lossy(&output.stderr);
+
+        return Err(Error::other(format!(
+            "curl exited with {} ({})",
+            output.status,
+            msg.trim()
+        )));
+    }
+
+    Ok(output.stdout)
+}
+
+/// Returns `true` if `src` is a URL that [`fetch()`] will download.
+pub fn is_url(src: &str) -> bool {
+    src.starts_with("http://") || src.starts_with("https://")
+}
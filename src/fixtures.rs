@@ -0,0 +1,193 @@
+use crate::log::{Direction, Logger};
+use crate::proto::pppoe::Padding;
+use crate::rt;
+use crate::socket::{MockPeer, MockTransport};
+use libc::{sockaddr_ll, AF_PACKET, ARPHRD_ETHER};
+use macaddr::MacAddr6;
+use std::fs::{read_dir, read_to_string};
+use std::mem::zeroed;
+use std::path::Path;
+use std::sync::Once;
+
+/// Load all captures in the `fixtures/synthetic` directory.
+///
+/// These are recorded from a PS4 simulator, not a real console, so they only prove that the parsers
+/// and the state machines agree with the simulator. Each capture is the packet dumps printed by the
+/// jailbreak. All lines other than the dumps are ignored so the output can be used as is.
+pub fn synthetic_captures() -> Vec<Capture> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/synthetic");
+    let mut captures = Vec::new();
+
+    for e in read_dir(dir).unwrap() {
+        let path = e.unwrap().path();
+
+        if path.extension().is_some_and(|v| v == "log") {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let frames =
+                parse(&read_to_string(&path).unwrap()).unwrap_or_else(|e| panic!("{name}: {e}"));
+
+            captures.push(Capture { name, frames });
+        }
+    }
+
+    captures.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    captures
+}
+
+/// Returns a runtime to run the servers. [`rt::Tokio`] is installed on the first call.
+pub fn runtime() -> tokio::runtime::Runtime {
+    static INSTALL: Once = Once::new();
+
+    INSTALL.call_once(|| rt::install(rt::Tokio));
+
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+/// Returns a [`MockTransport`] that does not log anything.
+pub fn transport() -> (MockTransport, MockPeer) {
+    let (logger, _) = Logger::new(16, None);
+
+    MockTransport::new(logger, Padding::Trim)
+}
+
+/// Returns the address of a frame from `peer` with `sll_pkttype` of `ty`.
+pub fn peer_addr(peer: MacAddr6, ty: u8) -> sockaddr_ll {
+    let mut addr: sockaddr_ll = unsafe { zeroed() };
+
+    addr.sll_family = AF_PACKET as _;
+    addr.sll_hatype = ARPHRD_ETHER;
+    addr.sll_pkttype = ty;
+    addr.sll_halen = 6;
+    addr.sll_addr[..6].copy_from_slice(peer.as_bytes());
+    addr
+}
+
+fn parse(data: &str) -> Result<Vec<Frame>, String> {
+    let mut frames = Vec::<Frame>::new();
+    let mut len = 0;
+
+    for (i, l) in data.lines().enumerate() {
+        let i = i + 1;
+
+        // Parse header.
+        let dir = match l.get(..3) {
+            Some("R: ") => Some(Direction::Received),
+            Some("S: ") => Some(Direction::Sent),
+            _ => None,
+        };
+
+        if let Some(dir) = dir {
+            len = l
+                .rsplit_once("Length = ")
+                .and_then(|(_, v)| v.strip_suffix(')')?.parse().ok())
+                .ok_or_else(|| format!("invalid header on line {i}"))?;
+
+            frames.push(Frame {
+                dir,
+                data: Vec::with_capacity(len),
+            });
+
+            continue;
+        }
+
+        // Parse data.
+        let f = match frames.last_mut() {
+            Some(v) if v.data.len() < len => v,
+            _ => continue,
+        };
+
+        let hex = match l.split_once(":   ") {
+            Some((o, v)) if o.len() == 4 => v,
+            _ => return Err(format!("truncated frame on line {i}")),
+        };
+
+        for b in hex.split_whitespace().take(16.min(len - f.data.len())) {
+            let b = u8::from_str_radix(b, 16).map_err(|_| format!("invalid byte on line {i}"))?;
+
+            f.data.push(b);
+        }
+    }
+
+    match frames.last() {
+        Some(f) if f.data.len() < len => Err("truncated frame at the end".into()),
+        _ => Ok(frames),
+    }
+}
+
+/// A capture from [`synthetic_captures()`].
+pub struct Capture {
+    pub name: String,
+    pub frames: Vec<Frame>,
+}
+
+/// A frame in [`Capture`] without the Ethernet header.
+pub struct Frame {
+    pub dir: Direction,
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    /// Returns `true` if this frame is PPPoE discovery.
+    pub fn is_discovery(&self) -> bool {
+        matches!(self.data[..], [0x11, c, ..] if c != 0)
+    }
+
+    /// Returns PPP frame without the Ethernet padding if this frame is PPPoE session.
+    pub fn ppp(&self) -> Option<&[u8]> {
+        match self.data[..] {
+            [0x11, 0, _, _, h, l, ..] => self
+                .data
+                .get(6..(6 + usize::from(u16::from_be_bytes([h, l])))),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if this frame is IPv6.
+    pub fn is_ipv6(&self) -> bool {
+        self.data.first().is_some_and(|&v| v >> 4 == 6)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_synthetic() {
+        let captures = synthetic_captures();
+
+        assert!(!captures.is_empty());
+
+        for c in captures {
+            assert!(!c.frames.is_empty(), "{}", c.name);
+
+            for (i, f) in c.frames.iter().enumerate() {
+                let known = f.is_discovery() || f.ppp().is_some() || f.is_ipv6();
+
+                assert!(known, "{}: unknown frame #{}", c.name, i);
+            }
+        }
+    }
+
+    #[test]
+    fn parse_dump() {
+        let data = "Attempt #1 started.\n\
+            R: 2:0:0:0:0:2 (Type = 1, Length = 18)\n\
+            0000:   11 09 00 00  00 0c 01 01  00 00 01 03  00 04 01 02   ................\n\
+            0010:   03 04                                                ..\n\
+            The PS4 seems to be connected.\n\
+            S: 2:0:0:0:0:2 (Length = 6)\n\
+            0000:   11 a7 00 01  00 00                                   ......\n";
+        let frames = parse(data).unwrap();
+
+        assert_eq!(frames.len(), 2);
+        assert!(matches!(frames[0].dir, Direction::Received));
+        assert_eq!(frames[0].data.len(), 18);
+        assert_eq!(frames[0].data[16..], [0x03, 0x04]);
+        assert!(matches!(frames[1].dir, Direction::Sent));
+        assert_eq!(frames[1].data, [0x11, 0xa7, 0x00, 0x01, 0x00, 0x00]);
+    }
+}
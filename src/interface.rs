@@ -0,0 +1,148 @@
+use crate::addr::{interface_mac, AddrBuilder};
+use crate::buffers::{BufferConfig, Buffers};
+use crate::chaos::Chaos;
+use crate::log::{info, warning, Logger};
+use crate::proto::pppoe::{EtherTypes, Padding};
+use crate::socket::{probe_cooked, Framing, PacketSocket};
+use macaddr::MacAddr6;
+use std::error::Error;
+use std::ffi::c_int;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+/// Sockets of an interface that connected with the consoles.
+///
+/// All servers on the same interface share these sockets so each frame is received only once.
+pub struct Interface {
+    pub index: c_int,
+    pub ab: Arc<AddrBuilder>,
+    #[cfg_attr(not(feature = "exploit"), allow(dead_code))]
+    pub mac: MacAddr6,
+    pub ethertypes: EtherTypes,
+    pub discovery: PacketSocket,
+    pub session: Arc<PacketSocket>,
+}
+
+impl Interface {
+    /// `SOCK_DGRAM` will be used unless `raw` is `true` or it is broken on the interface.
+    pub fn open(
+        index: c_int,
+        ethertypes: EtherTypes,
+        logger: &Logger,
+        padding: Padding,
+        chaos: Option<&Arc<Chaos>>,
+        buffers: &BufferConfig,
+        raw: bool,
+    ) -> Result<Self, InterfaceError> {
+        let ab = Arc::new(AddrBuilder::new(index));
+        let mac = interface_mac(index).map_err(InterfaceError::Mac)?;
+
+        // Check if the kernel handle the Ethernet header correctly.
+        let framing = if raw {
+            Framing::Raw(mac)
+        } else {
+            match probe_cooked(index, mac).map_err(InterfaceError::Probe)? {
+                Some(r) => {
+                    warning!("WARNING: SOCK_DGRAM does not work on interface {index} ({r}).");
+                    Framing::Raw(mac)
+                }
+                None => Framing::Cooked,
+            }
+        };
+
+        info!("Using {framing} sockets on interface {index}.");
+
+        let discovery = listen(
+            logger,
+            padding,
+            framing,
+            chaos,
+            buffers.discovery,
+            &ab,
+            ethertypes.discovery,
+        )
+        .map_err(|e| InterfaceError::Socket("PPPoE discovery", e))?;
+        let session = listen(
+            logger,
+            padding,
+            framing,
+            chaos,
+            buffers.session,
+            &ab,
+            ethertypes.session,
+        )
+        .map_err(|e| InterfaceError::Socket("PPPoE session", e))?;
+
+        Ok(Self {
+            index,
+            ab,
+            mac,
+            ethertypes,
+            discovery,
+            session: Arc::new(session),
+        })
+    }
+}
+
+/// Create a socket that receive the frames of ethertype `proto` on the interface of `ab`.
+pub fn listen(
+    logger: &Logger,
+    padding: Padding,
+    framing: Framing,
+    chaos: Option<&Arc<Chaos>>,
+    buffers: Buffers,
+    ab: &AddrBuilder,
+    proto: u16,
+) -> Result<PacketSocket, std::io::Error> {
+    let mut sock = PacketSocket::new(logger.clone(), padding, framing)?;
+
+    if let Some(v) = chaos {
+        sock.set_chaos(v.clone())?;
+    }
+
+    // The kernel may silently cap the buffers.
+    let actual = sock.set_buffers(buffers)?;
+
+    if buffers.ring != 0 {
+        sock.enable_rx_ring(buffers.ring)?;
+    }
+
+    if actual.recv < buffers.recv || actual.send < buffers.send {
+        warning!(
+            "WARNING: socket buffers for EtherType {:#06x} are {} instead of {}. Raise net.core.rmem_max and net.core.wmem_max or run with CAP_NET_ADMIN.",
+            proto,
+            actual,
+            buffers
+        );
+    }
+
+    sock.bind(ab.build(proto, None))?;
+
+    Ok(sock)
+}
+
+/// Represents an error when [`Interface::open()`] fails.
+#[derive(Debug)]
+pub enum InterfaceError {
+    Socket(&'static str, std::io::Error),
+    Mac(std::io::Error),
+    Probe(std::io::Error),
+}
+
+impl Error for InterfaceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Socket(_, e) | Self::Mac(e) | Self::Probe(e) => Some(e),
+        }
+    }
+}
+
+impl Display for InterfaceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Socket(n, _) => write!(f, "couldn't setup {n} socket"),
+            Self::Mac(_) => f.write_str("couldn't get MAC address"),
+            Self::Probe(_) => f.write_str("couldn't check SOCK_DGRAM"),
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use crate::proto::ppp::{PROTO_IPV4, PROTO_IPV6};
+use crate::rt;
+use crate::tun::configure_ptp;
+use libc::{
+    c_char, connect, ioctl, open, read, socket, Ioctl, IFNAMSIZ, O_NONBLOCK, O_RDWR, SOCK_STREAM,
+};
+use macaddr::MacAddr6;
+use std::ffi::c_int;
+use std::io::Error;
+use std::mem::size_of_val;
+use std::net::Ipv4Addr;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+const AF_PPPOX: c_int = 24;
+const PX_PROTO_OE: u32 = 0;
+const PPPIOCGCHAN: Ioctl = 0x80047437;
+const PPPIOCATTCHAN: Ioctl = 0x40047438;
+const PPPIOCCONNECT: Ioctl = 0x4004743a;
+const PPPIOCNEWUNIT: Ioctl = 0xc004743e;
+const PPPIOCSNPMODE: Ioctl = 0x4008744b;
+const NPMODE_PASS: c_int = 0;
+const NPMODE_DROP: c_int = 1;
+
+/// PPPoE session that has been handed off to the kernel PPP subsystem.
+///
+/// Once attached the kernel will carry the network protocols that was passed with
+/// [`KernelPpp::pass()`] so the host will see the session as a normal `pppN` interface. The control
+/// protocols (e.g. LCP) are still negotiated by the session server since the packet socket also
+/// receive a copy of each frame. The kernel queue another copy for [`KernelPpp::recv()`].
+pub struct KernelPpp {
+    #[allow(dead_code)] // The channel is detached when this closed.
+    channel: OwnedFd,
+    unit: rt::Fd,
+    #[allow(dead_code)] // The kernel stop using the session when this closed.
+    sock: OwnedFd,
+    index: c_int,
+}
+
+impl KernelPpp {
+    pub fn attach(interface: &str, session: u16, peer: MacAddr6) -> Result<Self, Error> {
+        // Create PPPoX socket. This require pppoe kernel module.
+        let sock = unsafe { socket(AF_PPPOX, SOCK_STREAM, PX_PROTO_OE as _) };
+
+        if sock < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        // Connect to the session.
+        let sock = unsafe { OwnedFd::from_raw_fd(sock) };
+        let mut addr = SockaddrPppox {
+            sa_family: AF_PPPOX as _,
+            sa_protocol: PX_PROTO_OE,
+            sid: session.to_be(),
+            remote: peer.into_array(),
+            dev: [0; IFNAMSIZ],
+        };
+
+        if interface.len() >= addr.dev.len() {
+            return Err(Error::other("the interface name is too long"));
+        }
+
+        for (i, b) in interface.bytes().enumerate() {
+            addr.dev[i] = b as _;
+        }
+
+        if unsafe {
+            connect(
+                sock.as_raw_fd(),
+                &addr as *const SockaddrPppox as _,
+                size_of_val(&addr).try_into().unwrap(),
+            )
+        } < 0
+        {
+            return Err(Error::last_os_error());
+        }
+
+        // Attach a PPP channel to the session.
+        let mut chan: c_int = 0;
+
+        if unsafe { ioctl(sock.as_raw_fd(), PPPIOCGCHAN, &mut chan) } < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        let channel = Self::open_ppp()?;
+
+        if unsafe { ioctl(channel.as_raw_fd(), PPPIOCATTCHAN, &chan) } < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        // Create a PPP unit and connect it with the channel.
+        let unit = Self::open_ppp()?;
+        let mut index: c_int = -1;
+
+        if unsafe { ioctl(unit.as_raw_fd(), PPPIOCNEWUNIT, &mut index) } < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        if unsafe { ioctl(channel.as_raw_fd(), PPPIOCCONNECT, &index) } < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        // Don't let the kernel carry anything until the network control protocols are opened.
+        for proto in [PROTO_IPV4, PROTO_IPV6] {
+            Self::set_mode(&unit, proto, NPMODE_DROP)?;
+        }
+
+        Ok(Self {
+            channel,
+            unit: rt::Fd::new(unit)?,
+            sock,
+            index,
+        })
+    }
+
+    /// Let the kernel carry network protocol `proto` (e.g. [`PROTO_IPV4`]).
+    pub fn pass(&self, proto: u16) -> Result<(), Error> {
+        Self::set_mode(&self.unit, proto, NPMODE_PASS)
+    }
+
+    /// Assign `local` to `pppN` as a point-to-point link with `peer` then bring it up.
+    pub fn configure(&self, local: Ipv4Addr, peer: Ipv4Addr) -> Result<(), Error> {
+        configure_ptp(&format!("ppp{}", self.index), local, peer, 1492)
+    }
+
+    /// Returns `N` of `pppN` interface.
+    pub fn index(&self) -> c_int {
+        self.index
+    }
+
+    /// Receive a frame of the control protocols or the network protocols that have not been
+    /// passed. The frame start with PPP protocol field.
+    pub async fn recv(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.unit
+            .read_with(|fd| {
+                let received = unsafe { read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+
+                if received < 0 {
+                    Err(Error::last_os_error())
+                } else {
+                    Ok(received as usize)
+                }
+            })
+            .await
+    }
+
+    fn set_mode(unit: &impl AsRawFd, proto: u16, mode: c_int) -> Result<(), Error> {
+        let req = Npioctl {
+            protocol: proto.into(),
+            mode,
+        };
+
+        if unsafe { ioctl(unit.as_raw_fd(), PPPIOCSNPMODE, &req) } < 0 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn open_ppp() -> Result<OwnedFd, Error> {
+        let fd = unsafe { open(c"/dev/ppp".as_ptr(), O_RDWR | O_NONBLOCK) };
+
+        if fd < 0 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+        }
+    }
+}
+
+/// Linux `struct npioctl`.
+#[repr(C)]
+struct Npioctl {
+    protocol: c_int,
+    mode: c_int,
+}
+
+/// Linux `sockaddr_pppox` with `pppoe_addr`.
+#[repr(C, packed)]
+struct SockaddrPppox {
+    sa_family: u16,
+    sa_protocol: u32,
+    sid: u16,
+    remote: [u8; 6],
+    dev: [c_char; IFNAMSIZ],
+}
@@ -0,0 +1,65 @@
+//! PPPoE jailbreak for PS4.
+//!
+//! The `jailbreak-11` binary is a thin layer over this library so other tools (e.g. a GUI or a
+//! router firmware) can embed the servers instead of running the binary. Use
+//! [`interface::Interface`] to open the sockets then run [`discovery::DiscoveryServer`] and
+//! [`session::SessionServer`] with the same [`session::Sessions`]. The exploit is driven by
+//! [`exploit::Exploit`], which is given to the discovery server.
+//!
+//! All messages are [`tracing`] events with the module path as the target so the embedder can use
+//! its own subscriber or call [`log::init()`] to write them the same as the binary.
+//!
+//! Only [`proto`] follows semantic versioning. The rest is the engine of the binary and can be
+//! changed at any time.
+pub mod addr;
+pub mod artifacts;
+pub mod attempt;
+pub mod banner;
+pub mod buffers;
+pub mod caps;
+pub mod chaos;
+pub mod compare;
+pub mod conflict;
+mod conformance;
+pub mod console;
+mod cookie;
+pub mod cooldown;
+mod digest;
+pub mod discovery;
+pub mod dns;
+#[cfg(feature = "exploit")]
+pub mod exploit;
+pub mod failure;
+pub mod fetch;
+#[cfg(test)]
+mod fixtures;
+pub mod interface;
+mod kppp;
+pub mod log;
+pub mod metrics;
+pub mod mirror;
+pub mod nat;
+pub mod netmgr;
+pub mod nic;
+pub mod passive;
+pub mod pcap;
+pub mod policy;
+pub mod profile;
+pub mod proto;
+mod random;
+mod recovery;
+pub mod replay;
+mod ring;
+pub mod rt;
+#[cfg(feature = "exploit")]
+mod scheduler;
+pub mod selfcheck;
+pub mod session;
+pub mod socket;
+pub mod telemetry;
+#[cfg(feature = "exploit")]
+mod template;
+mod tstamp;
+pub mod tun;
+pub mod update;
+pub mod web;
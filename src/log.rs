@@ -0,0 +1,916 @@
+use crate::mirror::Mirror;
+use crate::pcap::Capture;
+use libc::sockaddr_ll;
+use pretty_hex::{hex_write, HexConfig};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Write};
+use std::io::Write as _;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SendError, SyncSender};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::time::{Duration, Instant};
+use tracing::{Event, Metadata, Subscriber};
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::fmt::format::{self, FormatEvent, FormatFields};
+use tracing_subscriber::fmt::{FmtContext, MakeWriter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+/// Target of the packet dumps.
+pub const PACKET_TARGET: &str = "packet";
+
+/// Interval to report how many times a warning or error was repeated.
+const REPEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Maximum number of distinct messages to track for [`REPEAT_INTERVAL`].
+const MAX_REPEATS: usize = 256;
+
+/// Maximum time for [`flush()`] to wait for [`LogWriter`].
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+static FILTER: OnceLock<LogFilter> = OnceLock::new();
+static REPEATS: Mutex<Repeats> = Mutex::new(Repeats::new());
+static RECORDERS: Mutex<Vec<Weak<Mutex<Vec<String>>>>> = Mutex::new(Vec::new());
+static RECORDING: AtomicUsize = AtomicUsize::new(0);
+static DEBUG: AtomicBool = AtomicBool::new(false);
+static QUEUE: Mutex<Option<Queue>> = Mutex::new(None);
+
+thread_local! {
+    static HELD: RefCell<Option<Vec<(Level, String)>>> = const { RefCell::new(None) };
+}
+
+/// Set the filter for all messages and install a [`tracing`] subscriber that writes them. This can
+/// only be called once before any messages is logged otherwise the default filter will be used.
+///
+/// The library only emit [`tracing`] events so an application that embed it can install its own
+/// subscriber instead, in which case [`hold()`], [`record()`] and the collapsing of the repeated
+/// messages have no effect.
+pub fn init(filter: LogFilter) {
+    FILTER.set(filter).ok();
+
+    // The filter is checked on each event so toggle_debug() take effect immediately.
+    let layer = tracing_subscriber::fmt::layer()
+        .event_format(Format)
+        .with_writer(Output)
+        .with_filter(filter_fn(|m| enabled(m.level().into(), strip(m.target()))));
+
+    tracing_subscriber::registry().with(layer).try_init().ok();
+}
+
+/// Write all debug messages and packet dumps regardless of the filter until this is called again.
+/// Returns `true` if it is now enabled.
+pub fn toggle_debug() -> bool {
+    !DEBUG.fetch_xor(true, Ordering::Relaxed)
+}
+
+/// Returns `true` if a message of `level` on `target` will be written.
+pub fn enabled(level: Level, target: &str) -> bool {
+    if level <= Level::Debug && DEBUG.load(Ordering::Relaxed) {
+        return true;
+    }
+
+    FILTER
+        .get_or_init(LogFilter::default)
+        .enabled(level, target)
+}
+
+/// Strip the crate name from `target` so it can be specified as the module path (e.g.
+/// session::lcp).
+fn strip(target: &str) -> &str {
+    match target.split_once("::") {
+        Some((_, v)) => v,
+        None => "main",
+    }
+}
+
+/// Hold all messages from the current thread until the returned [`Held`] is dropped.
+///
+/// This is for a short time-critical code that cannot wait for a slow terminal. It must not
+/// await anything while the messages are held. Nested calls do nothing.
+pub fn hold() -> Held {
+    let active = HELD.with_borrow_mut(|h| match h {
+        Some(_) => false,
+        None => {
+            *h = Some(Vec::new());
+            true
+        }
+    });
+
+    Held { active }
+}
+
+/// Deliver a formatted message to the recorders then write it unless it is being held.
+fn deliver(level: Level, line: String) {
+    // Copy the message to the active recorders.
+    if RECORDING.load(Ordering::Relaxed) != 0 {
+        for r in RECORDERS.lock().unwrap().iter().filter_map(Weak::upgrade) {
+            r.lock().unwrap().push(line.clone());
+        }
+    }
+
+    // Keep the message for later if it is being held.
+    let line = HELD.with_borrow_mut(|h| match h {
+        Some(h) => {
+            h.push((level, line));
+            None
+        }
+        None => Some(line),
+    });
+
+    if let Some(line) = line {
+        emit(level, line);
+    }
+}
+
+fn emit(level: Level, line: String) {
+    match level {
+        Level::Error | Level::Warn => {
+            // Collapse the repeated messages so a flood of the same warning don't hide the others.
+            let mut repeats = REPEATS.lock().unwrap();
+            let now = Instant::now();
+
+            for l in repeats.expire(now, false) {
+                write(level, l);
+            }
+
+            if repeats.check(&line, now) {
+                write(level, line);
+            }
+        }
+        Level::Info | Level::Debug | Level::Trace => write(level, line),
+    }
+}
+
+/// Write `line` on the [`LogWriter`] that is running or directly if there is none (e.g. before it
+/// is started). The debug messages are dropped instead if the writer cannot keep up.
+fn write(level: Level, line: String) {
+    let queue = QUEUE.lock().unwrap().clone();
+
+    match queue {
+        Some(q) => q.push(Entry::Line { level, line }, level <= Level::Debug),
+        None => Entry::write_line(level, &line),
+    }
+}
+
+/// Write the messages that were held by [`hold()`] when dropped.
+pub struct Held {
+    active: bool,
+}
+
+impl Drop for Held {
+    fn drop(&mut self) {
+        if !self.active {
+            return;
+        }
+
+        for (level, line) in HELD.take().unwrap_or_default() {
+            emit(level, line);
+        }
+    }
+}
+
+/// Collect all messages from any thread that are written from now on until the returned
+/// [`Recorder`] is dropped.
+pub fn record() -> Recorder {
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let mut recorders = RECORDERS.lock().unwrap();
+
+    recorders.retain(|r| r.strong_count() != 0);
+    recorders.push(Arc::downgrade(&lines));
+
+    RECORDING.fetch_add(1, Ordering::Relaxed);
+
+    Recorder { lines }
+}
+
+/// Messages collected by [`record()`].
+pub struct Recorder {
+    lines: Arc<Mutex<Vec<String>>>,
+}
+
+impl Recorder {
+    /// Returns the messages that was collected so far.
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().clone()
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        RECORDING.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Report the warnings and errors that were suppressed since the last report then wait until
+/// [`LogWriter`] has written everything.
+pub fn flush() {
+    for l in REPEATS.lock().unwrap().expire(Instant::now(), true) {
+        write(Level::Warn, l);
+    }
+
+    let queue = match QUEUE.lock().unwrap().clone() {
+        Some(v) => v,
+        None => return,
+    };
+
+    let deadline = Instant::now() + FLUSH_TIMEOUT;
+
+    while queue.pending.load(Ordering::Relaxed) != 0 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Format of the messages from [`init()`]. The level and the target are only written in verbose
+/// mode.
+struct Format;
+
+impl<S, N> FormatEvent<S, N> for Format
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut w: format::Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        let meta = event.metadata();
+        let verbose = FILTER.get().is_some_and(|f| f.verbose) || DEBUG.load(Ordering::Relaxed);
+
+        if verbose {
+            let level: Level = meta.level().into();
+
+            write!(w, "[{} {}] ", level, strip(meta.target()))?;
+        }
+
+        ctx.field_format().format_fields(w.by_ref(), event)?;
+
+        writeln!(w)
+    }
+}
+
+/// Output of the messages from [`init()`].
+struct Output;
+
+impl<'a> MakeWriter<'a> for Output {
+    type Writer = Line;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        Line {
+            level: Level::Info,
+            buf: Vec::new(),
+        }
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        Line {
+            level: meta.level().into(),
+            buf: Vec::new(),
+        }
+    }
+}
+
+/// A message that is being formatted. It is delivered when dropped.
+struct Line {
+    level: Level,
+    buf: Vec<u8>,
+}
+
+impl std::io::Write for Line {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Line {
+    fn drop(&mut self) {
+        let mut line = String::from_utf8_lossy(&self.buf).into_owned();
+
+        if line.ends_with('\n') {
+            line.pop();
+        }
+
+        deliver(self.level, line);
+    }
+}
+
+/// Log an error on the current module. The message is written to stderr.
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::log::tracing::error!($($arg)*)
+    };
+}
+
+/// Log a warning on the current module. The message is written to stderr.
+#[macro_export]
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::log::tracing::warn!($($arg)*)
+    };
+}
+
+/// Log a progress message on the current module. The message is written to stdout.
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::log::tracing::info!($($arg)*)
+    };
+}
+
+/// Log a detailed message on the current module, which is hidden by default. The message is
+/// written to stdout.
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::log::tracing::debug!($($arg)*)
+    };
+}
+
+pub use crate::{debug, error, info, warning};
+pub use tracing;
+
+/// Handle to write the logs on a dedicated thread.
+///
+/// Writing the logs can be slow (e.g. the output is a slow terminal or SSH session) and we don't
+/// want it to delay the time-critical packets or block the runtime. Once [`LogWriter`] has been
+/// spawned all messages are also written by it. If the thread cannot keep up the packet dumps and
+/// the debug messages will be dropped instead of blocking the caller.
+#[derive(Clone)]
+pub struct Logger {
+    queue: Queue,
+    capacity: usize,
+    capture: Option<Arc<Capture>>,
+    mirror: Option<Arc<Mirror>>,
+}
+
+impl Logger {
+    /// `capacity` is a maximum number of pending entries. All packets will also be recorded to
+    /// `capture` if it is not [`None`].
+    pub fn new(capacity: usize, capture: Option<Arc<Capture>>) -> (Self, LogWriter) {
+        let (tx, rx) = sync_channel(capacity);
+        let queue = Queue {
+            tx,
+            pending: Arc::default(),
+            dropped: Arc::default(),
+        };
+        let writer = LogWriter {
+            rx,
+            queue: queue.clone(),
+        };
+
+        (
+            Self {
+                queue,
+                capacity,
+                capture,
+                mirror: None,
+            },
+            writer,
+        )
+    }
+
+    /// Also copy all packets to `mirror`. This must be called before the logger is cloned.
+    pub fn set_mirror(&mut self, mirror: Arc<Mirror>) {
+        self.mirror = Some(mirror);
+    }
+
+    /// Log a packet that was sent or received on a packet socket.
+    pub fn packet(&self, dir: Direction, addr: &sockaddr_ll, data: &[u8]) {
+        // The capture must be complete so it can't be dropped like the dumps.
+        if let Some(c) = &self.capture {
+            c.record(dir, addr, data);
+        }
+
+        if let Some(m) = &self.mirror {
+            m.record(dir, addr, data);
+        }
+
+        if !enabled(Level::Info, PACKET_TARGET) {
+            return;
+        }
+
+        let e = Entry::Packet {
+            dir,
+            addr: *addr,
+            data: data.to_vec(),
+        };
+
+        self.queue.push(e, true);
+    }
+
+    /// Write the state of the queue to `out`.
+    pub fn dump(&self, out: &mut String) {
+        writeln!(out, "Logger:").unwrap();
+        writeln!(
+            out,
+            "  Pending: {}/{}",
+            self.queue.pending.load(Ordering::Relaxed),
+            self.capacity
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "  Dropped: {}",
+            self.queue.dropped.load(Ordering::Relaxed)
+        )
+        .unwrap();
+
+        if let Some(m) = &self.mirror {
+            m.dump(out);
+        }
+    }
+}
+
+/// Writer of the logs from [`Logger`] and the messages. This must be run on a dedicated thread
+/// since writing to the stdout is blocking.
+pub struct LogWriter {
+    rx: Receiver<Entry>,
+    queue: Queue,
+}
+
+impl LogWriter {
+    /// Spawn a thread to run the writer and write all messages on it from now on. The thread
+    /// stop once another writer has been spawned and all [`Logger`] has been dropped.
+    pub fn spawn(self) -> std::thread::JoinHandle<()> {
+        *QUEUE.lock().unwrap() = Some(self.queue.clone());
+
+        std::thread::spawn(move || self.run())
+    }
+
+    fn run(self) {
+        let mut log = String::new();
+
+        while let Ok(e) = self.rx.recv() {
+            // Report dropped entries first since it was happened before this entry.
+            let dropped = self.queue.dropped.swap(0, Ordering::Relaxed);
+
+            if dropped != 0 {
+                writeln!(
+                    log,
+                    "{dropped} packet dumps or debug messages were dropped due to slow output."
+                )
+                .unwrap();
+            }
+
+            match e {
+                Entry::Packet { dir, addr, data } => {
+                    Self::write_packet(&mut log, dir, &addr, &data)
+                }
+                Entry::Line { level, line } => {
+                    Self::write_out(&mut log);
+                    Entry::write_line(level, &line);
+                }
+            }
+
+            Self::write_out(&mut log);
+
+            // Only count it as written once it is on the output so flush() don't return early.
+            self.queue.pending.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    fn write_out(log: &mut String) {
+        if log.is_empty() {
+            return;
+        }
+
+        let mut stdout = std::io::stdout().lock();
+
+        stdout.write_all(log.as_bytes()).unwrap();
+        stdout.flush().unwrap();
+
+        log.clear();
+    }
+
+    fn write_packet(log: &mut String, dir: Direction, addr: &sockaddr_ll, data: &[u8]) {
+        // Write header.
+        match dir {
+            Direction::Received => log.push_str("R: "),
+            Direction::Sent => log.push_str("S: "),
+        }
+
+        Self::write_addr(log, addr);
+
+        match dir {
+            Direction::Received => writeln!(
+                log,
+                " (Type = {}, Length = {})",
+                addr.sll_pkttype,
+                data.len()
+            )
+            .unwrap(),
+            Direction::Sent => writeln!(log, " (Length = {})", data.len()).unwrap(),
+        }
+
+        // Write data.
+        let conf = HexConfig {
+            title: false,
+            ..Default::default()
+        };
+
+        hex_write(log, data, conf).unwrap();
+
+        log.push('\n');
+    }
+
+    fn write_addr(w: &mut impl Write, addr: &sockaddr_ll) {
+        for i in 0..addr.sll_halen {
+            let i: usize = i.into();
+
+            if i != 0 {
+                write!(w, ":").unwrap();
+            }
+
+            write!(w, "{:x}", addr.sll_addr[i]).unwrap();
+        }
+    }
+}
+
+/// Recently written warnings and errors.
+struct Repeats(Option<HashMap<String, Repeat>>);
+
+impl Repeats {
+    const fn new() -> Self {
+        Self(None)
+    }
+
+    /// Returns `true` if `line` should be written.
+    fn check(&mut self, line: &str, now: Instant) -> bool {
+        let map = self.0.get_or_insert_with(HashMap::new);
+
+        if let Some(r) = map.get_mut(line) {
+            r.count += 1;
+            return false;
+        }
+
+        // Stop tracking when there are too many distinct messages.
+        if map.len() < MAX_REPEATS {
+            map.insert(
+                line.to_owned(),
+                Repeat {
+                    since: now,
+                    count: 0,
+                },
+            );
+        }
+
+        true
+    }
+
+    /// Returns the reports of the messages that was tracked for [`REPEAT_INTERVAL`] or all messages
+    /// if `all` is `true`.
+    fn expire(&mut self, now: Instant, all: bool) -> Vec<String> {
+        let map = match &mut self.0 {
+            Some(v) => v,
+            None => return Vec::new(),
+        };
+
+        let mut lines = Vec::new();
+
+        map.retain(|line, r| {
+            let elapsed = now.saturating_duration_since(r.since);
+
+            if elapsed < REPEAT_INTERVAL && !all {
+                return true;
+            }
+
+            if r.count == 0 {
+                return false;
+            }
+
+            lines.push(format!(
+                "{line} (message repeated {} times in the last {} seconds)",
+                r.count,
+                elapsed.as_secs()
+            ));
+
+            // Keep suppressing it in case the flood is still going on.
+            r.since = now;
+            r.count = 0;
+
+            true
+        });
+
+        lines
+    }
+}
+
+struct Repeat {
+    since: Instant,
+    count: u64,
+}
+
+/// Severity of a message.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<&tracing::Level> for Level {
+    fn from(v: &tracing::Level) -> Self {
+        match *v {
+            tracing::Level::ERROR => Self::Error,
+            tracing::Level::WARN => Self::Warn,
+            tracing::Level::INFO => Self::Info,
+            tracing::Level::DEBUG => Self::Debug,
+            tracing::Level::TRACE => Self::Trace,
+        }
+    }
+}
+
+impl Level {
+    const ALL: [Self; 5] = [
+        Self::Error,
+        Self::Warn,
+        Self::Info,
+        Self::Debug,
+        Self::Trace,
+    ];
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let v = match self {
+            Self::Error => "ERROR",
+            Self::Warn => "WARN",
+            Self::Info => "INFO",
+            Self::Debug => "DEBUG",
+            Self::Trace => "TRACE",
+        };
+
+        f.write_str(v)
+    }
+}
+
+/// Which messages to write. Each target is a module path (e.g. `discovery` or `session::lcp`) or
+/// [`PACKET_TARGET`] and the longest match is used.
+#[derive(Clone)]
+pub struct LogFilter {
+    /// Maximum level of the targets that are not in `targets`. [`None`] to write nothing.
+    default: Option<Level>,
+    targets: Vec<(String, Option<Level>)>,
+    /// Prefix each message with its level and target.
+    verbose: bool,
+}
+
+impl LogFilter {
+    /// Raise the default level by `v` and lower it by `q`. The level and target of each message
+    /// will be written if the default level is higher than [`Level::Info`].
+    pub fn adjust(&mut self, v: u8, q: u8) {
+        let current = self.default.map_or(-1, |l| l as i32);
+        let level = (current + i32::from(v) - i32::from(q)).min(Level::Trace as i32);
+
+        self.default = usize::try_from(level).ok().map(|i| Level::ALL[i]);
+        self.verbose = self.default > Some(Level::Info);
+    }
+
+    fn enabled(&self, level: Level, target: &str) -> bool {
+        let max = self
+            .targets
+            .iter()
+            .filter(|(t, _)| {
+                target == t
+                    || target
+                        .strip_prefix(t.as_str())
+                        .is_some_and(|v| v.starts_with("::"))
+            })
+            .max_by_key(|(t, _)| t.len())
+            .map_or(self.default, |(_, l)| *l);
+
+        max.is_some_and(|m| level <= m)
+    }
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self {
+            default: Some(Level::Info),
+            targets: Vec::new(),
+            verbose: false,
+        }
+    }
+}
+
+impl FromStr for LogFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = Self::default();
+        let level = |v: &str| match v {
+            "off" => Ok(None),
+            "error" => Ok(Some(Level::Error)),
+            "warn" => Ok(Some(Level::Warn)),
+            "info" => Ok(Some(Level::Info)),
+            "debug" => Ok(Some(Level::Debug)),
+            "trace" => Ok(Some(Level::Trace)),
+            _ => Err(format!("unknown level '{v}'")),
+        };
+
+        for item in s.split(',') {
+            match item.split_once('=') {
+                Some((t, l)) => filter.targets.push((t.into(), level(l)?)),
+                None => filter.default = level(item)?,
+            }
+        }
+
+        Ok(filter)
+    }
+}
+
+/// Direction of a packet.
+#[derive(Clone, Copy)]
+pub enum Direction {
+    Received,
+    Sent,
+}
+
+enum Entry {
+    Packet {
+        dir: Direction,
+        addr: sockaddr_ll,
+        data: Vec<u8>,
+    },
+    Line {
+        level: Level,
+        line: String,
+    },
+}
+
+impl Entry {
+    /// Write a message to stderr if it is a warning or error, otherwise to stdout.
+    fn write_line(level: Level, line: &str) {
+        match level {
+            Level::Error | Level::Warn => eprintln!("{line}"),
+            Level::Info | Level::Debug | Level::Trace => println!("{line}"),
+        }
+    }
+}
+
+/// Sending side of [`LogWriter`].
+#[derive(Clone)]
+struct Queue {
+    tx: SyncSender<Entry>,
+    pending: Arc<AtomicUsize>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl Queue {
+    /// Send `e` to the writer. It is dropped if the writer is behind and `droppable` is `true`,
+    /// otherwise this block until there is room for it.
+    fn push(&self, e: Entry, droppable: bool) {
+        // Count before sending so the writer never see the entry before it was counted.
+        self.pending.fetch_add(1, Ordering::Relaxed);
+
+        if droppable {
+            if self.tx.try_send(e).is_err() {
+                self.pending.fetch_sub(1, Ordering::Relaxed);
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        } else if let Err(SendError(e)) = self.tx.send(e) {
+            // The writer is gone so write the message here.
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+
+            if let Entry::Line { level, line } = e {
+                Entry::write_line(level, &line);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter() {
+        let mut f: LogFilter = "warn,packet=off,session=debug,session::lcp=error"
+            .parse()
+            .unwrap();
+
+        assert!(f.enabled(Level::Warn, "discovery"));
+        assert!(!f.enabled(Level::Info, "discovery"));
+        assert!(!f.enabled(Level::Error, PACKET_TARGET));
+        assert!(f.enabled(Level::Debug, "session"));
+        assert!(f.enabled(Level::Debug, "session::ipcp"));
+        assert!(!f.enabled(Level::Warn, "session::lcp"));
+        assert!(!f.enabled(Level::Info, "sessions"));
+
+        f.adjust(2, 0);
+
+        assert!(f.enabled(Level::Debug, "discovery"));
+        assert!(f.verbose);
+
+        f.adjust(0, 5);
+
+        assert!(!f.enabled(Level::Error, "discovery"));
+        assert!("loud".parse::<LogFilter>().is_err());
+    }
+
+    #[test]
+    fn record() {
+        init(LogFilter::default());
+
+        let a = super::record();
+
+        info!("First recorded message.");
+
+        let b = super::record();
+
+        info!("Second recorded message.");
+
+        assert!(a.lines().iter().any(|l| l == "First recorded message."));
+        assert!(a.lines().iter().any(|l| l == "Second recorded message."));
+        assert!(!b.lines().iter().any(|l| l == "First recorded message."));
+    }
+
+    #[test]
+    fn queue() {
+        let (tx, _rx) = sync_channel(1);
+        let queue = Queue {
+            tx,
+            pending: Arc::default(),
+            dropped: Arc::default(),
+        };
+        let line = |level| Entry::Line {
+            level,
+            line: String::new(),
+        };
+
+        // Only the debug messages can be dropped when the writer is behind.
+        queue.push(line(Level::Info), false);
+        queue.push(line(Level::Debug), true);
+
+        assert_eq!(queue.pending.load(Ordering::Relaxed), 1);
+        assert_eq!(queue.dropped.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn repeats() {
+        let mut r = Repeats::new();
+        let start = Instant::now();
+
+        assert!(r.check("a", start));
+        assert!(!r.check("a", start));
+        assert!(!r.check("a", start + Duration::from_secs(1)));
+        assert!(r.check("b", start + Duration::from_secs(1)));
+        assert!(r.expire(start + Duration::from_secs(5), false).is_empty());
+
+        let lines = r.expire(start + REPEAT_INTERVAL, false);
+
+        assert_eq!(
+            lines,
+            ["a (message repeated 2 times in the last 10 seconds)"]
+        );
+        assert!(!r.check("a", start + REPEAT_INTERVAL));
+
+        // Nothing was suppressed for b so it is forgotten.
+        assert!(r
+            .expire(start + REPEAT_INTERVAL + Duration::from_secs(1), false)
+            .is_empty());
+        assert!(r.check("b", start + REPEAT_INTERVAL + Duration::from_secs(1)));
+        assert!(!r.check("b", start + REPEAT_INTERVAL + Duration::from_secs(2)));
+
+        let mut lines = r.expire(start + REPEAT_INTERVAL + Duration::from_secs(3), true);
+
+        lines.sort();
+
+        assert_eq!(
+            lines,
+            [
+                "a (message repeated 1 times in the last 3 seconds)",
+                "b (message repeated 1 times in the last 2 seconds)"
+            ]
+        );
+    }
+
+    #[test]
+    fn hold() {
+        init(LogFilter::default());
+
+        let held = super::hold();
+        let nested = super::hold();
+
+        info!("held");
+        drop(nested);
+
+        // Only the outermost one write the messages.
+        assert_eq!(HELD.with_borrow(|h| h.as_ref().map(|h| h.len())), Some(1));
+        drop(held);
+        assert!(HELD.with_borrow(|h| h.is_none()));
+    }
+}
@@ -1,33 +1,613 @@
-use crate::addr::AddrBuilder;
-use crate::discovery::DiscoveryServer;
-use crate::session::{SessionServer, Sessions};
-use crate::socket::PacketSocket;
-use clap::{command, value_parser, Arg, ArgMatches};
+use clap::error::ErrorKind;
+use clap::{command, crate_name, value_parser, Arg, ArgAction, ArgGroup, ArgMatches, Command};
 use erdp::ErrorDisplay;
-use libc::{ETH_P_PPP_DISC, ETH_P_PPP_SES};
-use std::ffi::c_int;
+use jailbreak11::addr::{interface_mac, interface_name, AddrBuilder};
+use jailbreak11::artifacts::Artifacts;
+use jailbreak11::attempt::{Attempt, Attempts};
+use jailbreak11::banner::Banner;
+use jailbreak11::buffers::BufferConfig;
+use jailbreak11::caps;
+use jailbreak11::chaos::{Chaos, ChaosConfig};
+use jailbreak11::compare::compare;
+use jailbreak11::conflict::find_conflicts;
+use jailbreak11::console::ConsoleMonitor;
+use jailbreak11::cooldown::Cooldown;
+use jailbreak11::discovery::{DiscoveryConfig, DiscoveryServer};
+use jailbreak11::dns::{DnsBlocklist, DEFAULT_BLOCKLIST};
+#[cfg(feature = "exploit")]
+use jailbreak11::exploit::{
+    load_stage1, load_stage2, Checksum, DetectorConfig, Exploit, ExploitConfig, Firmware,
+    GroomConfig, OFFSETS_VERSION,
+};
+use jailbreak11::failure::{Failure, Vulnerability};
+use jailbreak11::interface::Interface;
+use jailbreak11::log::{error, info, warning, LogFilter, Logger};
+use jailbreak11::metrics::{Metrics, MetricsFile};
+use jailbreak11::mirror::{Mirror, MirrorTarget};
+use jailbreak11::nat::Nat;
+use jailbreak11::netmgr::{find_managers, Unmanaged};
+use jailbreak11::nic::check_nic;
+use jailbreak11::passive::Analyzer;
+use jailbreak11::pcap::{read_capture, Capture};
+use jailbreak11::policy::AnswerPolicy;
+use jailbreak11::profile::Profiles;
+use jailbreak11::proto::pppoe::{
+    EtherTypes, Padding, ETHERTYPE_DISCOVERY, ETHERTYPE_SESSION, MAX_PPP_MAX_PAYLOAD,
+};
+use jailbreak11::replay::Replay;
+use jailbreak11::session::{
+    EventKind, IpcpConfig, PapConfig, SessionConfig, SessionEvent, SessionServer, Sessions,
+};
+use jailbreak11::socket::{Framing, MockTransport, PacketSocket};
+use jailbreak11::tun::Tun;
+#[cfg(feature = "exploit")]
+use jailbreak11::update::{Manifest, DEFAULT_MANIFEST};
+use jailbreak11::web::{random_token, WebConfig, WebServer, TOKEN_HEADER};
+use jailbreak11::{log, rt, selfcheck};
+use libc::{ETH_P_ALL, SIGUSR1, SIGUSR2};
+use std::ffi::{c_int, OsString};
+use std::fmt::Write;
+use std::fs::File;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::path::PathBuf;
 use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 use tokio::select;
 use tokio_util::sync::CancellationToken;
 
-mod addr;
-mod discovery;
-mod payload;
-mod session;
-mod socket;
+/// Prefix of the TUN devices that are created for each session with `--nat`.
+const BRIDGE_PREFIX: &str = "ps4ppp";
 
 fn main() -> ExitCode {
     // Parse arguments.
-    let args = command!()
+    let cmd = command!()
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("compare")
+                .about("Print the differences between two captures of the attempts")
+                .arg(
+                    Arg::new("a")
+                        .help("The first pcap or pcapng file")
+                        .value_name("A")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("b")
+                        .help("The second pcap or pcapng file")
+                        .value_name("B")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("replay")
+                .about("Feed the frames of a PS4 from a capture to the servers and print the responses")
+                .arg(
+                    Arg::new("file")
+                        .help("The pcap or pcapng file")
+                        .value_name("FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Build every kind of frames and parse them back without touching the network"),
+        )
+        .subcommand(
+            Command::new("install-caps")
+                .long_flag("install-caps")
+                .about("Give this binary the capabilities it need to run without root (run once as root)"),
+        )
         .arg(
             Arg::new("interface")
                 .help("Index of the interface that connected with the PS4")
                 .value_name("IF")
                 .value_parser(value_parser!(c_int))
-                .required(true),
+                .required_unless_present("profile"),
+        )
+        .arg(
+            Arg::new("profile")
+                .help("Prepend the options of this profile, which can be overridden on the command line")
+                .long("profile")
+                .value_name("NAME"),
+        )
+        .arg(
+            Arg::new("profiles")
+                .help("File to read the profiles from")
+                .long("profiles")
+                .value_name("FILE")
+                .value_parser(value_parser!(PathBuf))
+                .default_value("/etc/jailbreak-11/profiles.conf")
+                .requires("profile"),
+        )
+        .arg(
+            Arg::new("unmanage")
+                .help("Stop NetworkManager and systemd-networkd from managing the interface until exit")
+                .long("unmanage")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("passive"),
+        )
+        .arg(
+            Arg::new("ignore-conflicts")
+                .help("Start even if another PPPoE listener is active on the interface")
+                .long("ignore-conflicts")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("cooldown")
+                .help("Number of seconds to ignore the PS4 after a failed attempt")
+                .long("cooldown")
+                .value_name("SECS")
+                .value_parser(value_parser!(u64))
+                .default_value("30"),
+        )
+        .arg(
+            Arg::new("cooldown-wait-padi")
+                .help("Restart the cooldown on each PADI from the PS4 during it")
+                .long("cooldown-wait-padi")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("max-attempts")
+                .help("Stop after this number of attempts (0 for no limit)")
+                .long("max-attempts")
+                .value_name("N")
+                .value_parser(value_parser!(u64))
+                .default_value("0"),
+        )
+        .arg(
+            Arg::new("max-consoles")
+                .help("Maximum number of PS4s to serve at the same time (0 for no limit)")
+                .long("max-consoles")
+                .value_name("N")
+                .value_parser(value_parser!(usize))
+                .default_value("0"),
+        )
+        .arg(
+            Arg::new("max-payload")
+                .help("Agree PPP-Max-Payload (RFC 4638) up to this number of bytes when the PS4 ask for it")
+                .long("max-payload")
+                .value_name("BYTES")
+                .value_parser(value_parser!(u16).range(1493..=i64::from(MAX_PPP_MAX_PAYLOAD)))
+                .conflicts_with("kernel-ppp"),
+        )
+        .arg(
+            Arg::new("padi-window")
+                .help("Number of milliseconds to ignore identical PADIs from the same PS4")
+                .long("padi-window")
+                .value_name("MS")
+                .value_parser(value_parser!(u64))
+                .default_value("250"),
+        )
+        .arg(
+            Arg::new("answer")
+                .help("Consoles to answer (all, first, none or comma-separated MAC addresses)")
+                .long("answer")
+                .value_name("POLICY")
+                .value_parser(value_parser!(AnswerPolicy))
+                .default_value("all"),
+        )
+        .arg(
+            Arg::new("ac-name")
+                .help("AC-Name to send in PADO")
+                .long("ac-name")
+                .value_name("NAME")
+                .default_value("OBHQ Jailbreak 11.00"),
+        )
+        .arg(
+            Arg::new("service-name")
+                .help("The only Service-Name to offer (* to accept any)")
+                .long("service-name")
+                .value_name("NAME")
+                .default_value("*"),
+        )
+        .arg(
+            Arg::new("banner")
+                .help("How to vary AC-Name and Service-Name between attempts (fixed, cycle:N or random[:SEED])")
+                .long("banner")
+                .value_name("MODE")
+                .value_parser(value_parser!(Banner))
+                .default_value("fixed"),
+        )
+        .arg(
+            Arg::new("pads-zero-id")
+                .help("Send PADS with SESSION_ID 0 instead of the allocated one")
+                .long("pads-zero-id")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("ethertypes")
+                .help("EtherTypes of PPPoE discovery and session stage in hex (e.g. 88b5,88b6)")
+                .long("ethertypes")
+                .value_name("DISCOVERY,SESSION")
+                .value_parser(value_parser!(EtherTypes))
+                .default_value("8863,8864")
+                .conflicts_with("kernel-ppp"),
+        )
+        .arg(
+            Arg::new("strict-padding")
+                .help("Drop PPPoE frames that has unexpected bytes after LENGTH")
+                .long("strict-padding")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("keep-link")
+                .help("Keep the PPP link up after exploitation")
+                .long("keep-link")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-exploit")
+                .help("Serve the sessions as a normal PPPoE server without exploiting the PS4")
+                .long("no-exploit")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("kernel-ppp"),
+        )
+        .group(
+            ArgGroup::new("link-up")
+                .args(["keep-link", "no-exploit"])
+                .multiple(true),
+        )
+        .arg(
+            Arg::new("tun")
+                .help("Forward IP traffic of the PPPoE sessions through a TUN device")
+                .long("tun")
+                .value_name("NAME")
+                .requires("link-up"),
+        )
+        .arg(
+            Arg::new("nat")
+                .help("Give each PPPoE session its own TUN device and masquerade its IPv4 traffic out of this interface")
+                .long("nat")
+                .value_name("IFACE")
+                .requires("link-up")
+                .conflicts_with_all(["tun", "ipv6-only"]),
+        )
+        .arg(
+            Arg::new("kernel-ppp")
+                .help("Hand off PPPoE sessions to the kernel PPP subsystem")
+                .long("kernel-ppp")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["tun", "nat"]),
+        )
+        .arg(
+            Arg::new("ip")
+                .help("IPv4 address to assign to the PS4")
+                .long("ip")
+                .value_name("ADDR")
+                .value_parser(value_parser!(Ipv4Addr))
+                .default_value("10.0.0.2")
+        )
+        .arg(
+            Arg::new("gateway")
+                .help("IPv4 address of this side of the link, which is the gateway of the PS4")
+                .long("gateway")
+                .value_name("ADDR")
+                .value_parser(value_parser!(Ipv4Addr))
+                .default_value("10.0.0.1")
+        )
+        .arg(
+            Arg::new("dns")
+                .help("Primary and optionally secondary DNS server to assign to the PS4")
+                .long("dns")
+                .value_name("ADDR[,ADDR]")
+                .value_parser(value_parser!(Ipv4Addr))
+                .value_delimiter(',')
+                .num_args(1..=2)
+        )
+        .arg(
+            Arg::new("block-dns")
+                .help("Answer the DNS queries from the PS4 for these domains and their subdomains with NXDOMAIN")
+                .long("block-dns")
+                .value_name("DOMAIN[,DOMAIN]")
+                .value_parser(value_parser!(DnsBlocklist))
+                .num_args(0..=1)
+                .default_missing_value(DEFAULT_BLOCKLIST)
+                .require_equals(true)
+                .requires("link-up")
+                .conflicts_with("kernel-ppp"),
         )
-        .get_matches();
+        .arg(
+            Arg::new("ipv6-only")
+                .help("Refuse IPCP so the PS4 only use IPv6")
+                .long("ipv6-only")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["ip", "gateway", "dns"]),
+        )
+        .arg(
+            Arg::new("pap")
+                .help("Ask the PS4 to authenticate with PAP and accept only USER:PASSWORD if specified")
+                .long("pap")
+                .value_name("USER:PASSWORD")
+                .value_parser(value_parser!(PapConfig))
+                .num_args(0..=1)
+                .default_missing_value("")
+        )
+        .arg(
+            Arg::new("echo-interval")
+                .help("Number of seconds between LCP Echo-Requests to the PS4 (0 to disable)")
+                .long("echo-interval")
+                .value_name("SECS")
+                .value_parser(value_parser!(u64))
+                .default_value("0")
+        )
+        .arg(
+            Arg::new("inactivity-timeout")
+                .help("Close the sessions that has not received anything for this number of seconds (0 to disable)")
+                .long("inactivity-timeout")
+                .value_name("SECS")
+                .value_parser(value_parser!(u64))
+                .default_value("300"),
+        )
+        .arg(
+            Arg::new("inactivity-padt")
+                .help("Send PADT when closing a session by --inactivity-timeout")
+                .long("inactivity-padt")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("strict")
+                .help("Fail the attempt on the first unexpected code, tag or state transition from the PS4")
+                .long("strict")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("passive")
+                .help("Decode and print all PPPoE frames on the interface without transmitting")
+                .long("passive")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "answer",
+                    "keep-link",
+                    "no-exploit",
+                    "tun",
+                    "nat",
+                    "kernel-ppp",
+                    "max-payload",
+                    "ip",
+                    "gateway",
+                    "dns",
+                    "block-dns",
+                    "ipv6-only",
+                    "pap",
+                    "echo-interval",
+                    "chaos",
+                    "strict",
+                ]),
+        )
+        .arg(
+            Arg::new("chaos")
+                .help("Drop, delay or reorder the frames for testing (e.g. drop=0.01,tx-delay=20,reorder=0.01,seed=1)")
+                .long("chaos")
+                .value_name("SPEC")
+                .value_parser(value_parser!(ChaosConfig)),
+        )
+        .arg(
+            Arg::new("pcap")
+                .help("Write all PPPoE frames that was sent and received to a pcapng file")
+                .long("pcap")
+                .value_name("FILE")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with("passive"),
+        )
+        .arg(
+            Arg::new("pcap-before")
+                .help("Number of seconds before the failed stage to keep in the capture of each failed attempt")
+                .long("pcap-before")
+                .value_name("SECS")
+                .value_parser(value_parser!(u64))
+                .default_value("5")
+                .requires("pcap"),
+        )
+        .arg(
+            Arg::new("pcap-after")
+                .help("Number of seconds after the failure to keep in the capture of each failed attempt")
+                .long("pcap-after")
+                .value_name("SECS")
+                .value_parser(value_parser!(u64))
+                .default_value("2")
+                .requires("pcap"),
+        )
+        .arg(
+            Arg::new("mirror")
+                .help("Copy the frames after PPPoE Discovery to another interface (index) or the TCP clients on ADDR:PORT as pcapng in real time")
+                .long("mirror")
+                .value_name("TARGET")
+                .value_parser(value_parser!(MirrorTarget))
+                .conflicts_with("passive"),
+        )
+        .arg(
+            Arg::new("attempt-dir")
+                .help("Write the log, capture, report and payload hashes of each attempt to a separate directory under DIR")
+                .long("attempt-dir")
+                .value_name("DIR")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with("passive"),
+        )
+        .arg(
+            Arg::new("socket-buffers")
+                .help("Sizes of the socket buffers on top of the defaults (e.g. rx=4M,ipv6-tx=1M,discovery-rx=0)")
+                .long("socket-buffers")
+                .value_name("SPEC")
+                .value_parser(value_parser!(BufferConfig))
+                .conflicts_with("passive"),
+        )
+        .arg(
+            Arg::new("rx-ring")
+                .help("Receive through PACKET_RX_RING with this number of frames on all sockets instead of recvmsg")
+                .long("rx-ring")
+                .value_name("FRAMES")
+                .value_parser(value_parser!(usize))
+                .conflicts_with("passive"),
+        )
+        .arg(
+            Arg::new("web")
+                .help("Serve a web UI to watch and control the attempts on this address (e.g. 127.0.0.1:8080)")
+                .long("web")
+                .value_name("ADDR:PORT")
+                .value_parser(value_parser!(SocketAddr))
+                .conflicts_with("passive"),
+        )
+        .arg(
+            Arg::new("api")
+                .help("Serve a JSON API to watch and control the attempts on this address (e.g. 127.0.0.1:8081)")
+                .long("api")
+                .value_name("ADDR:PORT")
+                .value_parser(value_parser!(SocketAddr))
+                .conflicts_with("passive"),
+        )
+        .arg(
+            Arg::new("web-token")
+                .help("Token that the POST requests to --web and --api must have (random by default)")
+                .long("web-token")
+                .value_name("TOKEN")
+                .conflicts_with("passive"),
+        )
+        .arg(
+            Arg::new("metrics")
+                .help("Append a snapshot of the counters to a CSV (*.csv) or InfluxDB line protocol file periodically")
+                .long("metrics")
+                .value_name("FILE")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with("passive"),
+        )
+        .arg(
+            Arg::new("metrics-interval")
+                .help("Number of seconds between the snapshots of --metrics")
+                .long("metrics-interval")
+                .value_name("SECS")
+                .value_parser(value_parser!(u64).range(1..))
+                .default_value("60")
+                .requires("metrics"),
+        )
+        .arg(
+            Arg::new("raw-socket")
+                .help("Always use SOCK_RAW instead of falling back to it when SOCK_DGRAM is broken")
+                .long("raw-socket")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("tx-timestamps")
+                .help("Record when the critical frames left the NIC on the timeline with SO_TIMESTAMPING")
+                .long("tx-timestamps")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("passive"),
+        )
+        .arg(
+            Arg::new("verbose")
+                .help("Write more messages (can be repeated)")
+                .short('v')
+                .long("verbose")
+                .action(ArgAction::Count)
+                .global(true),
+        )
+        .arg(
+            Arg::new("quiet")
+                .help("Write less messages (can be repeated)")
+                .short('q')
+                .long("quiet")
+                .action(ArgAction::Count)
+                .global(true),
+        )
+        .arg(
+            Arg::new("log")
+                .help("Level of each subsystem (e.g. packet=off,session=debug)")
+                .long("log")
+                .value_name("FILTER")
+                .value_parser(value_parser!(LogFilter))
+                .global(true),
+        )
+        .arg(
+            Arg::new("sync")
+                .help("Run on the current thread with poll(2) instead of the Tokio runtime")
+                .long("sync")
+                .action(ArgAction::SetTrue),
+        );
+
+    #[cfg(feature = "exploit")]
+    let cmd = cmd.args(exploit_args());
+
+    #[cfg(feature = "experimental")]
+    let cmd = cmd.arg(
+        Arg::new("experimental")
+            .help("Run the experimental stage after KASLR has been defeated")
+            .long("experimental")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("no-exploit"),
+    );
+
+    let mut cmd = cmd;
+    let args = cmd.clone().get_matches();
+
+    // Parse again with the options of the profile.
+    let args = match args.get_one::<String>("profile") {
+        Some(_) => {
+            let argv = profile_args(&mut cmd, &args);
+
+            cmd.clone().args_override_self(true).get_matches_from(argv)
+        }
+        None => args,
+    };
+
+    // Setup log filter. The packet dumps are replaced with the decoded frames when replaying.
+    let sub = args.subcommand().map_or(&args, |(_, v)| v);
+    let mut filter = match sub.get_one::<LogFilter>("log") {
+        Some(v) => v.clone(),
+        None if args.subcommand_name() == Some("replay") => "packet=off".parse().unwrap(),
+        None => LogFilter::default(),
+    };
+
+    filter.adjust(sub.get_count("verbose"), sub.get_count("quiet"));
+
+    log::init(filter);
+
+    // Run subcommands.
+    match args.subcommand() {
+        Some(("compare", args)) => return run_compare(args),
+        Some(("replay", args)) => {
+            // The servers are run with the default options.
+            return run_replay(args, &cmd.get_matches_from([crate_name!(), "0"]));
+        }
+        Some(("check", _)) => return run_check(),
+        Some(("install-caps", _)) => return run_install_caps(),
+        _ => {}
+    }
+
+    if let Some(v) = args.get_one::<String>("profile") {
+        info!("Using profile '{v}'.");
+    }
+
+    // Make sure the frames we are going to send are valid.
+    for c in selfcheck::run() {
+        if let Err(e) = c.result {
+            error!("Self-check failed on {}: {e}.", c.name);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    // Run without the Tokio runtime.
+    if args.get_flag("sync") {
+        let rt = match rt::Local::new() {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed to setup the runtime: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        rt::install(rt.clone());
+
+        let code = rt.block_on(run(args));
+
+        log::flush();
+
+        return code;
+    }
 
     // Setup Tokio.
     let tokio = tokio::runtime::Builder::new_current_thread()
@@ -35,54 +615,1344 @@ fn main() -> ExitCode {
         .build()
         .unwrap();
 
-    tokio.block_on(run(args))
+    rt::install(rt::Tokio);
+
+    let code = tokio.block_on(run(args));
+
+    log::flush();
+
+    code
 }
 
-async fn run(args: ArgMatches) -> ExitCode {
-    let ab = Arc::new(AddrBuilder::new(*args.get_one("interface").unwrap()));
-    let sessions = Arc::new(Sessions::default());
+/// Returns the command line with the options of the profile that was selected in `args` in front
+/// of the original options. This does not return if the profile cannot be loaded.
+fn profile_args(cmd: &mut Command, args: &ArgMatches) -> Vec<OsString> {
+    // Load the profile.
+    let name = args.get_one::<String>("profile").unwrap();
+    let path = args.get_one::<PathBuf>("profiles").unwrap();
+    let profiles = match Profiles::load(path) {
+        Ok(v) => v,
+        Err(e) => {
+            let m = format!("failed to load {}: {}", path.display(), e.display());
+
+            cmd.error(ErrorKind::Io, m).exit();
+        }
+    };
+
+    let profile = match profiles.get(name) {
+        Some(v) => v,
+        None => {
+            let names = profiles.names().collect::<Vec<_>>().join(", ");
+            let m = format!("no profile '{}' in {} ({})", name, path.display(), names);
+
+            cmd.error(ErrorKind::InvalidValue, m).exit();
+        }
+    };
+
+    // Convert the options. The values are joined with the options so they will not consume the
+    // arguments after it.
+    let mut options = Vec::new();
+    let mut interface = None;
+
+    cmd.build();
+
+    for (k, v) in profile.options() {
+        let arg = cmd
+            .get_arguments()
+            .find(|a| a.get_id() == k.as_str())
+            .filter(|a| !matches!(a.get_id().as_str(), "profile" | "profiles"));
+        let arg = match arg {
+            Some(v) => v,
+            None => {
+                let m = format!("unknown option '{k}' in profile '{name}'");
+
+                cmd.error(ErrorKind::UnknownArgument, m).exit();
+            }
+        };
+
+        let takes = arg.get_action().takes_values();
+        let v = match (v, takes) {
+            (Some(v), true) => Some(v),
+            (None, false) => None,
+            (Some(_), false) => {
+                let m = format!("option '{k}' in profile '{name}' does not take a value");
+
+                cmd.error(ErrorKind::TooManyValues, m).exit();
+            }
+            (None, true) => {
+                let m = format!("option '{k}' in profile '{name}' requires a value");
+
+                cmd.error(ErrorKind::TooFewValues, m).exit();
+            }
+        };
+
+        if arg.is_positional() {
+            interface = v.cloned();
+            continue;
+        }
+
+        match v {
+            Some(v) => options.push(format!("--{k}={v}")),
+            None => options.push(format!("--{k}")),
+        }
+    }
+
+    // The interface on the command line take precedence.
+    let mut argv = std::env::args_os().take(1).collect::<Vec<_>>();
+
+    if !args.contains_id("interface") {
+        match interface {
+            Some(v) => argv.push(v.into()),
+            None => {
+                let m = format!("profile '{name}' does not specify the interface");
+
+                cmd.error(ErrorKind::MissingRequiredArgument, m).exit();
+            }
+        }
+    }
+
+    argv.extend(options.into_iter().map(OsString::from));
+    argv.extend(std::env::args_os().skip(1));
+    argv
+}
+
+/// Returns the arguments of the exploit.
+#[cfg(feature = "exploit")]
+fn exploit_args() -> [Arg; 8] {
+    [
+        Arg::new("attempt-timeout")
+            .help("Fail the exploit if it does not finish within this number of seconds (0 to wait forever)")
+            .long("attempt-timeout")
+            .value_name("SECS")
+            .value_parser(value_parser!(u64))
+            .default_value("0")
+            .conflicts_with_all(["kernel-ppp", "passive", "no-exploit"]),
+        Arg::new("detect")
+            .help("Require a detector to agree on success (port=PORT, klog=TEXT, silence=SECS or script=CMD)")
+            .long("detect")
+            .value_name("DETECTOR")
+            .value_parser(value_parser!(DetectorConfig))
+            .action(ArgAction::Append)
+            .conflicts_with_all(["kernel-ppp", "passive", "no-exploit"]),
+        Arg::new("fw")
+            .help("Firmware version of the PS4")
+            .long("fw")
+            .value_name("VERSION")
+            .value_parser(value_parser!(Firmware))
+            .default_value("11.00")
+            .conflicts_with_all(["kernel-ppp", "passive", "no-exploit"]),
+        Arg::new("groom")
+            .help("Tune the heap grooming (e.g. spray-delay=50,pin-interval=2000)")
+            .long("groom")
+            .value_name("SPEC")
+            .value_parser(value_parser!(GroomConfig))
+            .conflicts_with_all(["kernel-ppp", "passive", "no-exploit"]),
+        Arg::new("stage1")
+            .help("Kernel shellcode to run after the kernel is pwned (file or HTTP(S) URL)")
+            .long("stage1")
+            .value_name("FILE|URL")
+            .conflicts_with_all(["kernel-ppp", "passive", "no-exploit"]),
+        Arg::new("stage2")
+            .help("Payload to be loaded by the stage1 (file or HTTP(S) URL)")
+            .long("stage2")
+            .value_name("FILE|URL")
+            .requires("stage1")
+            .conflicts_with_all(["kernel-ppp", "passive", "no-exploit"]),
+        Arg::new("sha256")
+            .help("SHA-256 that --stage1 and optionally --stage2 must match (required for a URL)")
+            .long("sha256")
+            .value_name("HASH[,HASH]")
+            .value_parser(value_parser!(Checksum))
+            .value_delimiter(',')
+            .num_args(1..=2)
+            .requires("stage1")
+            .conflicts_with_all(["kernel-ppp", "passive", "no-exploit"]),
+        Arg::new("update-check")
+            .help("Warn if the kernel offsets or the payloads are outdated according to a manifest (URL or file)")
+            .long("update-check")
+            .value_name("MANIFEST")
+            .num_args(0..=1)
+            .default_missing_value(DEFAULT_MANIFEST)
+            .require_equals(true)
+            .conflicts_with_all(["kernel-ppp", "passive", "no-exploit"]),
+    ]
+}
+
+/// Load the payloads and create [`Exploit`]. The error is already written when [`None`] is
+/// returned.
+#[cfg(feature = "exploit")]
+fn setup_exploit(
+    args: &ArgMatches,
+    iface: &Interface,
+    logger: &Logger,
+    chaos: Option<Arc<Chaos>>,
+    buffers: &BufferConfig,
+) -> Option<Exploit> {
+    // Load payloads.
+    let checksums: Vec<Checksum> = args
+        .get_many("sha256")
+        .map(|v| v.cloned().collect())
+        .unwrap_or_default();
+    let stage1 = match args.get_one::<String>("stage1") {
+        Some(p) => match load_stage1(p, checksums.first().copied()) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                error!("Failed to load {}: {}.", p, e.display());
+                return None;
+            }
+        },
+        None => None,
+    };
+    let stage2 = match args.get_one::<String>("stage2") {
+        Some(p) => match load_stage2(p, checksums.get(1).copied()) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                error!("Failed to load {}: {}.", p, e.display());
+                return None;
+            }
+        },
+        None => None,
+    };
+
+    // Check for updates in the background since the download may take a while.
+    if let Some(src) = args.get_one::<String>("update-check") {
+        let src = src.clone();
+        let payloads: Vec<_> = [("stage1", &stage1), ("stage2", &stage2)]
+            .into_iter()
+            .filter_map(|(n, p)| p.clone().map(|p| (n, p)))
+            .collect();
+
+        std::thread::spawn(move || check_updates(&src, &payloads));
+    }
+
+    // Report the non-default grooming since it affects the reliability.
+    let groom = args
+        .get_one::<GroomConfig>("groom")
+        .copied()
+        .unwrap_or_default();
+
+    if groom != GroomConfig::default() {
+        info!("Heap grooming: {groom}.");
+    }
+
+    // The experimental stage is not reviewed like the rest of the exploit.
+    let experimental = cfg!(feature = "experimental") && args.get_flag("experimental");
+
+    if experimental {
+        warning!("WARNING: the experimental stage is enabled. It may crash or hang the PS4.");
+    }
+
+    // Nothing else can run the payloads until the code execution is implemented.
+    if !experimental && (stage1.is_some() || stage2.is_some()) {
+        warning!(
+            "WARNING: the payloads are only used by the experimental stage, which is disabled."
+        );
+    }
+
+    Some(Exploit::new(
+        iface.session.clone(),
+        iface.ab.clone(),
+        logger.clone(),
+        iface.mac,
+        ExploitConfig {
+            ip: *args.get_one("ip").unwrap(),
+            ethertype: iface.ethertypes.session,
+            firmware: *args.get_one("fw").unwrap(),
+            groom,
+            chaos,
+            buffers: buffers.ipv6,
+            detectors: args
+                .get_many("detect")
+                .map(|v| v.cloned().collect())
+                .unwrap_or_default(),
+            stage1,
+            stage2,
+            experimental,
+        },
+    ))
+}
 
-    // Create a socket for PPPoE discovery.
-    let ds = match PacketSocket::new() {
+/// Warn about the outdated kernel offsets and `payloads` according to the manifest at `src`.
+#[cfg(feature = "exploit")]
+fn check_updates(src: &str, payloads: &[(&str, Vec<u8>)]) {
+    let manifest = match Manifest::fetch(src) {
         Ok(v) => v,
         Err(e) => {
-            eprintln!("Failed to create PPPoE discovery socket: {}.", e.display());
-            return ExitCode::FAILURE;
+            warning!("Failed to check for updates with {}: {}.", src, e.display());
+            return;
         }
     };
 
-    if let Err(e) = ds.bind(ab.build(ETH_P_PPP_DISC as _, None)) {
-        eprintln!("Failed to bind PPPoE discovery socket: {}.", e.display());
+    let outdated = manifest.check(OFFSETS_VERSION, payloads);
+
+    if outdated.is_empty() {
+        info!("The kernel offsets and the payloads are up to date.");
+        return;
+    }
+
+    for v in outdated {
+        warning!("WARNING: {v}.");
+    }
+
+    warning!(
+        "Outdated offsets or payloads may fail the exploit in a confusing way. Update them first."
+    );
+}
+
+fn run_compare(args: &ArgMatches) -> ExitCode {
+    // Load captures.
+    let mut captures = Vec::with_capacity(2);
+
+    for id in ["a", "b"] {
+        let path = args.get_one::<PathBuf>(id).unwrap();
+
+        match read_capture(path) {
+            Ok(v) => captures.push(v),
+            Err(e) => {
+                error!("Failed to read {}: {}.", path.display(), e.display());
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    // Compare.
+    let report = compare(&captures[0], &captures[1]);
+
+    print!("{report}");
+
+    match report.differences() {
+        0 => println!("No differences found."),
+        1 => println!("1 difference found."),
+        n => println!("{n} differences found."),
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn run_check() -> ExitCode {
+    let mut failed = 0;
+
+    for c in selfcheck::run() {
+        match c.result {
+            Ok(_) => println!("{}: ok", c.name),
+            Err(e) => {
+                println!("{}: {e}", c.name);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed == 0 {
+        println!("All checks passed.");
+        ExitCode::SUCCESS
+    } else {
+        println!("{failed} checks failed.");
+        ExitCode::FAILURE
+    }
+}
+
+fn run_install_caps() -> ExitCode {
+    if !caps::is_root() {
+        error!("install-caps must be run as root (e.g. with sudo).");
         return ExitCode::FAILURE;
     }
 
-    // Create a socket for PPPoE session.
-    let ss = match PacketSocket::new() {
+    // Set the capabilities on the actual file instead of a symlink to it.
+    let exe = match std::env::current_exe().and_then(|v| v.canonicalize()) {
         Ok(v) => v,
         Err(e) => {
-            eprintln!("Failed to create PPPoE session socket: {}.", e.display());
+            error!("Failed to get the path of the binary: {}.", e.display());
             return ExitCode::FAILURE;
         }
     };
 
-    if let Err(e) = ss.bind(ab.build(ETH_P_PPP_SES as _, None)) {
-        eprintln!("Failed to bind PPPoE session socket: {}.", e.display());
+    if let Err(e) = caps::set_file_caps(&exe) {
+        error!(
+            "Failed to set the capabilities on {}: {}.",
+            exe.display(),
+            e.display()
+        );
         return ExitCode::FAILURE;
     }
 
+    info!(
+        "CAP_NET_RAW and CAP_NET_ADMIN have been set on {}.",
+        exe.display()
+    );
+
+    // Let --kernel-ppp open /dev/ppp.
+    if let Err(e) = caps::install_udev_rule() {
+        error!("Failed to write {}: {}.", caps::UDEV_RULE, e.display());
+        return ExitCode::FAILURE;
+    }
+
+    info!("Wrote {}.", caps::UDEV_RULE);
+    info!("The jailbreak can now run without root except with --nat, which still need it.");
+    info!("Run this again each time the binary has been replaced.");
+
+    ExitCode::SUCCESS
+}
+
+fn run_replay(args: &ArgMatches, defaults: &ArgMatches) -> ExitCode {
+    // Load capture.
+    let path = args.get_one::<PathBuf>("file").unwrap();
+    let replay = match read_capture(path) {
+        Ok(v) => match Replay::new(v) {
+            Some(v) => v,
+            None => {
+                error!("No PADI found in {}.", path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        Err(e) => {
+            error!("Failed to read {}: {}.", path.display(), e.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Setup Tokio.
+    let tokio = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    rt::install(rt::Tokio);
+
+    tokio.block_on(replay_servers(replay, defaults));
+
+    log::flush();
+
+    ExitCode::SUCCESS
+}
+
+/// Run the servers with the options in `args` on the sockets that are fed by `replay`.
+async fn replay_servers(replay: Replay, args: &ArgMatches) {
+    let (logger, writer) = Logger::new(1024, None);
+    let ab = Arc::new(AddrBuilder::new(0));
+    let sessions = Arc::new(Sessions::new(false));
+    let attempts = Arc::new(Attempts::default());
+    let (discovery, mut dp) = MockTransport::new(logger.clone(), Padding::Trim);
+    let (session, mut sp) = MockTransport::new(logger, Padding::Trim);
+
+    writer.spawn();
+
+    sessions.on_event(report_session);
+
+    // Start servers.
+    let ds = match DiscoveryServer::new(
+        discovery,
+        ab.clone(),
+        sessions.clone(),
+        attempts,
+        Arc::new(Cooldown::new(Duration::ZERO)),
+        discovery_config(args, ETHERTYPE_DISCOVERY),
+    ) {
+        Ok(v) => Arc::new(v),
+        Err(e) => {
+            error!("Failed to setup PPPoE discovery server: {}.", e.display());
+            return;
+        }
+    };
+    let ss = Arc::new(SessionServer::new(
+        Arc::new(session),
+        ab,
+        sessions,
+        None,
+        session_config(args, ETHERTYPE_SESSION, ipcp_config(args)),
+    ));
+    let running = CancellationToken::new();
+
+    rt::spawn({
+        let ds = ds.clone();
+        let running = running.clone();
+
+        async move { ds.run(running).await }
+    });
+    rt::spawn({
+        let ss = ss.clone();
+        let running = running.clone();
+
+        async move { ss.run(running).await }
+    });
+
+    // Replay.
+    info!("Replaying the frames from {}.", replay.console());
+
+    replay.run(&mut dp, &mut sp).await;
+
+    running.cancel();
+    ds.abort().await;
+    ss.abort();
+}
+
+/// Print the session events that the session server does not report itself.
+async fn report_session(e: SessionEvent) {
+    match e.kind {
+        EventKind::Error(v) => warning!("Session {} with {}: {}.", e.id, e.peer, v),
+        // The session server already reported these.
+        EventKind::LcpUp | EventKind::IpcpUp(_) => {}
+        v => info!("Session {} with {} {}.", e.id, e.peer, v),
+    }
+}
+
+/// Returns the configuration of the discovery server from `args` without the exploit and kernel
+/// PPP, which need more than the options to setup.
+fn discovery_config(args: &ArgMatches, ethertype: u16) -> DiscoveryConfig {
+    DiscoveryConfig {
+        ethertype,
+        #[cfg(feature = "exploit")]
+        exploit: None,
+        #[cfg(feature = "exploit")]
+        attempt_timeout: match *args.get_one::<u64>("attempt-timeout").unwrap() {
+            0 => None,
+            v => Some(Duration::from_secs(v)),
+        },
+        kernel_ppp: None,
+        padi_window: Duration::from_millis(*args.get_one("padi-window").unwrap()),
+        policy: args.get_one::<AnswerPolicy>("answer").unwrap().clone(),
+        pads_zero_id: args.get_flag("pads-zero-id"),
+        ac_name: args.get_one::<String>("ac-name").unwrap().clone(),
+        service_name: match args.get_one::<String>("service-name").unwrap().as_str() {
+            "*" => None,
+            v => Some(v.into()),
+        },
+        banner: *args.get_one("banner").unwrap(),
+        inactivity: match *args.get_one::<u64>("inactivity-timeout").unwrap() {
+            0 => None,
+            v => Some(Duration::from_secs(v)),
+        },
+        inactivity_padt: args.get_flag("inactivity-padt"),
+        strict: args.get_flag("strict"),
+        max_consoles: NonZeroUsize::new(*args.get_one("max-consoles").unwrap()),
+        max_payload: args.get_one("max-payload").copied(),
+    }
+}
+
+/// Returns the configuration of the session server from `args` without the bridge, which need
+/// `--nat` to be setup.
+fn session_config(args: &ArgMatches, ethertype: u16, ipcp: IpcpConfig) -> SessionConfig {
+    SessionConfig {
+        ethertype,
+        kernel: args.get_flag("kernel-ppp"),
+        ipv4: !args.get_flag("ipv6-only"),
+        ipcp,
+        pap: args.get_one::<PapConfig>("pap").cloned(),
+        echo_interval: match *args.get_one::<u64>("echo-interval").unwrap() {
+            0 => None,
+            v => Some(Duration::from_secs(v)),
+        },
+        strict: args.get_flag("strict"),
+        bridge: None,
+        blocklist: args.get_one::<DnsBlocklist>("block-dns").cloned(),
+    }
+}
+
+fn ipcp_config(args: &ArgMatches) -> IpcpConfig {
+    IpcpConfig {
+        peer: *args.get_one("ip").unwrap(),
+        gateway: *args.get_one("gateway").unwrap(),
+        dns: args
+            .get_many("dns")
+            .map(|v| v.copied().collect())
+            .unwrap_or_default(),
+    }
+}
+
+async fn run_passive(
+    interface: c_int,
+    ab: &AddrBuilder,
+    sock: PacketSocket,
+    ethertypes: EtherTypes,
+) -> ExitCode {
+    // Setup analyzer.
+    let analyzer = match Analyzer::new(sock, ab.build(ETH_P_ALL as _, None), ethertypes) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed to setup PPPoE analyzer: {}.", e.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Enable promiscuous mode so we can see the frames between the other hosts.
+    if let Err(e) = analyzer.enable_promiscuous(interface) {
+        error!(
+            "Failed to enable promiscuous mode on interface {}: {}.",
+            interface,
+            e.display()
+        );
+    }
+
+    // Run analyzer.
+    let running = CancellationToken::new();
+
+    info!("Listening for PPPoE frames on interface {interface} without transmitting.");
+
+    select! {
+        _ = analyzer.run(running.clone()) => ExitCode::FAILURE,
+        v = rt::ctrl_c() => {
+            v.unwrap();
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+/// Write the frames of `attempt` around its failed stage to a file next to `path`.
+async fn trim_capture(
+    capture: Arc<Capture>,
+    path: PathBuf,
+    attempt: Arc<Attempt>,
+    before: Duration,
+    after: Duration,
+) {
+    let stage = match attempt.failed_stage() {
+        Some(v) => v,
+        None => return,
+    };
+
+    // Wait for the frames after the failure.
+    let to = Instant::now() + after;
+    let from = attempt.stage_started(stage).unwrap_or(to);
+    let from = from.checked_sub(before).unwrap_or(from);
+
+    rt::sleep(after).await;
+
+    // Write the frames.
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let out = path.with_file_name(format!("{}-attempt{}.pcapng", stem, attempt.id()));
+    let file = match File::create(&out) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed to create {}: {}.", out.display(), e.display());
+            return;
+        }
+    };
+
+    match capture.trim(file, attempt.peer(), from, to) {
+        Ok(n) => info!(
+            "Wrote {} frames around the failed {} stage of attempt #{} to {}.",
+            n,
+            stage,
+            attempt.id(),
+            out.display()
+        ),
+        Err(e) => error!("Failed to write {}: {}.", out.display(), e.display()),
+    }
+}
+
+/// Write the artifacts of `attempt` to its directory.
+async fn write_artifacts(artifacts: Arc<Artifacts>, attempt: Arc<Attempt>) {
+    match artifacts.finished(&attempt) {
+        Ok(v) => info!(
+            "Wrote the artifacts of attempt #{} to {}.",
+            attempt.id(),
+            v.display()
+        ),
+        Err((p, e)) => error!("Failed to write {}: {}.", p.display(), e.display()),
+    }
+}
+
+async fn run(args: ArgMatches) -> ExitCode {
+    let started = Instant::now();
+    let interface: c_int = *args.get_one("interface").unwrap();
+    let sessions = Arc::new(Sessions::new(args.get_flag("keep-link")));
+    let attempts = Arc::new(Attempts::new(NonZeroU64::new(
+        *args.get_one("max-attempts").unwrap(),
+    )));
+    let ethertypes: EtherTypes = *args.get_one("ethertypes").unwrap();
+
+    sessions.on_event(report_session);
+
+    if !ethertypes.is_standard() {
+        warning!(
+            "WARNING: using non-standard EtherTypes {:#06x} and {:#06x}. An unmodified PS4 will not see us.",
+            ethertypes.discovery,
+            ethertypes.session
+        );
+    }
+
+    // Check if another PPPoE daemon is active. Competing PADOs make the console pick a random one.
+    let passive = args.get_flag("passive");
+
+    match find_conflicts(interface, ethertypes) {
+        _ if passive => {}
+        Ok(v) if v.is_empty() => {}
+        Ok(v) => {
+            for c in &v {
+                warning!("WARNING: found {c} on interface {interface}.");
+            }
+
+            if !args.get_flag("ignore-conflicts") {
+                warning!("Stop the listeners above or use --ignore-conflicts to start anyway.");
+                return ExitCode::FAILURE;
+            }
+        }
+        Err(e) => error!(
+            "Failed to check for other PPPoE listeners: {}.",
+            e.display()
+        ),
+    }
+
+    // Check if a network manager is configuring the interface (e.g. running a DHCP client on it),
+    // which disturb the frames to the console.
+    let managers =
+        interface_name(interface).and_then(|n| find_managers(interface, &n).map(|m| (n, m)));
+    let _unmanaged = match managers {
+        _ if passive => None,
+        Ok((_, m)) if m.is_empty() => None,
+        Ok((n, m)) if args.get_flag("unmanage") => match Unmanaged::new(&n, &m) {
+            Ok(v) => {
+                for m in m {
+                    info!("Interface {interface} is no longer managed by {m} until exit.");
+                }
+
+                Some(v)
+            }
+            Err(e) => {
+                error!(
+                    "Failed to stop the network managers on interface {}: {}.",
+                    interface,
+                    e.display()
+                );
+
+                return ExitCode::FAILURE;
+            }
+        },
+        Ok((_, m)) => {
+            for m in m {
+                warning!("WARNING: interface {interface} is managed by {m}. Its DHCP client may fight with the PS4 over the interface.");
+            }
+
+            warning!("Use --unmanage to release the interface while the jailbreak is running.");
+
+            None
+        }
+        Err(e) => {
+            error!("Failed to check for network managers: {}.", e.display());
+            None
+        }
+    };
+
+    // Check if the network adapter is known to break the exploit.
+    match check_nic(interface) {
+        _ if passive => {}
+        Ok(None) => {}
+        Ok(Some(v)) => warning!("WARNING: {} on interface {}. {}", v, interface, v.hint()),
+        Err(e) => error!(
+            "Failed to check the network adapter of interface {}: {}.",
+            interface,
+            e.display()
+        ),
+    }
+
+    // Get the address of our interface for the Ethernet header of the recorded frames.
+    let dir = args.get_one::<PathBuf>("attempt-dir");
+    let mirror = args.get_one::<MirrorTarget>("mirror");
+    let mac = if args.contains_id("pcap") || dir.is_some() || mirror.is_some() {
+        match interface_mac(interface) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                error!(
+                    "Failed to get MAC address of interface {}: {}.",
+                    interface,
+                    e.display()
+                );
+
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        None
+    };
+
+    // Create capture file. The attempt directories need the frames even without it.
+    let capture = match args.get_one::<PathBuf>("pcap") {
+        p if p.is_some() || dir.is_some() => {
+            let mac = mac.unwrap();
+
+            match p.map(|p| (p, Capture::create(p, mac))) {
+                Some((p, Ok(v))) => {
+                    info!("Writing all PPPoE frames to {}.", p.display());
+                    Some(Arc::new(v))
+                }
+                Some((p, Err(e))) => {
+                    error!("Failed to create {}: {}.", p.display(), e.display());
+                    return ExitCode::FAILURE;
+                }
+                None => Some(Arc::new(Capture::memory(mac))),
+            }
+        }
+        _ => None,
+    };
+
+    // Start logger.
+    let (mut logger, writer) = Logger::new(1024, capture.clone());
+
+    if let Some(&t) = mirror {
+        match Mirror::new(t, mac.unwrap()) {
+            Ok(v) => {
+                match v.target() {
+                    MirrorTarget::Interface(i) => {
+                        info!("Mirroring the session frames to interface {i}.")
+                    }
+                    MirrorTarget::Listen(a) => {
+                        info!("Mirroring the session frames to the TCP clients on {a}.")
+                    }
+                }
+
+                logger.set_mirror(Arc::new(v));
+            }
+            Err(e) => {
+                error!("Failed to start the mirror: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    writer.spawn();
+
+    // Get how to handle Ethernet padding on the received frames.
+    let padding = if args.get_flag("strict-padding") {
+        Padding::Strict
+    } else {
+        Padding::Trim
+    };
+
+    // Run passive analyzer.
+    if passive {
+        let sock = match PacketSocket::new(logger, padding, Framing::Cooked) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed to create PPPoE analyzer socket: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        return run_passive(interface, &AddrBuilder::new(interface), sock, ethertypes).await;
+    }
+
+    // Get socket buffers.
+    let mut buffers = args
+        .get_one::<BufferConfig>("socket-buffers")
+        .copied()
+        .unwrap_or_default();
+
+    if let Some(&v) = args.get_one::<usize>("rx-ring") {
+        buffers.set_ring(v);
+    }
+
+    // Setup chaos.
+    let chaos = match args.get_one::<ChaosConfig>("chaos") {
+        Some(c) => match Chaos::new(c.clone()) {
+            Ok(v) => {
+                warning!("WARNING: impairing all frames ({}).", v.config());
+                Some(Arc::new(v))
+            }
+            Err(e) => {
+                error!("Failed to setup chaos: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+
+    // Setup the sockets on the interface.
+    let iface = match Interface::open(
+        interface,
+        ethertypes,
+        &logger,
+        padding,
+        chaos.as_ref(),
+        &buffers,
+        args.get_flag("raw-socket"),
+    ) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed to setup interface {}: {}.", interface, e.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Enable TX timestamps.
+    if args.get_flag("tx-timestamps") {
+        for (n, s) in [
+            ("discovery", &iface.discovery),
+            ("session", &*iface.session),
+        ] {
+            if let Err(e) = s.enable_tx_timestamps() {
+                error!(
+                    "Failed to enable TX timestamps on PPPoE {} socket: {}.",
+                    n,
+                    e.display()
+                );
+
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    // Create a TUN device for IP forwarding.
+    let tun = match args.get_one::<String>("tun") {
+        Some(n) => match Tun::open(n) {
+            Ok(v) => {
+                info!("Forwarding IP traffic through {}.", v.name());
+                Some(v)
+            }
+            Err(e) => {
+                error!("Failed to create TUN device {}: {}.", n, e.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+
+    // Masquerade the traffic of the sessions.
+    let ipcp = ipcp_config(&args);
+
+    let nat = match args.get_one::<String>("nat") {
+        Some(n) => match Nat::setup(ipcp.peer, ipcp.last_peer(), BRIDGE_PREFIX, n) {
+            Ok(v) => {
+                info!("Masquerading IPv4 traffic of the sessions out of {n}.");
+                Some(v)
+            }
+            Err(e) => {
+                error!("Failed to setup NAT out of {}: {}.", n, e.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+
+    // Get interface name for kernel PPP.
+    let kernel_ppp = if args.get_flag("kernel-ppp") {
+        match interface_name(interface) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                error!(
+                    "Failed to get name of interface {}: {}.",
+                    interface,
+                    e.display()
+                );
+
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        None
+    };
+
+    // Setup the exploit.
+    let no_exploit = args.get_flag("no-exploit") || cfg!(not(feature = "exploit"));
+
+    if no_exploit {
+        info!("The exploit is disabled. The sessions will stay up until the PS4 close it.");
+    }
+
+    // Write the frames around the failed stage of each attempt to a separate file.
+    if let (Some(c), Some(p), false) =
+        (capture.clone(), args.get_one::<PathBuf>("pcap"), no_exploit)
+    {
+        let p = p.clone();
+        let before = Duration::from_secs(*args.get_one("pcap-before").unwrap());
+        let after = Duration::from_secs(*args.get_one("pcap-after").unwrap());
+
+        attempts.on_finished(move |a| trim_capture(c.clone(), p.clone(), a, before, after));
+    }
+
+    #[cfg(feature = "exploit")]
+    let exploit: Option<Arc<Exploit>> = if no_exploit {
+        None
+    } else {
+        match setup_exploit(&args, &iface, &logger, chaos, &buffers) {
+            Some(v) => Some(Arc::new(v)),
+            None => return ExitCode::FAILURE,
+        }
+    };
+
+    // Keep the artifacts of each attempt.
+    if let (Some(root), Some(c)) = (dir, &capture) {
+        let artifacts = match Artifacts::new(root.clone(), c.clone()) {
+            Ok(v) => Arc::new(v),
+            Err(e) => {
+                error!("Failed to create {}: {}.", root.display(), e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        info!(
+            "Writing the artifacts of each attempt to {}.",
+            root.display()
+        );
+
+        attempts.on_started({
+            let artifacts = artifacts.clone();
+            move |a| artifacts.started(a)
+        });
+
+        attempts.on_finished({
+            let artifacts = artifacts.clone();
+            move |a| write_artifacts(artifacts.clone(), a)
+        });
+    }
+
     // Run servers.
     let running = CancellationToken::new();
-    let ds = DiscoveryServer::new(ds, ab.clone(), sessions.clone());
-    let ss = SessionServer::new(ss);
+    let mut cooldown = Cooldown::new(Duration::from_secs(*args.get_one("cooldown").unwrap()));
+
+    #[cfg(feature = "exploit")]
+    if exploit.is_some() {
+        cooldown = cooldown.with_guidance(args.get_one::<Firmware>("fw").unwrap().guidance());
+    }
+
+    if args.get_flag("cooldown-wait-padi") {
+        cooldown = cooldown.with_padi_wait();
+    }
+
+    let cooldown = Arc::new(cooldown);
+    let ab = iface.ab;
+    let ds = match DiscoveryServer::new(
+        iface.discovery,
+        ab.clone(),
+        sessions.clone(),
+        attempts.clone(),
+        cooldown.clone(),
+        DiscoveryConfig {
+            #[cfg(feature = "exploit")]
+            exploit: exploit.clone(),
+            kernel_ppp,
+            ..discovery_config(&args, iface.ethertypes.discovery)
+        },
+    ) {
+        Ok(v) => Arc::new(v),
+        Err(e) => {
+            error!("Failed to setup PPPoE discovery server: {}.", e.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    let ss = Arc::new(SessionServer::new(
+        iface.session,
+        ab.clone(),
+        sessions.clone(),
+        tun,
+        SessionConfig {
+            bridge: nat.as_ref().map(|_| BRIDGE_PREFIX.into()),
+            ..session_config(&args, iface.ethertypes.session, ipcp)
+        },
+    ));
+
+    // Record the frames that was dropped by the kernel during each attempt for the comparison.
+    let drops = Arc::new({
+        let ds = ds.clone();
+        let ss = ss.clone();
+
+        move || {
+            [ds.statistics(), ss.statistics()]
+                .into_iter()
+                .flatten()
+                .map(|v| v.dropped)
+                .sum::<u64>()
+        }
+    });
+
+    attempts.on_started({
+        let drops = drops.clone();
+        move |a| a.record_drops(drops())
+    });
+
+    attempts.on_finished(move |a| {
+        let drops = drops.clone();
+        async move { a.record_drops(drops()) }
+    });
+
+    let console = Arc::new(ConsoleMonitor::new(
+        iface.index,
+        ds.clone(),
+        sessions.clone(),
+    ));
+
+    // Start web UI and API.
+    let token = match args.get_one::<String>("web-token") {
+        Some(v) => v.clone(),
+        None => match random_token() {
+            Ok(v) => v,
+            Err(e) => {
+                error!(
+                    "Failed to generate a token for the web UI: {}.",
+                    e.display()
+                );
+                return ExitCode::FAILURE;
+            }
+        },
+    };
+
+    let config = WebConfig {
+        ui: true,
+        token,
+        attempts: attempts.clone(),
+        sessions: sessions.clone(),
+        cooldown: cooldown.clone(),
+        console: console.clone(),
+        #[cfg(feature = "exploit")]
+        exploit,
+    };
+
+    for (name, ui, what) in [("web", true, "web UI"), ("api", false, "API")] {
+        let addr = match args.get_one::<SocketAddr>(name) {
+            Some(&v) => v,
+            None => continue,
+        };
+
+        let server = WebConfig {
+            ui,
+            ..config.clone()
+        };
 
-    tokio::spawn(ds.run(running.clone()));
-    tokio::spawn(ss.run(running.clone()));
+        match WebServer::bind(addr, server) {
+            Ok(v) if ui => {
+                // The page does not contain the token so it is passed in the fragment, which the
+                // browser does not send.
+                info!("Serving the {} on http://{}/#{}.", what, addr, config.token);
+                v.start();
+            }
+            Ok(v) => {
+                info!(
+                    "Serving the {} on http://{} with {}: {}.",
+                    what, addr, TOKEN_HEADER, config.token
+                );
+                v.start();
+            }
+            Err(e) => {
+                error!("Failed to listen on {}: {}.", addr, e.display());
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    cooldown.watch_stdin();
+    rt::spawn({
+        let console = console.clone();
+        let running = running.clone();
+
+        async move { console.run(running).await }
+    });
+    rt::spawn({
+        let ds = ds.clone();
+        let running = running.clone();
+
+        async move { ds.run(running).await }
+    });
+    rt::spawn({
+        let ss = ss.clone();
+        let running = running.clone();
+
+        async move { ss.run(running).await }
+    });
+
+    // Dump the state on SIGUSR1 so we can see what is going on when it look stuck.
+    match rt::signal(SIGUSR1) {
+        Ok(mut sig) => rt::spawn({
+            let ds = ds.clone();
+            let ss = ss.clone();
+            let sessions = sessions.clone();
+            let attempts = attempts.clone();
+            let console = console.clone();
+
+            async move {
+                while sig.recv().await.is_ok() {
+                    let mut out = String::new();
+                    let uptime = started.elapsed().as_secs_f64();
+
+                    writeln!(out, "State dump after {uptime:.3} seconds:").unwrap();
+
+                    console.dump(&mut out);
+                    ds.dump(&mut out);
+                    ss.dump(&mut out);
+                    sessions.dump(&mut out);
+                    attempts.dump(&mut out);
+                    logger.dump(&mut out);
+
+                    print!("{out}");
+                }
+            }
+        }),
+        Err(e) => error!("Failed to listen for SIGUSR1: {}.", e.display()),
+    }
+
+    // Write metrics snapshots.
+    if let Some(path) = args.get_one::<PathBuf>("metrics") {
+        let mut file = match MetricsFile::open(path) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed to open {}: {}.", path.display(), e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let interval = Duration::from_secs(*args.get_one("metrics-interval").unwrap());
+        let path = path.clone();
+
+        info!(
+            "Writing the metrics to {} every {} seconds.",
+            path.display(),
+            interval.as_secs()
+        );
+
+        rt::spawn({
+            let ds = ds.clone();
+            let ss = ss.clone();
+            let sessions = sessions.clone();
+            let attempts = attempts.clone();
+            let console = console.clone();
+
+            async move {
+                loop {
+                    let mut m =
+                        Metrics::new(started.elapsed(), console.state().0, &attempts, &sessions);
+
+                    m.discovery = ds.statistics().unwrap_or_default();
+                    m.session = ss.statistics().unwrap_or_default();
+
+                    if let Err(e) = file.write(SystemTime::now(), &m) {
+                        error!("Failed to write {}: {}.", path.display(), e.display());
+                    }
+
+                    rt::sleep(interval).await;
+                }
+            }
+        });
+    }
+
+    // Toggle debug messages and packet dumps on SIGUSR2 so we don't need to restart when a
+    // problematic console show up.
+    match rt::signal(SIGUSR2) {
+        Ok(mut sig) => rt::spawn(async move {
+            while sig.recv().await.is_ok() {
+                if log::toggle_debug() {
+                    info!("Debug messages and packet dumps are enabled.");
+                } else {
+                    info!("Debug messages and packet dumps are disabled.");
+                }
+            }
+        }),
+        Err(e) => error!("Failed to listen for SIGUSR2: {}.", e.display()),
+    }
 
     // Wait for shutdown.
-    select! {
-        v = tokio::signal::ctrl_c() => v.unwrap(),
-        _ = running.cancelled() => {}
+    let graceful = select! {
+        v = rt::ctrl_c() => {
+            v.unwrap();
+            true
+        }
+        _ = attempts.exhausted() => {
+            info!("All attempts have been finished.");
+            true
+        }
+        _ = running.cancelled() => false
+    };
+
+    // Close all sessions.
+    if !graceful {
+        ds.abort().await;
+        ss.abort();
+    } else if sessions.peers().is_empty() {
+        ds.shutdown().await;
+        ss.shutdown().await;
+    } else {
+        info!("Closing all sessions. Press Ctrl+C again to exit immediately.");
+
+        select! {
+            _ = async { ss.terminate().await; ds.shutdown().await; ss.shutdown().await } => {}
+            v = rt::ctrl_c() => v.unwrap()
+        }
     }
 
-    ExitCode::SUCCESS
+    // Report the frames that was dropped by the kernel since it is invisible otherwise.
+    for (name, stats) in [("discovery", ds.statistics()), ("session", ss.statistics())] {
+        match stats {
+            Ok(v) if v.dropped != 0 => warning!(
+                "WARNING: the kernel dropped {} of {} frames on PPPoE {} socket.",
+                v.dropped,
+                v.received,
+                name
+            ),
+            Ok(v) => info!("PPPoE {name} socket: {v}."),
+            Err(e) => error!(
+                "Failed to get statistics of PPPoE {} socket: {}.",
+                name,
+                e.display()
+            ),
+        }
+    }
+
+    // Report how long each console took to reconnect, which is useful to tune the retry timing.
+    for (peer, v) in attempts.reconnects() {
+        let (min, max) = match (v.iter().min(), v.iter().max()) {
+            (Some(&min), Some(&max)) => (min, max),
+            _ => continue,
+        };
+
+        let avg = v.iter().sum::<Duration>() / v.len() as u32;
+
+        info!(
+            "{} reconnected {} times after {:.1} seconds on average (min {:.1}, max {:.1}).",
+            peer,
+            v.len(),
+            avg.as_secs_f64(),
+            min.as_secs_f64(),
+            max.as_secs_f64()
+        );
+    }
+
+    // There is no result without the exploit.
+    if no_exploit {
+        return if graceful {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+
+    // Print summary.
+    let mut result = Err(Failure::NoPadi);
+    let mut vulnerability = Vulnerability::Unknown;
+
+    for a in attempts.history() {
+        let r = a.result().unwrap();
+
+        match r {
+            Ok(_) => info!("Attempt #{} on {}: succeeded.", a.id(), a.peer()),
+            Err(e) => info!("Attempt #{} on {}: {}.", a.id(), a.peer(), e),
+        }
+
+        if let Some(v) = a.close_reason() {
+            info!("  Session closed: {v}.");
+        }
+
+        if result.is_err() {
+            result = r;
+            vulnerability = a.vulnerability().unwrap();
+        }
+    }
+
+    // Compare the attempts side by side.
+    if let Some(v) = attempts.comparison() {
+        info!("Comparison of the attempts:");
+
+        for l in v.lines() {
+            info!("  {l}");
+        }
+    }
+
+    match result {
+        Ok(_) => info!("Result: KASLR of the PS4 has been defeated."),
+        Err(_) => info!("Result: {vulnerability}."),
+    }
+
+    // Exit with success if any attempt was succeeded otherwise the last failure.
+    if !graceful {
+        ExitCode::FAILURE
+    } else if let Err(e) = result {
+        ExitCode::from(e.exit_code())
+    } else {
+        ExitCode::SUCCESS
+    }
 }
@@ -0,0 +1,210 @@
+use crate::attempt::Attempts;
+use crate::console::ConsoleState;
+use crate::failure::Vulnerability;
+use crate::session::Sessions;
+use crate::socket::SocketStats;
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{Error, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Snapshot of the counters that are useful to graph the reliability over a long time.
+pub struct Metrics {
+    pub uptime: Duration,
+    pub console: ConsoleState,
+    /// Number of started attempts, including the active ones.
+    pub attempts: u64,
+    pub active: u64,
+    pub succeeded: u64,
+    /// Number of failed attempts where the console reacted to the corruption.
+    pub vulnerable: u64,
+    pub not_vulnerable: u64,
+    /// Number of failed attempts that did not reach the vulnerable code.
+    pub unknown: u64,
+    pub sessions: u64,
+    pub discovery: SocketStats,
+    pub session: SocketStats,
+}
+
+impl Metrics {
+    /// Collect the counters from `attempts` and `sessions`. The socket statistics are zero.
+    pub fn new(
+        uptime: Duration,
+        console: ConsoleState,
+        attempts: &Attempts,
+        sessions: &Sessions,
+    ) -> Self {
+        let history = attempts.history();
+        let active = attempts.actives().len() as u64;
+        let mut m = Self {
+            uptime,
+            console,
+            attempts: history.len() as u64 + active,
+            active,
+            succeeded: 0,
+            vulnerable: 0,
+            not_vulnerable: 0,
+            unknown: 0,
+            sessions: sessions.peers().len() as u64,
+            discovery: SocketStats::default(),
+            session: SocketStats::default(),
+        };
+
+        for a in history {
+            match (a.result(), a.vulnerability()) {
+                (Some(Ok(_)), _) => m.succeeded += 1,
+                (_, Some(Vulnerability::Vulnerable)) => m.vulnerable += 1,
+                (_, Some(Vulnerability::NotVulnerable)) => m.not_vulnerable += 1,
+                _ => m.unknown += 1,
+            }
+        }
+
+        m
+    }
+
+    /// Returns the name and value of each field except the console state.
+    fn fields(&self) -> [(&'static str, u64); 12] {
+        [
+            ("uptime", self.uptime.as_secs()),
+            ("attempts", self.attempts),
+            ("active", self.active),
+            ("succeeded", self.succeeded),
+            ("vulnerable", self.vulnerable),
+            ("not_vulnerable", self.not_vulnerable),
+            ("unknown", self.unknown),
+            ("sessions", self.sessions),
+            ("discovery_received", self.discovery.received),
+            ("discovery_dropped", self.discovery.dropped),
+            ("session_received", self.session.received),
+            ("session_dropped", self.session.dropped),
+        ]
+    }
+}
+
+/// Format of [`MetricsFile`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MetricsFormat {
+    /// InfluxDB line protocol, which can be imported by Telegraf or InfluxDB as-is.
+    Line,
+    /// CSV with a header, which can be imported by a spreadsheet or the CSV data source of Grafana.
+    Csv,
+}
+
+impl MetricsFormat {
+    /// Returns [`MetricsFormat::Csv`] if `path` has `.csv` extension or
+    /// [`MetricsFormat::Line`] otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(v) if v.eq_ignore_ascii_case("csv") => Self::Csv,
+            _ => Self::Line,
+        }
+    }
+}
+
+/// File to append [`Metrics`] to.
+///
+/// The file is never truncated so the snapshots from all runs end up in the same file.
+pub struct MetricsFile {
+    file: File,
+    format: MetricsFormat,
+    header: bool,
+}
+
+impl MetricsFile {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let header = file.metadata()?.len() == 0;
+
+        Ok(Self {
+            file,
+            format: MetricsFormat::from_path(path),
+            header,
+        })
+    }
+
+    /// Append `metrics` that was collected at `time`.
+    pub fn write(&mut self, time: SystemTime, metrics: &Metrics) -> Result<(), Error> {
+        let mut out = String::new();
+
+        if self.format == MetricsFormat::Csv && std::mem::take(&mut self.header) {
+            out.push_str("time,console");
+
+            for (k, _) in metrics.fields() {
+                write!(out, ",{k}").unwrap();
+            }
+
+            out.push('\n');
+        }
+
+        format(&mut out, self.format, time, metrics);
+
+        self.file.write_all(out.as_bytes())
+    }
+}
+
+/// Write a single snapshot as a line.
+fn format(out: &mut String, format: MetricsFormat, time: SystemTime, metrics: &Metrics) {
+    let time = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+    match format {
+        MetricsFormat::Line => {
+            write!(out, "jailbreak console=\"{}\"", metrics.console.id()).unwrap();
+
+            for (k, v) in metrics.fields() {
+                write!(out, ",{k}={v}i").unwrap();
+            }
+
+            writeln!(out, " {}", time.as_nanos()).unwrap();
+        }
+        MetricsFormat::Csv => {
+            write!(out, "{},{}", time.as_secs(), metrics.console.id()).unwrap();
+
+            for (_, v) in metrics.fields() {
+                write!(out, ",{v}").unwrap();
+            }
+
+            out.push('\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats() {
+        let attempts = Attempts::default();
+        let sessions = Sessions::new(false);
+        let mut m = Metrics::new(
+            Duration::from_secs(90),
+            ConsoleState::Idle,
+            &attempts,
+            &sessions,
+        );
+        let time = UNIX_EPOCH + Duration::from_secs(1000);
+
+        m.discovery.received = 5;
+
+        let mut out = String::new();
+
+        format(&mut out, MetricsFormat::Line, time, &m);
+
+        assert_eq!(
+            out,
+            concat!(
+                "jailbreak console=\"IDLE\",uptime=90i,attempts=0i,active=0i,succeeded=0i,",
+                "vulnerable=0i,not_vulnerable=0i,unknown=0i,sessions=0i,discovery_received=5i,",
+                "discovery_dropped=0i,session_received=0i,session_dropped=0i 1000000000000\n"
+            )
+        );
+
+        out.clear();
+        format(&mut out, MetricsFormat::Csv, time, &m);
+
+        assert_eq!(out, "1000,IDLE,90,0,0,0,0,0,0,0,5,0,0,0\n");
+        assert!(MetricsFormat::from_path(Path::new("a.CSV")) == MetricsFormat::Csv);
+        assert!(MetricsFormat::from_path(Path::new("a.txt")) == MetricsFormat::Line);
+    }
+}
@@ -0,0 +1,272 @@
+use crate::log::{info, warning, Direction};
+use crate::pcap::{ethernet_frame, PcapWriter};
+use crate::proto::pppoe::ETHERTYPE_DISCOVERY;
+use erdp::ErrorDisplay;
+use libc::{c_int, sendto, sockaddr_ll, socket, AF_PACKET, SOCK_RAW};
+use macaddr::MacAddr6;
+use std::fmt::Write;
+use std::io::Error;
+use std::mem::{size_of_val, zeroed};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Maximum number of frames that are waiting to be mirrored.
+const QUEUE: usize = 4096;
+
+/// Maximum duration to block on a slow TCP client before it is disconnected.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Where [`Mirror`] copy the frames to.
+#[derive(Clone, Copy)]
+pub enum MirrorTarget {
+    /// Index of the interface to send the frames out of.
+    Interface(c_int),
+    /// Address to accept the TCP clients that will receive the frames in pcapng format.
+    Listen(SocketAddr),
+}
+
+impl FromStr for MirrorTarget {
+    type Err = String;
+
+    /// Parse either `ADDR:PORT` or an interface index.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(v) = s.parse() {
+            Ok(Self::Listen(v))
+        } else if let Ok(v) = s.parse() {
+            Ok(Self::Interface(v))
+        } else {
+            Err(format!("expect ADDR:PORT or an interface index, got '{s}'"))
+        }
+    }
+}
+
+/// Copy the frames after PPPoE Discovery to [`MirrorTarget`] in real time.
+///
+/// The frames are handed off to a dedicated thread so a slow target never delays the
+/// time-critical packets. The frames that does not fit in the queue are dropped instead.
+pub struct Mirror {
+    mac: MacAddr6,
+    target: MirrorTarget,
+    tx: SyncSender<(Duration, Direction, Vec<u8>)>,
+    mirrored: Arc<AtomicU64>,
+    dropped: AtomicU64,
+}
+
+impl Mirror {
+    /// `mac` is the address of the interface for the frames to be mirrored. The actual address is
+    /// used for [`MirrorTarget::Listen`] with port 0.
+    pub fn new(target: MirrorTarget, mac: MacAddr6) -> Result<Self, Error> {
+        let (tx, rx) = sync_channel(QUEUE);
+        let mirrored = Arc::new(AtomicU64::new(0));
+        let target = match target {
+            MirrorTarget::Interface(i) => {
+                let sock = raw_socket()?;
+                let mirrored = mirrored.clone();
+
+                std::thread::spawn(move || send_frames(sock, i, rx, mirrored));
+
+                target
+            }
+            MirrorTarget::Listen(addr) => {
+                let listener = TcpListener::bind(addr)?;
+                let addr = listener.local_addr()?;
+                let clients = Arc::new(Mutex::new(Vec::new()));
+                let mirrored = mirrored.clone();
+
+                std::thread::spawn({
+                    let clients = clients.clone();
+
+                    move || accept_clients(listener, clients)
+                });
+
+                std::thread::spawn(move || write_frames(clients, rx, mirrored));
+
+                MirrorTarget::Listen(addr)
+            }
+        };
+
+        Ok(Self {
+            mac,
+            target,
+            tx,
+            mirrored,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    pub fn target(&self) -> MirrorTarget {
+        self.target
+    }
+
+    /// Mirror a frame without Ethernet header. PPPoE Discovery is skipped.
+    pub fn record(&self, dir: Direction, addr: &sockaddr_ll, data: &[u8]) {
+        if u16::from_be(addr.sll_protocol) == ETHERTYPE_DISCOVERY {
+            return;
+        }
+
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let frame = ethernet_frame(self.mac, dir, addr, data);
+
+        if self.tx.try_send((time, dir, frame)).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Write the state of the mirror to `out`.
+    pub fn dump(&self, out: &mut String) {
+        writeln!(out, "Mirror:").unwrap();
+
+        match self.target {
+            MirrorTarget::Interface(i) => writeln!(out, "  Interface: {i}").unwrap(),
+            MirrorTarget::Listen(a) => writeln!(out, "  Listening: {a}").unwrap(),
+        }
+
+        writeln!(out, "  Mirrored: {}", self.mirrored.load(Ordering::Relaxed)).unwrap();
+        writeln!(out, "  Dropped: {}", self.dropped.load(Ordering::Relaxed)).unwrap();
+    }
+}
+
+fn raw_socket() -> Result<OwnedFd, Error> {
+    // Protocol 0 receive nothing, which is what we want.
+    let s = unsafe { socket(AF_PACKET, SOCK_RAW, 0) };
+
+    if s < 0 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(unsafe { OwnedFd::from_raw_fd(s) })
+    }
+}
+
+/// Send the frames from `rx` out of `interface` as-is until [`Mirror`] is dropped.
+fn send_frames(
+    sock: OwnedFd,
+    interface: c_int,
+    rx: Receiver<(Duration, Direction, Vec<u8>)>,
+    mirrored: Arc<AtomicU64>,
+) {
+    let mut addr: sockaddr_ll = unsafe { zeroed() };
+
+    addr.sll_family = AF_PACKET as _;
+    addr.sll_ifindex = interface;
+    addr.sll_halen = 6;
+
+    for (_, _, frame) in rx {
+        addr.sll_addr[..6].copy_from_slice(&frame[..6]);
+
+        // The frame that is larger than the MTU of the interface can't be mirrored.
+        let sent = unsafe {
+            sendto(
+                sock.as_raw_fd(),
+                frame.as_ptr().cast(),
+                frame.len(),
+                0,
+                &addr as *const sockaddr_ll as _,
+                size_of_val(&addr).try_into().unwrap(),
+            )
+        };
+
+        if sent >= 0 {
+            mirrored.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Accept the TCP clients on `listener` and start a pcapng stream on each of them.
+fn accept_clients(listener: TcpListener, clients: Arc<Mutex<Vec<PcapWriter<TcpStream>>>>) {
+    for client in listener.incoming() {
+        let client = match client {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+
+        let peer = client
+            .peer_addr()
+            .map(|v| v.to_string())
+            .unwrap_or_default();
+
+        // Push the client while holding the lock so it does not miss any frame after the header.
+        let mut clients = clients.lock().unwrap();
+        let writer = client
+            .set_write_timeout(Some(WRITE_TIMEOUT))
+            .and_then(|_| client.set_nodelay(true))
+            .and_then(|_| PcapWriter::new(client));
+
+        match writer {
+            Ok(v) => {
+                info!("Mirroring the session frames to {peer}.");
+                clients.push(v);
+            }
+            Err(e) => warning!("Failed to start the mirror for {}: {}.", peer, e.display()),
+        }
+    }
+}
+
+/// Write the frames from `rx` to all `clients` until [`Mirror`] is dropped.
+fn write_frames(
+    clients: Arc<Mutex<Vec<PcapWriter<TcpStream>>>>,
+    rx: Receiver<(Duration, Direction, Vec<u8>)>,
+    mirrored: Arc<AtomicU64>,
+) {
+    for (time, dir, frame) in rx {
+        let mut clients = clients.lock().unwrap();
+
+        // Disconnect the client that has gone or can't keep up.
+        clients.retain_mut(|c| c.write(time, dir, &frame).is_ok());
+
+        if !clients.is_empty() {
+            mirrored.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pcap::parse;
+    use std::io::Read;
+
+    #[test]
+    fn listen() {
+        let ours = MacAddr6::new(2, 0, 0, 0, 0, 1);
+        let mirror = Mirror::new("127.0.0.1:0".parse().unwrap(), ours).unwrap();
+        let addr = match mirror.target() {
+            MirrorTarget::Listen(v) => v,
+            MirrorTarget::Interface(_) => unreachable!(),
+        };
+
+        // Wait for the header so the client is ready.
+        let mut client = TcpStream::connect(addr).unwrap();
+        let mut data = vec![0; 60];
+
+        client.read_exact(&mut data).unwrap();
+
+        // Send a discovery frame and a session frame.
+        let frame = |proto: u16| {
+            let mut addr: sockaddr_ll = unsafe { zeroed() };
+
+            addr.sll_protocol = proto.to_be();
+            addr.sll_addr[..6].copy_from_slice(&[2, 0, 0, 0, 0, 2]);
+            addr
+        };
+
+        mirror.record(Direction::Received, &frame(0x8863), &[1]);
+        mirror.record(Direction::Sent, &frame(0x8864), &[2]);
+
+        // Read the Enhanced Packet Block of the session frame.
+        data.resize(60 + 60, 0);
+        client.read_exact(&mut data[60..]).unwrap();
+
+        let frames = parse(&data).unwrap();
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].data[..12], [2, 0, 0, 0, 0, 2, 2, 0, 0, 0, 0, 1]);
+        assert_eq!(frames[0].data[12..], [0x88, 0x64, 2]);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::log::error;
+use erdp::ErrorDisplay;
+use std::fs::{read_to_string, write};
+use std::io::Error;
+use std::net::Ipv4Addr;
+use std::process::Command;
+
+const IP_FORWARD: &str = "/proc/sys/net/ipv4/ip_forward";
+
+/// Masquerade the IPv4 traffic of the bridged sessions out of an interface.
+///
+/// The rules are added with `iptables` and removed when this is dropped. IPv4 forwarding is also
+/// enabled until then.
+pub struct Nat {
+    rules: Vec<Rule>,
+    forward: Option<String>,
+}
+
+impl Nat {
+    /// Masquerade the traffic from `first` to `last` on the devices that start with `prefix` out of
+    /// `out`.
+    pub fn setup(first: Ipv4Addr, last: Ipv4Addr, prefix: &str, out: &str) -> Result<Self, Error> {
+        let mut nat = Self {
+            rules: Vec::new(),
+            forward: None,
+        };
+
+        // Enable forwarding.
+        let forward = read_to_string(IP_FORWARD)?;
+
+        if forward.trim() != "1" {
+            write(IP_FORWARD, "1")?;
+            nat.forward = Some(forward);
+        }
+
+        // Add the rules. They are inserted at the top of the chains since the policy of FORWARD may
+        // be DROP (e.g. on a host with Docker).
+        let tun = format!("{prefix}+");
+        let source = format!("{first}-{last}");
+        let rules = [
+            Rule::new(
+                "nat",
+                "POSTROUTING",
+                [
+                    "-m",
+                    "iprange",
+                    "--src-range",
+                    &source,
+                    "-o",
+                    out,
+                    "-j",
+                    "MASQUERADE",
+                ],
+            ),
+            Rule::new("filter", "FORWARD", ["-i", &tun, "-o", out, "-j", "ACCEPT"]),
+            Rule::new(
+                "filter",
+                "FORWARD",
+                [
+                    "-i",
+                    out,
+                    "-o",
+                    &tun,
+                    "-m",
+                    "conntrack",
+                    "--ctstate",
+                    "RELATED,ESTABLISHED",
+                    "-j",
+                    "ACCEPT",
+                ],
+            ),
+        ];
+
+        for rule in rules {
+            rule.run("-I")?;
+            nat.rules.push(rule);
+        }
+
+        Ok(nat)
+    }
+}
+
+impl Drop for Nat {
+    fn drop(&mut self) {
+        for rule in self.rules.iter().rev() {
+            if let Err(e) = rule.run("-D") {
+                error!("Failed to remove NAT rule: {}.", e.display());
+            }
+        }
+
+        if let Some(v) = &self.forward {
+            if let Err(e) = write(IP_FORWARD, v) {
+                error!("Failed to restore {}: {}.", IP_FORWARD, e.display());
+            }
+        }
+    }
+}
+
+/// An `iptables` rule.
+struct Rule {
+    table: &'static str,
+    chain: &'static str,
+    spec: Vec<String>,
+}
+
+impl Rule {
+    fn new<'a>(
+        table: &'static str,
+        chain: &'static str,
+        spec: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        Self {
+            table,
+            chain,
+            spec: spec.into_iter().map(|v| v.to_owned()).collect(),
+        }
+    }
+
+    /// Run `iptables` with `command` (e.g. `-I`) on this rule.
+    fn run(&self, command: &str) -> Result<(), Error> {
+        let output = Command::new("iptables")
+            .args(["-t", self.table, command, self.chain])
+            .args(&self.spec)
+            .output()?;
+
+        if !output.status.success() {
+            let msg = String::from_utf8_lossy(&output.stderr);
+
+            return Err(Error::other(format!(
+                "iptables exited with {} ({})",
+                output.status,
+                msg.trim()
+            )));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+use crate::log::error;
+use erdp::ErrorDisplay;
+use std::ffi::c_int;
+use std::fmt::{Display, Formatter};
+use std::fs::read_to_string;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Directory of the runtime configurations of systemd-networkd.
+const NETWORKD_RUNTIME: &str = "/run/systemd/network";
+
+/// Network manager that can configure an interface on its own (e.g. run a DHCP client on it).
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Manager {
+    NetworkManager,
+    Networkd,
+}
+
+impl Display for Manager {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NetworkManager => f.write_str("NetworkManager"),
+            Self::Networkd => f.write_str("systemd-networkd"),
+        }
+    }
+}
+
+/// Returns the network managers that manage interface `index` named `name`.
+///
+/// The manager that is not installed or running is skipped.
+pub fn find_managers(index: c_int, name: &str) -> Result<Vec<Manager>, Error> {
+    let mut managers = Vec::new();
+
+    // Check NetworkManager.
+    match run("nmcli", ["-t", "-f", "DEVICE,STATE", "device", "status"]) {
+        Ok(v) if nm_managed(&v, name) => managers.push(Manager::NetworkManager),
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        // nmcli fails when NetworkManager is not running.
+        Err(e) if e.kind() == ErrorKind::Other => {}
+        Err(e) => return Err(e),
+    }
+
+    // Check systemd-networkd. The state file only exists when it is running.
+    match read_to_string(format!("/run/systemd/netif/links/{index}")) {
+        Ok(v) if networkd_managed(&v) => managers.push(Manager::Networkd),
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    Ok(managers)
+}
+
+/// Stop the network managers from managing an interface until this is dropped.
+///
+/// NetworkManager is told to stop managing it with `nmcli`. systemd-networkd does not have a
+/// command for this so a runtime configuration that match the interface is written instead.
+pub struct Unmanaged {
+    name: String,
+    managers: Vec<Manager>,
+}
+
+impl Unmanaged {
+    pub fn new(name: &str, managers: &[Manager]) -> Result<Self, Error> {
+        let mut unmanaged = Self {
+            name: name.to_owned(),
+            managers: Vec::new(),
+        };
+
+        for &m in managers {
+            match m {
+                Manager::NetworkManager => {
+                    run("nmcli", ["device", "set", name, "managed", "no"])?;
+                    unmanaged.managers.push(m);
+                }
+                Manager::Networkd => {
+                    let data = format!(
+                        "# Written by jailbreak-11 and removed when it exits.\n\
+                        [Match]\nName={name}\n\n[Link]\nUnmanaged=yes\n"
+                    );
+
+                    std::fs::create_dir_all(NETWORKD_RUNTIME)?;
+                    std::fs::write(unmanaged.networkd_config(), data)?;
+
+                    // Push it before reloading so the file is removed even if this fails.
+                    unmanaged.managers.push(m);
+                    run("networkctl", ["reload"])?;
+                }
+            }
+        }
+
+        Ok(unmanaged)
+    }
+
+    fn networkd_config(&self) -> PathBuf {
+        PathBuf::from(format!(
+            "{}/00-jailbreak-11-{}.network",
+            NETWORKD_RUNTIME, self.name
+        ))
+    }
+}
+
+impl Drop for Unmanaged {
+    fn drop(&mut self) {
+        for &m in self.managers.iter().rev() {
+            let r = match m {
+                Manager::NetworkManager => {
+                    run("nmcli", ["device", "set", &self.name, "managed", "yes"]).map(|_| ())
+                }
+                Manager::Networkd => std::fs::remove_file(self.networkd_config())
+                    .and_then(|_| run("networkctl", ["reload"]))
+                    .and_then(|_| run("networkctl", ["reconfigure", &self.name]))
+                    .map(|_| ()),
+            };
+
+            if let Err(e) = r {
+                error!(
+                    "Failed to give interface {} back to {}: {}.",
+                    self.name,
+                    m,
+                    e.display()
+                );
+            }
+        }
+    }
+}
+
+/// Returns `true` if `name` is not unmanaged in the output of
+/// `nmcli -t -f DEVICE,STATE device status`.
+fn nm_managed(output: &str, name: &str) -> bool {
+    output.lines().any(|l| match l.rsplit_once(':') {
+        Some((d, s)) => d == name && s != "unmanaged",
+        None => false,
+    })
+}
+
+/// Returns `true` if the link state file of systemd-networkd says it is configuring the link.
+fn networkd_managed(state: &str) -> bool {
+    state.lines().any(|l| match l.strip_prefix("ADMIN_STATE=") {
+        Some(v) => !matches!(v, "unmanaged" | "linger"),
+        None => false,
+    })
+}
+
+/// Run `program` and returns its stdout. The failure is reported as [`ErrorKind::Other`].
+fn run<'a>(program: &str, args: impl IntoIterator<Item = &'a str>) -> Result<String, Error> {
+    let output = Command::new(program).args(args).output()?;
+
+    if !output.status.success() {
+        let msg = String::from_utf8_lossy(&output.stderr);
+
+        return Err(Error::other(format!(
+            "{} exited with {} ({})",
+            program,
+            output.status,
+            msg.trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn managed() {
+        let nm = "eth0:connected\nenp3s0:unmanaged\nwlan0:disconnected\nlo:unmanaged\n";
+
+        assert!(nm_managed(nm, "eth0"));
+        assert!(nm_managed(nm, "wlan0"));
+        assert!(!nm_managed(nm, "enp3s0"));
+        assert!(!nm_managed(nm, "eth1"));
+        assert!(networkd_managed(
+            "# This is private data. Do not parse.\nADMIN_STATE=configured\nOPER_STATE=routable\n"
+        ));
+        assert!(!networkd_managed("ADMIN_STATE=unmanaged\n"));
+    }
+}
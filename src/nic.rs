@@ -0,0 +1,89 @@
+use crate::addr::interface_name;
+use std::ffi::c_int;
+use std::fmt::{Display, Formatter};
+use std::fs::read_link;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// Drivers that are known to reorder, batch or drop frames in a way that break the exploit.
+///
+/// Each entry is the name of kernel driver and the reason. Please open a pull request if you found
+/// another one.
+const KNOWN_BAD: [(&str, &str); 4] = [
+    (
+        "ax88179_178a",
+        "ASIX AX88179 adapters are reported to drop bursts of small frames",
+    ),
+    (
+        "asix",
+        "older ASIX USB 2.0 adapters are reported to drop frames under load",
+    ),
+    (
+        "cdc_ncm",
+        "CDC NCM batches multiple frames into a single USB transfer, which delays them",
+    ),
+    (
+        "cdc_ether",
+        "generic CDC Ethernet adapters are reported to drop frames under load",
+    ),
+];
+
+/// Known problem with the network adapter of an interface.
+pub enum NicProblem {
+    /// The interface is wireless.
+    Wireless,
+    /// The driver is in `KNOWN_BAD`.
+    KnownBad {
+        driver: String,
+        reason: &'static str,
+    },
+}
+
+impl NicProblem {
+    /// Returns what the user should do.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Self::Wireless => "Connect the PS4 to an Ethernet port directly.",
+            Self::KnownBad { .. } => {
+                "Use an onboard Ethernet port or a USB adapter with another chipset if the attempts \
+                keep failing."
+            }
+        }
+    }
+}
+
+impl Display for NicProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Wireless => f.write_str("the interface is wireless"),
+            Self::KnownBad { driver, reason } => write!(f, "driver {driver} is in use ({reason})"),
+        }
+    }
+}
+
+/// Check the network adapter of `interface` against the adapters with known problems.
+pub fn check_nic(interface: c_int) -> Result<Option<NicProblem>, Error> {
+    let name = interface_name(interface)?;
+    let dir = Path::new("/sys/class/net").join(name);
+
+    if dir.join("wireless").exists() {
+        return Ok(Some(NicProblem::Wireless));
+    }
+
+    // Virtual interfaces do not have a device.
+    let driver = match read_link(dir.join("device").join("driver")) {
+        Ok(v) => v,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let driver = match driver.file_name() {
+        Some(v) => v.to_string_lossy().into_owned(),
+        None => return Ok(None),
+    };
+
+    Ok(KNOWN_BAD
+        .iter()
+        .find(|(d, _)| *d == driver)
+        .map(|&(_, reason)| NicProblem::KnownBad { driver, reason }))
+}
@@ -0,0 +1,277 @@
+use crate::conflict::competing_ac;
+use crate::log::{error, warning};
+use crate::proto::pppoe::{Code, EtherTypes, EthernetPayload, Tag, Tags};
+use crate::socket::PacketSocket;
+use erdp::ErrorDisplay;
+use libc::{
+    c_int, sock_filter, sockaddr_ll, BPF_ABS, BPF_H, BPF_JEQ, BPF_JMP, BPF_K, BPF_LD, BPF_RET,
+    PACKET_OUTGOING, SKF_AD_OFF, SKF_AD_PROTOCOL,
+};
+use macaddr::MacAddr6;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::io::Error;
+use tokio::select;
+use tokio_util::sync::CancellationToken;
+
+/// Decode and print all PPPoE frames on the interface without transmitting anything.
+///
+/// The socket must be bound with `ETH_P_ALL` since the sockets that bound to a specific protocol
+/// will not receive the outgoing frames.
+pub struct Analyzer {
+    sock: PacketSocket,
+    ethertypes: EtherTypes,
+}
+
+impl Analyzer {
+    /// Bind `sock` to `addr`. The socket must not be bound yet.
+    pub fn new(
+        sock: PacketSocket,
+        addr: sockaddr_ll,
+        ethertypes: EtherTypes,
+    ) -> Result<Self, Error> {
+        // Accept only PPPoE frames.
+        let prog = [
+            filter(
+                BPF_LD | BPF_H | BPF_ABS,
+                (SKF_AD_OFF + SKF_AD_PROTOCOL) as u32,
+                0,
+                0,
+            ),
+            filter(BPF_JMP | BPF_JEQ | BPF_K, ethertypes.discovery.into(), 1, 0),
+            filter(BPF_JMP | BPF_JEQ | BPF_K, ethertypes.session.into(), 0, 1),
+            filter(BPF_RET | BPF_K, u32::MAX, 0, 0),
+            filter(BPF_RET | BPF_K, 0, 0, 0),
+        ];
+
+        sock.attach_filter(&prog)?;
+        sock.bind(addr)?;
+
+        Ok(Self { sock, ethertypes })
+    }
+
+    /// Receive the frames between the other hosts.
+    pub fn enable_promiscuous(&self, interface: c_int) -> Result<(), Error> {
+        self.sock.enable_promiscuous(interface)
+    }
+
+    pub async fn run(&self, running: CancellationToken) {
+        let mut buf = [0; 1500];
+        let mut competitors = HashSet::new();
+
+        loop {
+            let (len, addr) = select! {
+                _ = running.cancelled() => break,
+                v = self.sock.recv(&mut buf) => match v {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!("Failed to receive a PPPoE frame: {}.", e.display());
+                        break;
+                    }
+                }
+            };
+
+            println!("{}", decode(&addr, &buf[..len], self.ethertypes));
+
+            // Check if an access concentrator is answering on the segment.
+            if addr.sll_pkttype == PACKET_OUTGOING
+                || u16::from_be(addr.sll_protocol) != self.ethertypes.discovery
+            {
+                continue;
+            }
+
+            let mac = MacAddr6::from(TryInto::<[u8; 6]>::try_into(&addr.sll_addr[..6]).unwrap());
+            let ac = match EthernetPayload::<Tags>::deserialize(&buf[..len]) {
+                Some(v) => competing_ac(mac, &v),
+                None => None,
+            };
+
+            if let Some(c) = ac.filter(|_| competitors.insert(mac)) {
+                warning!("WARNING: found {c} on the segment. {}", c.hint());
+            }
+        }
+
+        running.cancel();
+    }
+
+    pub fn decode_discovery(addr: &sockaddr_ll, data: &[u8]) -> String {
+        let mut line = Self::header(addr);
+        let data = match EthernetPayload::<Tags>::deserialize(data) {
+            Some(v) => v,
+            None => {
+                line.push_str(" invalid PPPoE discovery frame");
+                return line;
+            }
+        };
+
+        // Write CODE and SESSION_ID.
+        let code = discovery_code_name(data.code());
+
+        write!(
+            line,
+            " {} ({:#04x}) SESSION_ID = {}",
+            code,
+            data.code(),
+            data.session_id()
+        )
+        .unwrap();
+
+        // Write tags.
+        for (t, v) in data.payload() {
+            match tag_name(*t) {
+                Some(n) => write!(line, ", {n} = ").unwrap(),
+                None => write!(line, ", {t:#06x} = ").unwrap(),
+            }
+
+            write_value(&mut line, v);
+        }
+
+        line
+    }
+
+    pub fn decode_session(addr: &sockaddr_ll, data: &[u8]) -> String {
+        let mut line = Self::header(addr);
+        let data = match EthernetPayload::<Cow<[u8]>>::deserialize(data) {
+            Some(v) if v.code() == 0 => v,
+            _ => {
+                line.push_str(" invalid PPPoE session frame");
+                return line;
+            }
+        };
+
+        write!(line, " SESSION_ID = {}", data.session_id()).unwrap();
+
+        // Write PPP protocol.
+        let ppp = data.payload();
+        let proto = match ppp.get(..2) {
+            Some(v) => u16::from_be_bytes(v.try_into().unwrap()),
+            None => {
+                line.push_str(", truncated PPP frame");
+                return line;
+            }
+        };
+
+        let name = protocol_name(proto);
+
+        write!(line, ", {} ({:#06x})", name, proto).unwrap();
+
+        // Write code of the control protocols.
+        if proto & 0x8000 == 0 {
+            write!(line, ", {} bytes", ppp.len() - 2).unwrap();
+            return line;
+        }
+
+        match ppp.get(2..4) {
+            Some(&[code, id]) => {
+                let name = control_code_name(proto, code);
+
+                write!(line, " {name} ({code}), Identifier = {id}").unwrap();
+            }
+            _ => line.push_str(", truncated control packet"),
+        }
+
+        line
+    }
+
+    fn header(addr: &sockaddr_ll) -> String {
+        let mac = MacAddr6::from(TryInto::<[u8; 6]>::try_into(&addr.sll_addr[..6]).unwrap());
+        let dir = match addr.sll_pkttype {
+            0 => "to us",
+            1 => "broadcast",
+            2 => "multicast",
+            3 => "to other host",
+            4 => "outgoing",
+            _ => "unknown",
+        };
+
+        format!("{mac} ({dir}):")
+    }
+}
+
+/// Decode a PPPoE frame without Ethernet header into a single line. `addr` is the address of the
+/// peer, which is the source of a received frame or the destination of an outgoing frame.
+pub fn decode(addr: &sockaddr_ll, data: &[u8], ethertypes: EtherTypes) -> String {
+    let proto = u16::from_be(addr.sll_protocol);
+
+    if proto == ethertypes.discovery {
+        Analyzer::decode_discovery(addr, data)
+    } else if proto == ethertypes.session {
+        Analyzer::decode_session(addr, data)
+    } else {
+        format!("{} not a PPPoE frame", Analyzer::header(addr))
+    }
+}
+
+/// Write `v` as a quoted string if it is printable or hex otherwise.
+pub fn write_value(line: &mut String, v: &[u8]) {
+    if v.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        write!(line, "'{}'", std::str::from_utf8(v).unwrap()).unwrap();
+    } else {
+        for b in v {
+            write!(line, "{b:02x}").unwrap();
+        }
+    }
+}
+
+/// Returns the name of CODE in a PPPoE discovery frame.
+pub fn discovery_code_name(code: u8) -> &'static str {
+    match Code::try_from(code) {
+        Ok(Code::Session) | Err(_) => "unknown",
+        Ok(v) => v.name(),
+    }
+}
+
+/// Returns the name of a PPPoE tag or [`None`] if unknown.
+pub fn tag_name(tag: u16) -> Option<&'static str> {
+    Tag::try_from(tag).ok().map(Tag::name)
+}
+
+/// Returns the name of a PPP protocol.
+pub fn protocol_name(proto: u16) -> &'static str {
+    match proto {
+        0x0021 => "IPv4",
+        0x0057 => "IPv6",
+        0x8021 => "IPCP",
+        0x8057 => "IPV6CP",
+        0xc021 => "LCP",
+        0xc023 => "PAP",
+        0xc223 => "CHAP",
+        _ => "unknown",
+    }
+}
+
+/// Returns the name of CODE in a packet of PPP control protocol `proto`.
+pub fn control_code_name(proto: u16, code: u8) -> &'static str {
+    match (proto, code) {
+        (0xc023, 1) => "Authenticate-Request",
+        (0xc023, 2) => "Authenticate-Ack",
+        (0xc023, 3) => "Authenticate-Nak",
+        (0xc223, 1) => "Challenge",
+        (0xc223, 2) => "Response",
+        (0xc223, 3) => "Success",
+        (0xc223, 4) => "Failure",
+        (0xc023 | 0xc223, _) => "unknown",
+        (_, 1) => "Configure-Request",
+        (_, 2) => "Configure-Ack",
+        (_, 3) => "Configure-Nak",
+        (_, 4) => "Configure-Reject",
+        (_, 5) => "Terminate-Request",
+        (_, 6) => "Terminate-Ack",
+        (_, 7) => "Code-Reject",
+        (0xc021, 8) => "Protocol-Reject",
+        (0xc021, 9) => "Echo-Request",
+        (0xc021, 10) => "Echo-Reply",
+        (0xc021, 11) => "Discard-Request",
+        _ => "unknown",
+    }
+}
+
+fn filter(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter {
+        code: code.try_into().unwrap(),
+        jt,
+        jf,
+        k,
+    }
+}
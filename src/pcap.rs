@@ -0,0 +1,539 @@
+use crate::log::{warning, Direction};
+use erdp::ErrorDisplay;
+use libc::{sockaddr_ll, PACKET_BROADCAST, PACKET_MULTICAST};
+use macaddr::MacAddr6;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+const LINKTYPE_ETHERNET: u16 = 1;
+
+/// Number of the most recent frames to keep for [`Capture::trim()`].
+const RECENT_FRAMES: usize = 100000;
+
+/// Record all frames on the packet sockets to a pcapng file.
+///
+/// The packet sockets does not see the Ethernet header so it is synthesized from `sockaddr_ll`
+/// and the address of our interface.
+pub struct Capture {
+    mac: MacAddr6,
+    writer: Mutex<Option<PcapWriter<File>>>,
+    recent: Mutex<VecDeque<Recorded>>,
+}
+
+impl Capture {
+    /// `mac` is the address of the interface for the frames to be recorded.
+    pub fn create(path: impl AsRef<Path>, mac: MacAddr6) -> Result<Self, std::io::Error> {
+        let file = File::create(path)?;
+
+        Ok(Self {
+            mac,
+            writer: Mutex::new(Some(PcapWriter::new(file)?)),
+            recent: Mutex::default(),
+        })
+    }
+
+    /// Same as [`Capture::create()`] but only keep the most recent frames for
+    /// [`Capture::trim()`].
+    pub fn memory(mac: MacAddr6) -> Self {
+        Self {
+            mac,
+            writer: Mutex::default(),
+            recent: Mutex::default(),
+        }
+    }
+
+    /// Record a frame without Ethernet header.
+    pub fn record(&self, dir: Direction, addr: &sockaddr_ll, data: &[u8]) {
+        let instant = Instant::now();
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let frame = ethernet_frame(self.mac, dir, addr, data);
+
+        // Write.
+        if let Some(Err(e)) = self
+            .writer
+            .lock()
+            .unwrap()
+            .as_mut()
+            .map(|w| w.write(time, dir, &frame))
+        {
+            warning!("Failed to write a frame to the capture: {}.", e.display());
+        }
+
+        // Keep it for trimming.
+        let mut recent = self.recent.lock().unwrap();
+
+        if recent.len() == RECENT_FRAMES {
+            recent.pop_front();
+        }
+
+        recent.push_back(Recorded {
+            instant,
+            time,
+            dir,
+            frame,
+        });
+    }
+
+    /// Write the frames from or to `peer` that was recorded between `from` and `to` to `w` in
+    /// pcapng format. Only the most recent frames are kept so the older ones will be missing.
+    ///
+    /// Returns the number of frames that was written.
+    pub fn trim(
+        &self,
+        w: impl Write,
+        peer: MacAddr6,
+        from: Instant,
+        to: Instant,
+    ) -> Result<usize, std::io::Error> {
+        let mut w = PcapWriter::new(w)?;
+        let mut n = 0;
+
+        for r in self.recent.lock().unwrap().iter() {
+            let on_peer = r.frame[..6] == *peer.as_bytes() || r.frame[6..12] == *peer.as_bytes();
+
+            if r.instant < from || r.instant > to || !on_peer {
+                continue;
+            }
+
+            w.write(r.time, r.dir, &r.frame)?;
+            n += 1;
+        }
+
+        Ok(n)
+    }
+}
+
+/// Returns a frame that was sent or received on a packet socket of the interface with `mac` with
+/// the Ethernet header synthesized from `addr`.
+pub(crate) fn ethernet_frame(
+    mac: MacAddr6,
+    dir: Direction,
+    addr: &sockaddr_ll,
+    data: &[u8],
+) -> Vec<u8> {
+    let peer = &addr.sll_addr[..6];
+    let mut frame = Vec::with_capacity(14 + data.len());
+
+    match dir {
+        Direction::Received => {
+            match addr.sll_pkttype {
+                PACKET_BROADCAST => frame.extend_from_slice(&[0xff; 6]),
+                // The group address is lost so use the one for all hosts.
+                PACKET_MULTICAST => frame.extend_from_slice(&[0x01, 0x00, 0x5e, 0, 0, 1]),
+                _ => frame.extend_from_slice(mac.as_bytes()),
+            }
+
+            frame.extend_from_slice(peer);
+        }
+        Direction::Sent => {
+            frame.extend_from_slice(peer);
+            frame.extend_from_slice(mac.as_bytes());
+        }
+    }
+
+    frame.extend_from_slice(&u16::from_be(addr.sll_protocol).to_be_bytes());
+    frame.extend_from_slice(data);
+    frame
+}
+
+/// A frame in [`Capture`] with Ethernet header.
+struct Recorded {
+    instant: Instant,
+    time: Duration,
+    dir: Direction,
+    frame: Vec<u8>,
+}
+
+/// Write Ethernet frames in pcapng format with nanosecond timestamps.
+pub struct PcapWriter<W> {
+    w: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Write Section Header Block and Interface Description Block to `w`.
+    pub fn new(mut w: W) -> Result<Self, std::io::Error> {
+        let mut data = Vec::new();
+
+        // Section Header Block with an unknown section length.
+        data.extend_from_slice(&0x0a0d0d0au32.to_le_bytes());
+        data.extend_from_slice(&28u32.to_le_bytes());
+        data.extend_from_slice(&0x1a2b3c4du32.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&u64::MAX.to_le_bytes());
+        data.extend_from_slice(&28u32.to_le_bytes());
+
+        // Interface Description Block with if_tsresol = 10^-9.
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&32u32.to_le_bytes());
+        data.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&[9, 0, 1, 0, 9, 0, 0, 0]);
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend_from_slice(&32u32.to_le_bytes());
+
+        w.write_all(&data)?;
+
+        Ok(Self { w })
+    }
+
+    /// Write an Enhanced Packet Block. Each block is written with a single call to the underlying
+    /// writer so a partial capture is still readable.
+    pub fn write(&mut self, time: Duration, dir: Direction, frame: &[u8]) -> std::io::Result<()> {
+        let caplen: u32 = frame.len().try_into().unwrap();
+        let padded = frame.len().next_multiple_of(4);
+        let len: u32 = (32 + padded + 12).try_into().unwrap();
+        let ticks = u64::try_from(time.as_nanos()).unwrap();
+        let flags: u32 = match dir {
+            Direction::Received => 1,
+            Direction::Sent => 2,
+        };
+
+        let mut data = Vec::with_capacity(len as usize);
+
+        data.extend_from_slice(&6u32.to_le_bytes());
+        data.extend_from_slice(&len.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&((ticks >> 32) as u32).to_le_bytes());
+        data.extend_from_slice(&(ticks as u32).to_le_bytes());
+        data.extend_from_slice(&caplen.to_le_bytes());
+        data.extend_from_slice(&caplen.to_le_bytes());
+        data.extend_from_slice(frame);
+        data.resize(28 + padded, 0);
+
+        // epb_flags with the direction.
+        data.extend_from_slice(&2u16.to_le_bytes());
+        data.extend_from_slice(&4u16.to_le_bytes());
+        data.extend_from_slice(&flags.to_le_bytes());
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend_from_slice(&len.to_le_bytes());
+
+        self.w.write_all(&data)
+    }
+}
+
+/// Read all Ethernet frames from a pcap or pcapng file.
+pub fn read_capture(path: impl AsRef<Path>) -> Result<Vec<Frame>, PcapError> {
+    let data = std::fs::read(path).map_err(PcapError::Read)?;
+
+    parse(&data)
+}
+
+pub(crate) fn parse(data: &[u8]) -> Result<Vec<Frame>, PcapError> {
+    let magic = data.get(..4).ok_or(PcapError::Truncated)?;
+
+    match magic {
+        [0x0a, 0x0d, 0x0d, 0x0a] => parse_pcapng(data),
+        [0xd4, 0xc3, 0xb2, 0xa1] | [0x4d, 0x3c, 0xb2, 0xa1] => parse_pcap(data, Endian::Little),
+        [0xa1, 0xb2, 0xc3, 0xd4] | [0xa1, 0xb2, 0x3c, 0x4d] => parse_pcap(data, Endian::Big),
+        _ => Err(PcapError::UnknownFormat),
+    }
+}
+
+fn parse_pcap(data: &[u8], e: Endian) -> Result<Vec<Frame>, PcapError> {
+    // Check header.
+    let nano = matches!(data[..4], [0x4d, 0x3c, ..] | [.., 0x3c, 0x4d]);
+    let link = e.u32(data, 20)?;
+
+    if link != LINKTYPE_ETHERNET.into() {
+        return Err(PcapError::UnsupportedLink(link));
+    }
+
+    // Read records.
+    let mut frames = Vec::new();
+    let mut off = 24;
+
+    while off < data.len() {
+        let secs = e.u32(data, off)?;
+        let frac = e.u32(data, off + 4)?;
+        let len: usize = e.u32(data, off + 8)?.try_into().unwrap();
+        let frame = data
+            .get((off + 16)..(off + 16 + len))
+            .ok_or(PcapError::Truncated)?;
+        let time = if nano {
+            Duration::new(secs.into(), frac)
+        } else {
+            Duration::new(secs.into(), 0) + Duration::from_micros(frac.into())
+        };
+
+        frames.push(Frame {
+            time,
+            data: frame.to_vec(),
+        });
+
+        off += 16 + len;
+    }
+
+    Ok(frames)
+}
+
+fn parse_pcapng(data: &[u8]) -> Result<Vec<Frame>, PcapError> {
+    let mut frames = Vec::new();
+    let mut e = Endian::Little;
+    let mut interfaces = Vec::<(u16, u64)>::new(); // Link type and ticks per second.
+    let mut off = 0;
+
+    while off < data.len() {
+        // Section Header Block determines the byte order of the section.
+        if data.get(off..(off + 4)) == Some(&[0x0a, 0x0d, 0x0d, 0x0a]) {
+            e = match data.get((off + 8)..(off + 12)) {
+                Some([0x4d, 0x3c, 0x2b, 0x1a]) => Endian::Little,
+                Some([0x1a, 0x2b, 0x3c, 0x4d]) => Endian::Big,
+                _ => return Err(PcapError::UnknownFormat),
+            };
+
+            interfaces.clear();
+        }
+
+        let ty = e.u32(data, off)?;
+        let len: usize = e.u32(data, off + 4)?.try_into().unwrap();
+
+        if !len.is_multiple_of(4) || len < 12 {
+            return Err(PcapError::Truncated);
+        }
+
+        let body = data
+            .get((off + 8)..(off + len - 4))
+            .ok_or(PcapError::Truncated)?;
+
+        match ty {
+            // Interface Description Block.
+            1 => {
+                let link = e.u16(body, 0)?;
+                let mut resol = 1_000_000;
+                let mut opt = 8;
+
+                while let (Ok(code), Ok(olen)) = (e.u16(body, opt), e.u16(body, opt + 2)) {
+                    let olen = usize::from(olen);
+
+                    if code == 0 {
+                        break;
+                    }
+
+                    // if_tsresol.
+                    if code == 9 && olen == 1 {
+                        let v = *body.get(opt + 4).ok_or(PcapError::Truncated)?;
+
+                        resol = if v & 0x80 == 0 {
+                            10u64.pow((v & 0x7f).into())
+                        } else {
+                            1u64 << (v & 0x7f)
+                        };
+                    }
+
+                    opt += 4 + olen.next_multiple_of(4);
+                }
+
+                interfaces.push((link, resol));
+            }
+            // Enhanced Packet Block.
+            6 => {
+                let id: usize = e.u32(body, 0)?.try_into().unwrap();
+                let (link, resol) = *interfaces.get(id).ok_or(PcapError::Truncated)?;
+                let ticks = (u64::from(e.u32(body, 4)?) << 32) | u64::from(e.u32(body, 8)?);
+                let caplen: usize = e.u32(body, 12)?.try_into().unwrap();
+                let frame = body.get(20..(20 + caplen)).ok_or(PcapError::Truncated)?;
+
+                if link != LINKTYPE_ETHERNET {
+                    return Err(PcapError::UnsupportedLink(link.into()));
+                }
+
+                frames.push(Frame {
+                    time: Duration::from_secs(ticks / resol)
+                        + Duration::from_nanos((ticks % resol) * 1_000_000_000 / resol),
+                    data: frame.to_vec(),
+                });
+            }
+            _ => {}
+        }
+
+        off += len;
+    }
+
+    Ok(frames)
+}
+
+/// An Ethernet frame in a capture.
+pub struct Frame {
+    /// Time since UNIX epoch.
+    pub time: Duration,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Copy)]
+enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    fn u16(self, data: &[u8], off: usize) -> Result<u16, PcapError> {
+        let v = data
+            .get(off..(off + 2))
+            .ok_or(PcapError::Truncated)?
+            .try_into()
+            .unwrap();
+
+        Ok(match self {
+            Self::Little => u16::from_le_bytes(v),
+            Self::Big => u16::from_be_bytes(v),
+        })
+    }
+
+    fn u32(self, data: &[u8], off: usize) -> Result<u32, PcapError> {
+        let v = data
+            .get(off..(off + 4))
+            .ok_or(PcapError::Truncated)?
+            .try_into()
+            .unwrap();
+
+        Ok(match self {
+            Self::Little => u32::from_le_bytes(v),
+            Self::Big => u32::from_be_bytes(v),
+        })
+    }
+}
+
+/// Represents an error when [`read_capture()`] fails.
+#[derive(Debug)]
+pub enum PcapError {
+    Read(std::io::Error),
+    UnknownFormat,
+    UnsupportedLink(u32),
+    Truncated,
+}
+
+impl Error for PcapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Read(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for PcapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read(_) => f.write_str("couldn't read the file"),
+            Self::UnknownFormat => f.write_str("not a pcap or pcapng file"),
+            Self::UnsupportedLink(v) => write!(f, "link type {v} is not Ethernet"),
+            Self::Truncated => f.write_str("the file is truncated"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pcap() {
+        let mut data = vec![
+            0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 1, 0, 0,
+            0,
+        ];
+
+        data.extend_from_slice(&[10, 0, 0, 0, 0x20, 0xa1, 0x07, 0, 3, 0, 0, 0, 3, 0, 0, 0]);
+        data.extend_from_slice(&[1, 2, 3]);
+
+        let frames = parse(&data).unwrap();
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].time, Duration::from_millis(10500));
+        assert_eq!(frames[0].data, [1, 2, 3]);
+
+        data.pop();
+
+        assert!(matches!(parse(&data), Err(PcapError::Truncated)));
+    }
+
+    #[test]
+    fn parse_pcapng() {
+        let mut data = Vec::new();
+
+        // Section Header Block.
+        data.extend_from_slice(&[0x0a, 0x0d, 0x0d, 0x0a, 28, 0, 0, 0, 0x4d, 0x3c, 0x2b, 0x1a]);
+        data.extend_from_slice(&[1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        data.extend_from_slice(&[28, 0, 0, 0]);
+
+        // Interface Description Block with if_tsresol = 10^-9.
+        data.extend_from_slice(&[1, 0, 0, 0, 32, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&[9, 0, 1, 0, 9, 0, 0, 0, 0, 0, 0, 0, 32, 0, 0, 0]);
+
+        // Enhanced Packet Block.
+        data.extend_from_slice(&[6, 0, 0, 0, 36, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&[0, 0, 0, 0, 0x00, 0xca, 0x9a, 0x3b, 3, 0, 0, 0, 3, 0, 0, 0]);
+        data.extend_from_slice(&[1, 2, 3, 0, 36, 0, 0, 0]);
+
+        let frames = parse(&data).unwrap();
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].time, Duration::from_secs(1));
+        assert_eq!(frames[0].data, [1, 2, 3]);
+    }
+
+    #[test]
+    fn trim() {
+        let path = std::env::temp_dir().join(format!("jailbreak11-{}.pcapng", std::process::id()));
+        let ours = MacAddr6::new(2, 0, 0, 0, 0, 1);
+        let console = [2, 0, 0, 0, 0, 2];
+        let capture = Capture::create(&path, ours).unwrap();
+        let addr = |peer: [u8; 6]| {
+            let mut addr: sockaddr_ll = unsafe { std::mem::zeroed() };
+
+            addr.sll_protocol = 0x8864u16.to_be();
+            addr.sll_addr[..6].copy_from_slice(&peer);
+            addr
+        };
+
+        std::fs::remove_file(&path).unwrap();
+
+        capture.record(Direction::Received, &addr(console), &[1]);
+
+        let from = Instant::now();
+
+        capture.record(Direction::Sent, &addr(console), &[2]);
+        capture.record(Direction::Received, &addr([2, 0, 0, 0, 0, 3]), &[3]);
+        capture.record(Direction::Received, &addr(console), &[4]);
+
+        let mut out = Vec::new();
+        let n = capture
+            .trim(&mut out, console.into(), from, Instant::now())
+            .unwrap();
+        let frames = parse(&out).unwrap();
+
+        assert_eq!(n, 2);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].data[..6], console);
+        assert_eq!(frames[0].data[14..], [2]);
+        assert_eq!(frames[1].data[6..12], console);
+        assert_eq!(frames[1].data[14..], [4]);
+    }
+
+    #[test]
+    fn write_pcapng() {
+        let mut w = PcapWriter::new(Vec::new()).unwrap();
+        let time = Duration::new(1700000000, 123456789);
+
+        w.write(time, Direction::Received, &[1, 2, 3]).unwrap();
+        w.write(time, Direction::Sent, &[4, 5, 6, 7]).unwrap();
+
+        let frames = parse(&w.w).unwrap();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].time, time);
+        assert_eq!(frames[0].data, [1, 2, 3]);
+        assert_eq!(frames[1].data, [4, 5, 6, 7]);
+    }
+}
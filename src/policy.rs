@@ -0,0 +1,86 @@
+use macaddr::MacAddr6;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Which consoles the discovery server should answer.
+#[derive(Clone)]
+pub enum AnswerPolicy {
+    /// Answer all consoles.
+    All,
+    /// Answer only the first console that we received PADI from.
+    First,
+    /// Answer only the consoles in the list.
+    Allow(Vec<MacAddr6>),
+    /// Never transmit anything. This is useful to survey a network segment safely.
+    None,
+}
+
+impl FromStr for AnswerPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let v = match s {
+            "all" => Self::All,
+            "first" => Self::First,
+            "none" => Self::None,
+            v => Self::Allow(
+                v.split(',')
+                    .map(|v| v.parse().map_err(|_| format!("invalid MAC address '{v}'")))
+                    .collect::<Result<_, _>>()?,
+            ),
+        };
+
+        Ok(v)
+    }
+}
+
+impl Display for AnswerPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::All => f.write_str("all"),
+            Self::First => f.write_str("first"),
+            Self::Allow(v) => {
+                for (i, a) in v.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(",")?;
+                    }
+
+                    write!(f, "{a}")?;
+                }
+
+                Ok(())
+            }
+            Self::None => f.write_str("none"),
+        }
+    }
+}
+
+/// State of [`AnswerPolicy`].
+pub struct Answerer {
+    policy: AnswerPolicy,
+    first: Mutex<Option<MacAddr6>>,
+}
+
+impl Answerer {
+    pub fn new(policy: AnswerPolicy) -> Self {
+        Self {
+            policy,
+            first: Mutex::new(None),
+        }
+    }
+
+    pub fn policy(&self) -> &AnswerPolicy {
+        &self.policy
+    }
+
+    /// Returns `true` if we should answer `peer`.
+    pub fn accept(&self, peer: MacAddr6) -> bool {
+        match &self.policy {
+            AnswerPolicy::All => true,
+            AnswerPolicy::First => *self.first.lock().unwrap().get_or_insert(peer) == peer,
+            AnswerPolicy::Allow(v) => v.contains(&peer),
+            AnswerPolicy::None => false,
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+/// Named sets of command line options from a profile file.
+///
+/// Each profile start with `[NAME]` and followed by one option per line in the form of
+/// `NAME = VALUE`, or just `NAME` for a flag. The names are the same as the long options without
+/// `--` (e.g. `cooldown = 10`) and `interface` for the interface. Empty lines and the lines that
+/// start with `#` are ignored.
+pub struct Profiles {
+    profiles: Vec<Profile>,
+}
+
+impl Profiles {
+    pub fn load(path: &Path) -> Result<Self, ProfileError> {
+        let data = std::fs::read_to_string(path).map_err(ProfileError::Read)?;
+
+        Self::parse(&data)
+    }
+
+    pub fn parse(data: &str) -> Result<Self, ProfileError> {
+        let mut profiles: Vec<Profile> = Vec::new();
+
+        for (i, line) in data.lines().enumerate() {
+            let line = line.trim();
+            let ln = i + 1;
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // Start a new profile.
+            if let Some(v) = line.strip_prefix('[') {
+                let name = match v.strip_suffix(']').map(|v| v.trim()) {
+                    Some(v) if !v.is_empty() => v,
+                    _ => return Err(ProfileError::InvalidName(ln)),
+                };
+
+                if profiles.iter().any(|p| p.name == name) {
+                    return Err(ProfileError::DuplicatedProfile(ln, name.into()));
+                }
+
+                profiles.push(Profile {
+                    name: name.into(),
+                    options: Vec::new(),
+                });
+
+                continue;
+            }
+
+            // Parse the option.
+            let profile = profiles
+                .last_mut()
+                .ok_or(ProfileError::OptionWithoutProfile(ln))?;
+            let (name, value) = match line.split_once('=') {
+                Some((n, v)) => (n.trim(), Some(v.trim().to_owned())),
+                None => (line, None),
+            };
+
+            if name.is_empty() || name.starts_with('-') {
+                return Err(ProfileError::InvalidOption(ln));
+            }
+
+            profile.options.push((name.into(), value));
+        }
+
+        Ok(Self { profiles })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.iter().map(|p| p.name.as_str())
+    }
+}
+
+/// A single profile in [`Profiles`].
+pub struct Profile {
+    name: String,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Profile {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the name and value of each option in the same order as the file.
+    pub fn options(&self) -> &[(String, Option<String>)] {
+        &self.options
+    }
+}
+
+/// Represents an error when [`Profiles`] fails to load.
+#[derive(Debug)]
+pub enum ProfileError {
+    Read(std::io::Error),
+    InvalidName(usize),
+    DuplicatedProfile(usize, String),
+    OptionWithoutProfile(usize),
+    InvalidOption(usize),
+}
+
+impl Error for ProfileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Read(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ProfileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read(_) => f.write_str("couldn't read the file"),
+            Self::InvalidName(l) => write!(f, "invalid profile name on line {l}"),
+            Self::DuplicatedProfile(l, n) => write!(f, "profile '{n}' on line {l} already exists"),
+            Self::OptionWithoutProfile(l) => write!(f, "option on line {l} is not in a profile"),
+            Self::InvalidOption(l) => write!(f, "invalid option on line {l}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let profiles = Profiles::parse(
+            "# Rig at home.\n[home]\ninterface = 2\ncooldown=10\n\n[event]\n  keep-link\n",
+        )
+        .unwrap();
+        let home = profiles.get("home").unwrap();
+
+        assert_eq!(
+            home.options(),
+            [
+                ("interface".into(), Some("2".into())),
+                ("cooldown".into(), Some("10".into()))
+            ]
+        );
+        assert_eq!(
+            profiles.get("event").unwrap().options(),
+            [("keep-link".into(), None)]
+        );
+        assert!(profiles.get("lab").is_none());
+        assert_eq!(profiles.names().collect::<Vec<_>>(), ["home", "event"]);
+
+        assert!(matches!(
+            Profiles::parse("cooldown = 10"),
+            Err(ProfileError::OptionWithoutProfile(1))
+        ));
+        assert!(matches!(
+            Profiles::parse("[a]\n[a]"),
+            Err(ProfileError::DuplicatedProfile(2, _))
+        ));
+        assert!(matches!(
+            Profiles::parse("[a]\n--cooldown 10"),
+            Err(ProfileError::InvalidOption(2))
+        ));
+        assert!(matches!(
+            Profiles::parse("[]"),
+            Err(ProfileError::InvalidName(1))
+        ));
+    }
+}
@@ -0,0 +1,25 @@
+//! Link Control Protocol specific values (RFC 1661).
+
+/// Code of Protocol-Reject.
+pub const PROTOCOL_REJECT: u8 = 8;
+
+/// Code of Echo-Request.
+pub const ECHO_REQUEST: u8 = 9;
+
+/// Code of Echo-Reply.
+pub const ECHO_REPLY: u8 = 10;
+
+/// Code of Discard-Request.
+pub const DISCARD_REQUEST: u8 = 11;
+
+/// Option type of Maximum-Receive-Unit.
+pub const OPT_MRU: u8 = 1;
+
+/// Option type of Authentication-Protocol.
+pub const OPT_AUTH: u8 = 3;
+
+/// Option type of Magic-Number.
+pub const OPT_MAGIC: u8 = 5;
+
+/// PPPoE can carry 1492 bytes of PPP payload (RFC 2516).
+pub const MAX_MRU: u16 = 1492;
@@ -0,0 +1,11 @@
+//! Wire format of PPPoE, PPP and LCP.
+//!
+//! This module is the public API of this crate and follow semantic versioning. Everything here
+//! does not do any I/O so it can be used to build or inspect the frames with any socket or
+//! capture.
+#![warn(missing_docs)]
+
+pub mod lcp;
+pub mod pap;
+pub mod ppp;
+pub mod pppoe;
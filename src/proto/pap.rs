@@ -0,0 +1,49 @@
+//! Password Authentication Protocol specific values (RFC 1334).
+
+/// Code of Authenticate-Request.
+pub const AUTHENTICATE_REQUEST: u8 = 1;
+
+/// Code of Authenticate-Ack.
+pub const AUTHENTICATE_ACK: u8 = 2;
+
+/// Code of Authenticate-Nak.
+pub const AUTHENTICATE_NAK: u8 = 3;
+
+/// Returns Peer-ID and Password from the data of Authenticate-Request. Returns [`None`] if `data`
+/// is malformed.
+pub fn parse_request(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let len: usize = (*data.first()?).into();
+    let id = data.get(1..(1 + len))?;
+    let data = &data[(1 + len)..];
+    let len: usize = (*data.first()?).into();
+    let password = data.get(1..(1 + len))?;
+
+    Some((id, password))
+}
+
+/// Returns the data of Authenticate-Ack or Authenticate-Nak with `message`.
+///
+/// # Panics
+/// If `message` is longer than 255 bytes.
+pub fn reply(message: &[u8]) -> Vec<u8> {
+    let mut data = vec![message.len().try_into().unwrap()];
+
+    data.extend_from_slice(message);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request() {
+        let data = [4, b'u', b's', b'e', b'r', 2, b'p', b'w'];
+
+        assert_eq!(parse_request(&data), Some((&b"user"[..], &b"pw"[..])));
+        assert_eq!(parse_request(&[0, 0]), Some((&[][..], &[][..])));
+        assert_eq!(parse_request(&data[..7]), None);
+        assert_eq!(parse_request(&[]), None);
+        assert_eq!(reply(b"ok"), [2, b'o', b'k']);
+    }
+}
@@ -0,0 +1,117 @@
+//! PPP frames (RFC 1661) and the packets of its control protocols.
+
+/// Protocol number of IPv4.
+pub const PROTO_IPV4: u16 = 0x0021;
+
+/// Protocol number of IPv6.
+pub const PROTO_IPV6: u16 = 0x0057;
+
+/// Protocol number of IP Control Protocol (RFC 1332).
+pub const PROTO_IPCP: u16 = 0x8021;
+
+/// Protocol number of IPv6 Control Protocol (RFC 5072).
+pub const PROTO_IPV6CP: u16 = 0x8057;
+
+/// Protocol number of Link Control Protocol.
+pub const PROTO_LCP: u16 = 0xc021;
+
+/// Protocol number of Password Authentication Protocol (RFC 1334).
+pub const PROTO_PAP: u16 = 0xc023;
+
+/// Protocol number of Challenge Handshake Authentication Protocol (RFC 1994).
+pub const PROTO_CHAP: u16 = 0xc223;
+
+/// Code of Configure-Request, which is shared by all control protocols.
+pub const CONFIGURE_REQUEST: u8 = 1;
+
+/// Code of Configure-Ack, which is shared by all control protocols.
+pub const CONFIGURE_ACK: u8 = 2;
+
+/// Code of Configure-Nak, which is shared by all control protocols.
+pub const CONFIGURE_NAK: u8 = 3;
+
+/// Code of Configure-Reject, which is shared by all control protocols.
+pub const CONFIGURE_REJECT: u8 = 4;
+
+/// Code of Terminate-Request, which is shared by all control protocols.
+pub const TERMINATE_REQUEST: u8 = 5;
+
+/// Code of Terminate-Ack, which is shared by all control protocols.
+pub const TERMINATE_ACK: u8 = 6;
+
+/// Code of Code-Reject, which is shared by all control protocols.
+pub const CODE_REJECT: u8 = 7;
+
+/// Build a control packet.
+///
+/// # Panics
+/// If `data` is longer than 65531 bytes.
+pub fn packet(code: u8, id: u8, data: &[u8]) -> Vec<u8> {
+    let len: u16 = (4 + data.len()).try_into().unwrap();
+    let mut packet = Vec::with_capacity(len.into());
+
+    packet.push(code);
+    packet.push(id);
+    packet.extend_from_slice(&len.to_be_bytes());
+    packet.extend_from_slice(data);
+    packet
+}
+
+/// Returns code, identifier and data of a control packet. The bytes after LENGTH are ignored.
+pub fn parse_packet(packet: &[u8]) -> Option<(u8, u8, &[u8])> {
+    let len: usize = u16::from_be_bytes(packet.get(2..4)?.try_into().unwrap()).into();
+    let data = packet.get(4..len)?;
+
+    Some((packet[0], packet[1], data))
+}
+
+/// Iterator over the options of Configure packets. Each item is type, value and the whole option.
+///
+/// The iteration stop on the first malformed option.
+pub struct Options<'a>(&'a [u8]);
+
+impl<'a> Options<'a> {
+    /// `data` is the data of a Configure packet without code, identifier and length.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self(data)
+    }
+}
+
+impl<'a> Iterator for Options<'a> {
+    type Item = (u8, &'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let len: usize = (*self.0.get(1)?).into();
+
+        if len < 2 || len > self.0.len() {
+            self.0 = &[];
+            return None;
+        }
+
+        let (raw, next) = self.0.split_at(len);
+
+        self.0 = next;
+
+        Some((raw[0], &raw[2..], raw))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_packet() {
+        let p = packet(CONFIGURE_REQUEST, 7, &[1, 4, 5, 0xd4, 5, 6, 1, 2, 3, 4]);
+
+        assert_eq!(p[..4], [1, 7, 0, 14]);
+
+        let (code, id, data) = parse_packet(&p).unwrap();
+        let opts: Vec<(u8, &[u8])> = Options::new(data).map(|(t, v, _)| (t, v)).collect();
+
+        assert_eq!((code, id), (CONFIGURE_REQUEST, 7));
+        assert_eq!(opts, [(1, &[5, 0xd4][..]), (5, &[1, 2, 3, 4][..])]);
+        assert!(parse_packet(&p[..13]).is_none());
+        assert_eq!(Options::new(&[1, 4, 5]).count(), 0);
+    }
+}
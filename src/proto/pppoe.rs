@@ -0,0 +1,534 @@
+//! PPPoE frames (RFC 2516).
+use std::borrow::Cow;
+use std::io::Write;
+use std::str::FromStr;
+
+/// EtherType of PPPoE discovery stage.
+pub const ETHERTYPE_DISCOVERY: u16 = 0x8863;
+
+/// EtherType of PPPoE session stage.
+pub const ETHERTYPE_SESSION: u16 = 0x8864;
+
+/// Largest PPP-Max-Payload (RFC 4638) that fits in a 9000 bytes jumbo frame.
+pub const MAX_PPP_MAX_PAYLOAD: u16 = 8992;
+
+/// EtherTypes to use for PPPoE frames.
+///
+/// The default is [`ETHERTYPE_DISCOVERY`] and [`ETHERTYPE_SESSION`]. The other values are only
+/// useful in a closed lab with a modified client stack.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EtherTypes {
+    /// EtherType of the discovery stage.
+    pub discovery: u16,
+    /// EtherType of the session stage.
+    pub session: u16,
+}
+
+impl EtherTypes {
+    /// Returns `true` if these are the values from RFC 2516.
+    pub fn is_standard(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Default for EtherTypes {
+    fn default() -> Self {
+        Self {
+            discovery: ETHERTYPE_DISCOVERY,
+            session: ETHERTYPE_SESSION,
+        }
+    }
+}
+
+impl FromStr for EtherTypes {
+    type Err = String;
+
+    /// Parse `DISCOVERY,SESSION` in hex (e.g. `88b5,88b6`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (d, s) = s
+            .split_once(',')
+            .ok_or_else(|| format!("expect DISCOVERY,SESSION, got '{s}'"))?;
+        let parse = |v: &str| {
+            let t = v.trim_start_matches("0x");
+
+            // The values below 0x0600 are the length of IEEE 802.3 frames.
+            match u16::from_str_radix(t, 16) {
+                Ok(v) if v >= 0x0600 => Ok(v),
+                _ => Err(format!("invalid EtherType '{v}'")),
+            }
+        };
+        let v = Self {
+            discovery: parse(d)?,
+            session: parse(s)?,
+        };
+
+        if v.discovery == v.session {
+            return Err("EtherTypes of the discovery and session stage must be different".into());
+        }
+
+        Ok(v)
+    }
+}
+
+/// Minimum length of Ethernet payload. The sender will pad the shorter frames up to this length.
+pub const MIN_PAYLOAD: usize = 46;
+
+/// How to handle the bytes after LENGTH of PPPoE frame.
+#[derive(Clone, Copy)]
+#[non_exhaustive]
+pub enum Padding {
+    /// Discard all bytes after LENGTH.
+    Trim,
+    /// Same as [`Padding::Trim`] but reject the frame if it is longer than [`MIN_PAYLOAD`] and has
+    /// any bytes after LENGTH.
+    Strict,
+}
+
+impl Padding {
+    /// Returns PPPoE header and payload of `data` without the padding. Returns [`None`] if `data`
+    /// is truncated or it has unexpected trailing bytes.
+    pub fn trim(self, data: &[u8]) -> Option<&[u8]> {
+        let length: usize = u16::from_be_bytes(data.get(4..6)?.try_into().unwrap()).into();
+        let end = 6 + length;
+
+        if data.len() < end {
+            return None;
+        }
+
+        match self {
+            Self::Strict if data.len() != end && data.len() > MIN_PAYLOAD => None,
+            _ => Some(&data[..end]),
+        }
+    }
+}
+
+/// CODE of PPPoE packet.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum Code {
+    /// Session stage.
+    Session = 0x00,
+    /// PPPoE Active Discovery Offer.
+    Pado = 0x07,
+    /// PPPoE Active Discovery Initiation.
+    Padi = 0x09,
+    /// PPPoE Active Discovery Request.
+    Padr = 0x19,
+    /// PPPoE Active Discovery Session-confirmation.
+    Pads = 0x65,
+    /// PPPoE Active Discovery Terminate.
+    Padt = 0xa7,
+}
+
+impl Code {
+    /// Returns the name of this code (e.g. `PADI`).
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Session => "Session",
+            Self::Pado => "PADO",
+            Self::Padi => "PADI",
+            Self::Padr => "PADR",
+            Self::Pads => "PADS",
+            Self::Padt => "PADT",
+        }
+    }
+}
+
+impl TryFrom<u8> for Code {
+    type Error = u8;
+
+    /// Returns the value back if it is an unknown code.
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        let v = match value {
+            0x00 => Self::Session,
+            0x07 => Self::Pado,
+            0x09 => Self::Padi,
+            0x19 => Self::Padr,
+            0x65 => Self::Pads,
+            0xa7 => Self::Padt,
+            v => return Err(v),
+        };
+
+        Ok(v)
+    }
+}
+
+impl From<Code> for u8 {
+    fn from(value: Code) -> Self {
+        value as u8
+    }
+}
+
+/// TAG_TYPE of the discovery stage.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u16)]
+pub enum Tag {
+    /// End-Of-List.
+    EndOfList = 0x0000,
+    /// Service-Name.
+    ServiceName = 0x0101,
+    /// AC-Name.
+    AcName = 0x0102,
+    /// Host-Uniq.
+    HostUniq = 0x0103,
+    /// AC-Cookie.
+    AcCookie = 0x0104,
+    /// Vendor-Specific.
+    VendorSpecific = 0x0105,
+    /// Relay-Session-Id.
+    RelaySessionId = 0x0110,
+    /// PPP-Max-Payload (RFC 4638).
+    PppMaxPayload = 0x0120,
+    /// Service-Name-Error.
+    ServiceNameError = 0x0201,
+    /// AC-System-Error.
+    AcSystemError = 0x0202,
+    /// Generic-Error.
+    GenericError = 0x0203,
+}
+
+impl Tag {
+    /// Returns the name of this tag (e.g. `Service-Name`).
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::EndOfList => "End-Of-List",
+            Self::ServiceName => "Service-Name",
+            Self::AcName => "AC-Name",
+            Self::HostUniq => "Host-Uniq",
+            Self::AcCookie => "AC-Cookie",
+            Self::VendorSpecific => "Vendor-Specific",
+            Self::RelaySessionId => "Relay-Session-Id",
+            Self::PppMaxPayload => "PPP-Max-Payload",
+            Self::ServiceNameError => "Service-Name-Error",
+            Self::AcSystemError => "AC-System-Error",
+            Self::GenericError => "Generic-Error",
+        }
+    }
+}
+
+impl TryFrom<u16> for Tag {
+    type Error = u16;
+
+    /// Returns the value back if it is an unknown tag.
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        let v = match value {
+            0x0000 => Self::EndOfList,
+            0x0101 => Self::ServiceName,
+            0x0102 => Self::AcName,
+            0x0103 => Self::HostUniq,
+            0x0104 => Self::AcCookie,
+            0x0105 => Self::VendorSpecific,
+            0x0110 => Self::RelaySessionId,
+            0x0120 => Self::PppMaxPayload,
+            0x0201 => Self::ServiceNameError,
+            0x0202 => Self::AcSystemError,
+            0x0203 => Self::GenericError,
+            v => return Err(v),
+        };
+
+        Ok(v)
+    }
+}
+
+impl From<Tag> for u16 {
+    fn from(value: Tag) -> Self {
+        value as u16
+    }
+}
+
+/// Ethernet payload for PPPoE packet.
+///
+/// Use [`Tags`] as `T` for the discovery stage and `Cow<[u8]>` for the PPP frame of the session
+/// stage.
+pub struct EthernetPayload<T> {
+    code: u8,
+    session_id: u16,
+    payload: T,
+}
+
+impl<T> EthernetPayload<T> {
+    /// Create a packet with VER and TYPE of 1.
+    pub fn new(code: u8, session_id: u16, payload: T) -> Self {
+        Self {
+            code,
+            session_id,
+            payload,
+        }
+    }
+
+    /// Parse PPPoE header and payload from `data`. The bytes after LENGTH are ignored so `data`
+    /// must be passed to [`Padding::trim()`] first if they need to be rejected. Returns [`None`] if
+    /// `data` is not a valid PPPoE packet.
+    pub fn deserialize<'a>(data: &'a [u8]) -> Option<Self>
+    where
+        T: Payload<'a>,
+    {
+        // Check minimum Ethernet payload length.
+        if data.len() < 6 {
+            return None;
+        }
+
+        // Check version and type.
+        let ver = data[0] & 0xf;
+        let ty = data[0] >> 4;
+
+        if ver != 1 || ty != 1 {
+            return None;
+        }
+
+        // Read CODE, SESSION_ID, LENGTH and payload.
+        let code = data[1];
+        let session_id = u16::from_be_bytes(data[2..4].try_into().unwrap());
+        let length: usize = u16::from_be_bytes(data[4..6].try_into().unwrap()).into();
+        let payload = data[6..].get(..length)?;
+
+        Some(Self {
+            code,
+            session_id,
+            payload: T::deserialize(payload)?,
+        })
+    }
+
+    /// Returns CODE of the packet, which is zero for the session stage.
+    pub fn code(&self) -> u8 {
+        self.code
+    }
+
+    /// Returns SESSION_ID of the packet.
+    pub fn session_id(&self) -> u16 {
+        self.session_id
+    }
+
+    /// Returns the payload of the packet.
+    pub fn payload(&self) -> &T {
+        &self.payload
+    }
+
+    /// Returns a mutable reference to the payload of the packet.
+    pub fn payload_mut(&mut self) -> &mut T {
+        &mut self.payload
+    }
+
+    /// Returns the packet as an Ethernet payload without the padding.
+    ///
+    /// # Panics
+    /// If the packet is longer than a jumbo frame can carry (see [`MAX_PPP_MAX_PAYLOAD`]).
+    pub fn serialize<'a>(&self) -> Vec<u8>
+    where
+        T: Payload<'a>,
+    {
+        let mut buf = Vec::new();
+
+        // Write VER, TYPE and CODE.
+        buf.push(0x11);
+        buf.push(self.code);
+
+        // Write SESSION_ID and payload.
+        buf.write_all(&self.session_id.to_be_bytes()).unwrap();
+        buf.write_all(&[0; 2]).unwrap();
+
+        self.payload.serialize(&mut buf);
+
+        assert!(buf.len() <= usize::from(MAX_PPP_MAX_PAYLOAD) + 8);
+
+        // Write LENGTH.
+        let len: u16 = (buf.len() - 6).try_into().unwrap();
+
+        buf[4..6].copy_from_slice(&len.to_be_bytes());
+        buf
+    }
+}
+
+impl<'a> EthernetPayload<Tags<'a>> {
+    /// Returns the value of the first `tag` or [`None`] if there is no such tag.
+    pub fn tag(&self, tag: Tag) -> Option<&[u8]> {
+        let tag: u16 = tag.into();
+
+        self.payload
+            .iter()
+            .find(|(t, _)| *t == tag)
+            .map(|(_, v)| v.as_ref())
+    }
+
+    /// Returns the value of the first Service-Name tag.
+    pub fn service_name(&self) -> Option<&[u8]> {
+        self.tag(Tag::ServiceName)
+    }
+
+    /// Returns the value of the first AC-Name tag.
+    pub fn ac_name(&self) -> Option<&[u8]> {
+        self.tag(Tag::AcName)
+    }
+
+    /// Returns the value of the first Host-Uniq tag.
+    pub fn host_uniq(&self) -> Option<&[u8]> {
+        self.tag(Tag::HostUniq)
+    }
+
+    /// Returns the value of the first AC-Cookie tag.
+    pub fn ac_cookie(&self) -> Option<&[u8]> {
+        self.tag(Tag::AcCookie)
+    }
+
+    /// Returns the value of the first PPP-Max-Payload tag (RFC 4638) or [`None`] if there is no
+    /// such tag or it is not 2 bytes.
+    pub fn ppp_max_payload(&self) -> Option<u16> {
+        self.tag(Tag::PppMaxPayload)
+            .and_then(|v| v.try_into().ok())
+            .map(u16::from_be_bytes)
+    }
+}
+
+/// Payload of PPPoE packet.
+pub trait Payload<'a>: Sized {
+    /// Parse the payload from `data`, which is exactly LENGTH bytes.
+    fn deserialize(data: &'a [u8]) -> Option<Self>;
+
+    /// Append the payload to `buf`.
+    fn serialize(&self, buf: &mut Vec<u8>);
+}
+
+impl<'a> Payload<'a> for Cow<'a, [u8]> {
+    fn deserialize(data: &'a [u8]) -> Option<Self> {
+        Some(Cow::Borrowed(data))
+    }
+
+    fn serialize(&self, buf: &mut Vec<u8>) {
+        buf.extend(self.as_ref())
+    }
+}
+
+/// Tags of the discovery stage. Each item is TAG_TYPE and TAG_VALUE.
+pub type Tags<'a> = Vec<(u16, Cow<'a, [u8]>)>;
+
+impl<'a> Payload<'a> for Tags<'a> {
+    fn deserialize(mut data: &'a [u8]) -> Option<Self> {
+        let mut tags = Vec::new();
+
+        while !data.is_empty() {
+            if data.len() < 4 {
+                return None;
+            }
+
+            let ty = u16::from_be_bytes(data[..2].try_into().unwrap());
+            let length: usize = u16::from_be_bytes(data[2..4].try_into().unwrap()).into();
+            let value = data[4..].get(..length)?;
+
+            tags.push((ty, Cow::Borrowed(value)));
+            data = &data[(4 + length)..];
+        }
+
+        Some(tags)
+    }
+
+    fn serialize(&self, buf: &mut Vec<u8>) {
+        for (t, v) in self {
+            let l: u16 = v.len().try_into().unwrap();
+
+            buf.write_all(&t.to_be_bytes()).unwrap();
+            buf.write_all(&l.to_be_bytes()).unwrap();
+            buf.write_all(v).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// PADI with an empty Service-Name and 4 bytes Host-Uniq padded to the minimum frame size.
+    const PADI: [u8; MIN_PAYLOAD] = [
+        0x11, 0x09, 0x00, 0x00, 0x00, 0x0c, 0x01, 0x01, 0x00, 0x00, 0x01, 0x03, 0x00, 0x04, 0x01,
+        0x02, 0x03, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ];
+
+    /// PADT for SESSION_ID 1 without any tags padded to the minimum frame size.
+    const PADT: [u8; MIN_PAYLOAD] = {
+        let mut v = [0; MIN_PAYLOAD];
+
+        v[0] = 0x11;
+        v[1] = 0xa7;
+        v[3] = 0x01;
+        v
+    };
+
+    #[test]
+    fn parse_ethertypes() {
+        let v: EtherTypes = "88b5,0x88B6".parse().unwrap();
+
+        assert_eq!((v.discovery, v.session), (0x88b5, 0x88b6));
+        assert!(!v.is_standard());
+        assert!("8863,8864".parse::<EtherTypes>().unwrap().is_standard());
+        assert!("8863".parse::<EtherTypes>().is_err());
+        assert!("8863,8863".parse::<EtherTypes>().is_err());
+        assert!("8863,05dc".parse::<EtherTypes>().is_err());
+        assert!("8863,xyz".parse::<EtherTypes>().is_err());
+    }
+
+    #[test]
+    fn typed_tags() {
+        let padi =
+            EthernetPayload::<Tags>::deserialize(Padding::Trim.trim(&PADI).unwrap()).unwrap();
+
+        assert_eq!(Code::try_from(padi.code()), Ok(Code::Padi));
+        assert_eq!(padi.service_name(), Some(&[][..]));
+        assert_eq!(padi.host_uniq(), Some(&[1, 2, 3, 4][..]));
+        assert_eq!(padi.ac_name(), None);
+        assert_eq!(padi.ppp_max_payload(), None);
+        assert_eq!(Tag::try_from(0x0104), Ok(Tag::AcCookie));
+        assert_eq!(Tag::try_from(0x0199), Err(0x0199));
+        assert_eq!(u8::from(Code::Padt), 0xa7);
+    }
+
+    #[test]
+    fn trim_padi() {
+        for p in [Padding::Trim, Padding::Strict] {
+            let data = p.trim(&PADI).unwrap();
+            let padi = EthernetPayload::<Cow<[u8]>>::deserialize(data).unwrap();
+
+            assert_eq!(data.len(), 18);
+            assert_eq!(padi.code(), Code::Padi.into());
+            assert_eq!(padi.session_id(), 0);
+            assert_eq!(padi.payload().as_ref(), &PADI[6..18]);
+        }
+    }
+
+    #[test]
+    fn trim_padt() {
+        for p in [Padding::Trim, Padding::Strict] {
+            let data = p.trim(&PADT).unwrap();
+            let padt = EthernetPayload::<Cow<[u8]>>::deserialize(data).unwrap();
+
+            assert_eq!(data.len(), 6);
+            assert_eq!(padt.code(), Code::Padt.into());
+            assert_eq!(padt.session_id(), 1);
+            assert!(padt.payload().is_empty());
+        }
+    }
+
+    #[test]
+    fn trim_unpadded() {
+        let data = &PADI[..18];
+
+        assert_eq!(Padding::Trim.trim(data), Some(data));
+        assert_eq!(Padding::Strict.trim(data), Some(data));
+    }
+
+    #[test]
+    fn trim_truncated() {
+        assert_eq!(Padding::Trim.trim(&PADI[..17]), None);
+        assert_eq!(Padding::Trim.trim(&PADI[..5]), None);
+    }
+
+    #[test]
+    fn trim_trailing() {
+        let mut data = PADI.to_vec();
+
+        data.push(0);
+
+        assert_eq!(Padding::Trim.trim(&data), Some(&PADI[..18]));
+        assert_eq!(Padding::Strict.trim(&data), None);
+    }
+}
@@ -0,0 +1,28 @@
+use libc::getrandom;
+use std::io::{Error, ErrorKind};
+
+/// Fill `buf` with random bytes from the kernel.
+///
+/// This only fails if the kernel cannot provide them (e.g. it does not support `getrandom(2)`).
+pub fn random_bytes(buf: &mut [u8]) -> Result<(), Error> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        let rem = &mut buf[filled..];
+        let n = unsafe { getrandom(rem.as_mut_ptr().cast(), rem.len(), 0) };
+
+        if n < 0 {
+            let e = Error::last_os_error();
+
+            if e.kind() != ErrorKind::Interrupted {
+                return Err(e);
+            }
+        } else if n == 0 {
+            return Err(Error::from(ErrorKind::UnexpectedEof));
+        } else {
+            filled += n as usize;
+        }
+    }
+
+    Ok(())
+}
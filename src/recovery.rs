@@ -0,0 +1,173 @@
+use crate::log::{error, info, warning};
+use crate::rt;
+use erdp::ErrorDisplay;
+use libc::{EACCES, EAGAIN, EINTR, EIO, ENETDOWN, ENOBUFS, ENODEV, ENOMEM, ENXIO, EPERM};
+use std::io::Error;
+use std::time::Duration;
+use tokio::select;
+use tokio_util::sync::CancellationToken;
+
+/// Delay before the first retry.
+const INITIAL_DELAY: Duration = Duration::from_millis(10);
+
+/// Maximum delay between the retries.
+const MAX_DELAY: Duration = Duration::from_secs(1);
+
+/// Number of consecutive failures before a transient error is considered fatal.
+const MAX_FAILURES: u32 = 30;
+
+/// Recover from the errors of a socket that is receiving in a loop.
+///
+/// A transient error is retried with exponential backoff so a single error does not stop the
+/// servers. The fatal one and the transient one that keep failing will cancel `running`.
+pub struct Recovery {
+    what: &'static str,
+    failures: u32,
+}
+
+impl Recovery {
+    /// `what` is the name of the socket in the messages (e.g. `PPPoE discovery socket`).
+    pub fn new(what: &'static str) -> Self {
+        Self { what, failures: 0 }
+    }
+
+    /// Must be called after each successful receive.
+    pub fn succeeded(&mut self) {
+        if self.failures != 0 {
+            info!("Receiving from {} again.", self.what);
+            self.failures = 0;
+        }
+    }
+
+    /// Handle `e` from the receive and wait before the next one. Returns `false` if the caller
+    /// must stop receiving, in which case `running` is already cancelled.
+    pub async fn failed(&mut self, e: &Error, running: &CancellationToken) -> bool {
+        // Check if we can retry.
+        let delay = match (classify(e), self.failures) {
+            (Class::Transient, n) if n < MAX_FAILURES => delay(n),
+            (Class::Transient, n) => {
+                error!(
+                    "Failed to receive a packet from {} {} times in a row: {}.",
+                    self.what,
+                    n + 1,
+                    e.display()
+                );
+
+                running.cancel();
+                return false;
+            }
+            (Class::Fatal(hint), _) => {
+                error!(
+                    "Failed to receive a packet from {}: {} ({}).",
+                    self.what,
+                    e.display(),
+                    hint
+                );
+
+                running.cancel();
+                return false;
+            }
+        };
+
+        if self.failures == 0 {
+            warning!(
+                "Failed to receive a packet from {}: {}. Retrying.",
+                self.what,
+                e.display()
+            );
+        }
+
+        self.failures += 1;
+
+        // Wait.
+        select! {
+            _ = running.cancelled() => false,
+            _ = rt::sleep(delay) => true,
+        }
+    }
+}
+
+/// Class of an error from a socket.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Class {
+    Transient,
+    /// The value is a hint of the cause.
+    Fatal(&'static str),
+}
+
+fn classify(e: &Error) -> Class {
+    let hint = match e.raw_os_error() {
+        // The link was down or the kernel was temporary out of memory.
+        Some(EINTR | EAGAIN | ENOBUFS | ENOMEM | ENETDOWN) => return Class::Transient,
+        Some(ENODEV | ENXIO) => "the interface was removed",
+        Some(EIO) => "the device is not working",
+        Some(EPERM | EACCES) => "the permission was revoked",
+        Some(_) => "this is likely a bug",
+        None => "the socket was closed",
+    };
+
+    Class::Fatal(hint)
+}
+
+fn delay(failures: u32) -> Duration {
+    INITIAL_DELAY
+        .saturating_mul(1 << failures.min(16))
+        .min(MAX_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::runtime;
+    use libc::EBADF;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn classify_errors() {
+        assert_eq!(
+            classify(&Error::from_raw_os_error(ENETDOWN)),
+            Class::Transient
+        );
+        assert_eq!(
+            classify(&Error::from_raw_os_error(ENOBUFS)),
+            Class::Transient
+        );
+        assert!(matches!(
+            classify(&Error::from_raw_os_error(ENODEV)),
+            Class::Fatal(_)
+        ));
+        assert!(matches!(
+            classify(&Error::from_raw_os_error(EBADF)),
+            Class::Fatal(_)
+        ));
+        assert!(matches!(
+            classify(&Error::from(ErrorKind::BrokenPipe)),
+            Class::Fatal(_)
+        ));
+        assert_eq!(delay(0), INITIAL_DELAY);
+        assert_eq!(delay(3), INITIAL_DELAY * 8);
+        assert_eq!(delay(MAX_FAILURES), MAX_DELAY);
+    }
+
+    #[test]
+    fn retry() {
+        runtime().block_on(async {
+            let running = CancellationToken::new();
+            let mut r = Recovery::new("test socket");
+            let transient = Error::from_raw_os_error(ENETDOWN);
+
+            assert!(r.failed(&transient, &running).await);
+            assert!(r.failed(&transient, &running).await);
+            assert_eq!(r.failures, 2);
+
+            r.succeeded();
+
+            assert_eq!(r.failures, 0);
+            assert!(!running.is_cancelled());
+
+            // A fatal error must stop everything.
+            assert!(!r.failed(&Error::from_raw_os_error(ENODEV), &running).await);
+            assert!(running.is_cancelled());
+        });
+    }
+}
@@ -0,0 +1,233 @@
+use crate::passive::decode;
+use crate::pcap::Frame;
+use crate::proto::pppoe::{
+    Code, EtherTypes, EthernetPayload, Tag, Tags, ETHERTYPE_DISCOVERY, ETHERTYPE_SESSION,
+};
+use crate::rt;
+use crate::socket::MockPeer;
+use libc::{sockaddr_ll, AF_PACKET, ARPHRD_ETHER, PACKET_BROADCAST, PACKET_HOST};
+use macaddr::MacAddr6;
+use std::borrow::Cow;
+use std::mem::zeroed;
+use std::time::Duration;
+
+/// Maximum gap between two frames. The longer gaps in the capture are shortened to this so a long
+/// capture can be replayed quickly.
+const MAX_GAP: Duration = Duration::from_secs(1);
+
+/// Duration to wait for the responses after the last frame.
+const SETTLE: Duration = Duration::from_secs(1);
+
+/// Feed the frames of a console from a capture to the servers then print what they respond with.
+///
+/// AC-Cookie and SESSION_ID are different on every run so the values in the frames from the
+/// console are replaced with the latest one that was sent by the servers.
+pub struct Replay {
+    console: MacAddr6,
+    frames: Vec<Frame>,
+    cookie: Option<Vec<u8>>,
+    session: Option<u16>,
+}
+
+impl Replay {
+    /// Returns [`None`] if there is no PADI in `frames`. The console is the sender of the first
+    /// PADI and only its frames will be replayed.
+    pub fn new(frames: Vec<Frame>) -> Option<Self> {
+        let console = frames.iter().find_map(|f| {
+            let d = &f.data;
+
+            if d.get(12..14)? == ETHERTYPE_DISCOVERY.to_be_bytes()
+                && *d.get(15)? == Code::Padi.into()
+            {
+                Some(MacAddr6::from(
+                    TryInto::<[u8; 6]>::try_into(&d[6..12]).unwrap(),
+                ))
+            } else {
+                None
+            }
+        })?;
+
+        let frames = frames
+            .into_iter()
+            .filter(|f| {
+                let d = &f.data;
+
+                d.len() > 14
+                    && d[6..12] == *console.as_bytes()
+                    && [ETHERTYPE_DISCOVERY, ETHERTYPE_SESSION]
+                        .contains(&u16::from_be_bytes([d[12], d[13]]))
+            })
+            .collect();
+
+        Some(Self {
+            console,
+            frames,
+            cookie: None,
+            session: None,
+        })
+    }
+
+    pub fn console(&self) -> MacAddr6 {
+        self.console
+    }
+
+    /// Feed the frames with the original timing. `discovery` and `session` are the other end of
+    /// the sockets of the discovery server and the session server.
+    pub async fn run(mut self, discovery: &mut MockPeer, session: &mut MockPeer) {
+        let frames = std::mem::take(&mut self.frames);
+        let mut last = None;
+
+        for f in frames {
+            // Wait for the time between the frames.
+            if let Some(t) = last {
+                rt::sleep(f.time.saturating_sub(t).min(MAX_GAP)).await;
+            }
+
+            last = Some(f.time);
+
+            self.collect(discovery, session);
+
+            // Replace the values that are specific to the original run.
+            let proto = u16::from_be_bytes([f.data[12], f.data[13]]);
+            let data = self.rewrite(proto, &f.data[14..]);
+            let addr = self.addr(proto, &f.data[..6]);
+
+            println!("> {}", decode(&addr, &data, EtherTypes::default()));
+
+            match proto {
+                ETHERTYPE_DISCOVERY => discovery.send(&data, addr),
+                _ => session.send(&data, addr),
+            }
+        }
+
+        rt::sleep(SETTLE).await;
+
+        self.collect(discovery, session);
+    }
+
+    /// Print the frames that was sent by the servers and learn AC-Cookie and SESSION_ID from it.
+    fn collect(&mut self, discovery: &mut MockPeer, session: &mut MockPeer) {
+        while let Some((data, addr)) = discovery.try_recv() {
+            println!("< {}", decode(&addr, &data, EtherTypes::default()));
+
+            let data = match EthernetPayload::<Tags>::deserialize(&data) {
+                Some(v) => v,
+                None => continue,
+            };
+
+            match Code::try_from(data.code()) {
+                Ok(Code::Pado) => self.cookie = data.ac_cookie().map(|v| v.to_vec()),
+                Ok(Code::Pads) if data.session_id() != 0 => self.session = Some(data.session_id()),
+                _ => {}
+            }
+        }
+
+        while let Some((data, addr)) = session.try_recv() {
+            println!("< {}", decode(&addr, &data, EtherTypes::default()));
+        }
+    }
+
+    fn rewrite(&self, proto: u16, data: &[u8]) -> Vec<u8> {
+        let mut data = data.to_vec();
+
+        if proto == ETHERTYPE_DISCOVERY && data.get(1) == Some(&Code::Padr.into()) {
+            if let (Some(cookie), Some(mut padr)) =
+                (&self.cookie, EthernetPayload::<Tags>::deserialize(&data))
+            {
+                for (t, v) in padr.payload_mut() {
+                    if *t == Tag::AcCookie.into() {
+                        *v = Cow::Borrowed(cookie);
+                    }
+                }
+
+                return padr.serialize();
+            }
+        }
+
+        // PADT and the session frames.
+        let padt = proto == ETHERTYPE_DISCOVERY && data.get(1) == Some(&Code::Padt.into());
+
+        if let (true, Some(id)) = (padt || proto == ETHERTYPE_SESSION, self.session) {
+            if data.len() >= 4 {
+                data[2..4].copy_from_slice(&id.to_be_bytes());
+            }
+        }
+
+        data
+    }
+
+    /// Returns the address of a frame from the console to `dst`.
+    fn addr(&self, proto: u16, dst: &[u8]) -> sockaddr_ll {
+        let mut addr: sockaddr_ll = unsafe { zeroed() };
+
+        addr.sll_family = AF_PACKET as _;
+        addr.sll_protocol = proto.to_be();
+        addr.sll_hatype = ARPHRD_ETHER;
+        addr.sll_pkttype = if dst == [0xff; 6] {
+            PACKET_BROADCAST
+        } else {
+            PACKET_HOST
+        };
+        addr.sll_halen = 6;
+        addr.sll_addr[..6].copy_from_slice(self.console.as_bytes());
+
+        addr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(src: [u8; 6], proto: u16, payload: &[u8]) -> Frame {
+        let mut data = vec![0xff; 6];
+
+        data.extend_from_slice(&src);
+        data.extend_from_slice(&proto.to_be_bytes());
+        data.extend_from_slice(payload);
+
+        Frame {
+            time: Duration::ZERO,
+            data,
+        }
+    }
+
+    #[test]
+    fn rewrite() {
+        let console = [2, 0, 0, 0, 0, 2];
+        let sn = (Tag::ServiceName.into(), Cow::Borrowed(&[][..]));
+        let padi = EthernetPayload::new(Code::Padi.into(), 0, vec![sn.clone()]);
+        let pado = EthernetPayload::new(Code::Pado.into(), 0, vec![sn]);
+        let padr = EthernetPayload::new(
+            Code::Padr.into(),
+            0,
+            vec![(Tag::AcCookie.into(), Cow::Borrowed(&[1u8][..]))],
+        );
+        let frames = vec![
+            frame([2, 0, 0, 0, 0, 1], ETHERTYPE_DISCOVERY, &pado.serialize()),
+            frame(console, ETHERTYPE_DISCOVERY, &padi.serialize()),
+            frame(
+                console,
+                ETHERTYPE_SESSION,
+                &[0x11, 0, 0, 7, 0, 2, 0xc0, 0x21],
+            ),
+        ];
+
+        let mut r = Replay::new(frames).unwrap();
+
+        assert_eq!(r.console(), MacAddr6::from(console));
+        assert_eq!(r.frames.len(), 2);
+
+        r.cookie = Some(vec![2, 3]);
+        r.session = Some(5);
+
+        let padr = r.rewrite(ETHERTYPE_DISCOVERY, &padr.serialize());
+        let padr = EthernetPayload::<Tags>::deserialize(&padr).unwrap();
+
+        assert_eq!(padr.payload()[0].1.as_ref(), [2, 3]);
+        assert_eq!(
+            r.rewrite(ETHERTYPE_SESSION, &r.frames[1].data[14..]),
+            [0x11, 0, 0, 5, 0, 2, 0xc0, 0x21]
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use libc::{
+    mmap, munmap, setsockopt, sockaddr_ll, tpacket2_hdr, tpacket_req, tpacket_versions, MAP_FAILED,
+    MAP_NORESERVE, MAP_SHARED, PACKET_RX_RING, PACKET_VERSION, PROT_READ, PROT_WRITE, SOL_PACKET,
+    TPACKET_ALIGNMENT, TP_STATUS_KERNEL, TP_STATUS_USER,
+};
+use std::ffi::{c_int, c_void};
+use std::io::{Error, ErrorKind};
+use std::mem::size_of_val;
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+/// Size of each frame in the ring, which is enough for a full Ethernet frame with the headers.
+const FRAME_SIZE: usize = 2048;
+
+/// Size of each block in the ring.
+const BLOCK_SIZE: usize = 1 << 16;
+
+/// `PACKET_RX_RING` with `TPACKET_V2` that was mapped into our address space.
+///
+/// The kernel write each received frame directly into the ring so receiving is only a copy from
+/// the mapped memory without any syscall.
+pub struct RxRing {
+    map: *mut u8,
+    len: usize,
+    frames: usize,
+    head: Mutex<usize>,
+}
+
+impl RxRing {
+    /// Setup a ring with at least `frames` frames on `fd`, which must be a packet socket without
+    /// any ring.
+    pub fn new(fd: c_int, frames: usize) -> Result<Self, Error> {
+        let setopt = |opt, val: *const c_void, len: usize| {
+            if unsafe { setsockopt(fd, SOL_PACKET, opt, val, len.try_into().unwrap()) } < 0 {
+                Err(Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        };
+
+        // Switch to TPACKET_V2 since the layout of V1 depend on the word size.
+        let version = tpacket_versions::TPACKET_V2 as c_int;
+
+        setopt(
+            PACKET_VERSION,
+            &version as *const c_int as _,
+            size_of_val(&version),
+        )?;
+
+        // Setup the ring. The kernel require the number of frames to be a multiple of the frames
+        // per block.
+        let per_block = BLOCK_SIZE / FRAME_SIZE;
+        let blocks = frames.div_ceil(per_block).max(1);
+        let req = tpacket_req {
+            tp_block_size: BLOCK_SIZE.try_into().unwrap(),
+            tp_block_nr: blocks
+                .try_into()
+                .map_err(|_| Error::from(ErrorKind::InvalidInput))?,
+            tp_frame_size: FRAME_SIZE.try_into().unwrap(),
+            tp_frame_nr: (blocks * per_block)
+                .try_into()
+                .map_err(|_| Error::from(ErrorKind::InvalidInput))?,
+        };
+
+        setopt(
+            PACKET_RX_RING,
+            &req as *const tpacket_req as _,
+            size_of_val(&req),
+        )?;
+
+        // Map the ring.
+        let len = blocks * BLOCK_SIZE;
+        let map = unsafe {
+            mmap(
+                null_mut(),
+                len,
+                PROT_READ | PROT_WRITE,
+                MAP_SHARED | MAP_NORESERVE,
+                fd,
+                0,
+            )
+        };
+
+        if map == MAP_FAILED {
+            return Err(Error::last_os_error());
+        }
+
+        Ok(Self {
+            map: map.cast(),
+            len,
+            frames: blocks * per_block,
+            head: Mutex::new(0),
+        })
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Copy the next frame into `buf` without the first `skip` bytes that is visible to the
+    /// socket. Returns [`ErrorKind::WouldBlock`] if the ring is empty.
+    pub fn recv(&self, buf: &mut [u8], skip: usize) -> Result<(usize, sockaddr_ll), Error> {
+        let mut head = self.head.lock().unwrap();
+        let frame = unsafe { self.map.add(*head * FRAME_SIZE) };
+        let status = unsafe { AtomicU32::from_ptr(frame.cast()) };
+
+        if status.load(Ordering::Acquire) & TP_STATUS_USER == 0 {
+            return Err(Error::from(ErrorKind::WouldBlock));
+        }
+
+        // Get the frame.
+        let hdr = unsafe { frame.cast::<tpacket2_hdr>().read() };
+        let addr = unsafe {
+            frame
+                .add(align(size_of_val(&hdr)))
+                .cast::<sockaddr_ll>()
+                .read_unaligned()
+        };
+
+        // A frame shorter than `skip` will be returned as an empty frame.
+        let len = usize::try_from(hdr.tp_snaplen)
+            .unwrap()
+            .saturating_sub(skip);
+        let start = usize::from(hdr.tp_mac) + skip;
+        let copied = len.min(buf.len());
+        let data = unsafe { std::slice::from_raw_parts(frame.add(start), copied) };
+
+        buf[..copied].copy_from_slice(data);
+
+        // Give the frame back to the kernel.
+        status.store(TP_STATUS_KERNEL, Ordering::Release);
+
+        *head = (*head + 1) % self.frames;
+
+        Ok((copied, addr))
+    }
+}
+
+impl Drop for RxRing {
+    fn drop(&mut self) {
+        unsafe { munmap(self.map.cast(), self.len) };
+    }
+}
+
+// The frames are only accessed while holding the head.
+unsafe impl Send for RxRing {}
+unsafe impl Sync for RxRing {}
+
+fn align(v: usize) -> usize {
+    (v + TPACKET_ALIGNMENT - 1) & !(TPACKET_ALIGNMENT - 1)
+}
@@ -0,0 +1,406 @@
+use super::{BoxFuture, Registration, Runtime, Signal};
+use libc::{
+    c_int, eventfd, poll, pollfd, read, sigaddset, sigemptyset, sigismember, signalfd,
+    signalfd_siginfo, sigprocmask, sigset_t, write, EFD_NONBLOCK, POLLIN, SFD_NONBLOCK, SIGINT,
+    SIGUSR1, SIGUSR2, SIG_BLOCK,
+};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::mem::{size_of_val, zeroed};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::{Duration, Instant};
+
+/// Signals that can be received with [`Runtime::signal()`].
+const SIGNALS: [c_int; 3] = [SIGINT, SIGUSR1, SIGUSR2];
+
+/// Implementation of [`Runtime`] that run all tasks on the current thread with `poll(2)`.
+///
+/// This does not start the Tokio runtime (no reactor, timer or worker threads) so it has less
+/// jitter on a slow computer. Tokio is still linked since the servers use its channels, `Notify`
+/// and `select!`, which work on any executor.
+#[derive(Clone)]
+pub struct Local(Arc<Inner>);
+
+impl Local {
+    /// This must be called before any other threads has been spawned since it need to block the
+    /// signals for [`Runtime::ctrl_c()`] and [`Runtime::signal()`].
+    pub fn new() -> Result<Self, Error> {
+        // Create eventfd to interrupt poll.
+        let wakeup = unsafe { eventfd(0, EFD_NONBLOCK) };
+
+        if wakeup < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        let wakeup = unsafe { OwnedFd::from_raw_fd(wakeup) };
+
+        // Block the signals so we can receive it with signalfd.
+        let mut blocked: sigset_t = unsafe { zeroed() };
+
+        unsafe { sigemptyset(&mut blocked) };
+
+        for sig in SIGNALS {
+            unsafe { sigaddset(&mut blocked, sig) };
+        }
+
+        if unsafe { sigprocmask(SIG_BLOCK, &blocked, std::ptr::null_mut()) } < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        let signal = open_signal(SIGINT)?;
+
+        Ok(Self(Arc::new(Inner {
+            queue: Mutex::default(),
+            timers: Mutex::default(),
+            next_timer: AtomicU64::new(0),
+            waiters: Mutex::default(),
+            wakeup,
+            blocked,
+            signal,
+        })))
+    }
+
+    /// Run `f` and all spawned tasks until `f` completed.
+    pub fn block_on<F>(&self, f: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send,
+    {
+        let output = Arc::new(Mutex::new(None));
+
+        self.spawn(Box::pin({
+            let output = output.clone();
+
+            async move { *output.lock().unwrap() = Some(f.await) }
+        }));
+
+        loop {
+            // Run all ready tasks.
+            loop {
+                let task = match self.0.queue.lock().unwrap().pop_front() {
+                    Some(v) => v,
+                    None => break,
+                };
+
+                task.poll();
+            }
+
+            if let Some(v) = output.lock().unwrap().take() {
+                break v;
+            }
+
+            self.0.wait();
+        }
+    }
+}
+
+impl Runtime for Local {
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(task)),
+            rt: Arc::downgrade(&self.0),
+        });
+
+        self.0.schedule(task);
+    }
+
+    fn sleep(&self, dur: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(Sleep {
+            rt: self.0.clone(),
+            id: self.0.next_timer.fetch_add(1, Ordering::Relaxed),
+            deadline: Instant::now() + dur,
+        })
+    }
+
+    fn ctrl_c(&self) -> BoxFuture<'static, Result<(), Error>> {
+        let rt = self.0.clone();
+
+        Box::pin(async move { rt.recv_signal(rt.signal.as_raw_fd()).await })
+    }
+
+    fn register(&self, fd: OwnedFd) -> Result<Box<dyn Registration>, Error> {
+        Ok(Box::new(LocalFd {
+            rt: self.0.clone(),
+            fd,
+        }))
+    }
+
+    fn signal(&self, sig: c_int) -> Result<Box<dyn Signal>, Error> {
+        if unsafe { sigismember(&self.0.blocked, sig) } != 1 {
+            return Err(Error::from(ErrorKind::Unsupported));
+        }
+
+        Ok(Box::new(LocalSignal {
+            rt: self.0.clone(),
+            fd: open_signal(sig)?,
+        }))
+    }
+}
+
+struct Inner {
+    queue: Mutex<VecDeque<Arc<Task>>>,
+    timers: Mutex<HashMap<u64, (Instant, Waker)>>,
+    next_timer: AtomicU64,
+    waiters: Mutex<HashMap<RawFd, Waker>>,
+    wakeup: OwnedFd,
+    blocked: sigset_t,
+    signal: OwnedFd,
+}
+
+impl Inner {
+    fn schedule(&self, task: Arc<Task>) {
+        self.queue.lock().unwrap().push_back(task);
+
+        // Interrupt poll in case we are not on the runtime thread.
+        let v = 1u64;
+
+        unsafe { write(self.wakeup.as_raw_fd(), &v as *const u64 as _, 8) };
+    }
+
+    /// Read a signal from signalfd `fd`.
+    async fn recv_signal(&self, fd: RawFd) -> Result<(), Error> {
+        loop {
+            let mut info: signalfd_siginfo = unsafe { zeroed() };
+            let len = size_of_val(&info);
+
+            if unsafe { read(fd, &mut info as *mut signalfd_siginfo as _, len) } >= 0 {
+                break Ok(());
+            }
+
+            let e = Error::last_os_error();
+
+            if e.kind() != ErrorKind::WouldBlock {
+                break Err(e);
+            }
+
+            Readable {
+                rt: self,
+                fd,
+                registered: false,
+            }
+            .await;
+        }
+    }
+
+    /// Wait for timers or file descriptors.
+    fn wait(&self) {
+        // Get timeout.
+        let now = Instant::now();
+        let timeout = match self.timers.lock().unwrap().values().map(|v| v.0).min() {
+            Some(v) => v
+                .saturating_duration_since(now)
+                .as_millis()
+                .saturating_add(1)
+                .try_into()
+                .unwrap_or(i32::MAX),
+            None => -1,
+        };
+
+        // Setup file descriptors.
+        let mut fds = vec![pollfd {
+            fd: self.wakeup.as_raw_fd(),
+            events: POLLIN,
+            revents: 0,
+        }];
+
+        fds.extend(self.waiters.lock().unwrap().keys().map(|&fd| pollfd {
+            fd,
+            events: POLLIN,
+            revents: 0,
+        }));
+
+        // Wait.
+        if unsafe { poll(fds.as_mut_ptr(), fds.len() as _, timeout) } < 0 {
+            let e = Error::last_os_error();
+
+            if e.kind() != ErrorKind::Interrupted {
+                panic!("poll failed: {e}");
+            }
+        }
+
+        // Clear wakeup.
+        let mut v = 0u64;
+
+        if fds[0].revents != 0 {
+            unsafe { read(self.wakeup.as_raw_fd(), &mut v as *mut u64 as _, 8) };
+        }
+
+        // Wake the tasks that waiting on file descriptors. Any events including error should wake
+        // the task since it will get the error from the operation.
+        let mut waiters = self.waiters.lock().unwrap();
+
+        for p in &fds[1..] {
+            if p.revents != 0 {
+                if let Some(w) = waiters.remove(&p.fd) {
+                    w.wake();
+                }
+            }
+        }
+
+        drop(waiters);
+
+        // Wake expired timers. The timer will be removed when the future is polled.
+        let now = Instant::now();
+
+        for (deadline, waker) in self.timers.lock().unwrap().values() {
+            if *deadline <= now {
+                waker.wake_by_ref();
+            }
+        }
+    }
+}
+
+/// Spawned task on [`Local`].
+struct Task {
+    future: Mutex<Option<BoxFuture<'static, ()>>>,
+    rt: Weak<Inner>,
+}
+
+impl Task {
+    fn poll(self: Arc<Self>) {
+        let waker = Waker::from(self.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut future = self.future.lock().unwrap();
+
+        if let Some(f) = future.as_mut() {
+            if f.as_mut().poll(&mut cx).is_ready() {
+                *future = None;
+            }
+        }
+    }
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        if let Some(rt) = self.rt.upgrade() {
+            rt.schedule(self);
+        }
+    }
+}
+
+/// Implementation of [`Registration`] for [`Local`].
+struct LocalFd {
+    rt: Arc<Inner>,
+    fd: OwnedFd,
+}
+
+impl Registration for LocalFd {
+    fn fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    fn readable(&self) -> BoxFuture<'_, Result<(), Error>> {
+        let fd = self.fd.as_raw_fd();
+
+        Box::pin(async move {
+            Readable {
+                rt: &self.rt,
+                fd,
+                registered: false,
+            }
+            .await;
+
+            Ok(())
+        })
+    }
+}
+
+/// Implementation of [`Signal`] for [`Local`].
+struct LocalSignal {
+    rt: Arc<Inner>,
+    fd: OwnedFd,
+}
+
+impl Signal for LocalSignal {
+    fn recv(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(self.rt.recv_signal(self.fd.as_raw_fd()))
+    }
+}
+
+/// Future to wait until `fd` became readable.
+struct Readable<'a> {
+    rt: &'a Inner,
+    fd: RawFd,
+    registered: bool,
+}
+
+impl Future for Readable<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut waiters = self.rt.waiters.lock().unwrap();
+
+        // The waker will be removed by the runtime when the file descriptor is readable.
+        if self.registered && !waiters.contains_key(&self.fd) {
+            drop(waiters);
+            self.registered = false;
+            return Poll::Ready(());
+        }
+
+        waiters.insert(self.fd, cx.waker().clone());
+        drop(waiters);
+
+        self.registered = true;
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Readable<'_> {
+    fn drop(&mut self) {
+        if self.registered {
+            self.rt.waiters.lock().unwrap().remove(&self.fd);
+        }
+    }
+}
+
+/// Future for [`Runtime::sleep()`] on [`Local`].
+struct Sleep {
+    rt: Arc<Inner>,
+    id: u64,
+    deadline: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut timers = self.rt.timers.lock().unwrap();
+
+        if Instant::now() >= self.deadline {
+            timers.remove(&self.id);
+            return Poll::Ready(());
+        }
+
+        timers.insert(self.id, (self.deadline, cx.waker().clone()));
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.rt.timers.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Create a non-blocking signalfd for `sig`. The signal must be already blocked.
+fn open_signal(sig: c_int) -> Result<OwnedFd, Error> {
+    let mut set: sigset_t = unsafe { zeroed() };
+
+    unsafe { sigemptyset(&mut set) };
+    unsafe { sigaddset(&mut set, sig) };
+
+    let fd = unsafe { signalfd(-1, &set, SFD_NONBLOCK) };
+
+    if fd < 0 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+}
@@ -0,0 +1,103 @@
+pub use self::local::*;
+pub use self::tokio::*;
+use std::ffi::c_int;
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+mod local;
+mod tokio;
+
+static RUNTIME: OnceLock<Box<dyn Runtime>> = OnceLock::new();
+
+/// Async runtime to run the servers.
+///
+/// All spawning, timers, signal handling and I/O readiness are done through the runtime installed
+/// with [`install()`] so the servers can run on any executor.
+pub trait Runtime: Send + Sync {
+    fn spawn(&self, task: BoxFuture<'static, ()>);
+    fn sleep(&self, dur: Duration) -> BoxFuture<'static, ()>;
+    fn ctrl_c(&self) -> BoxFuture<'static, Result<(), Error>>;
+    fn register(&self, fd: OwnedFd) -> Result<Box<dyn Registration>, Error>;
+    fn signal(&self, sig: c_int) -> Result<Box<dyn Signal>, Error>;
+}
+
+/// Non-blocking file descriptor that was registered with [`Runtime`].
+pub trait Registration: Send + Sync {
+    fn fd(&self) -> RawFd;
+
+    /// Wait until the file descriptor is readable. This may return spuriously.
+    fn readable(&self) -> BoxFuture<'_, Result<(), Error>>;
+}
+
+/// Stream of a Unix signal that was registered with [`Runtime`].
+pub trait Signal: Send {
+    /// Wait for the next delivery of the signal.
+    fn recv(&mut self) -> BoxFuture<'_, Result<(), Error>>;
+}
+
+/// Non-blocking file descriptor on the installed runtime.
+pub struct Fd(Box<dyn Registration>);
+
+impl Fd {
+    pub fn new(fd: OwnedFd) -> Result<Self, Error> {
+        get().register(fd).map(Self)
+    }
+
+    /// Invoke `f` until it does not fail with [`ErrorKind::WouldBlock`].
+    pub async fn read_with<T>(
+        &self,
+        mut f: impl FnMut(RawFd) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        loop {
+            match f(self.0.fd()) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => self.0.readable().await?,
+                r => break r,
+            }
+        }
+    }
+}
+
+impl AsRawFd for Fd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.fd()
+    }
+}
+
+/// Install `rt` as the runtime. This can be called only once and must be called before any other
+/// functions in this module.
+pub fn install(rt: impl Runtime + 'static) {
+    if RUNTIME.set(Box::new(rt)).is_err() {
+        panic!("the runtime is already installed");
+    }
+}
+
+pub fn spawn(task: impl Future<Output = ()> + Send + 'static) {
+    get().spawn(Box::pin(task))
+}
+
+pub fn sleep(dur: Duration) -> BoxFuture<'static, ()> {
+    get().sleep(dur)
+}
+
+/// Wait for Ctrl+C.
+pub fn ctrl_c() -> BoxFuture<'static, Result<(), Error>> {
+    get().ctrl_c()
+}
+
+/// Listen for `sig`.
+pub fn signal(sig: c_int) -> Result<Box<dyn Signal>, Error> {
+    get().signal(sig)
+}
+
+fn get() -> &'static dyn Runtime {
+    RUNTIME
+        .get()
+        .expect("no runtime has been installed")
+        .as_ref()
+}
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
@@ -0,0 +1,69 @@
+use super::{BoxFuture, Registration, Runtime, Signal};
+use std::ffi::c_int;
+use std::io::Error;
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::time::Duration;
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+use tokio::signal::unix::SignalKind;
+
+/// Implementation of [`Runtime`] using Tokio.
+pub struct Tokio;
+
+impl Runtime for Tokio {
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        tokio::spawn(task);
+    }
+
+    fn sleep(&self, dur: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(dur))
+    }
+
+    fn ctrl_c(&self) -> BoxFuture<'static, Result<(), Error>> {
+        Box::pin(tokio::signal::ctrl_c())
+    }
+
+    fn register(&self, fd: OwnedFd) -> Result<Box<dyn Registration>, Error> {
+        let fd = AsyncFd::with_interest(fd, Interest::READABLE)?;
+
+        Ok(Box::new(TokioFd(fd)))
+    }
+
+    fn signal(&self, sig: c_int) -> Result<Box<dyn Signal>, Error> {
+        let s = tokio::signal::unix::signal(SignalKind::from_raw(sig))?;
+
+        Ok(Box::new(TokioSignal(s)))
+    }
+}
+
+/// Implementation of [`Registration`] using Tokio.
+struct TokioFd(AsyncFd<OwnedFd>);
+
+impl Registration for TokioFd {
+    fn fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+
+    fn readable(&self) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async {
+            // The caller always try the operation before waiting so we can clear the readiness
+            // here.
+            self.0.readable().await?.clear_ready();
+            Ok(())
+        })
+    }
+}
+
+/// Implementation of [`Signal`] using Tokio.
+struct TokioSignal(tokio::signal::unix::Signal);
+
+impl Signal for TokioSignal {
+    fn recv(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async {
+            match self.0.recv().await {
+                Some(_) => Ok(()),
+                None => Err(Error::other("the signal stream has been closed")),
+            }
+        })
+    }
+}
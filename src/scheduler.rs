@@ -0,0 +1,141 @@
+use crate::rt;
+use crate::socket::PacketSocket;
+use crate::telemetry::SendTiming;
+use libc::{
+    itimerspec, read, sockaddr_ll, timerfd_create, timerfd_settime, timespec, CLOCK_MONOTONIC,
+    TFD_CLOEXEC, TFD_NONBLOCK,
+};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::hint::spin_loop;
+use std::io::Error;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::{Duration, Instant};
+
+/// Transmit frames at precise instants.
+///
+/// The scheduler sleeps on a timerfd until shortly before the deadline then busy-wait for the rest
+/// since the timer alone has too much jitter. This block the runtime during the busy-wait so it
+/// should be used only when the spacing between the frames matter more than anything else.
+pub struct Scheduler<'a> {
+    sock: &'a PacketSocket,
+    timer: rt::Fd,
+    spin: Duration,
+    queue: BinaryHeap<Reverse<Frame>>,
+    next: u64,
+}
+
+impl<'a> Scheduler<'a> {
+    /// `spin` is the duration to busy-wait before each deadline.
+    pub fn new(sock: &'a PacketSocket, spin: Duration) -> Result<Self, Error> {
+        let timer = unsafe { timerfd_create(CLOCK_MONOTONIC, TFD_NONBLOCK | TFD_CLOEXEC) };
+
+        if timer < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        Ok(Self {
+            sock,
+            timer: rt::Fd::new(unsafe { OwnedFd::from_raw_fd(timer) })?,
+            spin,
+            queue: BinaryHeap::new(),
+            next: 0,
+        })
+    }
+
+    /// Queue `data` to be sent to `addr` at `at`. The frames with the same instant will be sent in
+    /// the order they were queued.
+    pub fn push(&mut self, at: Instant, addr: sockaddr_ll, data: impl Into<Vec<u8>>) {
+        let seq = self.next;
+
+        self.next += 1;
+        self.queue.push(Reverse(Frame {
+            at,
+            seq,
+            addr,
+            data: data.into(),
+        }));
+    }
+
+    /// Send all queued frames. The frames that are already late will be sent immediately.
+    pub async fn run(&mut self, timing: &SendTiming) -> Result<(), Error> {
+        while let Some(Reverse(f)) = self.queue.pop() {
+            self.wait(f.at).await?;
+            self.sock.send_timed(f.addr, &f.data, timing)?;
+        }
+
+        Ok(())
+    }
+
+    async fn wait(&self, at: Instant) -> Result<(), Error> {
+        // Sleep on the timer.
+        let now = Instant::now();
+
+        if let Some(v) = at.checked_sub(self.spin).filter(|&v| v > now) {
+            let dur = v - now;
+            let spec = itimerspec {
+                it_interval: timespec {
+                    tv_sec: 0,
+                    tv_nsec: 0,
+                },
+                it_value: timespec {
+                    tv_sec: dur.as_secs().try_into().unwrap(),
+                    tv_nsec: dur.subsec_nanos().max(1).into(),
+                },
+            };
+
+            if unsafe { timerfd_settime(self.timer.as_raw_fd(), 0, &spec, std::ptr::null_mut()) }
+                < 0
+            {
+                return Err(Error::last_os_error());
+            }
+
+            self.timer
+                .read_with(|fd| {
+                    let mut expirations = 0u64;
+
+                    if unsafe { read(fd, &mut expirations as *mut u64 as _, 8) } < 0 {
+                        Err(Error::last_os_error())
+                    } else {
+                        Ok(())
+                    }
+                })
+                .await?;
+        }
+
+        // Busy-wait for the rest.
+        while Instant::now() < at {
+            spin_loop();
+        }
+
+        Ok(())
+    }
+}
+
+/// A frame in the queue of [`Scheduler`].
+struct Frame {
+    at: Instant,
+    seq: u64,
+    addr: sockaddr_ll,
+    data: Vec<u8>,
+}
+
+impl PartialEq for Frame {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Frame {}
+
+impl PartialOrd for Frame {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Frame {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.at, self.seq).cmp(&(other.at, other.seq))
+    }
+}
@@ -0,0 +1,185 @@
+use crate::cookie::{CookieJar, COOKIE_LEN};
+use crate::proto::lcp::MAX_MRU;
+use crate::proto::ppp::{parse_packet, Options, CONFIGURE_REJECT, CONFIGURE_REQUEST, PROTO_PAP};
+use crate::proto::pppoe::{Code, EthernetPayload, Padding, Tag, Tags, MIN_PAYLOAD};
+use erdp::ErrorDisplay;
+use std::borrow::Cow;
+
+/// Maximum length of Ethernet payload.
+const MAX_PAYLOAD: usize = 1500;
+
+/// Result of a single check.
+pub struct Check {
+    pub name: String,
+    pub result: Result<(), String>,
+}
+
+/// Build every kind of frames we can send and parse them back with our own parsers.
+///
+/// This does not touch the network so it can catch a broken build before sending anything to the
+/// console.
+pub fn run() -> Vec<Check> {
+    let mut checks = Vec::new();
+    let mut push = |name: &str, result| {
+        checks.push(Check {
+            name: name.into(),
+            result,
+        })
+    };
+
+    // Discovery stage. The values are the longest one we can send.
+    let ac_name = [b'A'; 255];
+    let service = [b'S'; 255];
+    let host_uniq = [0x55; 255];
+    let jar = CookieJar::new();
+    let peer = [2, 0, 0, 0, 0, 1].into();
+    let cookie = match &jar {
+        Ok(v) => v.issue(peer),
+        Err(_) => [0; COOKIE_LEN],
+    };
+    let frames: [(&str, Code, u16, Tags); 4] = [
+        (
+            "PADO",
+            Code::Pado,
+            0,
+            vec![
+                (Tag::AcName.into(), Cow::Borrowed(&ac_name)),
+                (Tag::ServiceName.into(), Cow::Borrowed(&service)),
+                (Tag::HostUniq.into(), Cow::Borrowed(&host_uniq)),
+                (Tag::AcCookie.into(), Cow::Borrowed(&cookie)),
+            ],
+        ),
+        (
+            "PADS",
+            Code::Pads,
+            0xffff,
+            vec![
+                (Tag::ServiceName.into(), Cow::Borrowed(&service)),
+                (Tag::HostUniq.into(), Cow::Borrowed(&host_uniq)),
+            ],
+        ),
+        (
+            "PADS with error",
+            Code::Pads,
+            0,
+            vec![
+                (Tag::ServiceName.into(), Cow::Borrowed(&service)),
+                (Tag::AcSystemError.into(), Cow::Borrowed(&ac_name)),
+                (Tag::HostUniq.into(), Cow::Borrowed(&host_uniq)),
+            ],
+        ),
+        ("PADT", Code::Padt, 0xffff, Vec::new()),
+    ];
+
+    for (name, code, id, tags) in frames {
+        push(name, discovery(code.into(), id, tags));
+    }
+
+    match jar {
+        Ok(v) if v.verify(peer, &cookie) && !v.verify([2, 0, 0, 0, 0, 2].into(), &cookie) => {
+            push("AC-Cookie", Ok(()))
+        }
+        Ok(_) => push("AC-Cookie", Err("cookie does not verify".into())),
+        Err(e) => push(
+            "AC-Cookie",
+            Err(format!("failed to generate the key: {}", e.display())),
+        ),
+    }
+
+    // Session stage.
+    for (name, proto, packet) in crate::session::sample_packets() {
+        push(name, session(proto, &packet));
+    }
+
+    #[cfg(feature = "exploit")]
+    push("exploit", crate::exploit::self_check());
+
+    checks
+}
+
+fn discovery(code: u8, id: u16, tags: Tags) -> Result<(), String> {
+    let frame = EthernetPayload::new(code, id, tags);
+    let data = roundtrip(&frame.serialize())?;
+    let parsed =
+        EthernetPayload::<Tags>::deserialize(&data).ok_or("failed to parse the serialized tags")?;
+
+    if parsed.code() != code || parsed.session_id() != id {
+        Err("header does not match".into())
+    } else if parsed.payload() != frame.payload() {
+        Err("tags does not match".into())
+    } else {
+        Ok(())
+    }
+}
+
+fn session(proto: u16, packet: &[u8]) -> Result<(), String> {
+    // Check the length limit before serialize since it will panic.
+    if packet.len() > MAX_MRU.into() {
+        return Err(format!("{} bytes is larger than MRU", packet.len()));
+    }
+
+    let mut ppp = proto.to_be_bytes().to_vec();
+
+    ppp.extend_from_slice(packet);
+
+    let data = roundtrip(&EthernetPayload::new(0x00, 1, Cow::Owned(ppp.clone())).serialize())?;
+    let parsed = EthernetPayload::<Cow<[u8]>>::deserialize(&data)
+        .ok_or("failed to parse the serialized frame")?;
+
+    if parsed.code() != 0x00 || parsed.session_id() != 1 || *parsed.payload() != ppp {
+        return Err("frame does not match".into());
+    }
+
+    // Check the control packet.
+    let (code, _, data) = parse_packet(packet).ok_or("failed to parse the control packet")?;
+
+    if 4 + data.len() != packet.len() {
+        return Err("LENGTH does not match the packet".into());
+    }
+
+    // PAP has the same codes with different contents.
+    if proto != PROTO_PAP && (CONFIGURE_REQUEST..=CONFIGURE_REJECT).contains(&code) {
+        let len: usize = Options::new(data).map(|(_, _, raw)| raw.len()).sum();
+
+        if len != data.len() {
+            return Err("malformed options".into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Pad `data` the same way as the NIC then strip it with [`Padding::Strict`].
+fn roundtrip(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() > MAX_PAYLOAD {
+        return Err(format!(
+            "{} bytes is larger than Ethernet payload",
+            data.len()
+        ));
+    }
+
+    let mut padded = data.to_vec();
+
+    if padded.len() < MIN_PAYLOAD {
+        padded.resize(MIN_PAYLOAD, 0);
+    }
+
+    match Padding::Strict.trim(&padded) {
+        Some(v) if v == data => Ok(v.to_vec()),
+        _ => Err("padding was not stripped correctly".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pass() {
+        for c in run() {
+            if let Err(e) = c.result {
+                panic!("{}: {}", c.name, e);
+            }
+        }
+    }
+}
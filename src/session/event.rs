@@ -0,0 +1,90 @@
+use crate::failure::Failure;
+use macaddr::MacAddr6;
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::net::Ipv4Addr;
+use std::num::NonZeroU16;
+use std::pin::Pin;
+
+/// Lifecycle event of a PPPoE session.
+#[derive(Clone)]
+pub struct SessionEvent {
+    pub id: NonZeroU16,
+    pub peer: MacAddr6,
+    pub kind: EventKind,
+}
+
+/// Kind of [`SessionEvent`].
+#[derive(Clone)]
+pub enum EventKind {
+    Opened,
+    /// LCP has been opened.
+    LcpUp,
+    /// IPCP has been opened with the address of the peer.
+    IpcpUp(Ipv4Addr),
+    Closed(CloseReason),
+    Error(String),
+}
+
+impl Display for EventKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Opened => f.write_str("opened"),
+            Self::LcpUp => f.write_str("opened LCP"),
+            Self::IpcpUp(v) => write!(f, "opened IPCP with {v}"),
+            Self::Closed(r) => write!(f, "closed ({r})"),
+            Self::Error(e) => write!(f, "error ({e})"),
+        }
+    }
+}
+
+/// Why a session was closed.
+#[derive(Clone)]
+pub enum CloseReason {
+    /// The peer sent PADT.
+    Padt,
+    /// The peer sent LCP Terminate-Request.
+    LcpTerminated,
+    /// The peer did not reply to LCP Echo-Request.
+    EchoTimeout,
+    /// The peer sent a new PADI while the session is active.
+    Restarted,
+    /// The exploit did not finish in time.
+    Timeout,
+    /// Nothing was received from the peer for too long.
+    Inactive,
+    /// We closed the session (e.g. we are exiting).
+    Shutdown,
+    /// The exploit finished and the link was not kept.
+    ExploitFinished,
+    /// The exploit failed and the link was not kept.
+    ExploitFailed(Failure),
+    /// The kernel PPP channel was closed.
+    KernelClosed,
+    /// The peer violated the protocol in strict mode.
+    Violation(String),
+    /// The session was dropped without a reason (e.g. a server error).
+    Error(String),
+}
+
+impl Display for CloseReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Padt => f.write_str("PADT from the peer"),
+            Self::LcpTerminated => f.write_str("LCP Terminate-Request from the peer"),
+            Self::EchoTimeout => f.write_str("no reply for LCP Echo-Request"),
+            Self::Restarted => f.write_str("new PADI from the peer"),
+            Self::Timeout => f.write_str("attempt timed out"),
+            Self::Inactive => f.write_str("no traffic from the peer"),
+            Self::Shutdown => f.write_str("server shutdown"),
+            Self::ExploitFinished => f.write_str("exploit finished"),
+            Self::ExploitFailed(e) => write!(f, "exploit failed with {}", e.id()),
+            Self::KernelClosed => f.write_str("kernel PPP channel closed"),
+            Self::Violation(e) => write!(f, "protocol violation ({e})"),
+            Self::Error(e) => write!(f, "error ({e})"),
+        }
+    }
+}
+
+pub(super) type EventCallback =
+    Box<dyn Fn(SessionEvent) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
@@ -0,0 +1,85 @@
+use libc::{
+    sock_filter, BPF_ABS, BPF_H, BPF_JEQ, BPF_JMP, BPF_K, BPF_LD, BPF_MAXINSNS, BPF_RET, BPF_W,
+    SKF_LL_OFF,
+};
+use macaddr::MacAddr6;
+use std::num::NonZeroU16;
+
+/// Build a classic BPF program for PPPoE session socket that only accept frames from the active
+/// sessions.
+///
+/// `header` is the offset of PPPoE header, which is zero for `SOCK_DGRAM`. The source address is
+/// read from the link-layer header via `SKF_LL_OFF`. We can't use
+/// `PACKET_ADD_MEMBERSHIP` for this since it only control which multicast and promiscuous frames
+/// the interface will receive, not which unicast frames get delivered to the socket.
+///
+/// Each session takes 7 instructions so the kernel limit of [`BPF_MAXINSNS`] is reached at 586
+/// sessions. Returns [`None`] in this case, which should fall back to [`accept_all()`] since the
+/// frames are checked again in userspace.
+pub fn build_filter(
+    sessions: &[(NonZeroU16, MacAddr6)],
+    header: usize,
+) -> Option<Vec<sock_filter>> {
+    let len = sessions.len() * 7 + 1;
+
+    if len > BPF_MAXINSNS as usize {
+        return None;
+    }
+
+    let mut prog = Vec::with_capacity(len);
+    let src = (SKF_LL_OFF + 6) as u32;
+    let header: u32 = header.try_into().unwrap();
+
+    for (id, peer) in sessions {
+        let peer = peer.as_bytes();
+        let hi = u16::from_be_bytes(peer[..2].try_into().unwrap());
+        let lo = u32::from_be_bytes(peer[2..].try_into().unwrap());
+
+        // Each block jump to the next block when the frame does not belong to this session.
+        prog.push(stmt(BPF_LD | BPF_H | BPF_ABS, header + 2));
+        prog.push(jump(BPF_JMP | BPF_JEQ | BPF_K, id.get().into(), 0, 5));
+        prog.push(stmt(BPF_LD | BPF_H | BPF_ABS, src));
+        prog.push(jump(BPF_JMP | BPF_JEQ | BPF_K, hi.into(), 0, 3));
+        prog.push(stmt(BPF_LD | BPF_W | BPF_ABS, src + 2));
+        prog.push(jump(BPF_JMP | BPF_JEQ | BPF_K, lo, 0, 1));
+        prog.push(stmt(BPF_RET | BPF_K, u32::MAX));
+    }
+
+    prog.push(stmt(BPF_RET | BPF_K, 0));
+
+    Some(prog)
+}
+
+/// Build a classic BPF program that accept all frames.
+pub fn accept_all() -> Vec<sock_filter> {
+    vec![stmt(BPF_RET | BPF_K, u32::MAX)]
+}
+
+fn stmt(code: u32, k: u32) -> sock_filter {
+    jump(code, k, 0, 0)
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter {
+        code: code.try_into().unwrap(),
+        jt,
+        jf,
+        k,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_sessions() {
+        let sessions: Vec<_> = (1..=586)
+            .map(|i| (NonZeroU16::new(i).unwrap(), MacAddr6::new(2, 0, 0, 0, 0, 2)))
+            .collect();
+        let prog = build_filter(&sessions[..585], 0).unwrap();
+
+        assert_eq!(prog.len(), BPF_MAXINSNS as usize);
+        assert!(build_filter(&sessions, 0).is_none());
+    }
+}
@@ -0,0 +1,330 @@
+use crate::proto::ppp::{
+    packet, parse_packet, Options, CODE_REJECT, CONFIGURE_ACK, CONFIGURE_NAK, CONFIGURE_REJECT,
+    CONFIGURE_REQUEST, TERMINATE_ACK, TERMINATE_REQUEST,
+};
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+const RESTART_TIMER: Duration = Duration::from_secs(3);
+const MAX_CONFIGURE: u8 = 10;
+
+/// Option negotiation automaton of PPP control protocols (RFC 1661).
+///
+/// This does not do any I/O. All methods return the packets to be sent to the peer, without the
+/// PPP protocol field. The protocol specific logic is provided by [`Negotiator`].
+pub struct Fsm<N> {
+    negotiator: N,
+    state: FsmState,
+    id: u8,
+    restart: Option<Instant>,
+    retries: u8,
+    violation: Option<String>,
+}
+
+impl<N: Negotiator> Fsm<N> {
+    pub fn new(negotiator: N) -> Self {
+        Self {
+            negotiator,
+            state: FsmState::Initial,
+            id: 0,
+            restart: None,
+            retries: 0,
+            violation: None,
+        }
+    }
+
+    pub fn negotiator(&self) -> &N {
+        &self.negotiator
+    }
+
+    pub fn negotiator_mut(&mut self) -> &mut N {
+        &mut self.negotiator
+    }
+
+    pub fn state(&self) -> FsmState {
+        self.state
+    }
+
+    /// Returns how the peer violated the protocol since the last call. The violations are handled
+    /// the same way as RFC 1661 regardless of this so it is for reporting only.
+    pub fn take_violation(&mut self) -> Option<String> {
+        self.violation.take()
+    }
+
+    /// Returns the time when [`Fsm::timeout()`] need to be called.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.restart
+    }
+
+    /// Start negotiation.
+    pub fn open(&mut self) -> Vec<Vec<u8>> {
+        self.retries = MAX_CONFIGURE;
+        self.state = FsmState::ReqSent;
+
+        vec![self.configure_request()]
+    }
+
+    /// Start terminating the link.
+    pub fn close(&mut self) -> Vec<Vec<u8>> {
+        if matches!(self.state, FsmState::Initial | FsmState::Stopped) {
+            return Vec::new();
+        }
+
+        let id = self.next_id();
+
+        self.state = FsmState::Closing;
+        self.restart = Some(Instant::now() + RESTART_TIMER);
+
+        vec![packet(TERMINATE_REQUEST, id, &[])]
+    }
+
+    /// Process the expiration of the restart timer.
+    pub fn timeout(&mut self) -> Vec<Vec<u8>> {
+        self.restart = None;
+
+        match self.state {
+            // We close the link only when exiting so there is no point to retransmit.
+            FsmState::Closing => {
+                self.state = FsmState::Stopped;
+                Vec::new()
+            }
+            FsmState::ReqSent | FsmState::AckRcvd | FsmState::AckSent if self.retries == 0 => {
+                self.state = FsmState::Stopped;
+                Vec::new()
+            }
+            FsmState::ReqSent | FsmState::AckRcvd => {
+                self.state = FsmState::ReqSent;
+                vec![self.configure_request()]
+            }
+            FsmState::AckSent => vec![self.configure_request()],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Process a packet from the peer.
+    pub fn receive(&mut self, raw: &[u8]) -> Vec<Vec<u8>> {
+        // Parse the header.
+        let (code, id, data) = match parse_packet(raw) {
+            Some(v) => v,
+            None => {
+                self.violation = Some("malformed packet".into());
+                return Vec::new();
+            }
+        };
+
+        if matches!(self.state, FsmState::Initial | FsmState::Stopped) {
+            return match code {
+                TERMINATE_REQUEST => vec![packet(TERMINATE_ACK, id, data)],
+                _ => Vec::new(),
+            };
+        }
+
+        if self.state == FsmState::Closing {
+            return match code {
+                TERMINATE_REQUEST => vec![packet(TERMINATE_ACK, id, data)],
+                TERMINATE_ACK if id == self.id => {
+                    self.state = FsmState::Stopped;
+                    self.restart = None;
+                    Vec::new()
+                }
+                _ => Vec::new(),
+            };
+        }
+
+        match code {
+            CONFIGURE_REQUEST => self.receive_configure_request(id, data),
+            CONFIGURE_ACK if id == self.id => self.receive_configure_ack(),
+            CONFIGURE_NAK | CONFIGURE_REJECT if id == self.id => {
+                self.receive_configure_nak(code, data)
+            }
+            CONFIGURE_ACK | CONFIGURE_NAK | CONFIGURE_REJECT => {
+                // Stale reply.
+                self.violation = Some(format!("code {code} with unexpected identifier {id}"));
+                Vec::new()
+            }
+            TERMINATE_REQUEST => {
+                self.state = FsmState::Stopped;
+                self.restart = None;
+
+                vec![packet(TERMINATE_ACK, id, data)]
+            }
+            TERMINATE_ACK => Vec::new(),
+            CODE_REJECT => {
+                self.violation = Some("Code-Reject for our packet".into());
+                Vec::new()
+            }
+            _ => {
+                let opened = self.state == FsmState::Opened;
+
+                match self.negotiator.receive(code, id, data, opened) {
+                    Some(v) => v,
+                    None => {
+                        let id = self.next_id();
+
+                        self.violation = Some(format!("unknown code {code}"));
+
+                        vec![packet(CODE_REJECT, id, raw)]
+                    }
+                }
+            }
+        }
+    }
+
+    fn receive_configure_request(&mut self, id: u8, data: &[u8]) -> Vec<Vec<u8>> {
+        let mut out = Vec::new();
+
+        // Negotiate again if the link is already up.
+        if self.state == FsmState::Opened {
+            self.retries = MAX_CONFIGURE;
+            self.state = FsmState::ReqSent;
+            out.push(self.configure_request());
+        }
+
+        // Check each option.
+        let mut nak = Vec::new();
+        let mut rej = Vec::new();
+
+        for (ty, value, raw) in Options::new(data) {
+            match self.negotiator.check(ty, value) {
+                Verdict::Ack => {}
+                Verdict::Nak(v) => {
+                    nak.push(ty);
+                    nak.push((2 + v.len()).try_into().unwrap());
+                    nak.extend_from_slice(&v);
+                }
+                Verdict::Reject => rej.extend_from_slice(raw),
+            }
+        }
+
+        // Reply.
+        let good = if !rej.is_empty() {
+            out.push(packet(CONFIGURE_REJECT, id, &rej));
+            false
+        } else if !nak.is_empty() {
+            out.push(packet(CONFIGURE_NAK, id, &nak));
+            false
+        } else {
+            out.push(packet(CONFIGURE_ACK, id, data));
+            true
+        };
+
+        self.state = match (self.state, good) {
+            (FsmState::AckRcvd, true) => {
+                self.restart = None;
+                FsmState::Opened
+            }
+            (FsmState::AckRcvd, false) => FsmState::AckRcvd,
+            (_, true) => FsmState::AckSent,
+            (_, false) => FsmState::ReqSent,
+        };
+
+        out
+    }
+
+    fn receive_configure_ack(&mut self) -> Vec<Vec<u8>> {
+        match self.state {
+            FsmState::ReqSent => {
+                self.retries = MAX_CONFIGURE;
+                self.state = FsmState::AckRcvd;
+                Vec::new()
+            }
+            FsmState::AckSent => {
+                self.restart = None;
+                self.state = FsmState::Opened;
+                Vec::new()
+            }
+            FsmState::AckRcvd | FsmState::Opened => {
+                self.state = FsmState::ReqSent;
+                vec![self.configure_request()]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn receive_configure_nak(&mut self, code: u8, data: &[u8]) -> Vec<Vec<u8>> {
+        // Update our options.
+        for (ty, value, _) in Options::new(data) {
+            if code == CONFIGURE_NAK {
+                self.negotiator.nak(ty, value);
+            } else {
+                self.negotiator.reject(ty);
+            }
+        }
+
+        if self.state != FsmState::AckSent {
+            self.state = FsmState::ReqSent;
+        }
+
+        vec![self.configure_request()]
+    }
+
+    fn configure_request(&mut self) -> Vec<u8> {
+        let data = self.negotiator.request();
+
+        self.retries = self.retries.saturating_sub(1);
+        self.restart = Some(Instant::now() + RESTART_TIMER);
+
+        let id = self.next_id();
+
+        packet(CONFIGURE_REQUEST, id, &data)
+    }
+
+    fn next_id(&mut self) -> u8 {
+        self.id = self.id.wrapping_add(1);
+        self.id
+    }
+}
+
+/// Protocol specific logic of [`Fsm`].
+pub trait Negotiator {
+    /// Returns the options for our Configure-Request.
+    fn request(&self) -> Vec<u8>;
+
+    /// Check an option from the peer's Configure-Request.
+    fn check(&mut self, ty: u8, value: &[u8]) -> Verdict;
+
+    /// Invoked when the peer proposed `value` for our option.
+    fn nak(&mut self, ty: u8, value: &[u8]);
+
+    /// Invoked when the peer rejected our option.
+    fn reject(&mut self, ty: u8);
+
+    /// Process a code that is not handled by [`Fsm`]. Returns [`None`] if the code is unknown.
+    fn receive(&mut self, code: u8, id: u8, data: &[u8], opened: bool) -> Option<Vec<Vec<u8>>>;
+}
+
+/// Result of [`Negotiator::check()`].
+pub enum Verdict {
+    Ack,
+    /// Suggest a value.
+    Nak(Vec<u8>),
+    Reject,
+}
+
+/// State of [`Fsm`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FsmState {
+    Initial,
+    ReqSent,
+    AckRcvd,
+    AckSent,
+    Opened,
+    Closing,
+    Stopped,
+}
+
+impl Display for FsmState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let v = match self {
+            Self::Initial => "initial",
+            Self::ReqSent => "request sent",
+            Self::AckRcvd => "ack received",
+            Self::AckSent => "ack sent",
+            Self::Opened => "opened",
+            Self::Closing => "closing",
+            Self::Stopped => "stopped",
+        };
+
+        f.write_str(v)
+    }
+}
@@ -0,0 +1,98 @@
+use super::fsm::{Fsm, Negotiator, Verdict};
+use std::net::Ipv4Addr;
+
+const OPT_ADDRESS: u8 = 3;
+const OPT_PRIMARY_DNS: u8 = 129;
+const OPT_SECONDARY_DNS: u8 = 131;
+
+/// IPCP state machine of a single PPP link (RFC 1332).
+pub type Ipcp = Fsm<IpcpOptions>;
+
+/// Addresses to assign to the peer with IPCP.
+#[derive(Clone)]
+pub struct IpcpConfig {
+    /// Address of the peer.
+    pub peer: Ipv4Addr,
+    /// Our address, which is the default gateway of the peer.
+    pub gateway: Ipv4Addr,
+    /// Primary and secondary DNS server (RFC 1877).
+    pub dns: Vec<Ipv4Addr>,
+}
+
+impl IpcpConfig {
+    /// Number of addresses from [`IpcpConfig::peer`] that can be assigned to the sessions.
+    pub const POOL: u32 = 256;
+
+    /// Returns the last address that can be assigned to the sessions.
+    pub fn last_peer(&self) -> Ipv4Addr {
+        u32::from(self.peer).saturating_add(Self::POOL - 1).into()
+    }
+}
+
+/// Our IPCP options.
+pub struct IpcpOptions {
+    config: IpcpConfig,
+    address: bool,
+}
+
+impl IpcpOptions {
+    pub fn new(config: IpcpConfig) -> Self {
+        Self {
+            config,
+            address: true,
+        }
+    }
+
+    pub fn config(&self) -> &IpcpConfig {
+        &self.config
+    }
+}
+
+impl Negotiator for IpcpOptions {
+    fn request(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        if self.address {
+            data.extend_from_slice(&[OPT_ADDRESS, 6]);
+            data.extend_from_slice(&self.config.gateway.octets());
+        }
+
+        data
+    }
+
+    fn check(&mut self, ty: u8, value: &[u8]) -> Verdict {
+        let expect = match (ty, value.len()) {
+            (OPT_ADDRESS, 4) => self.config.peer,
+            (OPT_PRIMARY_DNS, 4) => match self.config.dns.first() {
+                Some(&v) => v,
+                None => return Verdict::Reject,
+            },
+            (OPT_SECONDARY_DNS, 4) => match self.config.dns.get(1) {
+                Some(&v) => v,
+                None => return Verdict::Reject,
+            },
+            _ => return Verdict::Reject,
+        };
+
+        // The peer requests 0.0.0.0 for the addresses it want us to assign.
+        if value == expect.octets() {
+            Verdict::Ack
+        } else {
+            Verdict::Nak(expect.octets().into())
+        }
+    }
+
+    fn nak(&mut self, _: u8, _: &[u8]) {
+        // Our address is not negotiable so just send it again.
+    }
+
+    fn reject(&mut self, ty: u8) {
+        if ty == OPT_ADDRESS {
+            self.address = false;
+        }
+    }
+
+    fn receive(&mut self, _: u8, _: u8, _: &[u8], _: bool) -> Option<Vec<Vec<u8>>> {
+        None
+    }
+}
@@ -0,0 +1,156 @@
+use super::fsm::{Fsm, Negotiator, Verdict};
+use crate::log::warning;
+use crate::random::random_bytes;
+use erdp::ErrorDisplay;
+use std::net::Ipv6Addr;
+
+const OPT_INTERFACE_ID: u8 = 1;
+
+/// IPv6CP state machine of a single PPP link (RFC 5072).
+pub type Ipv6cp = Fsm<Ipv6cpOptions>;
+
+/// Our IPv6CP options.
+///
+/// Only the interface identifiers are negotiated. They form the link-local addresses of both ends,
+/// which is all the peer need to complete the negotiation.
+pub struct Ipv6cpOptions {
+    local: Option<u64>,
+    peer: Option<u64>,
+}
+
+impl Ipv6cpOptions {
+    pub fn new() -> Self {
+        Self {
+            local: random(),
+            peer: None,
+        }
+    }
+
+    /// Returns the link-local address of the peer. This is final only after IPv6CP is opened.
+    pub fn peer(&self) -> Option<Ipv6Addr> {
+        self.peer.map(link_local)
+    }
+}
+
+impl Negotiator for Ipv6cpOptions {
+    fn request(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        if let Some(v) = self.local {
+            data.extend_from_slice(&[OPT_INTERFACE_ID, 10]);
+            data.extend_from_slice(&v.to_be_bytes());
+        }
+
+        data
+    }
+
+    fn check(&mut self, ty: u8, value: &[u8]) -> Verdict {
+        let id = match (ty, value.len()) {
+            (OPT_INTERFACE_ID, 8) => u64::from_be_bytes(value.try_into().unwrap()),
+            _ => return Verdict::Reject,
+        };
+
+        // The peer requests zero for the identifier it want us to assign.
+        if id == 0 || Some(id) == self.local {
+            loop {
+                match random() {
+                    Some(v) if Some(v) == self.local => continue,
+                    Some(v) => break Verdict::Nak(v.to_be_bytes().into()),
+                    None => break Verdict::Reject,
+                }
+            }
+        } else {
+            self.peer = Some(id);
+            Verdict::Ack
+        }
+    }
+
+    fn nak(&mut self, ty: u8, value: &[u8]) {
+        if ty != OPT_INTERFACE_ID {
+            return;
+        }
+
+        // Use the suggested one unless it conflicts with the peer.
+        self.local = match value.try_into().map(u64::from_be_bytes) {
+            Ok(v) if v != 0 && Some(v) != self.peer => Some(v),
+            _ => random(),
+        };
+    }
+
+    fn reject(&mut self, ty: u8) {
+        if ty == OPT_INTERFACE_ID {
+            self.local = None;
+        }
+    }
+
+    fn receive(&mut self, _: u8, _: u8, _: &[u8], _: bool) -> Option<Vec<Vec<u8>>> {
+        None
+    }
+}
+
+/// Returns `fe80::/64` with `id` as the interface identifier.
+fn link_local(id: u64) -> Ipv6Addr {
+    Ipv6Addr::from((0xfe80u128 << 112) | u128::from(id))
+}
+
+/// Returns a random non-zero interface identifier or [`None`] if the kernel cannot provide one.
+fn random() -> Option<u64> {
+    let mut v = [0u8; 8];
+
+    loop {
+        if let Err(e) = random_bytes(&mut v) {
+            warning!(
+                "Failed to generate IPv6CP interface identifier: {}.",
+                e.display()
+            );
+            return None;
+        }
+
+        match u64::from_ne_bytes(v) {
+            0 => continue,
+            v => return Some(v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let mut opts = Ipv6cpOptions::new();
+        let local = opts.local.unwrap();
+        let nak = |v: Verdict| match v {
+            Verdict::Nak(v) => u64::from_be_bytes(v.try_into().unwrap()),
+            _ => panic!("expect Configure-Nak"),
+        };
+
+        // Zero asks us to assign one and our own one would collide.
+        let v = nak(opts.check(OPT_INTERFACE_ID, &0u64.to_be_bytes()));
+
+        assert!(v != 0 && v != local);
+        assert!(opts.peer().is_none());
+
+        let v = nak(opts.check(OPT_INTERFACE_ID, &local.to_be_bytes()));
+
+        assert!(v != 0 && v != local);
+        assert!(opts.peer().is_none());
+
+        // A valid one.
+        let id = local.wrapping_add(1).max(1);
+
+        assert!(matches!(
+            opts.check(OPT_INTERFACE_ID, &id.to_be_bytes()),
+            Verdict::Ack
+        ));
+        assert_eq!(opts.peer(), Some(link_local(id)));
+
+        // Unknown options and a malformed identifier.
+        assert!(matches!(opts.check(2, &[0, 0x57]), Verdict::Reject));
+        assert!(matches!(
+            opts.check(OPT_INTERFACE_ID, &[1, 2, 3, 4]),
+            Verdict::Reject
+        ));
+    }
+}
@@ -0,0 +1,305 @@
+use super::fsm::{Fsm, Negotiator, Verdict};
+use crate::log::warning;
+use crate::proto::lcp::{
+    DISCARD_REQUEST, ECHO_REPLY, ECHO_REQUEST, MAX_MRU, OPT_AUTH, OPT_MAGIC, OPT_MRU,
+    PROTOCOL_REJECT,
+};
+use crate::proto::ppp::{packet, PROTO_PAP};
+use crate::random::random_bytes;
+use erdp::ErrorDisplay;
+use std::time::{Duration, Instant};
+
+const MAX_ECHO_MISSED: u8 = 3;
+
+/// Sizes of LCP packet to probe after the MRU of the peer is not answered.
+const PROBE_SIZES: [u16; 5] = [MAX_MRU, 1472, 1400, 1280, 576];
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// LCP state machine of a single PPP link (RFC 1661).
+pub type Lcp = Fsm<LcpOptions>;
+
+/// Our LCP options.
+pub struct LcpOptions {
+    max_mru: u16,
+    mru: Option<u16>,
+    magic: Option<u32>,
+    pap: bool,
+    echo_interval: Option<Duration>,
+    echo_next: Option<Instant>,
+    echo_id: u8,
+    echo_missed: u8,
+    reject_id: u8,
+    peer_mru: Option<u16>,
+    probe: MtuProbe,
+}
+
+impl LcpOptions {
+    /// If `echo_interval` is not [`None`] an Echo-Request will be sent on each interval while the
+    /// link is opened. If `pap` is `true` the peer will be asked to authenticate with PAP.
+    pub fn new(echo_interval: Option<Duration>, pap: bool) -> Self {
+        Self {
+            max_mru: MAX_MRU,
+            mru: Some(MAX_MRU),
+            magic: random(),
+            pap,
+            echo_interval,
+            echo_next: None,
+            echo_id: 0,
+            echo_missed: 0,
+            reject_id: 0,
+            peer_mru: None,
+            probe: MtuProbe::Idle,
+        }
+    }
+
+    /// Allow the MRU up to `v` on both directions instead of [`MAX_MRU`]. This must be called
+    /// before the link is opened and only when PPP-Max-Payload (RFC 4638) of `v` was agreed.
+    pub fn set_max_mru(&mut self, v: u16) {
+        self.max_mru = v;
+        self.mru = Some(v);
+    }
+
+    /// Returns `true` if the peer has agreed to authenticate with PAP. This is final only after the
+    /// link is opened.
+    pub fn pap(&self) -> bool {
+        self.pap
+    }
+
+    /// Returns the time when [`LcpOptions::echo()`] need to be called.
+    pub fn echo_deadline(&self) -> Option<Instant> {
+        self.echo_next
+    }
+
+    /// Start sending Echo-Request. This must be called when the link is opened.
+    pub fn start_echo(&mut self) {
+        self.echo_next = self.echo_interval.map(|v| Instant::now() + v);
+        self.echo_missed = 0;
+    }
+
+    pub fn stop_echo(&mut self) {
+        self.echo_next = None;
+    }
+
+    /// Returns the next Echo-Request or [`None`] if the peer did not reply to the previous ones.
+    pub fn echo(&mut self) -> Option<Vec<u8>> {
+        if self.echo_missed == MAX_ECHO_MISSED {
+            self.echo_next = None;
+            return None;
+        }
+
+        self.echo_next = self.echo_interval.map(|v| Instant::now() + v);
+        self.echo_missed += 1;
+        self.echo_id = self.echo_id.wrapping_add(1);
+
+        let magic = self.magic.unwrap_or(0).to_be_bytes();
+
+        Some(packet(ECHO_REQUEST, self.echo_id, &magic))
+    }
+
+    pub fn probe(&self) -> MtuProbe {
+        self.probe
+    }
+
+    /// Returns the time when [`LcpOptions::probe_timeout()`] need to be called.
+    pub fn probe_deadline(&self) -> Option<Instant> {
+        match self.probe {
+            MtuProbe::Probing { deadline, .. } => Some(deadline),
+            _ => None,
+        }
+    }
+
+    /// Start probing the largest packet the peer can receive with padded Echo-Requests. This must
+    /// be called when the link is opened.
+    pub fn start_probe(&mut self) -> Vec<u8> {
+        let size = self.peer_mru.unwrap_or(MAX_MRU).min(self.max_mru);
+
+        self.probe_next(size, 0)
+    }
+
+    pub fn stop_probe(&mut self) {
+        self.probe = MtuProbe::Idle;
+    }
+
+    /// Returns Echo-Request of the next smaller size or [`None`] if the probe is finished.
+    pub fn probe_timeout(&mut self) -> Option<Vec<u8>> {
+        let (size, id) = match self.probe {
+            MtuProbe::Probing { size, id, .. } => (size, id),
+            _ => return None,
+        };
+
+        match PROBE_SIZES.iter().find(|&&v| v < size) {
+            Some(&v) => Some(self.probe_next(v, id.wrapping_add(1))),
+            None => {
+                self.probe = MtuProbe::Done(None);
+                None
+            }
+        }
+    }
+
+    fn probe_next(&mut self, size: u16, id: u8) -> Vec<u8> {
+        let mut data = self.magic.unwrap_or(0).to_be_bytes().to_vec();
+
+        data.resize(usize::from(size) - 4, 0);
+
+        self.probe = MtuProbe::Probing {
+            size,
+            id,
+            deadline: Instant::now() + PROBE_TIMEOUT,
+        };
+
+        packet(ECHO_REQUEST, id, &data)
+    }
+
+    /// Returns Protocol-Reject for `packet` of `proto`. This must be sent only when the link is
+    /// opened.
+    pub fn protocol_reject(&mut self, proto: u16, packet: &[u8]) -> Vec<u8> {
+        let mut data = proto.to_be_bytes().to_vec();
+        let max = usize::from(MAX_MRU) - 4 - data.len();
+
+        data.extend_from_slice(&packet[..packet.len().min(max)]);
+
+        self.reject_id = self.reject_id.wrapping_add(1);
+
+        self::packet(PROTOCOL_REJECT, self.reject_id, &data)
+    }
+}
+
+impl Negotiator for LcpOptions {
+    fn request(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        if let Some(v) = self.mru {
+            data.extend_from_slice(&[OPT_MRU, 4]);
+            data.extend_from_slice(&v.to_be_bytes());
+        }
+
+        if self.pap {
+            data.extend_from_slice(&[OPT_AUTH, 4]);
+            data.extend_from_slice(&PROTO_PAP.to_be_bytes());
+        }
+
+        if let Some(v) = self.magic {
+            data.extend_from_slice(&[OPT_MAGIC, 6]);
+            data.extend_from_slice(&v.to_be_bytes());
+        }
+
+        data
+    }
+
+    fn check(&mut self, ty: u8, value: &[u8]) -> Verdict {
+        match (ty, value.len()) {
+            (OPT_MRU, 2) => {
+                let mru = u16::from_be_bytes(value.try_into().unwrap());
+
+                if mru > self.max_mru {
+                    Verdict::Nak(self.max_mru.to_be_bytes().into())
+                } else {
+                    self.peer_mru = Some(mru);
+                    Verdict::Ack
+                }
+            }
+            (OPT_MAGIC, 4) => {
+                let v = u32::from_be_bytes(value.try_into().unwrap());
+
+                // Zero is not allowed and our own number indicates a looped-back link.
+                if v == 0 || Some(v) == self.magic {
+                    match random() {
+                        Some(v) => Verdict::Nak(v.to_be_bytes().into()),
+                        None => Verdict::Reject,
+                    }
+                } else {
+                    Verdict::Ack
+                }
+            }
+            _ => Verdict::Reject,
+        }
+    }
+
+    fn nak(&mut self, ty: u8, value: &[u8]) {
+        match (ty, value.len()) {
+            (OPT_MRU, 2) => {
+                let mru = u16::from_be_bytes(value.try_into().unwrap());
+
+                self.mru = Some(mru.min(self.max_mru));
+            }
+            (OPT_MAGIC, 4) => self.magic = random(),
+            // The peer want another protocol (e.g. CHAP), which we don't support.
+            (OPT_AUTH, _) => self.pap = false,
+            _ => {}
+        }
+    }
+
+    fn reject(&mut self, ty: u8) {
+        match ty {
+            OPT_MRU => self.mru = None,
+            OPT_MAGIC => self.magic = None,
+            OPT_AUTH => self.pap = false,
+            _ => {}
+        }
+    }
+
+    fn receive(&mut self, code: u8, id: u8, data: &[u8], opened: bool) -> Option<Vec<Vec<u8>>> {
+        match code {
+            ECHO_REQUEST if opened => {
+                let mut reply = self.magic.unwrap_or(0).to_be_bytes().to_vec();
+
+                reply.extend_from_slice(data.get(4..).unwrap_or_default());
+
+                Some(vec![packet(ECHO_REPLY, id, &reply)])
+            }
+            ECHO_REPLY if opened && self.probe.is_waiting(id, data.len() + 4) => {
+                if let MtuProbe::Probing { size, .. } = self.probe {
+                    self.probe = MtuProbe::Done(Some(size));
+                }
+
+                Some(Vec::new())
+            }
+            ECHO_REPLY if opened && id == self.echo_id => {
+                self.echo_missed = 0;
+                Some(Vec::new())
+            }
+            ECHO_REQUEST | ECHO_REPLY | DISCARD_REQUEST | PROTOCOL_REJECT => Some(Vec::new()),
+            _ => None,
+        }
+    }
+}
+
+/// State of the MTU probe.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MtuProbe {
+    Idle,
+    Probing {
+        size: u16,
+        id: u8,
+        deadline: Instant,
+    },
+    /// Contains the largest LCP packet that was answered or [`None`] if nothing was answered.
+    Done(Option<u16>),
+}
+
+impl MtuProbe {
+    fn is_waiting(&self, reply: u8, len: usize) -> bool {
+        match *self {
+            Self::Probing { size, id, .. } => id == reply && len == usize::from(size),
+            _ => false,
+        }
+    }
+}
+
+/// Returns a random non-zero magic number or [`None`] if the kernel cannot provide one, in which
+/// case the link goes without it.
+fn random() -> Option<u32> {
+    let mut v = [0u8; 4];
+
+    loop {
+        if let Err(e) = random_bytes(&mut v) {
+            warning!("Failed to generate LCP magic number: {}.", e.display());
+            return None;
+        }
+
+        match u32::from_ne_bytes(v) {
+            0 => continue,
+            v => return Some(v),
+        }
+    }
+}
@@ -1,60 +1,406 @@
-use std::collections::HashMap;
+use super::{CloseReason, EventCallback, EventKind, SessionEvent};
+use crate::attempt::{Attempt, Milestone, Stage};
+#[cfg(feature = "exploit")]
+use crate::exploit::Exploit;
+use crate::failure::Failure;
+use crate::kppp::KernelPpp;
+use crate::log::{debug, info, warning};
+use crate::proto::ppp::{PROTO_IPV4, PROTO_IPV6};
+use crate::random::random_bytes;
+use crate::rt;
+use erdp::ErrorDisplay;
+use macaddr::MacAddr6;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::future::Future;
+use std::net::Ipv4Addr;
 use std::num::NonZeroU16;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::select;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+/// Number of closed sessions to keep for [`Sessions::dump()`].
+const CLOSED_HISTORY: usize = 16;
+
+/// The largest session ID. 0xffff is reserved by RFC 2516.
+const MAX_ID: u16 = 0xfffe;
+
+/// Number of random IDs to try before scanning for a free one.
+const RANDOM_TRIES: usize = 16;
 
 /// Active PPPoE sessions.
 ///
 /// Lock order of the members are the same as their definition order.
 #[derive(Default)]
 pub struct Sessions {
-    list: Mutex<HashMap<NonZeroU16, UnboundedSender<()>>>,
-    free: Mutex<Vec<NonZeroU16>>,
+    list: Mutex<HashMap<NonZeroU16, Entry>>,
+    closed: Mutex<VecDeque<(NonZeroU16, MacAddr6, CloseReason)>>,
+    changed: Notify,
+    emptied: Notify,
+    callbacks: Mutex<Vec<EventCallback>>,
+    keep_link: bool,
 }
 
 impl Sessions {
-    pub fn spawn(self: &Arc<Self>) -> Option<Session> {
+    /// If `keep_link` is `true` the sessions will stay up after exploitation.
+    pub fn new(keep_link: bool) -> Self {
+        Self {
+            keep_link,
+            ..Default::default()
+        }
+    }
+
+    /// Allocate a session for `peer`. `max_payload` is PPP-Max-Payload (RFC 4638) that was agreed
+    /// in the discovery, if any.
+    pub fn spawn(self: &Arc<Self>, peer: MacAddr6, max_payload: Option<u16>) -> Option<Session> {
         // Get session ID.
         let mut list = self.list.lock().unwrap();
-        let mut free = self.free.lock().unwrap();
-        let id = match free.pop() {
-            Some(v) => v,
-            None => (list.len() + 1)
-                .try_into()
-                .ok()
-                .map(|v| unsafe { NonZeroU16::new_unchecked(v) })?,
-        };
+        let id = self.allocate(&list)?;
 
         // Allocate a session.
         let (tx, rx) = unbounded_channel();
+        let token = CancellationToken::new();
+        let events = self.dispatch();
+        let entry = Entry {
+            peer,
+            tx,
+            events,
+            token: token.clone(),
+            max_payload,
+            ipv4: None,
+            received: Instant::now(),
+            expired: false,
+        };
+
+        Self::emit_to(&entry, id, EventKind::Opened);
 
-        assert!(list.insert(id, tx).is_none());
+        assert!(list.insert(id, entry).is_none());
+
+        self.changed.notify_one();
 
         Some(Session {
             slot: Slot {
                 list: self.clone(),
                 id,
+                reason: None,
             },
+            peer,
             rx,
+            token,
         })
     }
 
-    fn free(&self, id: NonZeroU16) {
+    /// Register a callback to be invoked on each [`SessionEvent`].
+    ///
+    /// The callbacks are run on a dedicated task of each session so they will not block the
+    /// session. The events of the same session are delivered in order and each invocation must
+    /// complete before the next one is started. Only the sessions that are spawned after this will
+    /// invoke `f`.
+    pub fn on_event<F, R>(&self, f: F)
+    where
+        F: Fn(SessionEvent) -> R + Send + Sync + 'static,
+        R: Future<Output = ()> + Send + 'static,
+    {
+        self.callbacks
+            .lock()
+            .unwrap()
+            .push(Box::new(move |e| Box::pin(f(e))));
+    }
+
+    /// Returns ID and peer address of all active sessions.
+    pub fn peers(&self) -> Vec<(NonZeroU16, MacAddr6)> {
+        let list = self.list.lock().unwrap();
+
+        list.iter().map(|(k, v)| (*k, v.peer)).collect()
+    }
+
+    /// Wait until a session has been added or removed since the last call.
+    ///
+    /// Only a single task should wait on this.
+    pub async fn changed(&self) {
+        self.changed.notified().await
+    }
+
+    /// Forward PPP `frame` from `peer` to the session `id`. The frame will be discarded if the
+    /// session does not exists, it belongs to another peer or it does not accept the frames
+    /// anymore.
+    ///
+    /// Returns `false` if the frame does not belong to any session.
+    pub fn deliver(&self, id: NonZeroU16, peer: MacAddr6, frame: &[u8]) -> bool {
+        match self.list.lock().unwrap().get_mut(&id) {
+            Some(e) if e.peer == peer => {
+                e.received = Instant::now();
+                e.tx.send(SessionMsg::Frame(frame.to_vec())).ok();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Send `msg` to the session `id`. Returns `false` if there is no such session.
+    pub fn send(&self, id: NonZeroU16, msg: SessionMsg) -> bool {
+        match self.list.lock().unwrap().get(&id) {
+            Some(e) => {
+                e.tx.send(msg).ok();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the token to close the session `id` the same as [`Sessions::close_all()`] without
+    /// touching the other sessions. Returns [`None`] if there is no such session.
+    pub fn token(&self, id: NonZeroU16) -> Option<CancellationToken> {
+        self.list.lock().unwrap().get(&id).map(|e| e.token.clone())
+    }
+
+    /// Returns PPP-Max-Payload that was agreed for the session `id`. Returns [`None`] if it was not
+    /// agreed or there is no such session.
+    pub fn max_payload(&self, id: NonZeroU16) -> Option<u16> {
+        self.list
+            .lock()
+            .unwrap()
+            .get(&id)
+            .and_then(|e| e.max_payload)
+    }
+
+    /// Set the IPv4 address that will be assigned to session `id` with IPCP.
+    pub fn set_ipv4(&self, id: NonZeroU16, addr: Ipv4Addr) {
+        if let Some(e) = self.list.lock().unwrap().get_mut(&id) {
+            e.ipv4 = Some(addr);
+        }
+    }
+
+    /// Returns [`None`] if no IPv4 address was set for session `id` or there is no such session.
+    pub fn ipv4(&self, id: NonZeroU16) -> Option<Ipv4Addr> {
+        self.list.lock().unwrap().get(&id).and_then(|e| e.ipv4)
+    }
+
+    /// Returns [`None`] if there is no session `id`.
+    pub fn peer(&self, id: NonZeroU16) -> Option<MacAddr6> {
+        self.list.lock().unwrap().get(&id).map(|e| e.peer)
+    }
+
+    /// Returns the time since the most recent frame on any active session. The time when the
+    /// session was spawned is used if it has not received any frames. Returns [`None`] if there
+    /// are no active sessions.
+    pub fn idle(&self) -> Option<Duration> {
+        let list = self.list.lock().unwrap();
+
+        list.values().map(|e| e.received.elapsed()).min()
+    }
+
+    /// Returns the time when the least recently active session will have been idle for `timeout`.
+    /// The sessions that was already expired by [`Sessions::expire()`] are not included.
+    pub fn next_expiry(&self, timeout: Duration) -> Option<Instant> {
+        let list = self.list.lock().unwrap();
+
+        list.values()
+            .filter(|e| !e.expired)
+            .map(|e| e.received + timeout)
+            .min()
+    }
+
+    /// Send [`SessionMsg::Inactive`] to all sessions that has not received any frames within
+    /// `timeout`. Returns ID and peer address of those sessions.
+    pub fn expire(&self, timeout: Duration) -> Vec<(NonZeroU16, MacAddr6)> {
         let mut list = self.list.lock().unwrap();
-        let mut free = self.free.lock().unwrap();
+        let mut expired = Vec::new();
+
+        for (&id, e) in list.iter_mut() {
+            if e.expired || e.received.elapsed() < timeout {
+                continue;
+            }
+
+            e.tx.send(SessionMsg::Inactive).ok();
+            e.expired = true;
+            expired.push((id, e.peer));
+        }
+
+        expired
+    }
 
-        if Into::<usize>::into(id.get()) != list.len() {
-            free.push(id);
+    /// Send [`SessionMsg::Restart`] to all sessions with `peer`. Returns the number of sessions
+    /// that was requested to close.
+    pub fn restart(&self, peer: MacAddr6) -> usize {
+        let list = self.list.lock().unwrap();
+        let mut n = 0;
+
+        for e in list.values().filter(|e| e.peer == peer) {
+            e.tx.send(SessionMsg::Restart).ok();
+            n += 1;
         }
 
-        list.remove(&id).unwrap();
+        n
+    }
+
+    /// Send [`SessionMsg::Violation`] with `reason` to all sessions with `peer`. Returns the number
+    /// of sessions that was requested to close.
+    pub fn violate(&self, peer: MacAddr6, reason: &str) -> usize {
+        let list = self.list.lock().unwrap();
+        let mut n = 0;
+
+        for e in list.values().filter(|e| e.peer == peer) {
+            e.tx.send(SessionMsg::Violation(reason.into())).ok();
+            n += 1;
+        }
+
+        n
+    }
+
+    /// Request all sessions to close.
+    pub fn close_all(&self) {
+        for e in self.list.lock().unwrap().values() {
+            e.tx.send(SessionMsg::Terminate).ok();
+        }
+    }
+
+    /// Wait until there are no active sessions.
+    pub async fn wait_empty(&self) {
+        loop {
+            // Register the waiter before checking so we don't miss the notification.
+            let emptied = self.emptied.notified();
+            tokio::pin!(emptied);
+            emptied.as_mut().enable();
+
+            if self.list.lock().unwrap().is_empty() {
+                break;
+            }
+
+            emptied.await;
+        }
+    }
+
+    /// Returns ID, peer address and the reason of the recently closed sessions, oldest first.
+    pub fn closed(&self) -> Vec<(NonZeroU16, MacAddr6, CloseReason)> {
+        self.closed.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Write the active sessions and the state of ID allocator to `out`.
+    pub fn dump(&self, out: &mut String) {
+        let list = self.list.lock().unwrap();
+        let mut ids: Vec<_> = list.keys().copied().collect();
+
+        ids.sort_unstable();
+
+        writeln!(out, "Sessions:").unwrap();
+        writeln!(out, "  Free IDs: {}", usize::from(MAX_ID) - list.len()).unwrap();
+        writeln!(out, "  Keep link: {}", self.keep_link).unwrap();
+        writeln!(out, "  Callbacks: {}", self.callbacks.lock().unwrap().len()).unwrap();
+
+        for id in ids {
+            let e = &list[&id];
+
+            match e.max_payload {
+                Some(v) => writeln!(out, "  {}: {} (PPP-Max-Payload: {})", id, e.peer, v).unwrap(),
+                None => writeln!(out, "  {}: {}", id, e.peer).unwrap(),
+            }
+        }
+
+        for (id, peer, reason) in self.closed.lock().unwrap().iter() {
+            writeln!(out, "  {id}: {peer} (closed: {reason})").unwrap();
+        }
+    }
+
+    /// Returns a random ID that is not used by `list`, which is the locked [`Sessions::list`].
+    ///
+    /// The recently closed IDs are also avoided so the late frames of a closed session will not be
+    /// delivered to the new one. Returns [`None`] if all IDs are in use or the kernel cannot provide
+    /// a random number.
+    fn allocate(&self, list: &HashMap<NonZeroU16, Entry>) -> Option<NonZeroU16> {
+        let closed = self.closed.lock().unwrap();
+
+        for _ in 0..RANDOM_TRIES {
+            let id = random_id()?;
+
+            if !list.contains_key(&id) && closed.iter().all(|(v, _, _)| *v != id) {
+                return Some(id);
+            }
+        }
+
+        // Most of IDs are in use so scan all of them from a random one.
+        let start = u32::from(random_id()?.get());
+        let max = u32::from(MAX_ID);
+
+        (0..max)
+            .map(|i| NonZeroU16::new(((start - 1 + i) % max + 1) as u16).unwrap())
+            .find(|id| !list.contains_key(id))
+    }
+
+    fn free(&self, id: NonZeroU16, reason: CloseReason) {
+        let mut list = self.list.lock().unwrap();
+        let mut closed = self.closed.lock().unwrap();
+        let e = list.remove(&id).unwrap();
+
+        if closed.len() == CLOSED_HISTORY {
+            closed.pop_front();
+        }
+
+        closed.push_back((id, e.peer, reason.clone()));
+
+        self.changed.notify_one();
+
+        Self::emit_to(&e, id, EventKind::Closed(reason));
+
+        if list.is_empty() {
+            self.emptied.notify_waiters();
+        }
+    }
+
+    /// Spawn a task to run the callbacks for a new session in order. Returns [`None`] if there is
+    /// no callbacks. The task will stop after the last event once the session is removed.
+    fn dispatch(self: &Arc<Self>) -> Option<UnboundedSender<SessionEvent>> {
+        if self.callbacks.lock().unwrap().is_empty() {
+            return None;
+        }
+
+        let (tx, mut rx) = unbounded_channel::<SessionEvent>();
+        let list = self.clone();
+
+        rt::spawn(async move {
+            while let Some(e) = rx.recv().await {
+                let calls: Vec<_> = list
+                    .callbacks
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|f| f(e.clone()))
+                    .collect();
+
+                for c in calls {
+                    c.await;
+                }
+            }
+        });
+
+        Some(tx)
+    }
+
+    /// Queue `kind` for the callbacks of session `id` if it is still active.
+    pub(super) fn emit(&self, id: NonZeroU16, kind: EventKind) {
+        if let Some(e) = self.list.lock().unwrap().get(&id) {
+            Self::emit_to(e, id, kind);
+        }
+    }
+
+    fn emit_to(e: &Entry, id: NonZeroU16, kind: EventKind) {
+        let peer = e.peer;
+
+        if let Some(v) = &e.events {
+            v.send(SessionEvent { id, peer, kind }).ok();
+        }
     }
 }
 
 /// Active PPPoE session.
 pub struct Session {
     slot: Slot, // Drop first.
-    rx: UnboundedReceiver<()>,
+    peer: MacAddr6,
+    rx: UnboundedReceiver<SessionMsg>,
+    token: CancellationToken,
 }
 
 impl Session {
@@ -62,17 +408,425 @@ impl Session {
         self.slot.id
     }
 
-    pub async fn run(self) {}
+    /// Returns the address that negotiated this session. Only the frames from this address are
+    /// delivered to the session.
+    pub fn peer(&self) -> MacAddr6 {
+        self.peer
+    }
+
+    /// Report an error to the callbacks registered with [`Sessions::on_event()`].
+    pub fn error(&self, reason: impl Into<String>) {
+        let kind = EventKind::Error(reason.into());
+
+        self.slot.list.emit(self.slot.id, kind);
+    }
+
+    /// Returns the result of the exploit. The exploit will be failed with
+    /// [`Failure::AttemptTimeout`] if it does not finish within `timeout`.
+    #[cfg(feature = "exploit")]
+    pub async fn run(
+        mut self,
+        attempt: &Arc<Attempt>,
+        exploit: &Exploit,
+        timeout: Option<Duration>,
+    ) -> Result<(), Failure> {
+        attempt.record(Stage::Session, format!("Session {} started", self.id()));
+        attempt.advance(Milestone::SessionStarted);
+
+        // Run the exploit.
+        let (frames, mut rx) = unbounded_channel();
+        let id = self.id();
+        let peer = self.peer();
+        let sessions = self.slot.list.clone();
+        let run = exploit.run(&sessions, id, peer, &mut rx, attempt);
+        let timeout = async {
+            match timeout {
+                Some(v) => rt::sleep(v).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::pin!(run);
+        tokio::pin!(timeout);
+
+        let result = loop {
+            let msg = select! {
+                v = &mut run => break v,
+                v = self.recv() => v,
+                _ = &mut timeout => {
+                    self.close(attempt, CloseReason::Timeout);
+                    attempt.complete(Stage::Exploit, Err(Failure::AttemptTimeout));
+                    return Err(Failure::AttemptTimeout);
+                }
+            };
+
+            match msg {
+                Some(SessionMsg::Frame(v)) => drop(frames.send(v)),
+                Some(v @ SessionMsg::Violation(_)) => {
+                    self.closed(attempt, v);
+                    break Err(Failure::ProtocolViolation);
+                }
+                Some(v) => {
+                    self.closed(attempt, v);
+                    break Err(Failure::Aborted);
+                }
+                None => break Err(Failure::Aborted),
+            }
+        };
+
+        attempt.complete(Stage::Exploit, result);
+
+        if let Err(Failure::Aborted | Failure::ProtocolViolation) = result {
+            return result;
+        }
+
+        if self.slot.list.keep_link {
+            self.wait_closed(attempt).await;
+        } else {
+            let reason = match result {
+                Ok(_) => CloseReason::ExploitFinished,
+                Err(e) => CloseReason::ExploitFailed(e),
+            };
+
+            self.close(attempt, reason);
+        }
+
+        result
+    }
+
+    /// Service the session without the exploit until it is closed. Always returns
+    /// [`Failure::Aborted`] since the exploit is never finished, unless the peer violated the
+    /// protocol in strict mode.
+    pub async fn serve(mut self, attempt: &Attempt) -> Result<(), Failure> {
+        attempt.record(
+            Stage::Session,
+            format!("Session {} started without exploit", self.id()),
+        );
+        attempt.advance(Milestone::SessionStarted);
+
+        self.wait_closed(attempt).await;
+
+        match self.slot.reason {
+            Some(CloseReason::Violation(_)) => Err(Failure::ProtocolViolation),
+            _ => Err(Failure::Aborted),
+        }
+    }
+
+    /// Keep servicing the link until the session is closed. Nothing will receive the frames from
+    /// now on.
+    async fn wait_closed(&mut self, attempt: &Attempt) {
+        loop {
+            match self.recv().await {
+                Some(SessionMsg::Frame(_)) => {}
+                Some(v) => {
+                    self.closed(attempt, v);
+                    break;
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Returns [`SessionMsg::Terminate`] once the token from [`Sessions::token()`] has been
+    /// cancelled.
+    async fn recv(&mut self) -> Option<SessionMsg> {
+        select! {
+            biased;
+            _ = self.token.cancelled() => Some(SessionMsg::Terminate),
+            v = self.rx.recv() => v,
+        }
+    }
+
+    /// Record why the session is closed by `msg`.
+    fn closed(&mut self, attempt: &Attempt, msg: SessionMsg) {
+        let reason = match msg {
+            SessionMsg::Frame(_) | SessionMsg::IpcpOpened { .. } | SessionMsg::Ipv6cpOpened => {
+                unreachable!()
+            }
+            SessionMsg::Terminate => CloseReason::Shutdown,
+            SessionMsg::EchoTimeout => CloseReason::EchoTimeout,
+            SessionMsg::LcpTerminated => CloseReason::LcpTerminated,
+            SessionMsg::Padt => CloseReason::Padt,
+            SessionMsg::Restart => CloseReason::Restarted,
+            SessionMsg::Inactive => CloseReason::Inactive,
+            SessionMsg::Violation(v) => CloseReason::Violation(v),
+        };
+
+        self.close(attempt, reason);
+    }
+
+    /// Record `reason` on `attempt` and report it once the session has been removed.
+    fn close(&mut self, attempt: &Attempt, reason: CloseReason) {
+        attempt.record(Stage::Session, format!("Session closed: {reason}"));
+        attempt.set_close_reason(reason.clone());
+
+        self.slot.reason = Some(reason);
+    }
+
+    /// Run the session that was handed off to the kernel PPP subsystem.
+    pub async fn run_kernel(mut self, attempt: &Attempt, ppp: KernelPpp) {
+        let mut buf = [0; 1500];
+
+        loop {
+            let res = select! {
+                v = self.recv() => match v {
+                    // The kernel already received the frame.
+                    Some(SessionMsg::Frame(_)) => continue,
+                    Some(SessionMsg::IpcpOpened { local, peer }) => {
+                        let r = ppp.configure(local, peer).and_then(|_| ppp.pass(PROTO_IPV4));
+
+                        self.network_opened(&ppp, "IPv4", r);
+                        continue;
+                    }
+                    Some(SessionMsg::Ipv6cpOpened) => {
+                        let r = ppp.pass(PROTO_IPV6);
+
+                        self.network_opened(&ppp, "IPv6", r);
+                        continue;
+                    }
+                    Some(v) => {
+                        self.closed(attempt, v);
+                        break;
+                    }
+                    None => break,
+                },
+                v = ppp.recv(&mut buf) => v,
+            };
+
+            let len = match res {
+                Ok(0) => {
+                    self.close(attempt, CloseReason::KernelClosed);
+                    break;
+                }
+                Ok(v) => v,
+                Err(e) => {
+                    let e = format!(
+                        "failed to receive a frame from ppp{}: {}",
+                        ppp.index(),
+                        e.display()
+                    );
+
+                    self.error(e.clone());
+                    self.close(attempt, CloseReason::Error(e));
+                    break;
+                }
+            };
+
+            // The server negotiate the control protocols from its own copy of the frame so this one
+            // is only drained to keep the queue of the unit from filling up.
+            match buf[..len].get(..2) {
+                Some(v) => debug!(
+                    "ppp{}: Received PPP protocol {:#06x} with {} bytes.",
+                    ppp.index(),
+                    u16::from_be_bytes(v.try_into().unwrap()),
+                    len
+                ),
+                None => warning!("ppp{}: Received an invalid PPP frame.", ppp.index()),
+            }
+        }
+    }
+
+    /// Report the result of passing network protocol `name` to the kernel.
+    fn network_opened(&self, ppp: &KernelPpp, name: &str, r: Result<(), std::io::Error>) {
+        match r {
+            Ok(_) => info!(
+                "Session {} with {}: {} is now carried by ppp{}.",
+                self.id(),
+                self.peer(),
+                name,
+                ppp.index()
+            ),
+            Err(e) => self.error(format!(
+                "failed to pass {} to ppp{}: {}",
+                name,
+                ppp.index(),
+                e.display()
+            )),
+        }
+    }
+}
+
+/// Message to a running [`Session`].
+pub enum SessionMsg {
+    /// PPP frame from the peer.
+    #[cfg_attr(not(feature = "exploit"), allow(dead_code))]
+    Frame(Vec<u8>),
+    /// Close the session (e.g. we are exiting).
+    Terminate,
+    /// The peer did not reply to LCP Echo-Request.
+    EchoTimeout,
+    /// The peer closed LCP with Terminate-Request.
+    LcpTerminated,
+    /// The peer closed the session with PADT.
+    Padt,
+    /// The peer started a new discovery so the session is abandoned.
+    Restart,
+    /// Nothing was received from the peer for too long.
+    Inactive,
+    /// The peer violated the protocol in strict mode.
+    Violation(String),
+    /// IPCP has been opened with `local` as our address. This is only sent to the sessions that
+    /// are attached to the kernel PPP subsystem.
+    IpcpOpened { local: Ipv4Addr, peer: Ipv4Addr },
+    /// IPv6CP has been opened. This is only sent to the sessions that are attached to the kernel
+    /// PPP subsystem.
+    Ipv6cpOpened,
+}
+
+/// An entry in the active list.
+struct Entry {
+    peer: MacAddr6,
+    tx: UnboundedSender<SessionMsg>,
+    events: Option<UnboundedSender<SessionEvent>>,
+    token: CancellationToken,
+    max_payload: Option<u16>,
+    ipv4: Option<Ipv4Addr>,
+    received: Instant,
+    expired: bool,
 }
 
 /// RAII struct to remove a session from active list.
 struct Slot {
     list: Arc<Sessions>,
     id: NonZeroU16,
+    reason: Option<CloseReason>,
 }
 
 impl Drop for Slot {
     fn drop(&mut self) {
-        self.list.free(self.id);
+        let reason = self
+            .reason
+            .take()
+            .unwrap_or_else(|| CloseReason::Error("dropped by the server".into()));
+
+        self.list.free(self.id, reason);
+    }
+}
+
+/// Returns a random session ID from 1 to [`MAX_ID`] or [`None`] if the kernel cannot provide one.
+fn random_id() -> Option<NonZeroU16> {
+    let mut v = [0u8; 2];
+
+    loop {
+        if let Err(e) = random_bytes(&mut v) {
+            warning!("Failed to generate session ID: {}.", e.display());
+            return None;
+        }
+
+        match u16::from_ne_bytes(v) {
+            0 | 0xffff => continue,
+            v => return NonZeroU16::new(v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocate() {
+        let sessions = Arc::new(Sessions::new(false));
+        let peer = MacAddr6::new(2, 0, 0, 0, 0, 1);
+        let mut active: Vec<_> = (0..100)
+            .map(|_| sessions.spawn(peer, None).unwrap())
+            .collect();
+        let mut ids: Vec<_> = active.iter().map(|s| s.id()).collect();
+
+        ids.sort_unstable();
+        ids.dedup();
+
+        assert_eq!(ids.len(), 100);
+
+        // The closed ID must not be reused while it is in the history.
+        let closed = active.pop().unwrap().id();
+
+        for _ in 1..CLOSED_HISTORY {
+            let s = sessions.spawn(peer, None).unwrap();
+
+            assert_ne!(s.id(), closed);
+            assert!(s.id().get() <= MAX_ID);
+        }
+    }
+
+    #[test]
+    fn allocate_full() {
+        let sessions = Arc::new(Sessions::new(false));
+        let peer = MacAddr6::new(2, 0, 0, 0, 0, 1);
+        let active: Vec<_> = (0..MAX_ID)
+            .map(|_| sessions.spawn(peer, None).unwrap())
+            .collect();
+        let mut ids: Vec<_> = active.iter().map(|s| s.id()).collect();
+
+        ids.sort_unstable();
+        ids.dedup();
+
+        assert_eq!(ids.len(), usize::from(MAX_ID));
+        assert!(sessions.spawn(peer, None).is_none());
+    }
+
+    #[test]
+    fn token() {
+        crate::fixtures::runtime().block_on(async {
+            let sessions = Arc::new(Sessions::new(false));
+            let attempts = crate::attempt::Attempts::default();
+            let peer = MacAddr6::new(2, 0, 0, 0, 0, 1);
+            let attempt = attempts.get_or_start(peer).unwrap();
+            let first = sessions.spawn(peer, None).unwrap();
+            let second = sessions.spawn(peer, None).unwrap();
+            let id = first.id();
+
+            // Only the session of the token must be closed.
+            sessions.token(id).unwrap().cancel();
+
+            assert!(matches!(first.serve(&attempt).await, Err(Failure::Aborted)));
+            assert!(sessions.token(id).is_none());
+            assert_eq!(sessions.peers(), [(second.id(), peer)]);
+            assert!(matches!(
+                sessions.closed().last(),
+                Some((v, _, CloseReason::Shutdown)) if *v == id
+            ));
+        });
+    }
+
+    #[test]
+    fn events() {
+        crate::fixtures::runtime().block_on(async {
+            let sessions = Arc::new(Sessions::new(false));
+            let received = Arc::new(Mutex::new(Vec::new()));
+            let peer = MacAddr6::new(2, 0, 0, 0, 0, 1);
+
+            sessions.on_event({
+                let received = received.clone();
+
+                move |e| {
+                    let received = received.clone();
+
+                    async move {
+                        // The later events must wait for this.
+                        rt::sleep(Duration::from_millis(10)).await;
+                        received.lock().unwrap().push(e.kind.to_string());
+                    }
+                }
+            });
+
+            let session = sessions.spawn(peer, None).unwrap();
+
+            sessions.emit(session.id(), EventKind::LcpUp);
+            session.error("test");
+            drop(session);
+
+            rt::sleep(Duration::from_millis(100)).await;
+
+            assert_eq!(
+                *received.lock().unwrap(),
+                [
+                    "opened",
+                    "opened LCP",
+                    "error (test)",
+                    "closed (error (dropped by the server))"
+                ]
+            );
+        });
     }
 }
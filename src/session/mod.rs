@@ -1,42 +1,264 @@
+pub use self::event::*;
+use self::filter::{accept_all, build_filter};
+use self::fsm::FsmState;
+pub use self::ipcp::IpcpConfig;
+use self::ipcp::{Ipcp, IpcpOptions};
+use self::ipv6cp::{Ipv6cp, Ipv6cpOptions};
+use self::lcp::{Lcp, LcpOptions, MtuProbe};
 pub use self::list::*;
-use crate::payload::EthernetPayload;
-use crate::socket::PacketSocket;
+use self::pap::authenticate;
+pub use self::pap::PapConfig;
+use crate::addr::AddrBuilder;
+use crate::dns::DnsBlocklist;
+use crate::log::{debug, error, info, warning};
+use crate::proto::lcp::{ECHO_REQUEST, MAX_MRU, OPT_MRU};
+use crate::proto::pap::AUTHENTICATE_REQUEST;
+use crate::proto::ppp::{
+    packet, CONFIGURE_ACK, CONFIGURE_REQUEST, PROTO_IPCP, PROTO_IPV4, PROTO_IPV6, PROTO_IPV6CP,
+    PROTO_LCP, PROTO_PAP, TERMINATE_REQUEST,
+};
+use crate::proto::pppoe::{EthernetPayload, MAX_PPP_MAX_PAYLOAD};
+use crate::recovery::Recovery;
+use crate::rt;
+use crate::socket::{PacketSocket, SocketStats, Transport};
+use crate::tun::Tun;
 use erdp::ErrorDisplay;
 use macaddr::MacAddr6;
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::future::pending;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::num::NonZeroU16;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::select;
-use tokio_util::sync::CancellationToken;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Notify;
+use tokio_util::sync::{CancellationToken, DropGuard};
 
+mod event;
+mod filter;
+mod fsm;
+mod ipcp;
+mod ipv6cp;
+mod lcp;
 mod list;
+mod pap;
 
 /// Server for PPPoE Session Stage.
-pub struct SessionServer {
-    sock: PacketSocket,
+pub struct SessionServer<T = PacketSocket> {
+    sock: Arc<T>,
+    ab: Arc<AddrBuilder>,
+    sessions: Arc<Sessions>,
+    tun: Option<Tun>,
+    bridge: Option<String>,
+    bridges: Mutex<HashMap<NonZeroU16, Bridge>>,
+    bridged: UnboundedSender<(NonZeroU16, Vec<u8>)>,
+    from_bridges: tokio::sync::Mutex<UnboundedReceiver<(NonZeroU16, Vec<u8>)>>,
+    blocklist: Option<DnsBlocklist>,
+    blocked: AtomicU64,
+    ethertype: u16,
+    kernel: bool,
+    ipv4: bool,
+    ipcp: IpcpConfig,
+    pap: Option<PapConfig>,
+    echo_interval: Option<Duration>,
+    strict: bool,
+    routes: Mutex<HashMap<IpAddr, (u16, MacAddr6)>>,
+    links: Mutex<HashMap<NonZeroU16, Link>>,
+    spoofed: AtomicU64,
+    terminated: Notify,
+    stop: CancellationToken,
 }
 
-impl SessionServer {
-    pub fn new(sock: PacketSocket) -> Self {
-        Self { sock }
+impl<T: Transport> SessionServer<T> {
+    pub fn new(
+        sock: Arc<T>,
+        ab: Arc<AddrBuilder>,
+        sessions: Arc<Sessions>,
+        tun: Option<Tun>,
+        config: SessionConfig,
+    ) -> Self {
+        let (bridged, from_bridges) = unbounded_channel();
+
+        Self {
+            sock,
+            ab,
+            sessions,
+            tun,
+            bridge: config.bridge,
+            bridges: Mutex::default(),
+            bridged,
+            from_bridges: tokio::sync::Mutex::new(from_bridges),
+            blocklist: config.blocklist,
+            blocked: AtomicU64::new(0),
+            ethertype: config.ethertype,
+            kernel: config.kernel,
+            ipv4: config.ipv4,
+            ipcp: config.ipcp,
+            pap: config.pap,
+            echo_interval: config.echo_interval,
+            strict: config.strict,
+            routes: Mutex::default(),
+            links: Mutex::default(),
+            spoofed: AtomicU64::new(0),
+            terminated: Notify::new(),
+            stop: CancellationToken::new(),
+        }
+    }
+
+    /// Keep servicing the sessions until all of it has been closed then stop.
+    ///
+    /// Use [`crate::discovery::DiscoveryServer::shutdown()`] to close the sessions.
+    pub async fn shutdown(&self) {
+        self.sessions.wait_empty().await;
+        self.stop.cancel();
+    }
+
+    /// Send LCP Terminate-Request on all links then wait until the peers acknowledged it or
+    /// `TERMINATE_TIMEOUT`.
+    ///
+    /// This should be called before sending PADT so the peers know the link is closed on purpose.
+    pub async fn terminate(&self) {
+        for (&id, link) in self.links.lock().unwrap().iter_mut() {
+            self.update_link(id, link, PROTO_LCP, |l| l.lcp.close());
+        }
+
+        // Wait for Terminate-Ack.
+        let acked = async {
+            loop {
+                // Register the waiter before checking so we don't miss the notification.
+                let terminated = self.terminated.notified();
+                tokio::pin!(terminated);
+                terminated.as_mut().enable();
+
+                if self
+                    .links
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .all(|l| matches!(l.lcp.state(), FsmState::Initial | FsmState::Stopped))
+                {
+                    break;
+                }
+
+                terminated.await;
+            }
+        };
+
+        select! {
+            _ = acked => {}
+            _ = rt::sleep(TERMINATE_TIMEOUT) => {}
+        }
+    }
+
+    /// Stop immediately.
+    pub fn abort(&self) {
+        self.stop.cancel();
+    }
+
+    /// Returns the kernel counters of the session socket.
+    pub fn statistics(&self) -> Result<SocketStats, std::io::Error> {
+        self.sock.statistics()
+    }
+
+    /// Write the state of this server to `out`.
+    pub fn dump(&self, out: &mut String) {
+        writeln!(out, "Session server:").unwrap();
+        writeln!(out, "  Stopped: {}", self.stop.is_cancelled()).unwrap();
+        writeln!(
+            out,
+            "  Spoofed frames: {}",
+            self.spoofed.load(Ordering::Relaxed)
+        )
+        .unwrap();
+
+        if let Some(v) = &self.blocklist {
+            writeln!(
+                out,
+                "  Blocked DNS queries: {} ({})",
+                self.blocked.load(Ordering::Relaxed),
+                v
+            )
+            .unwrap();
+        }
+
+        if let Some(tun) = &self.tun {
+            writeln!(out, "  TUN: {}", tun.name()).unwrap();
+        }
+
+        for (id, b) in self.bridges.lock().unwrap().iter() {
+            writeln!(
+                out,
+                "  Bridge: {} -> session {} with {}",
+                b.tun.name(),
+                id,
+                b.peer
+            )
+            .unwrap();
+        }
+
+        for (ip, (id, peer)) in self.routes.lock().unwrap().iter() {
+            writeln!(out, "  Route: {ip} -> session {id} with {peer}").unwrap();
+        }
+
+        for (id, l) in self.links.lock().unwrap().iter() {
+            writeln!(out, "  LCP of session {}: {}", id, l.lcp.state()).unwrap();
+            writeln!(out, "  IPCP of session {}: {}", id, l.ipcp.state()).unwrap();
+            writeln!(out, "  IPv6CP of session {}: {}", id, l.ipv6cp.state()).unwrap();
+        }
+
+        self.sock.dump(out);
     }
 
-    pub async fn run(self, running: CancellationToken) {
-        let mut buf = [0; 1500];
+    pub async fn run(&self, running: CancellationToken) {
+        let mut buf = [0; MAX_PPP_MAX_PAYLOAD as usize + 8];
+        let mut packet = [0; 1500];
+        let mut recovery = Recovery::new("PPPoE session socket");
+        let mut tun = Recovery::new("TUN device");
+        let mut from_bridges = self.from_bridges.lock().await;
+
+        // Drop frames that does not belong to any session until the first one is spawned.
+        self.update_filter();
 
         loop {
             // Wait for PPPoE session packet.
             let (len, addr) = select! {
                 _ = running.cancelled() => break,
-                v = self.sock.recv(&mut buf) => match v {
-                    Ok(v) => v,
-                    Err(e) => {
-                        eprintln!(
-                            "Failed to receive a packet from PPPoE session socket: {}.",
-                            e.display()
-                        );
+                _ = self.stop.cancelled() => break,
+                _ = self.sessions.changed() => {
+                    self.update_filter();
+                    continue;
+                }
+                _ = self.restart_timer() => {
+                    self.timeout_links();
+                    continue;
+                }
+                v = async { self.tun.as_ref().unwrap().recv(&mut packet).await }, if self.tun.is_some() => match v {
+                    Ok(v) => {
+                        tun.succeeded();
+                        self.forward_to_peer(&packet[..v], None);
+                        continue;
+                    }
+                    Err(e) if tun.failed(&e, &running).await => continue,
+                    Err(_) => return,
+                },
+                v = from_bridges.recv() => {
+                    // The sender is owned by us so it never closed.
+                    let (id, packet) = v.unwrap();
 
-                        running.cancel();
-                        return;
+                    self.forward_to_peer(&packet, Some(id));
+                    continue;
+                }
+                v = self.sock.recv(&mut buf) => match v {
+                    Ok(v) => {
+                        recovery.succeeded();
+                        v
                     }
+                    Err(e) if recovery.failed(&e, &running).await => continue,
+                    Err(_) => return,
                 }
             };
 
@@ -48,7 +270,8 @@ impl SessionServer {
             };
 
             if ty != 0 {
-                eprintln!("Unexpected sll_pkttype for PPPoE session packet from {addr}.");
+                warning!("Unexpected sll_pkttype for PPPoE session packet from {addr}.");
+                self.violation(addr, "PPPoE session frame that is not unicast");
                 continue;
             }
 
@@ -56,22 +279,1149 @@ impl SessionServer {
             let data = match Payload::deserialize(&buf[..len]) {
                 Some(v) => v,
                 None => {
-                    eprintln!("Unexpected PPPoE session packet from {addr}.");
+                    warning!("Unexpected PPPoE session packet from {addr}.");
+                    self.violation(addr, "malformed PPPoE session frame");
                     continue;
                 }
             };
 
             if data.code() != 0x00 {
-                eprintln!(
+                warning!(
                     "Unexpected PPPoE session packet {} from {}.",
                     data.code(),
                     addr
                 );
 
+                self.violation(
+                    addr,
+                    &format!("PPPoE session frame with code {:#04x}", data.code()),
+                );
+
+                continue;
+            }
+
+            // Get PPP protocol.
+            let frame = data.payload();
+            let proto = match frame.get(..2) {
+                Some(v) => u16::from_be_bytes(v.try_into().unwrap()),
+                None => {
+                    warning!("Invalid PPP frame from {addr}.");
+                    self.violation(addr, "PPP frame without protocol");
+                    continue;
+                }
+            };
+
+            // The filter may not be updated yet so we need to check if the frame belongs to the
+            // session.
+            let id = match NonZeroU16::new(data.session_id()) {
+                Some(v) => v,
+                None => continue,
+            };
+
+            if !self.sessions.deliver(id, addr, frame) {
+                // Only the address that negotiated the session can use it.
+                if let Some(owner) = self.sessions.peer(id) {
+                    self.spoofed.fetch_add(1, Ordering::Relaxed);
+                    warning!("Dropped a frame for session {id} of {owner} from {addr}.");
+                }
+
                 continue;
             }
+
+            match proto {
+                PROTO_IPV4 | PROTO_IPV6 => self.forward_to_host(addr, id, &frame[2..]),
+                _ => self.receive_control(proto, data.session_id(), &frame[2..]),
+            }
+        }
+    }
+
+    /// Close the sessions of `addr` for violating the protocol if we are in strict mode.
+    fn violation(&self, addr: MacAddr6, reason: &str) {
+        if self.strict {
+            self.sessions.violate(addr, reason);
+        }
+    }
+
+    fn update_filter(&self) {
+        let peers = self.sessions.peers();
+        let prog = build_filter(&peers, self.sock.framing().header_len()).unwrap_or_else(|| {
+            warning!("Too many sessions for the filter of PPPoE session socket, checking the frames without it.");
+            accept_all()
+        });
+
+        if let Err(e) = self.sock.attach_filter(&prog) {
+            error!(
+                "Failed to attach a filter to PPPoE session socket: {}.",
+                e.display()
+            );
+
+            // The old filter would drop the frames of the new sessions.
+            if let Err(e) = self.sock.attach_filter(&accept_all()) {
+                error!(
+                    "Failed to remove the filter of PPPoE session socket: {}.",
+                    e.display()
+                );
+            }
+        }
+
+        // Remove routes to the sessions that no longer exists.
+        self.routes
+            .lock()
+            .unwrap()
+            .retain(|_, (id, peer)| peers.iter().any(|(i, p)| i.get() == *id && p == peer));
+
+        // Start LCP on the new sessions.
+        let mut links = self.links.lock().unwrap();
+
+        links.retain(|id, l| peers.contains(&(*id, l.peer)));
+
+        // Remove the TUN devices of the closed sessions.
+        self.bridges
+            .lock()
+            .unwrap()
+            .retain(|id, b| peers.contains(&(*id, b.peer)));
+
+        // Each session need its own address otherwise the routes to the consoles will collide.
+        let mut taken: Vec<Ipv4Addr> = links
+            .values()
+            .map(|l| l.ipcp.negotiator().config().peer)
+            .collect();
+
+        for &(id, peer) in &peers {
+            links.entry(id).or_insert_with(|| {
+                let mut lcp = LcpOptions::new(self.echo_interval, self.pap.is_some());
+
+                if let Some(v) = self.sessions.max_payload(id) {
+                    lcp.set_max_mru(v);
+                }
+
+                let ipcp = self.allocate_ipcp(&taken);
+
+                self.sessions.set_ipv4(id, ipcp.peer);
+
+                let mut link = Link {
+                    peer,
+                    lcp: Lcp::new(lcp),
+                    ipcp: Ipcp::new(IpcpOptions::new(ipcp)),
+                    ipv6cp: Ipv6cp::new(Ipv6cpOptions::new()),
+                    authenticated: false,
+                };
+
+                self.update_link(id, &mut link, PROTO_LCP, |l| l.lcp.open());
+                taken.push(link.ipcp.negotiator().config().peer);
+
+                link
+            });
+        }
+    }
+
+    /// Returns [`SessionConfig::ipcp`] with the first peer address from its pool that is not
+    /// `taken` or the gateway.
+    fn allocate_ipcp(&self, taken: &[Ipv4Addr]) -> IpcpConfig {
+        let first = u32::from(self.ipcp.peer);
+        let last = u32::from(self.ipcp.last_peer());
+        let peer = match (first..=last)
+            .map(Ipv4Addr::from)
+            .find(|a| *a != self.ipcp.gateway && !taken.contains(a))
+        {
+            Some(v) => v,
+            None => {
+                warning!(
+                    "WARNING: all {} addresses from {} are in use. The new session will share it.",
+                    IpcpConfig::POOL,
+                    self.ipcp.peer
+                );
+
+                self.ipcp.peer
+            }
+        };
+
+        IpcpConfig {
+            peer,
+            ..self.ipcp.clone()
+        }
+    }
+
+    /// Wait until the restart timer of any control protocol has been expired.
+    async fn restart_timer(&self) {
+        let deadline = self
+            .links
+            .lock()
+            .unwrap()
+            .values()
+            .flat_map(|l| {
+                [
+                    l.lcp.deadline(),
+                    l.lcp.negotiator().echo_deadline(),
+                    l.lcp.negotiator().probe_deadline(),
+                    l.ipcp.deadline(),
+                    l.ipv6cp.deadline(),
+                ]
+            })
+            .flatten()
+            .min();
+
+        match deadline {
+            Some(v) => rt::sleep(v.saturating_duration_since(Instant::now())).await,
+            None => pending().await,
+        }
+    }
+
+    fn timeout_links(&self) {
+        let now = Instant::now();
+
+        for (&id, link) in self.links.lock().unwrap().iter_mut() {
+            if link.lcp.deadline().is_some_and(|v| v <= now) {
+                self.update_link(id, link, PROTO_LCP, |l| l.lcp.timeout());
+            }
+
+            if link.ipcp.deadline().is_some_and(|v| v <= now) {
+                self.update_link(id, link, PROTO_IPCP, |l| l.ipcp.timeout());
+            }
+
+            if link.ipv6cp.deadline().is_some_and(|v| v <= now) {
+                self.update_link(id, link, PROTO_IPV6CP, |l| l.ipv6cp.timeout());
+            }
+
+            // Probe the next size.
+            if link
+                .lcp
+                .negotiator()
+                .probe_deadline()
+                .is_some_and(|v| v <= now)
+            {
+                self.update_link(id, link, PROTO_LCP, |l| {
+                    l.lcp.negotiator_mut().probe_timeout().into_iter().collect()
+                });
+            }
+
+            // Send keepalive.
+            if link
+                .lcp
+                .negotiator()
+                .echo_deadline()
+                .is_some_and(|v| v <= now)
+            {
+                match link.lcp.negotiator_mut().echo() {
+                    Some(v) => self.send_control(id, link.peer, PROTO_LCP, vec![v]),
+                    None => {
+                        info!(
+                            "Session {} with {}: no reply for LCP Echo-Request.",
+                            id, link.peer
+                        );
+
+                        self.sessions.send(id, SessionMsg::EchoTimeout);
+                    }
+                }
+            }
+        }
+    }
+
+    fn receive_control(&self, proto: u16, session: u16, packet: &[u8]) {
+        let id = match NonZeroU16::new(session) {
+            Some(v) => v,
+            None => return,
+        };
+
+        let mut links = self.links.lock().unwrap();
+        let link = match links.get_mut(&id) {
+            Some(v) => v,
+            None => return,
+        };
+
+        match proto {
+            PROTO_LCP => {
+                let lcp = link.lcp.state();
+
+                self.update_link(id, link, proto, |l| l.lcp.receive(packet));
+
+                // Close the session if the peer terminated LCP on its own.
+                if packet.first() == Some(&TERMINATE_REQUEST)
+                    && lcp != FsmState::Closing
+                    && lcp != link.lcp.state()
+                {
+                    info!(
+                        "Session {} with {}: LCP was terminated by the peer.",
+                        id, link.peer
+                    );
+
+                    self.sessions.send(id, SessionMsg::LcpTerminated);
+                }
+            }
+            // Network control protocols are discarded until LCP is opened (RFC 1661).
+            PROTO_IPCP if link.lcp.state() == FsmState::Opened && !self.ipv4 => {
+                self.update_link(id, link, PROTO_LCP, |l| {
+                    vec![l.lcp.negotiator_mut().protocol_reject(proto, packet)]
+                })
+            }
+            PROTO_IPCP if link.lcp.state() == FsmState::Opened => {
+                self.update_link(id, link, proto, |l| l.ipcp.receive(packet))
+            }
+            PROTO_IPV6CP if link.lcp.state() == FsmState::Opened => {
+                self.update_link(id, link, proto, |l| l.ipv6cp.receive(packet))
+            }
+            PROTO_PAP if link.lcp.state() == FsmState::Opened => self.receive_pap(id, link, packet),
+            // Reject the protocols we don't implement (e.g. CCP) otherwise the peer will keep
+            // retransmitting it until its own timeout.
+            _ if link.lcp.state() == FsmState::Opened => {
+                info!(
+                    "Session {} with {}: rejected PPP protocol {:#06x}.",
+                    id, link.peer, proto
+                );
+
+                self.update_link(id, link, PROTO_LCP, |l| {
+                    vec![l.lcp.negotiator_mut().protocol_reject(proto, packet)]
+                })
+            }
+            _ => {}
+        }
+    }
+
+    /// Answer PAP Authenticate-Request. This is done even if we did not ask for it since some
+    /// peers authenticate anyway.
+    fn receive_pap(&self, id: NonZeroU16, link: &mut Link, packet: &[u8]) {
+        let (reply, result) = match authenticate(self.pap.as_ref(), packet) {
+            Some(v) => v,
+            None => return,
+        };
+
+        self.send_control(id, link.peer, PROTO_PAP, vec![reply]);
+
+        match result {
+            // The peer will retransmit the request if our Authenticate-Ack was lost.
+            Ok(_) if link.authenticated => {}
+            Ok(v) => {
+                info!(
+                    "Session {} with {}: authenticated as '{}' with PAP.",
+                    id, link.peer, v
+                );
+
+                link.authenticated = true;
+
+                if link.lcp.negotiator().pap() {
+                    self.start_network(id, link);
+                }
+            }
+            Err(v) => {
+                warning!(
+                    "Session {} with {}: invalid PAP credentials for '{}'.",
+                    id,
+                    link.peer,
+                    v
+                );
+
+                self.update_link(id, link, PROTO_LCP, |l| l.lcp.close());
+            }
+        }
+    }
+
+    /// Start the network control protocols on `link`.
+    fn start_network(&self, id: NonZeroU16, link: &mut Link) {
+        if self.ipv4 {
+            let packets = link.ipcp.open();
+
+            self.send_control(id, link.peer, PROTO_IPCP, packets);
+        }
+
+        let packets = link.ipv6cp.open();
+
+        self.send_control(id, link.peer, PROTO_IPV6CP, packets);
+    }
+
+    /// Run `f` on `link` then send the resulting packets of `proto`.
+    fn update_link(
+        &self,
+        id: NonZeroU16,
+        link: &mut Link,
+        proto: u16,
+        f: impl FnOnce(&mut Link) -> Vec<Vec<u8>>,
+    ) {
+        let lcp = link.lcp.state();
+        let ipcp = link.ipcp.state();
+        let ipv6cp = link.ipv6cp.state();
+        let probe = link.lcp.negotiator().probe();
+        let packets = f(link);
+
+        self.send_control(id, link.peer, proto, packets);
+
+        // Check if the peer did something RFC 1661 does not expect.
+        for (name, v) in [
+            ("LCP", link.lcp.take_violation()),
+            ("IPCP", link.ipcp.take_violation()),
+            ("IPv6CP", link.ipv6cp.take_violation()),
+        ] {
+            let v = match v {
+                Some(v) => format!("{name} {v}"),
+                None => continue,
+            };
+
+            if self.strict {
+                warning!("Session {} with {}: {}.", id, link.peer, v);
+                self.sessions.send(id, SessionMsg::Violation(v));
+            } else {
+                debug!("Session {} with {}: {}.", id, link.peer, v);
+            }
+        }
+
+        // Start the network control protocols once LCP is opened and start over when LCP goes down.
+        match link.lcp.state() {
+            v if v == lcp => {}
+            FsmState::Opened => {
+                let packet = link.lcp.negotiator_mut().start_probe();
+
+                link.lcp.negotiator_mut().start_echo();
+
+                self.send_control(id, link.peer, PROTO_LCP, vec![packet]);
+
+                // Wait for the peer to authenticate if it agreed to.
+                if !link.lcp.negotiator().pap() || link.authenticated {
+                    self.start_network(id, link);
+                }
+            }
+            _ if lcp == FsmState::Opened => {
+                link.lcp.negotiator_mut().stop_echo();
+                link.lcp.negotiator_mut().stop_probe();
+                link.ipcp = Ipcp::new(IpcpOptions::new(link.ipcp.negotiator().config().clone()));
+                link.ipv6cp = Ipv6cp::new(Ipv6cpOptions::new());
+                link.authenticated = false;
+            }
+            _ => {}
+        }
+
+        // Report the changes.
+        match link.lcp.state() {
+            v if v == lcp => {}
+            FsmState::Opened => {
+                info!("Session {} with {}: LCP is opened.", id, link.peer);
+                self.sessions.emit(id, EventKind::LcpUp);
+            }
+            FsmState::Stopped => {
+                info!("Session {} with {}: LCP is stopped.", id, link.peer);
+                self.terminated.notify_waiters();
+            }
+            _ => {}
+        }
+
+        match link.lcp.negotiator().probe() {
+            v if v == probe => {}
+            MtuProbe::Done(Some(v)) if v >= MAX_MRU => {
+                info!(
+                    "Session {} with {}: path MTU is {} bytes.",
+                    id, link.peer, v
+                )
+            }
+            MtuProbe::Done(Some(v)) => {
+                warning!(
+                "WARNING: session {} with {}: path MTU is only {} bytes. Some USB NICs silently \
+                drop large frames, which will break the exploit.",
+                id, link.peer, v
+            )
+            }
+            MtuProbe::Done(None) => warning!(
+                "WARNING: session {} with {}: no padded LCP Echo-Request was answered.",
+                id,
+                link.peer
+            ),
+            _ => {}
+        }
+
+        match link.ipcp.state() {
+            v if v == ipcp => {}
+            FsmState::Opened => {
+                info!(
+                    "Session {} with {}: IPCP is opened with {}.",
+                    id,
+                    link.peer,
+                    link.ipcp.negotiator().config().peer
+                );
+
+                self.sessions
+                    .emit(id, EventKind::IpcpUp(link.ipcp.negotiator().config().peer));
+
+                if self.kernel {
+                    let config = link.ipcp.negotiator().config();
+                    let msg = SessionMsg::IpcpOpened {
+                        local: config.gateway,
+                        peer: config.peer,
+                    };
+
+                    self.sessions.send(id, msg);
+                }
+
+                self.open_bridge(id, link);
+            }
+            FsmState::Stopped => info!("Session {} with {}: IPCP is stopped.", id, link.peer),
+            _ => {}
+        }
+
+        match link.ipv6cp.state() {
+            v if v == ipv6cp => {}
+            FsmState::Opened => {
+                info!(
+                    "Session {} with {}: IPv6CP is opened with {}.",
+                    id,
+                    link.peer,
+                    link.ipv6cp.negotiator().peer().unwrap()
+                );
+
+                if self.kernel {
+                    self.sessions.send(id, SessionMsg::Ipv6cpOpened);
+                }
+            }
+            FsmState::Stopped => info!("Session {} with {}: IPv6CP is stopped.", id, link.peer),
+            _ => {}
+        }
+    }
+
+    fn send_control(&self, id: NonZeroU16, peer: MacAddr6, proto: u16, packets: Vec<Vec<u8>>) {
+        for p in packets {
+            let mut frame = Vec::with_capacity(2 + p.len());
+
+            frame.extend_from_slice(&proto.to_be_bytes());
+            frame.extend_from_slice(&p);
+
+            let frame = Payload::new(0x00, id.get(), Cow::Owned(frame));
+
+            if let Err(e) = self.sock.send(
+                self.ab.build(self.ethertype, Some(peer)),
+                &frame.serialize(),
+            ) {
+                error!(
+                    "Failed to send PPP control packet {:#06x} to {}: {}.",
+                    proto,
+                    peer,
+                    e.display()
+                );
+            }
+        }
+    }
+
+    /// Create a TUN device for session `id` if the sessions are bridged and it does not have one.
+    fn open_bridge(&self, id: NonZeroU16, link: &Link) {
+        let prefix = match &self.bridge {
+            Some(v) => v,
+            None => return,
+        };
+
+        let mut bridges = self.bridges.lock().unwrap();
+
+        if bridges.contains_key(&id) {
+            return;
+        }
+
+        // Create the device.
+        let config = link.ipcp.negotiator().config();
+        let tun = match Tun::open(&format!("{prefix}%d")) {
+            Ok(v) => Arc::new(v),
+            Err(e) => {
+                error!(
+                    "Failed to create TUN device for session {}: {}.",
+                    id,
+                    e.display()
+                );
+                return;
+            }
+        };
+
+        if let Err(e) = tun.configure(config.gateway, config.peer, 1492) {
+            error!("Failed to configure {}: {}.", tun.name(), e.display());
+            return;
+        }
+
+        info!(
+            "Session {} with {}: forwarding IP traffic through {}.",
+            id,
+            link.peer,
+            tun.name()
+        );
+
+        // Spawn a task to read the device. The device will be removed once the task is stopped.
+        let stop = CancellationToken::new();
+        let bridged = self.bridged.clone();
+        let task = {
+            let tun = tun.clone();
+            let stop = stop.clone();
+
+            async move {
+                let mut buf = [0; 1500];
+                let mut recovery = Recovery::new("TUN device");
+
+                loop {
+                    let len = select! {
+                        _ = stop.cancelled() => break,
+                        v = tun.recv(&mut buf) => match v {
+                            Ok(v) => {
+                                recovery.succeeded();
+                                v
+                            }
+                            Err(e) if recovery.failed(&e, &stop).await => continue,
+                            Err(_) => break,
+                        }
+                    };
+
+                    if bridged.send((id, buf[..len].to_vec())).is_err() {
+                        break;
+                    }
+                }
+            }
+        };
+
+        rt::spawn(task);
+
+        bridges.insert(
+            id,
+            Bridge {
+                tun,
+                peer: link.peer,
+                _stop: stop.drop_guard(),
+            },
+        );
+    }
+
+    fn forward_to_host(&self, addr: MacAddr6, id: NonZeroU16, packet: &[u8]) {
+        // Answer the DNS queries for the blocked domains ourself.
+        if let Some((name, resp)) = self.blocklist.as_ref().and_then(|v| v.intercept(packet)) {
+            info!("Session {id} with {addr}: blocked DNS query for {name}.");
+
+            self.blocked.fetch_add(1, Ordering::Relaxed);
+            self.send_ip(id.get(), addr, &resp);
+            return;
+        }
+
+        // Use the TUN device of the session if it has one.
+        if let Some(b) = self.bridges.lock().unwrap().get(&id) {
+            if let Err(e) = b.tun.send(packet) {
+                error!(
+                    "Failed to forward IP packet from {} to {}: {}.",
+                    addr,
+                    b.tun.name(),
+                    e.display()
+                );
+            }
+
+            return;
+        }
+
+        let session = id.get();
+        let tun = match &self.tun {
+            Some(v) => v,
+            None => return,
+        };
+
+        // Learn the address of the peer so we know where to forward the reply.
+        let src = match Self::packet_addrs(packet) {
+            Some((v, _)) => v,
+            None => {
+                warning!("Invalid IP packet from {addr}.");
+                return;
+            }
+        };
+
+        self.routes.lock().unwrap().insert(src, (session, addr));
+
+        if let Err(e) = tun.send(packet) {
+            error!(
+                "Failed to forward IP packet from {} to {}: {}.",
+                addr,
+                tun.name(),
+                e.display()
+            );
         }
     }
+
+    /// Forward `packet` to the session that own its destination address or session `bridge` if it
+    /// is from the TUN device of that session.
+    fn forward_to_peer(&self, packet: &[u8], bridge: Option<NonZeroU16>) {
+        let dst = match Self::packet_addrs(packet) {
+            Some((_, v)) => v,
+            None => return,
+        };
+
+        // Lookup the session.
+        let route = match bridge {
+            Some(id) => self
+                .bridges
+                .lock()
+                .unwrap()
+                .get(&id)
+                .map(|b| (id.get(), b.peer)),
+            None => self.routes.lock().unwrap().get(&dst).copied(),
+        };
+
+        let (session, peer) = match route {
+            Some(v) => v,
+            None => return,
+        };
+
+        self.send_ip(session, peer, packet);
+    }
+
+    /// Encapsulate an IP packet into session `session` with `peer`.
+    fn send_ip(&self, session: u16, peer: MacAddr6, packet: &[u8]) {
+        let proto = match packet.first().map(|v| v >> 4) {
+            Some(4) => PROTO_IPV4,
+            Some(6) => PROTO_IPV6,
+            _ => return,
+        };
+
+        // PPP protocol field take 2 bytes from the PPPoE MTU.
+        if packet.len() > 1492 {
+            warning!(
+                "Dropped a {} bytes IP packet to {peer} (MTU is 1492).",
+                packet.len()
+            );
+            return;
+        }
+
+        // Encapsulate.
+        let mut frame = Vec::with_capacity(2 + packet.len());
+
+        frame.extend_from_slice(&proto.to_be_bytes());
+        frame.extend_from_slice(packet);
+
+        let frame = Payload::new(0x00, session, Cow::Owned(frame));
+
+        if let Err(e) = self.sock.send(
+            self.ab.build(self.ethertype, Some(peer)),
+            &frame.serialize(),
+        ) {
+            error!("Failed to forward IP packet to {}: {}.", peer, e.display());
+        }
+    }
+
+    /// Returns source and destination address of an IP packet.
+    fn packet_addrs(packet: &[u8]) -> Option<(IpAddr, IpAddr)> {
+        match packet.first()? >> 4 {
+            4 if packet.len() >= 20 => {
+                let src: [u8; 4] = packet[12..16].try_into().unwrap();
+                let dst: [u8; 4] = packet[16..20].try_into().unwrap();
+
+                Some((Ipv4Addr::from(src).into(), Ipv4Addr::from(dst).into()))
+            }
+            6 if packet.len() >= 40 => {
+                let src: [u8; 16] = packet[8..24].try_into().unwrap();
+                let dst: [u8; 16] = packet[24..40].try_into().unwrap();
+
+                Some((Ipv6Addr::from(src).into(), Ipv6Addr::from(dst).into()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// TUN device of a session.
+struct Bridge {
+    tun: Arc<Tun>,
+    peer: MacAddr6,
+    _stop: DropGuard,
+}
+
+/// PPP link of a session.
+struct Link {
+    peer: MacAddr6,
+    lcp: Lcp,
+    ipcp: Ipcp,
+    ipv6cp: Ipv6cp,
+    authenticated: bool,
+}
+
+/// Configurations for [`SessionServer`].
+pub struct SessionConfig {
+    /// EtherType of the frames to send.
+    pub ethertype: u16,
+    /// If `true` the sessions are attached to the kernel PPP subsystem, which will carry the
+    /// network protocols once the control protocols have been negotiated by the server.
+    pub kernel: bool,
+    /// If `false` IPCP will be refused with LCP Protocol-Reject so only IPv6 is available.
+    pub ipv4: bool,
+    /// Addresses to assign to the peers. Each session get the first free address from
+    /// [`IpcpConfig::peer`] to [`IpcpConfig::last_peer()`] so the routes to the consoles don't
+    /// collide.
+    pub ipcp: IpcpConfig,
+    /// If not [`None`] the peers will be asked to authenticate with PAP.
+    pub pap: Option<PapConfig>,
+    /// Interval of LCP Echo-Request to the peers. [`None`] to send nothing.
+    pub echo_interval: Option<Duration>,
+    /// Close the session of the peer that violate the protocol instead of ignoring it.
+    pub strict: bool,
+    /// If not [`None`] a TUN device named with this prefix will be created for each session once
+    /// IPCP is opened. The IP traffic of the session will go through it instead of the shared one.
+    pub bridge: Option<String>,
+    /// The DNS queries from the peers for these domains will be answered with NXDOMAIN instead of
+    /// being forwarded.
+    pub blocklist: Option<DnsBlocklist>,
+}
+
+/// Returns a sample of every kind of control packets the server can send. Each item is the name,
+/// PPP protocol and the packet. This is used by the self-check.
+pub(crate) fn sample_packets() -> Vec<(&'static str, u16, Vec<u8>)> {
+    let config = IpcpConfig {
+        peer: Ipv4Addr::new(10, 0, 0, 2),
+        gateway: Ipv4Addr::new(10, 0, 0, 1),
+        dns: vec![Ipv4Addr::new(8, 8, 8, 8), Ipv4Addr::new(8, 8, 4, 4)],
+    };
+    let mut lcp = Lcp::new(LcpOptions::new(Some(Duration::from_secs(1)), true));
+    let mut ipcp = Ipcp::new(IpcpOptions::new(config));
+    let mut ipv6cp = Ipv6cp::new(Ipv6cpOptions::new());
+    let mut packets = Vec::new();
+    let mut push = |name, proto, mut v: Vec<Vec<u8>>| {
+        if let Some(v) = v.pop() {
+            packets.push((name, proto, v));
+        }
+    };
+
+    // Open the link.
+    let req = lcp.open();
+    let id = req[0][1];
+
+    push("LCP Configure-Request", PROTO_LCP, req);
+    push(
+        "LCP Configure-Reject",
+        PROTO_LCP,
+        lcp.receive(&packet(CONFIGURE_REQUEST, 1, &[0xff, 2])),
+    );
+    push(
+        "LCP Configure-Nak",
+        PROTO_LCP,
+        lcp.receive(&packet(CONFIGURE_REQUEST, 2, &[OPT_MRU, 4, 0xff, 0xff])),
+    );
+    push(
+        "LCP Configure-Ack",
+        PROTO_LCP,
+        lcp.receive(&packet(CONFIGURE_REQUEST, 3, &[OPT_MRU, 4, 0x05, 0xd4])),
+    );
+
+    lcp.receive(&packet(CONFIGURE_ACK, id, &[]));
+    lcp.negotiator_mut().start_echo();
+
+    // Packets on the opened link.
+    push(
+        "LCP Echo-Request",
+        PROTO_LCP,
+        lcp.negotiator_mut().echo().into_iter().collect(),
+    );
+    push(
+        "LCP Echo-Reply",
+        PROTO_LCP,
+        lcp.receive(&packet(ECHO_REQUEST, 4, &[1, 2, 3, 4])),
+    );
+    push(
+        "LCP Echo-Request for MTU probe",
+        PROTO_LCP,
+        vec![lcp.negotiator_mut().start_probe()],
+    );
+    push(
+        "LCP Protocol-Reject",
+        PROTO_LCP,
+        vec![lcp.negotiator_mut().protocol_reject(PROTO_IPV6, &[0; 1500])],
+    );
+    push(
+        "LCP Code-Reject",
+        PROTO_LCP,
+        lcp.receive(&packet(0xff, 5, &[0; 1400])),
+    );
+    push(
+        "PAP Authenticate-Nak",
+        PROTO_PAP,
+        authenticate(
+            Some(&"ps4:ps4".parse().unwrap()),
+            &packet(AUTHENTICATE_REQUEST, 1, &[1, b'a', 1, b'b']),
+        )
+        .into_iter()
+        .map(|v| v.0)
+        .collect(),
+    );
+    push("IPCP Configure-Request", PROTO_IPCP, ipcp.open());
+    push(
+        "IPCP Configure-Nak",
+        PROTO_IPCP,
+        ipcp.receive(&packet(
+            CONFIGURE_REQUEST,
+            1,
+            &[3, 6, 0, 0, 0, 0, 129, 6, 0, 0, 0, 0, 131, 6, 0, 0, 0, 0],
+        )),
+    );
+    push("IPv6CP Configure-Request", PROTO_IPV6CP, ipv6cp.open());
+    push(
+        "IPv6CP Configure-Nak",
+        PROTO_IPV6CP,
+        ipv6cp.receive(&packet(
+            CONFIGURE_REQUEST,
+            1,
+            &[1, 10, 0, 0, 0, 0, 0, 0, 0, 0],
+        )),
+    );
+    push("LCP Terminate-Request", PROTO_LCP, lcp.close());
+    push(
+        "LCP Terminate-Ack",
+        PROTO_LCP,
+        lcp.receive(&packet(TERMINATE_REQUEST, 6, &[])),
+    );
+
+    packets
 }
 
+/// Maximum duration to wait for LCP Terminate-Ack when exiting.
+const TERMINATE_TIMEOUT: Duration = Duration::from_secs(1);
+
 type Payload<'a> = EthernetPayload<Cow<'a, [u8]>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attempt::Attempts;
+    use crate::fixtures::{peer_addr, runtime, synthetic_captures, transport};
+    use crate::log::Direction;
+    use crate::proto::lcp::PROTOCOL_REJECT;
+    use crate::proto::ppp::{CONFIGURE_NAK, CONFIGURE_REJECT, TERMINATE_ACK};
+    use crate::socket::MockPeer;
+    use libc::PACKET_HOST;
+
+    /// Replay the Configure-Requests from the synthetic captures then compare our replies with the
+    /// captured one. The captures must be recorded with the default options.
+    #[test]
+    fn replay_synthetic_configure_requests() {
+        let config = IpcpConfig {
+            peer: Ipv4Addr::new(10, 0, 0, 2),
+            gateway: Ipv4Addr::new(10, 0, 0, 1),
+            dns: Vec::new(),
+        };
+
+        for c in synthetic_captures() {
+            let mut lcp = Lcp::new(LcpOptions::new(None, false));
+            let mut ipcp = Ipcp::new(IpcpOptions::new(config.clone()));
+            let packets: Vec<_> = c
+                .frames
+                .iter()
+                .filter_map(|f| {
+                    let ppp = f.ppp()?;
+                    let proto = u16::from_be_bytes(ppp.get(..2)?.try_into().unwrap());
+                    let packet = ppp.get(2..)?;
+                    let len = u16::from_be_bytes(packet.get(2..4)?.try_into().unwrap());
+
+                    Some((f.dir, proto, packet.get(..len.into())?))
+                })
+                .collect();
+
+            lcp.open();
+            ipcp.open();
+
+            for (i, &(dir, proto, packet)) in packets.iter().enumerate() {
+                if !matches!(dir, Direction::Received) || packet[0] != CONFIGURE_REQUEST {
+                    continue;
+                }
+
+                let replies = match proto {
+                    PROTO_LCP => lcp.receive(packet),
+                    PROTO_IPCP => ipcp.receive(packet),
+                    _ => continue,
+                };
+
+                // Find the reply in the capture. Skip the request that has no reply (e.g. IPCP
+                // before LCP is opened).
+                let is_reply = |p: &[u8]| (CONFIGURE_ACK..=CONFIGURE_REJECT).contains(&p[0]);
+                let expect = packets[(i + 1)..]
+                    .iter()
+                    .find(|&&(d, p, v)| {
+                        matches!(d, Direction::Sent)
+                            && p == proto
+                            && v[1] == packet[1]
+                            && is_reply(v)
+                    })
+                    .map(|v| v.2);
+                let expect = match expect {
+                    Some(v) => v,
+                    None => continue,
+                };
+
+                let actual = replies
+                    .iter()
+                    .find(|v| is_reply(v))
+                    .unwrap_or_else(|| panic!("{}: no reply to packet #{}", c.name, i));
+
+                assert_eq!(actual, expect, "{}: reply to packet #{}", c.name, i);
+            }
+        }
+    }
+
+    #[test]
+    fn allocate_ipcp() {
+        let (sock, _) = transport();
+        let server = SessionServer::new(
+            Arc::new(sock),
+            Arc::new(AddrBuilder::new(0)),
+            Arc::new(Sessions::new(false)),
+            None,
+            SessionConfig {
+                ethertype: 0x8864,
+                kernel: false,
+                ipv4: true,
+                ipcp: IpcpConfig {
+                    peer: Ipv4Addr::new(10, 0, 0, 2),
+                    gateway: Ipv4Addr::new(10, 0, 0, 3),
+                    dns: vec![Ipv4Addr::new(8, 8, 8, 8)],
+                },
+                pap: None,
+                echo_interval: None,
+                strict: false,
+                bridge: None,
+                blocklist: None,
+            },
+        );
+        let first = server.allocate_ipcp(&[]);
+        let second = server.allocate_ipcp(&[first.peer]);
+
+        assert_eq!(first.peer, Ipv4Addr::new(10, 0, 0, 2));
+        assert_eq!(first.gateway, Ipv4Addr::new(10, 0, 0, 3));
+        assert_eq!(first.dns, [Ipv4Addr::new(8, 8, 8, 8)]);
+
+        // The gateway must be skipped.
+        assert_eq!(second.peer, Ipv4Addr::new(10, 0, 0, 4));
+        assert_eq!(second.gateway, first.gateway);
+
+        // The address of a closed session can be reused.
+        assert_eq!(
+            server.allocate_ipcp(&[second.peer]).peer,
+            Ipv4Addr::new(10, 0, 0, 2)
+        );
+    }
+
+    #[test]
+    fn open_link() {
+        const PROTO_CCP: u16 = 0x80fd;
+
+        runtime().block_on(async {
+            let (sock, mut peer) = transport();
+            let sessions = Arc::new(Sessions::new(false));
+            let server = Arc::new(SessionServer::new(
+                Arc::new(sock),
+                Arc::new(AddrBuilder::new(0)),
+                sessions.clone(),
+                None,
+                SessionConfig {
+                    ethertype: 0x8864,
+                    kernel: false,
+                    ipv4: true,
+                    ipcp: IpcpConfig {
+                        peer: Ipv4Addr::new(10, 0, 0, 2),
+                        gateway: Ipv4Addr::new(10, 0, 0, 1),
+                        dns: Vec::new(),
+                    },
+                    pap: None,
+                    echo_interval: None,
+                    strict: false,
+                    bridge: None,
+                    blocklist: None,
+                },
+            ));
+            let running = CancellationToken::new();
+            let console = MacAddr6::new(2, 0, 0, 0, 0, 1);
+            let session = sessions.spawn(console, None).unwrap();
+            let id = session.id().get();
+
+            rt::spawn({
+                let server = server.clone();
+                let running = running.clone();
+
+                async move { server.run(running).await }
+            });
+
+            // Wait for a control packet of `proto` with `code`.
+            let expect = async |peer: &mut MockPeer, proto: u16, code: u8| loop {
+                let (data, _) = peer.recv().await.unwrap();
+                let frame = Payload::deserialize(&data).unwrap();
+                let ppp = frame.payload();
+
+                assert_eq!(frame.session_id(), id);
+
+                if ppp[..2] == proto.to_be_bytes() && ppp[2] == code {
+                    break ppp[2..].to_vec();
+                }
+            };
+            let send = |peer: &MockPeer, proto: u16, packet: Vec<u8>| {
+                let frame = [&proto.to_be_bytes()[..], &packet].concat();
+                let frame = Payload::new(0x00, id, Cow::Owned(frame));
+
+                peer.send(&frame.serialize(), peer_addr(console, PACKET_HOST));
+            };
+
+            // LCP must be started on the new session.
+            let req = expect(&mut peer, PROTO_LCP, CONFIGURE_REQUEST).await;
+
+            send(
+                &peer,
+                PROTO_LCP,
+                packet(CONFIGURE_REQUEST, 1, &[1, 4, 5, 0xd4]),
+            );
+
+            assert_eq!(
+                expect(&mut peer, PROTO_LCP, CONFIGURE_ACK).await,
+                packet(CONFIGURE_ACK, 1, &[1, 4, 5, 0xd4])
+            );
+
+            // IPCP and IPv6CP must be started once LCP is opened.
+            send(&peer, PROTO_LCP, packet(CONFIGURE_ACK, req[1], &req[4..]));
+            expect(&mut peer, PROTO_IPCP, CONFIGURE_REQUEST).await;
+            expect(&mut peer, PROTO_IPV6CP, CONFIGURE_REQUEST).await;
+
+            // The interface identifier of the peer must be accepted unless it is zero.
+            let id1 = [1, 10, 0, 0, 0, 0, 0, 0, 0, 1];
+
+            send(&peer, PROTO_IPV6CP, packet(CONFIGURE_REQUEST, 1, &id1));
+
+            assert_eq!(
+                expect(&mut peer, PROTO_IPV6CP, CONFIGURE_ACK).await,
+                packet(CONFIGURE_ACK, 1, &id1)
+            );
+
+            send(
+                &peer,
+                PROTO_IPV6CP,
+                packet(CONFIGURE_REQUEST, 2, &[1, 10, 0, 0, 0, 0, 0, 0, 0, 0]),
+            );
+
+            let nak = expect(&mut peer, PROTO_IPV6CP, CONFIGURE_NAK).await;
+
+            assert_eq!(nak[4..6], [1, 10]);
+            assert_ne!(nak[6..], [0; 8]);
+
+            // The unknown protocols must be rejected.
+            let ccp = packet(CONFIGURE_REQUEST, 1, &[]);
+
+            send(&peer, PROTO_CCP, ccp.clone());
+
+            let reject = expect(&mut peer, PROTO_LCP, PROTOCOL_REJECT).await;
+
+            assert_eq!(reject[4..6], PROTO_CCP.to_be_bytes());
+            assert_eq!(reject[6..], ccp);
+
+            // The frames from another host must not reach the session.
+            let frame = [
+                &PROTO_LCP.to_be_bytes()[..],
+                &packet(TERMINATE_REQUEST, 3, &[]),
+            ]
+            .concat();
+            let frame = Payload::new(0x00, id, Cow::Owned(frame));
+            let other = MacAddr6::new(2, 0, 0, 0, 0, 2);
+
+            peer.send(&frame.serialize(), peer_addr(other, PACKET_HOST));
+
+            // The session must be closed when the peer terminates LCP.
+            let attempt = Attempts::default().get_or_start(console).unwrap();
+            let serve = tokio::spawn(async move { session.serve(&attempt).await });
+
+            send(&peer, PROTO_LCP, packet(TERMINATE_REQUEST, 2, &[]));
+            expect(&mut peer, PROTO_LCP, TERMINATE_ACK).await;
+            assert!(serve.await.unwrap().is_err());
+
+            let closed = sessions.closed();
+
+            assert_eq!(closed.len(), 1);
+            assert!(matches!(closed[0].2, CloseReason::LcpTerminated));
+            assert_eq!(server.spoofed.load(Ordering::Relaxed), 1);
+
+            running.cancel();
+            server.abort();
+        });
+    }
+}
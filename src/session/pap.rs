@@ -0,0 +1,102 @@
+use crate::proto::pap::{
+    parse_request, reply, AUTHENTICATE_ACK, AUTHENTICATE_NAK, AUTHENTICATE_REQUEST,
+};
+use crate::proto::ppp::{packet, parse_packet};
+use std::str::FromStr;
+
+/// Credentials to accept with PAP (RFC 1334).
+#[derive(Clone, Default)]
+pub struct PapConfig {
+    /// Username and password the peer must send. [`None`] to accept anything.
+    pub credentials: Option<(String, String)>,
+}
+
+impl PapConfig {
+    fn verify(&self, id: &[u8], password: &[u8]) -> bool {
+        match &self.credentials {
+            Some((u, p)) => u.as_bytes() == id && p.as_bytes() == password,
+            None => true,
+        }
+    }
+}
+
+impl FromStr for PapConfig {
+    type Err = String;
+
+    /// Parse `USER:PASSWORD`. An empty string accepts anything.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let (u, p) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expect USER:PASSWORD, got '{s}'"))?;
+
+        Ok(Self {
+            credentials: Some((u.into(), p.into())),
+        })
+    }
+}
+
+/// Returns the reply to PAP `packet` and Peer-ID of the peer, which is [`Ok`] if the peer was
+/// authenticated. Returns [`None`] if `packet` is not a valid Authenticate-Request.
+///
+/// Anything is accepted if `config` is [`None`].
+pub fn authenticate(
+    config: Option<&PapConfig>,
+    packet: &[u8],
+) -> Option<(Vec<u8>, Result<String, String>)> {
+    let (code, id, data) = parse_packet(packet)?;
+
+    if code != AUTHENTICATE_REQUEST {
+        return None;
+    }
+
+    let (peer, password) = parse_request(data)?;
+    let peer = String::from_utf8_lossy(peer).into_owned();
+
+    if config.is_none_or(|c| c.verify(peer.as_bytes(), password)) {
+        Some((self::packet(AUTHENTICATE_ACK, id, &reply(b"")), Ok(peer)))
+    } else {
+        let reply = reply(b"Invalid username or password");
+
+        Some((self::packet(AUTHENTICATE_NAK, id, &reply), Err(peer)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials() {
+        let config: PapConfig = "ps4:secret".parse().unwrap();
+        let req = |u: &[u8], p: &[u8]| {
+            let data = [&[u.len() as u8], u, &[p.len() as u8], p].concat();
+
+            packet(AUTHENTICATE_REQUEST, 7, &data)
+        };
+
+        let (reply, result) = authenticate(Some(&config), &req(b"ps4", b"secret")).unwrap();
+
+        assert_eq!(reply, [AUTHENTICATE_ACK, 7, 0, 5, 0]);
+        assert_eq!(result, Ok("ps4".into()));
+
+        let (reply, result) = authenticate(Some(&config), &req(b"ps4", b"wrong")).unwrap();
+
+        assert_eq!(reply[0], AUTHENTICATE_NAK);
+        assert_eq!(result, Err("ps4".into()));
+
+        // Anything is accepted without the credentials.
+        let any = "".parse::<PapConfig>().unwrap();
+
+        assert!(authenticate(Some(&any), &req(b"a", b"b"))
+            .unwrap()
+            .1
+            .is_ok());
+        assert!(authenticate(None, &req(b"a", b"b")).unwrap().1.is_ok());
+        assert!(authenticate(None, &packet(AUTHENTICATE_ACK, 1, &[0])).is_none());
+        assert!("ps4".parse::<PapConfig>().is_err());
+    }
+}
@@ -1,22 +1,66 @@
+use crate::buffers::Buffers;
+use crate::chaos::{Chaos, Verdict, REORDER_DELAY};
+use crate::conformance;
+use crate::log::{warning, Direction, Logger};
+use crate::proto::pppoe::Padding;
+use crate::ring::RxRing;
+use crate::rt::{self, BoxFuture};
+use crate::telemetry::SendTiming;
+use crate::tstamp::TxStamps;
+use erdp::ErrorDisplay;
 use libc::{
-    fcntl, recvfrom, sendto, sockaddr, sockaddr_ll, socket, socklen_t, AF_PACKET, F_GETFL, F_SETFL,
-    O_NONBLOCK, SOCK_DGRAM,
+    c_int, fcntl, getsockopt, iovec, msghdr, packet_mreq, poll, pollfd, recvfrom, recvmsg, sendmsg,
+    sendto, setsockopt, sock_filter, sock_fprog, sockaddr, sockaddr_ll, socket, socklen_t,
+    tpacket_stats, AF_PACKET, ETH_P_ALL, F_GETFL, F_SETFL, O_NONBLOCK, PACKET_ADD_MEMBERSHIP,
+    PACKET_MR_PROMISC, PACKET_STATISTICS, POLLIN, SOCK_DGRAM, SOCK_RAW, SOL_PACKET, SOL_SOCKET,
+    SO_ATTACH_FILTER, SO_RCVBUF, SO_RCVBUFFORCE, SO_SNDBUF, SO_SNDBUFFORCE,
 };
-use pretty_hex::{hex_write, HexConfig};
-use std::fmt::Write;
-use std::io::Error;
+use macaddr::MacAddr6;
+use std::fmt::{Display, Formatter, Write};
+use std::io::{Error, ErrorKind};
 use std::mem::{size_of_val, zeroed};
-use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
-use tokio::io::unix::AsyncFd;
-use tokio::io::Interest;
-
-/// Encapsulate an `AF_PACKET` socket.
-pub struct PacketSocket(AsyncFd<OwnedFd>);
+use std::ops::AddAssign;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Instant, SystemTime};
+use tokio::select;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// EtherType for local experiments (IEEE 802), which is used by [`probe_cooked()`].
+const PROBE_PROTO: u16 = 0x88b5;
+const PROBE_DATA: &[u8] = b"jailbreak-11 socket probe";
+const PROBE_TIMEOUT: c_int = 200;
+
+/// Length of Ethernet header.
+const HEADER_LEN: usize = 14;
+
+pub use crate::tstamp::TxCallback;
+
+/// Encapsulate an `AF_PACKET` socket for PPPoE frames.
+pub struct PacketSocket {
+    fd: rt::Fd,
+    logger: Logger,
+    padding: Padding,
+    framing: Framing,
+    received: AtomicU64,
+    sent: AtomicU64,
+    chaos: Option<(Arc<Chaos>, Arc<OwnedFd>)>,
+    held: Mutex<Option<(Vec<u8>, sockaddr_ll)>>,
+    kernel: Mutex<SocketStats>,
+    ring: Option<RxRing>,
+    tx: OnceLock<TxStamps>,
+}
 
 impl PacketSocket {
-    pub fn new() -> Result<Self, Error> {
+    pub fn new(logger: Logger, padding: Padding, framing: Framing) -> Result<Self, Error> {
         // Create socket.
-        let s = unsafe { socket(AF_PACKET, SOCK_DGRAM, 0) };
+        let ty = match framing {
+            Framing::Cooked => SOCK_DGRAM,
+            Framing::Raw(_) => SOCK_RAW,
+        };
+
+        let s = unsafe { socket(AF_PACKET, ty, 0) };
 
         if s < 0 {
             return Err(Error::last_os_error());
@@ -30,12 +74,61 @@ impl PacketSocket {
             return Err(Error::last_os_error());
         }
 
-        // Register with Tokio.
-        Ok(Self(AsyncFd::with_interest(s, Interest::READABLE)?))
+        // Register with the runtime.
+        Ok(Self {
+            fd: rt::Fd::new(s)?,
+            logger,
+            padding,
+            framing,
+            received: AtomicU64::new(0),
+            sent: AtomicU64::new(0),
+            chaos: None,
+            held: Mutex::default(),
+            kernel: Mutex::default(),
+            ring: None,
+            tx: OnceLock::new(),
+        })
+    }
+
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    /// Impair all frames on this socket with `chaos`.
+    pub fn set_chaos(&mut self, chaos: Arc<Chaos>) -> Result<(), Error> {
+        // The delayed frames are sent from another thread so it need its own descriptor.
+        let fd = unsafe { BorrowedFd::borrow_raw(self.fd.as_raw_fd()) }.try_clone_to_owned()?;
+
+        self.chaos = Some((chaos, Arc::new(fd)));
+
+        Ok(())
+    }
+
+    /// Receive the frames through `PACKET_RX_RING` with at least `frames` frames instead of
+    /// `recvmsg`. Returns the actual number of frames in the ring.
+    pub fn enable_rx_ring(&mut self, frames: usize) -> Result<usize, Error> {
+        let ring = RxRing::new(self.fd.as_raw_fd(), frames)?;
+        let frames = ring.frames();
+
+        self.ring = Some(ring);
+
+        Ok(frames)
+    }
+
+    /// Request software TX timestamps for the frames that was sent with
+    /// [`Self::send_confirmed()`].
+    pub fn enable_tx_timestamps(&self) -> Result<(), Error> {
+        let fd = unsafe { BorrowedFd::borrow_raw(self.fd.as_raw_fd()) };
+
+        if self.tx.get().is_none() {
+            let _ = self.tx.set(TxStamps::new(fd)?);
+        }
+
+        Ok(())
     }
 
     pub fn bind(&self, addr: sockaddr_ll) -> Result<(), Error> {
-        let fd = self.0.as_raw_fd();
+        let fd = self.fd.as_raw_fd();
         let len = size_of_val(&addr).try_into().unwrap();
         let addr = &addr as *const sockaddr_ll as *const sockaddr;
 
@@ -46,102 +139,797 @@ impl PacketSocket {
         }
     }
 
+    /// Receive all frames on `interface` including the frames for the other hosts.
+    pub fn enable_promiscuous(&self, interface: c_int) -> Result<(), Error> {
+        let mut req: packet_mreq = unsafe { zeroed() };
+
+        req.mr_ifindex = interface;
+        req.mr_type = PACKET_MR_PROMISC as _;
+
+        if unsafe {
+            setsockopt(
+                self.fd.as_raw_fd(),
+                SOL_PACKET,
+                PACKET_ADD_MEMBERSHIP,
+                &req as *const packet_mreq as _,
+                size_of_val(&req).try_into().unwrap(),
+            ) < 0
+        } {
+            Err(Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Set the sizes of the socket buffers and returns the sizes that the kernel actually use.
+    ///
+    /// The request is not limited by `net.core.rmem_max` and `net.core.wmem_max` when running with
+    /// `CAP_NET_ADMIN`. The zero sizes are left untouched.
+    pub fn set_buffers(&self, sizes: Buffers) -> Result<Buffers, Error> {
+        let recv = self.set_buffer(SO_RCVBUFFORCE, SO_RCVBUF, sizes.recv)?;
+        let send = self.set_buffer(SO_SNDBUFFORCE, SO_SNDBUF, sizes.send)?;
+
+        Ok(Buffers {
+            recv,
+            send,
+            ring: sizes.ring,
+        })
+    }
+
+    fn set_buffer(&self, force: c_int, opt: c_int, size: usize) -> Result<usize, Error> {
+        let fd = self.fd.as_raw_fd();
+
+        if size != 0 {
+            let v: c_int = size.try_into().unwrap_or(c_int::MAX);
+            let len = size_of_val(&v).try_into().unwrap();
+            let set = |o| unsafe { setsockopt(fd, SOL_SOCKET, o, &v as *const c_int as _, len) };
+
+            // Fall back to the limited one without CAP_NET_ADMIN.
+            if set(force) < 0
+                && (Error::last_os_error().kind() != ErrorKind::PermissionDenied || set(opt) < 0)
+            {
+                return Err(Error::last_os_error());
+            }
+        }
+
+        // The kernel double the value for its bookkeeping.
+        let mut v: c_int = 0;
+        let mut len: socklen_t = size_of_val(&v).try_into().unwrap();
+
+        if unsafe { getsockopt(fd, SOL_SOCKET, opt, &mut v as *mut c_int as _, &mut len) } < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        Ok((v / 2).try_into().unwrap())
+    }
+
+    /// Returns the kernel counters since the socket was created.
+    pub fn statistics(&self) -> Result<SocketStats, Error> {
+        // The kernel reset its counters on every read so we need to keep the total.
+        let mut v: tpacket_stats = unsafe { zeroed() };
+        let mut len: socklen_t = size_of_val(&v).try_into().unwrap();
+
+        if unsafe {
+            getsockopt(
+                self.fd.as_raw_fd(),
+                SOL_PACKET,
+                PACKET_STATISTICS,
+                &mut v as *mut tpacket_stats as _,
+                &mut len,
+            ) < 0
+        } {
+            return Err(Error::last_os_error());
+        }
+
+        let v = SocketStats {
+            received: v.tp_packets.into(),
+            dropped: v.tp_drops.into(),
+        };
+
+        let mut total = self.kernel.lock().unwrap();
+
+        *total += v;
+
+        Ok(*total)
+    }
+
+    /// Replace the socket filter with `prog`.
+    pub fn attach_filter(&self, prog: &[sock_filter]) -> Result<(), Error> {
+        let prog = sock_fprog {
+            len: prog
+                .len()
+                .try_into()
+                .map_err(|_| Error::from(ErrorKind::InvalidInput))?,
+            filter: prog.as_ptr().cast_mut(),
+        };
+
+        if unsafe {
+            setsockopt(
+                self.fd.as_raw_fd(),
+                SOL_SOCKET,
+                SO_ATTACH_FILTER,
+                &prog as *const sock_fprog as _,
+                size_of_val(&prog).try_into().unwrap(),
+            ) < 0
+        } {
+            Err(Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Receive a PPPoE frame. The Ethernet padding will be removed according to [`Padding`].
     pub async fn recv(&self, buf: &mut [u8]) -> Result<(usize, sockaddr_ll), Error> {
-        // Receive.
-        let mut addr: sockaddr_ll = unsafe { zeroed() };
-        let received = loop {
-            if let Ok(v) = self.0.readable().await?.try_io(|s| {
-                let mut alen: socklen_t = size_of_val(&addr).try_into().unwrap();
-                let received = unsafe {
-                    recvfrom(
-                        s.as_raw_fd(),
-                        buf.as_mut_ptr().cast(),
-                        buf.len(),
-                        0,
-                        &mut addr as *mut sockaddr_ll as _,
-                        &mut alen,
-                    )
-                };
+        loop {
+            let (received, addr) = self.recv_raw(buf).await?;
+
+            match self.padding.trim(&buf[..received]) {
+                Some(v) => break Ok((v.len(), addr)),
+                None => warning!(
+                    "Dropped a malformed PPPoE frame with {} bytes from {}.",
+                    received,
+                    MacAddr6::from(TryInto::<[u8; 6]>::try_into(&addr.sll_addr[..6]).unwrap())
+                ),
+            }
+        }
+    }
 
-                if received < 0 {
-                    return Err(Error::last_os_error());
+    /// Same as [`Self::recv()`] but the frame is not required to be PPPoE and will be returned as
+    /// is.
+    pub async fn recv_raw(&self, buf: &mut [u8]) -> Result<(usize, sockaddr_ll), Error> {
+        let chaos = match &self.chaos {
+            Some((v, _)) => v,
+            None => return self.recv_once(buf).await,
+        };
+
+        loop {
+            // Return the frame that was held for reordering if nothing overtake it in time.
+            let (received, addr) = if self.held.lock().unwrap().is_some() {
+                select! {
+                    v = self.recv_once(buf) => v?,
+                    _ = rt::sleep(REORDER_DELAY) => {
+                        let (data, addr) = self.held.lock().unwrap().take().unwrap();
+
+                        buf[..data.len()].copy_from_slice(&data);
+
+                        return Ok((data.len(), addr));
+                    }
+                }
+            } else {
+                self.recv_once(buf).await?
+            };
+
+            match chaos.inbound() {
+                Verdict::Pass => {}
+                Verdict::Drop => continue,
+                Verdict::Delay(v) => rt::sleep(v).await,
+                Verdict::Reorder(_) => {
+                    let mut held = self.held.lock().unwrap();
+
+                    // Only a single frame can be held at a time.
+                    if held.is_none() {
+                        *held = Some((buf[..received].to_vec(), addr));
+                        continue;
+                    }
                 }
+            }
 
-                assert_eq!(alen, size_of_val(&addr).try_into().unwrap());
+            return Ok((received, addr));
+        }
+    }
 
-                Ok(received as usize)
-            }) {
-                break v?;
+    async fn recv_once(&self, buf: &mut [u8]) -> Result<(usize, sockaddr_ll), Error> {
+        let (received, addr) = match &self.ring {
+            Some(r) => {
+                let skip = self.framing.header_len();
+
+                self.fd.read_with(|_| r.recv(buf, skip)).await?
             }
+            None => self.recv_msg(buf).await?,
         };
 
-        // Print header.
-        let mut log = String::from("R: ");
+        self.received.fetch_add(1, Ordering::Relaxed);
+        self.logger
+            .packet(Direction::Received, &addr, &buf[..received]);
+
+        Ok((received, addr))
+    }
+
+    async fn recv_msg(&self, buf: &mut [u8]) -> Result<(usize, sockaddr_ll), Error> {
+        // Receive. The Ethernet header is read into a separate buffer for SOCK_RAW.
+        let mut addr: sockaddr_ll = unsafe { zeroed() };
+        let mut header = [0u8; HEADER_LEN];
+        let received = self
+            .fd
+            .read_with(|s| {
+                let mut iov = [
+                    iovec {
+                        iov_base: header.as_mut_ptr().cast(),
+                        iov_len: header.len(),
+                    },
+                    iovec {
+                        iov_base: buf.as_mut_ptr().cast(),
+                        iov_len: buf.len(),
+                    },
+                ];
+
+                let iov = match self.framing {
+                    Framing::Cooked => &mut iov[1..],
+                    Framing::Raw(_) => &mut iov[..],
+                };
+
+                let mut msg: msghdr = unsafe { zeroed() };
 
-        Self::write_addr(&mut log, &addr);
+                msg.msg_name = &mut addr as *mut sockaddr_ll as _;
+                msg.msg_namelen = size_of_val(&addr).try_into().unwrap();
+                msg.msg_iov = iov.as_mut_ptr();
+                msg.msg_iovlen = iov.len();
 
-        writeln!(log, " (Type = {}, Length = {})", addr.sll_pkttype, received).unwrap();
+                let received = unsafe { recvmsg(s, &mut msg, 0) };
 
-        // Print data.
-        let mut conf = HexConfig::default();
+                if received < 0 {
+                    return Err(Error::last_os_error());
+                }
 
-        conf.title = false;
+                assert_eq!(msg.msg_namelen, size_of_val(&addr).try_into().unwrap());
 
-        hex_write(&mut log, &buf[..received], conf).unwrap();
+                Ok(received as usize)
+            })
+            .await?;
 
-        println!("{log}");
+        // A frame shorter than the header will be dropped as a malformed frame.
+        let received = match self.framing {
+            Framing::Cooked => received,
+            Framing::Raw(_) => received.saturating_sub(HEADER_LEN),
+        };
 
         Ok((received, addr))
     }
 
     pub fn send(&self, addr: sockaddr_ll, buf: impl AsRef<[u8]>) -> Result<(), Error> {
-        // Send.
-        let buf = buf.as_ref();
-        let sent = unsafe {
-            sendto(
-                self.0.as_raw_fd(),
-                buf.as_ptr().cast(),
-                buf.len(),
-                0,
-                &addr as *const sockaddr_ll as _,
-                size_of_val(&addr).try_into().unwrap(),
-            )
+        self.send_with(addr, buf.as_ref(), None)
+    }
+
+    fn send_with(
+        &self,
+        addr: sockaddr_ll,
+        buf: &[u8],
+        confirmed: Option<TxCallback>,
+    ) -> Result<(), Error> {
+        // Impair the frame.
+        if let Some((chaos, fd)) = &self.chaos {
+            let delay = match chaos.outbound() {
+                Verdict::Pass => None,
+                Verdict::Drop => return Ok(()),
+                Verdict::Delay(v) => Some(v),
+                Verdict::Reorder(v) => Some(v + REORDER_DELAY),
+            };
+
+            if let Some(v) = delay {
+                let frame = match self.framing {
+                    Framing::Cooked => buf.to_vec(),
+                    Framing::Raw(mac) => [&self.header(mac, &addr)[..], buf].concat(),
+                };
+
+                chaos.send_later(fd.clone(), addr, &frame, v);
+                self.sent.fetch_add(1, Ordering::Relaxed);
+                self.logger.packet(Direction::Sent, &addr, buf);
+                return Ok(());
+            }
+        }
+
+        // Send. The Ethernet header is written from a separate buffer for SOCK_RAW.
+        let header = match self.framing {
+            Framing::Cooked => [0; HEADER_LEN],
+            Framing::Raw(mac) => self.header(mac, &addr),
         };
 
+        let mut iov = [
+            iovec {
+                iov_base: header.as_ptr().cast_mut().cast(),
+                iov_len: header.len(),
+            },
+            iovec {
+                iov_base: buf.as_ptr().cast_mut().cast(),
+                iov_len: buf.len(),
+            },
+        ];
+
+        let iov = match self.framing {
+            Framing::Cooked => &mut iov[1..],
+            Framing::Raw(_) => &mut iov[..],
+        };
+
+        let mut msg: msghdr = unsafe { zeroed() };
+        let expected: usize = iov.iter().map(|v| v.iov_len).sum();
+
+        msg.msg_name = &addr as *const sockaddr_ll as *mut sockaddr_ll as _;
+        msg.msg_namelen = size_of_val(&addr).try_into().unwrap();
+        msg.msg_iov = iov.as_mut_ptr();
+        msg.msg_iovlen = iov.len();
+
+        // Request TX timestamp. The delayed frames above are not confirmed since they are sent
+        // from another descriptor.
+        let confirmed = self.tx.get().zip(confirmed);
+        let mut control = TxStamps::control();
+
+        if confirmed.is_some() {
+            msg.msg_control = control.0.as_mut_ptr().cast();
+            msg.msg_controllen = control.0.len();
+        }
+
+        let time = SystemTime::now();
+        let sent = unsafe { sendmsg(self.fd.as_raw_fd(), &msg, 0) };
+
         if sent < 0 {
             return Err(Error::last_os_error());
         }
 
-        assert_eq!(sent as usize, buf.len());
+        assert_eq!(sent as usize, expected);
 
-        // Print header.
-        let mut log = String::from("S: ");
+        if let Some((tx, f)) = confirmed {
+            tx.push(time, f);
+        }
+
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        self.logger.packet(Direction::Sent, &addr, buf);
 
-        Self::write_addr(&mut log, &addr);
+        Ok(())
+    }
 
-        writeln!(log, " (Length = {})", sent).unwrap();
+    /// Returns Ethernet header from `mac` to the peer of `addr`.
+    fn header(&self, mac: MacAddr6, addr: &sockaddr_ll) -> [u8; HEADER_LEN] {
+        let mut header = [0u8; HEADER_LEN];
 
-        // Print sent data.
-        let mut conf = HexConfig::default();
+        header[..6].copy_from_slice(&addr.sll_addr[..6]);
+        header[6..12].copy_from_slice(mac.as_bytes());
+        header[12..].copy_from_slice(&u16::from_be(addr.sll_protocol).to_be_bytes());
+        header
+    }
 
-        conf.title = false;
+    /// Same as [`Self::send()`] but also record the timing to `timing`.
+    pub fn send_timed(
+        &self,
+        addr: sockaddr_ll,
+        buf: impl AsRef<[u8]>,
+        timing: &SendTiming,
+    ) -> Result<(), Error> {
+        let started = Instant::now();
+        let r = self.send(addr, buf);
 
-        hex_write(&mut log, buf, conf).unwrap();
+        timing.record(started, Instant::now());
 
-        println!("{log}");
+        r
+    }
 
-        Ok(())
+    /// Same as [`Self::send_timed()`] but also invoke `confirmed` with the delay until the frame
+    /// left the NIC if [`Self::enable_tx_timestamps()`] has been called. `confirmed` will not be
+    /// invoked if the timestamps are not enabled or the frame is impaired by [`Chaos`].
+    pub fn send_confirmed(
+        &self,
+        addr: sockaddr_ll,
+        buf: impl AsRef<[u8]>,
+        timing: &SendTiming,
+        confirmed: TxCallback,
+    ) -> Result<(), Error> {
+        let started = Instant::now();
+        let r = self.send_with(addr, buf.as_ref(), Some(confirmed));
+
+        timing.record(started, Instant::now());
+
+        r
     }
 
-    fn write_addr(w: &mut impl Write, addr: &sockaddr_ll) {
-        for i in 0..addr.sll_halen {
-            let i: usize = i.into();
+    /// Write the packet counters to `out`.
+    pub fn dump(&self, out: &mut String) {
+        writeln!(
+            out,
+            "  Socket: {} received, {} sent",
+            self.received.load(Ordering::Relaxed),
+            self.sent.load(Ordering::Relaxed)
+        )
+        .unwrap();
+
+        match self.statistics() {
+            Ok(v) => writeln!(out, "  Kernel: {v}").unwrap(),
+            Err(e) => writeln!(out, "  Kernel: {}", e.display()).unwrap(),
+        }
+    }
+}
 
-            if i != 0 {
-                write!(w, ":").unwrap();
+impl Transport for PacketSocket {
+    fn recv<'a>(&'a self, buf: &'a mut [u8]) -> BoxFuture<'a, Result<(usize, sockaddr_ll), Error>> {
+        Box::pin(PacketSocket::recv(self, buf))
+    }
+
+    fn send(&self, addr: sockaddr_ll, buf: &[u8]) -> Result<(), Error> {
+        conformance::verify(&addr, buf);
+        PacketSocket::send(self, addr, buf)
+    }
+
+    fn send_confirmed(
+        &self,
+        addr: sockaddr_ll,
+        buf: &[u8],
+        timing: &SendTiming,
+        confirmed: TxCallback,
+    ) -> Result<(), Error> {
+        conformance::verify(&addr, buf);
+        PacketSocket::send_confirmed(self, addr, buf, timing, confirmed)
+    }
+
+    fn attach_filter(&self, prog: &[sock_filter]) -> Result<(), Error> {
+        PacketSocket::attach_filter(self, prog)
+    }
+
+    fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    fn statistics(&self) -> Result<SocketStats, Error> {
+        PacketSocket::statistics(self)
+    }
+
+    fn dump(&self, out: &mut String) {
+        PacketSocket::dump(self, out)
+    }
+}
+
+/// Link-layer transport of the servers.
+///
+/// This is [`PacketSocket`] on a real interface. Use [`MockTransport`] to run the servers without
+/// root privileges or any hardware.
+pub trait Transport: Send + Sync + 'static {
+    /// Receive a PPPoE frame without Ethernet header and padding. The address is the source of the
+    /// frame.
+    fn recv<'a>(&'a self, buf: &'a mut [u8]) -> BoxFuture<'a, Result<(usize, sockaddr_ll), Error>>;
+
+    /// Send a PPPoE frame without Ethernet header to `addr`.
+    fn send(&self, addr: sockaddr_ll, buf: &[u8]) -> Result<(), Error>;
+
+    /// Same as [`Transport::send()`] but also record the timing to `timing`.
+    fn send_timed(&self, addr: sockaddr_ll, buf: &[u8], timing: &SendTiming) -> Result<(), Error> {
+        let started = Instant::now();
+        let r = self.send(addr, buf);
+
+        timing.record(started, Instant::now());
+
+        r
+    }
+
+    /// Same as [`Transport::send_timed()`] but also invoke `confirmed` once the frame left the NIC.
+    /// The default implementation never invoke `confirmed`.
+    fn send_confirmed(
+        &self,
+        addr: sockaddr_ll,
+        buf: &[u8],
+        timing: &SendTiming,
+        confirmed: TxCallback,
+    ) -> Result<(), Error> {
+        drop(confirmed);
+        self.send_timed(addr, buf, timing)
+    }
+
+    /// Replace the filter of the received frames with `prog`, which will see the header of
+    /// [`Transport::framing()`].
+    fn attach_filter(&self, prog: &[sock_filter]) -> Result<(), Error>;
+
+    fn framing(&self) -> Framing;
+
+    /// Write the packet counters to `out`.
+    /// Returns the kernel counters of the socket since it was created.
+    fn statistics(&self) -> Result<SocketStats, Error>;
+
+    fn dump(&self, out: &mut String);
+}
+
+/// Counters of a packet socket that is maintained by the kernel (`PACKET_STATISTICS`).
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct SocketStats {
+    /// Number of frames that passed the filter, including the dropped ones.
+    pub received: u64,
+    /// Number of frames that was dropped because the receive buffer was full.
+    pub dropped: u64,
+}
+
+impl AddAssign for SocketStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.received += rhs.received;
+        self.dropped += rhs.dropped;
+    }
+}
+
+impl Display for SocketStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} received, {} dropped", self.received, self.dropped)
+    }
+}
+
+/// Implementation of [`Transport`] that is not connected to any interface. The frames are
+/// exchanged with [`MockPeer`] instead.
+pub struct MockTransport {
+    logger: Logger,
+    padding: Padding,
+    rx: tokio::sync::Mutex<UnboundedReceiver<(Vec<u8>, sockaddr_ll)>>,
+    tx: UnboundedSender<(Vec<u8>, sockaddr_ll)>,
+    received: AtomicU64,
+    sent: AtomicU64,
+}
+
+impl MockTransport {
+    pub fn new(logger: Logger, padding: Padding) -> (Self, MockPeer) {
+        let (tx, peer_rx) = unbounded_channel();
+        let (peer_tx, rx) = unbounded_channel();
+        let transport = Self {
+            logger,
+            padding,
+            rx: tokio::sync::Mutex::new(rx),
+            tx,
+            received: AtomicU64::new(0),
+            sent: AtomicU64::new(0),
+        };
+
+        let peer = MockPeer {
+            tx: peer_tx,
+            rx: peer_rx,
+        };
+
+        (transport, peer)
+    }
+}
+
+impl Transport for MockTransport {
+    fn recv<'a>(&'a self, buf: &'a mut [u8]) -> BoxFuture<'a, Result<(usize, sockaddr_ll), Error>> {
+        Box::pin(async move {
+            let mut rx = self.rx.lock().await;
+
+            loop {
+                let (data, addr) = rx.recv().await.ok_or(ErrorKind::BrokenPipe)?;
+
+                self.received.fetch_add(1, Ordering::Relaxed);
+                self.logger.packet(Direction::Received, &addr, &data);
+
+                match self.padding.trim(&data) {
+                    Some(v) if v.len() <= buf.len() => {
+                        buf[..v.len()].copy_from_slice(v);
+                        break Ok((v.len(), addr));
+                    }
+                    _ => warning!(
+                        "Dropped a malformed PPPoE frame with {} bytes from {}.",
+                        data.len(),
+                        MacAddr6::from(TryInto::<[u8; 6]>::try_into(&addr.sll_addr[..6]).unwrap())
+                    ),
+                }
             }
+        })
+    }
+
+    fn send(&self, addr: sockaddr_ll, buf: &[u8]) -> Result<(), Error> {
+        conformance::verify(&addr, buf);
+
+        self.tx
+            .send((buf.to_vec(), addr))
+            .map_err(|_| Error::from(ErrorKind::BrokenPipe))?;
+
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        self.logger.packet(Direction::Sent, &addr, buf);
+
+        Ok(())
+    }
+
+    fn attach_filter(&self, _: &[sock_filter]) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn framing(&self) -> Framing {
+        Framing::Cooked
+    }
+
+    fn statistics(&self) -> Result<SocketStats, Error> {
+        Ok(SocketStats {
+            received: self.received.load(Ordering::Relaxed),
+            dropped: 0,
+        })
+    }
+
+    fn dump(&self, out: &mut String) {
+        writeln!(
+            out,
+            "  Socket: {} received, {} sent",
+            self.received.load(Ordering::Relaxed),
+            self.sent.load(Ordering::Relaxed)
+        )
+        .unwrap();
+    }
+}
+
+/// Other end of a [`MockTransport`].
+pub struct MockPeer {
+    tx: UnboundedSender<(Vec<u8>, sockaddr_ll)>,
+    rx: UnboundedReceiver<(Vec<u8>, sockaddr_ll)>,
+}
+
+impl MockPeer {
+    /// Deliver a frame without Ethernet header to the transport. `addr` is the source of the
+    /// frame.
+    pub fn send(&self, data: &[u8], addr: sockaddr_ll) {
+        self.tx.send((data.to_vec(), addr)).ok();
+    }
+
+    /// Returns the next frame that was sent by the transport if any.
+    pub fn try_recv(&mut self) -> Option<(Vec<u8>, sockaddr_ll)> {
+        self.rx.try_recv().ok()
+    }
+
+    /// Wait for the next frame that was sent by the transport. Returns [`None`] if the transport
+    /// has been dropped.
+    pub async fn recv(&mut self) -> Option<(Vec<u8>, sockaddr_ll)> {
+        self.rx.recv().await
+    }
+}
+
+/// How the Ethernet header is handled by [`PacketSocket`].
+#[derive(Clone, Copy)]
+pub enum Framing {
+    /// `SOCK_DGRAM`, which the kernel add and remove the header.
+    Cooked,
+    /// `SOCK_RAW` with the header built from the address of our interface.
+    Raw(MacAddr6),
+}
+
+impl Framing {
+    /// Returns the length of the header that is visible to the socket.
+    pub fn header_len(self) -> usize {
+        match self {
+            Self::Cooked => 0,
+            Self::Raw(_) => HEADER_LEN,
+        }
+    }
+}
+
+impl Display for Framing {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cooked => f.write_str("SOCK_DGRAM"),
+            Self::Raw(_) => f.write_str("SOCK_RAW"),
+        }
+    }
+}
+
+/// Check if `SOCK_DGRAM` works on `interface` by sending a frame to ourselves. Returns the reason
+/// if it is broken.
+///
+/// The frame is sent to `mac` so it never reach the console. The packet sockets see all outgoing
+/// frames so we can check how the kernel build and strip the header without any peer. It can't
+/// tell if the frame cannot be sent (e.g. the link is down), in which case [`None`] is returned.
+pub fn probe_cooked(interface: c_int, mac: MacAddr6) -> Result<Option<&'static str>, Error> {
+    // Setup sockets. The sender can't be one of the taps since a socket does not see its own
+    // frames.
+    let raw = probe_socket(SOCK_RAW, interface)?;
+    let cooked = probe_socket(SOCK_DGRAM, interface)?;
+    let sender = probe_socket(SOCK_DGRAM, interface)?;
+    let mut addr: sockaddr_ll = unsafe { zeroed() };
+
+    addr.sll_family = AF_PACKET as _;
+    addr.sll_protocol = PROBE_PROTO.to_be();
+    addr.sll_ifindex = interface;
+    addr.sll_halen = 6;
+    addr.sll_addr[..6].copy_from_slice(mac.as_bytes());
+
+    let sent = unsafe {
+        sendto(
+            sender.as_raw_fd(),
+            PROBE_DATA.as_ptr().cast(),
+            PROBE_DATA.len(),
+            0,
+            &addr as *const sockaddr_ll as _,
+            size_of_val(&addr).try_into().unwrap(),
+        )
+    };
+
+    if sent < 0 {
+        return Ok(None);
+    }
+
+    // Check the frame on the wire.
+    let mut expected = Vec::with_capacity(HEADER_LEN + PROBE_DATA.len());
+
+    expected.extend_from_slice(mac.as_bytes());
+    expected.extend_from_slice(mac.as_bytes());
+    expected.extend_from_slice(&PROBE_PROTO.to_be_bytes());
+    expected.extend_from_slice(PROBE_DATA);
+
+    match probe_recv(&raw)? {
+        Some((_, d)) if d.starts_with(&expected) => {}
+        Some(_) => return Ok(Some("the kernel built an invalid Ethernet header")),
+        None => return Ok(None),
+    }
+
+    // Check the frame seen by SOCK_DGRAM.
+    match probe_recv(&cooked)? {
+        Some((a, d)) if d == PROBE_DATA && a.sll_addr[..6] == *mac.as_bytes() => Ok(None),
+        Some((_, d)) if d == PROBE_DATA => Ok(Some("the source address is missing")),
+        Some(_) => Ok(Some("the kernel did not strip the Ethernet header")),
+        None => Ok(Some("the frame was not received")),
+    }
+}
+
+fn probe_socket(ty: c_int, interface: c_int) -> Result<OwnedFd, Error> {
+    // Only ETH_P_ALL see the outgoing frames.
+    let proto = (ETH_P_ALL as u16).to_be();
+    let s = unsafe { socket(AF_PACKET, ty, proto.into()) };
+
+    if s < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    let s = unsafe { OwnedFd::from_raw_fd(s) };
+    let mut addr: sockaddr_ll = unsafe { zeroed() };
+
+    addr.sll_family = AF_PACKET as _;
+    addr.sll_protocol = proto;
+    addr.sll_ifindex = interface;
+
+    if unsafe {
+        libc::bind(
+            s.as_raw_fd(),
+            &addr as *const sockaddr_ll as _,
+            size_of_val(&addr).try_into().unwrap(),
+        )
+    } < 0
+    {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(s)
+}
+
+/// Wait for a frame of [`PROBE_PROTO`] that contains [`PROBE_DATA`] until [`PROBE_TIMEOUT`].
+fn probe_recv(s: &OwnedFd) -> Result<Option<(sockaddr_ll, Vec<u8>)>, Error> {
+    let deadline = Instant::now() + std::time::Duration::from_millis(PROBE_TIMEOUT as _);
+    let mut buf = vec![0u8; 2048];
+
+    loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let mut pfd = pollfd {
+            fd: s.as_raw_fd(),
+            events: POLLIN,
+            revents: 0,
+        };
+
+        if timeout.is_zero() {
+            break Ok(None);
+        }
+
+        match unsafe { poll(&mut pfd, 1, timeout.as_millis().try_into().unwrap()) } {
+            0 => break Ok(None),
+            v if v < 0 => break Err(Error::last_os_error()),
+            _ => {}
+        }
+
+        let mut addr: sockaddr_ll = unsafe { zeroed() };
+        let mut alen: socklen_t = size_of_val(&addr).try_into().unwrap();
+        let received = unsafe {
+            recvfrom(
+                s.as_raw_fd(),
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+                0,
+                &mut addr as *mut sockaddr_ll as _,
+                &mut alen,
+            )
+        };
+
+        if received < 0 {
+            break Err(Error::last_os_error());
+        }
+
+        let data = &buf[..(received as usize)];
 
-            write!(w, "{:x}", addr.sll_addr[i]).unwrap();
+        if u16::from_be(addr.sll_protocol) == PROBE_PROTO
+            && data.windows(PROBE_DATA.len()).any(|w| w == PROBE_DATA)
+        {
+            break Ok(Some((addr, data.to_vec())));
         }
     }
 }
@@ -0,0 +1,98 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Timing of the packets that was sent with [`crate::socket::PacketSocket::send_timed()`].
+///
+/// The exploit need to hit a small timing window so this is useful to tell if the NIC or its
+/// driver is too slow.
+#[derive(Default)]
+pub struct SendTiming {
+    state: Mutex<TimingState>,
+}
+
+impl SendTiming {
+    /// Record a send that was started at `started` and returned at `finished`.
+    pub fn record(&self, started: Instant, finished: Instant) {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(v) = state.last {
+            state.gaps.record(started.saturating_duration_since(v));
+        }
+
+        state
+            .latencies
+            .record(finished.saturating_duration_since(started));
+        state.last = Some(started);
+    }
+
+    /// Returns the gaps between the start of each send.
+    pub fn gaps(&self) -> Histogram {
+        self.state.lock().unwrap().gaps.clone()
+    }
+
+    /// Returns the duration of each send call.
+    pub fn latencies(&self) -> Histogram {
+        self.state.lock().unwrap().latencies.clone()
+    }
+}
+
+/// Histogram of durations with power-of-two buckets in microseconds.
+#[derive(Default, Clone)]
+pub struct Histogram {
+    buckets: [u64; 32],
+    count: u64,
+    max: Duration,
+}
+
+impl Histogram {
+    pub fn record(&mut self, v: Duration) {
+        // Bucket N contains the values less than 2^N microseconds.
+        let us: u32 = v.as_micros().try_into().unwrap_or(u32::MAX);
+        let i: usize = (u32::BITS - us.leading_zeros()).try_into().unwrap();
+
+        self.buckets[i.min(self.buckets.len() - 1)] += 1;
+        self.count += 1;
+        self.max = self.max.max(v);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the upper bound of the bucket that contains `p` percentile.
+    pub fn percentile(&self, p: u64) -> Duration {
+        let target = (self.count * p).div_ceil(100).max(1);
+        let mut seen = 0;
+
+        for (i, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+
+            if seen >= target {
+                return Duration::from_micros(1 << i).min(self.max);
+            }
+        }
+
+        self.max
+    }
+}
+
+impl Display for Histogram {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "p50 = {}us, p90 = {}us, p99 = {}us, max = {}us",
+            self.percentile(50).as_micros(),
+            self.percentile(90).as_micros(),
+            self.percentile(99).as_micros(),
+            self.max.as_micros()
+        )
+    }
+}
+
+#[derive(Default)]
+struct TimingState {
+    gaps: Histogram,
+    latencies: Histogram,
+    last: Option<Instant>,
+}
@@ -0,0 +1,95 @@
+/// Pre-serialized frame for the packets that need to be sent repeatedly.
+///
+/// Only the declared fields can be changed on each render so the hot loop is just a copy and
+/// patch instead of building the whole packet again. If the frame has an Internet checksum it
+/// will be updated incrementally (RFC 1624) from the patched fields.
+pub struct FrameTemplate {
+    data: Vec<u8>,
+    checksum: Option<usize>,
+}
+
+impl FrameTemplate {
+    /// Create a template from a complete frame. The current content of each field is used as a
+    /// placeholder so the replacement must have the same length.
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            checksum: None,
+        }
+    }
+
+    /// Set the offset of the 16-bit Internet checksum that cover all of the fields. The checksum in
+    /// the template must already be valid.
+    ///
+    /// # Panics
+    /// If the checksum is outside the template.
+    pub fn with_checksum(mut self, offset: usize) -> Self {
+        assert!(offset + 2 <= self.data.len());
+
+        self.checksum = Some(offset);
+        self
+    }
+
+    /// Declare a field of `len` bytes at `offset`.
+    ///
+    /// # Panics
+    /// If the field is outside the template or the template has a checksum and the field is not
+    /// 16-bit aligned.
+    pub fn field(&self, offset: usize, len: usize) -> Field {
+        assert!(offset + len <= self.data.len());
+
+        if let Some(c) = self.checksum {
+            assert!(offset % 2 == c % 2 && len.is_multiple_of(2));
+        }
+
+        Field { offset, len }
+    }
+
+    /// Copy the template into `buf` and apply `patches`.
+    ///
+    /// # Panics
+    /// If `buf` is smaller than the template or the length of any patch does not match its field.
+    pub fn render<'a>(&self, buf: &'a mut [u8], patches: &[(Field, &[u8])]) -> &'a [u8] {
+        let buf = &mut buf[..self.data.len()];
+
+        buf.copy_from_slice(&self.data);
+
+        for (f, v) in patches {
+            buf[f.offset..(f.offset + f.len)].copy_from_slice(v);
+        }
+
+        // HC' = ~(~HC + ~m + m') for each 16-bit word that was replaced.
+        if let Some(c) = self.checksum {
+            let mut sum = u32::from(!u16::from_be_bytes([buf[c], buf[c + 1]]));
+
+            for (f, v) in patches {
+                let old = &self.data[f.offset..(f.offset + f.len)];
+
+                for (o, n) in old.chunks(2).zip(v.chunks(2)) {
+                    sum += u32::from(!u16::from_be_bytes([o[0], o[1]]));
+                    sum += u32::from(u16::from_be_bytes([n[0], n[1]]));
+                }
+            }
+
+            while sum > 0xffff {
+                sum = (sum & 0xffff) + (sum >> 16);
+            }
+
+            buf[c..(c + 2)].copy_from_slice(&(!(sum as u16)).to_be_bytes());
+        }
+
+        buf
+    }
+
+    /// Returns the length of the rendered frame.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+}
+
+/// Location of a field in [`FrameTemplate`].
+#[derive(Clone, Copy)]
+pub struct Field {
+    offset: usize,
+    len: usize,
+}
@@ -0,0 +1,216 @@
+use crate::log::error;
+use crate::rt;
+use erdp::ErrorDisplay;
+use libc::{
+    c_int, c_uint, cmsghdr, iovec, msghdr, recvmsg, setsockopt, timespec, CMSG_DATA, CMSG_FIRSTHDR,
+    CMSG_LEN, CMSG_NXTHDR, CMSG_SPACE, EAGAIN, MSG_DONTWAIT, MSG_ERRQUEUE,
+    SOF_TIMESTAMPING_OPT_TSONLY, SOF_TIMESTAMPING_SOFTWARE, SOF_TIMESTAMPING_TX_SOFTWARE,
+    SOL_SOCKET,
+};
+use std::collections::VecDeque;
+use std::io::Error;
+use std::mem::{size_of, size_of_val, zeroed};
+use std::os::fd::{AsRawFd, BorrowedFd, OwnedFd};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// `SO_TIMESTAMPING` is not exposed by libc on glibc targets. This is `SO_TIMESTAMPING_OLD`, which
+/// use the native `timespec`.
+const SO_TIMESTAMPING: c_int = 37;
+
+/// Maximum duration to wait for the timestamp of a frame.
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Interval to poll the error queue while there are pending frames.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Invoked with the delay from the send call to the time the frame was handed to the NIC, or
+/// [`None`] if it was not confirmed within a second.
+pub type TxCallback = Box<dyn FnOnce(Option<Duration>) + Send>;
+
+/// Software TX timestamps (`SO_TIMESTAMPING`) of the frames that was sent on a packet socket.
+///
+/// The kernel take the timestamp when the driver hand the frame to the NIC and queue it on the
+/// error queue of the socket. The timestamps are only requested for the frames that was sent with
+/// [`TxStamps::control()`] and they are matched in order since the packet sockets may not support
+/// `SOF_TIMESTAMPING_OPT_ID`.
+pub struct TxStamps {
+    fd: Arc<OwnedFd>,
+    state: Arc<Mutex<State>>,
+}
+
+impl TxStamps {
+    /// Enable the reporting of TX timestamps on `fd`.
+    pub fn new(fd: BorrowedFd) -> Result<Self, Error> {
+        let flags: c_uint = SOF_TIMESTAMPING_SOFTWARE | SOF_TIMESTAMPING_OPT_TSONLY;
+
+        if unsafe {
+            setsockopt(
+                fd.as_raw_fd(),
+                SOL_SOCKET,
+                SO_TIMESTAMPING,
+                &flags as *const c_uint as _,
+                size_of_val(&flags).try_into().unwrap(),
+            )
+        } < 0
+        {
+            return Err(Error::last_os_error());
+        }
+
+        // The error queue is read from a task so it need its own descriptor.
+        Ok(Self {
+            fd: Arc::new(fd.try_clone_to_owned()?),
+            state: Arc::default(),
+        })
+    }
+
+    /// Returns the ancillary data for `sendmsg` to request a TX timestamp of the frame.
+    pub fn control() -> Control {
+        let mut buf = Control([0; CONTROL_LEN]);
+        let mut msg: msghdr = unsafe { zeroed() };
+
+        msg.msg_control = buf.0.as_mut_ptr().cast();
+        msg.msg_controllen = buf.0.len();
+
+        unsafe {
+            let cmsg = CMSG_FIRSTHDR(&msg);
+
+            (*cmsg).cmsg_level = SOL_SOCKET;
+            (*cmsg).cmsg_type = SO_TIMESTAMPING;
+            (*cmsg).cmsg_len = CMSG_LEN(size_of::<c_uint>() as _) as _;
+
+            CMSG_DATA(cmsg)
+                .cast::<c_uint>()
+                .write_unaligned(SOF_TIMESTAMPING_TX_SOFTWARE);
+        }
+
+        buf
+    }
+
+    /// Invoke `f` once the timestamp of the frame that was sent at `sent` with
+    /// [`TxStamps::control()`] has been received.
+    pub fn push(&self, sent: SystemTime, f: TxCallback) {
+        let mut state = self.state.lock().unwrap();
+
+        state.pending.push_back(Pending {
+            sent,
+            deadline: Instant::now() + TIMEOUT,
+            f,
+        });
+
+        if std::mem::replace(&mut state.draining, true) {
+            return;
+        }
+
+        drop(state);
+
+        rt::spawn(Self::drain(self.fd.clone(), self.state.clone()));
+    }
+
+    /// Read the error queue until all pending frames have been confirmed or expired.
+    async fn drain(fd: Arc<OwnedFd>, state: Arc<Mutex<State>>) {
+        loop {
+            rt::sleep(POLL_INTERVAL).await;
+
+            // Match the timestamps.
+            loop {
+                let ts = match read(&fd) {
+                    Ok(Some(v)) => v,
+                    Ok(None) => break,
+                    Err(e) => {
+                        error!("Failed to read TX timestamp: {}.", e.display());
+                        break;
+                    }
+                };
+
+                let p = match state.lock().unwrap().pending.pop_front() {
+                    Some(v) => v,
+                    None => break,
+                };
+
+                (p.f)(Some(ts.duration_since(p.sent).unwrap_or_default()));
+            }
+
+            // Expire the frames that was not confirmed in time.
+            let now = Instant::now();
+            let mut s = state.lock().unwrap();
+
+            while s.pending.front().is_some_and(|p| p.deadline <= now) {
+                let p = s.pending.pop_front().unwrap();
+
+                drop(s);
+                (p.f)(None);
+                s = state.lock().unwrap();
+            }
+
+            if s.pending.is_empty() {
+                s.draining = false;
+                break;
+            }
+        }
+    }
+}
+
+/// Ancillary data from [`TxStamps::control()`].
+#[repr(C, align(8))]
+pub struct Control(pub [u8; CONTROL_LEN]);
+
+const CONTROL_LEN: usize = unsafe { CMSG_SPACE(size_of::<c_uint>() as _) } as usize;
+
+#[derive(Default)]
+struct State {
+    pending: VecDeque<Pending>,
+    draining: bool,
+}
+
+struct Pending {
+    sent: SystemTime,
+    deadline: Instant,
+    f: TxCallback,
+}
+
+/// Read a software timestamp from the error queue of `fd`. Returns [`None`] if the queue is empty.
+fn read(fd: &OwnedFd) -> Result<Option<SystemTime>, Error> {
+    let mut data = [0u8; 64];
+    let mut control = [0u64; 64];
+
+    loop {
+        let mut iov = iovec {
+            iov_base: data.as_mut_ptr().cast(),
+            iov_len: data.len(),
+        };
+
+        let mut msg: msghdr = unsafe { zeroed() };
+
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = size_of_val(&control);
+
+        if unsafe { recvmsg(fd.as_raw_fd(), &mut msg, MSG_ERRQUEUE | MSG_DONTWAIT) } < 0 {
+            let e = Error::last_os_error();
+
+            return match e.raw_os_error() {
+                Some(EAGAIN) => Ok(None),
+                _ => Err(e),
+            };
+        }
+
+        // Find the timestamps. The first one is the software timestamp. Skip the message without
+        // it since it is not for any frame.
+        let mut cmsg = unsafe { CMSG_FIRSTHDR(&msg) };
+
+        while !cmsg.is_null() {
+            let hdr: &cmsghdr = unsafe { &*cmsg };
+
+            if hdr.cmsg_level == SOL_SOCKET && hdr.cmsg_type == SO_TIMESTAMPING {
+                let ts = unsafe { CMSG_DATA(cmsg).cast::<timespec>().read_unaligned() };
+                let time = UNIX_EPOCH + Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32);
+
+                return Ok(Some(time));
+            }
+
+            cmsg = unsafe { CMSG_NXTHDR(&msg, cmsg) };
+        }
+    }
+}
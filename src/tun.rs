@@ -0,0 +1,158 @@
+use crate::rt;
+use libc::{
+    ifreq, ioctl, open, read, sockaddr, sockaddr_in, socket, write, AF_INET, IFF_NO_PI,
+    IFF_RUNNING, IFF_TUN, IFF_UP, O_NONBLOCK, O_RDWR, SIOCGIFFLAGS, SIOCSIFADDR, SIOCSIFDSTADDR,
+    SIOCSIFFLAGS, SIOCSIFMTU, SIOCSIFNETMASK, SOCK_DGRAM, TUNSETIFF,
+};
+use std::ffi::CStr;
+use std::io::Error;
+use std::mem::zeroed;
+use std::net::Ipv4Addr;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+/// Encapsulate a TUN device without packet information.
+pub struct Tun {
+    fd: rt::Fd,
+    name: String,
+}
+
+impl Tun {
+    /// Create or attach to TUN device `name`. The kernel will pick the name if it is empty. A `%d`
+    /// in `name` will be replaced with the first unused number.
+    pub fn open(name: &str) -> Result<Self, Error> {
+        // Open TUN clone device.
+        let fd = unsafe { open(c"/dev/net/tun".as_ptr(), O_RDWR | O_NONBLOCK) };
+
+        if fd < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        // Allocate the interface.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let mut req: ifreq = unsafe { zeroed() };
+
+        if name.len() >= req.ifr_name.len() {
+            return Err(Error::other("the name is too long"));
+        }
+
+        for (i, b) in name.bytes().enumerate() {
+            req.ifr_name[i] = b as _;
+        }
+
+        req.ifr_ifru.ifru_flags = (IFF_TUN | IFF_NO_PI) as _;
+
+        if unsafe { ioctl(fd.as_raw_fd(), TUNSETIFF, &mut req) } < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        let name = unsafe { CStr::from_ptr(req.ifr_name.as_ptr()) };
+
+        Ok(Self {
+            fd: rt::Fd::new(fd)?,
+            name: name.to_string_lossy().into_owned(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Assign `local` to the device as a point-to-point link with `peer`, set the MTU to `mtu` then
+    /// bring it up.
+    pub fn configure(&self, local: Ipv4Addr, peer: Ipv4Addr, mtu: u16) -> Result<(), Error> {
+        configure_ptp(&self.name, local, peer, mtu)
+    }
+
+    /// Receive an IP packet from the host.
+    pub async fn recv(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.fd
+            .read_with(|fd| {
+                let received = unsafe { read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+
+                if received < 0 {
+                    Err(Error::last_os_error())
+                } else {
+                    Ok(received as usize)
+                }
+            })
+            .await
+    }
+
+    /// Send an IP packet to the host.
+    pub fn send(&self, buf: &[u8]) -> Result<(), Error> {
+        let sent = unsafe { write(self.fd.as_raw_fd(), buf.as_ptr().cast(), buf.len()) };
+
+        if sent < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        assert_eq!(sent as usize, buf.len());
+
+        Ok(())
+    }
+}
+
+/// Assign `local` to interface `name` as a point-to-point link with `peer`, set the MTU to `mtu` then
+/// bring it up.
+pub fn configure_ptp(name: &str, local: Ipv4Addr, peer: Ipv4Addr, mtu: u16) -> Result<(), Error> {
+    // The interface ioctls need a socket of the same family.
+    let sock = unsafe { socket(AF_INET, SOCK_DGRAM, 0) };
+
+    if sock < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    let sock = unsafe { OwnedFd::from_raw_fd(sock) };
+    let mut req: ifreq = unsafe { zeroed() };
+
+    if name.len() >= req.ifr_name.len() {
+        return Err(Error::other("the name is too long"));
+    }
+
+    for (i, b) in name.bytes().enumerate() {
+        req.ifr_name[i] = b as _;
+    }
+
+    let ioctls = [
+        (SIOCSIFADDR, local),
+        (SIOCSIFDSTADDR, peer),
+        (SIOCSIFNETMASK, Ipv4Addr::BROADCAST),
+    ];
+
+    for (op, addr) in ioctls {
+        req.ifr_ifru.ifru_addr = inet(addr);
+
+        if unsafe { ioctl(sock.as_raw_fd(), op, &mut req) } < 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+
+    req.ifr_ifru.ifru_mtu = mtu.into();
+
+    if unsafe { ioctl(sock.as_raw_fd(), SIOCSIFMTU, &mut req) } < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    // Bring it up.
+    if unsafe { ioctl(sock.as_raw_fd(), SIOCGIFFLAGS, &mut req) } < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    unsafe { req.ifr_ifru.ifru_flags |= (IFF_UP | IFF_RUNNING) as i16 };
+
+    if unsafe { ioctl(sock.as_raw_fd(), SIOCSIFFLAGS, &mut req) } < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Returns `addr` as `sockaddr` for the interface ioctls.
+fn inet(addr: Ipv4Addr) -> sockaddr {
+    let mut sin: sockaddr_in = unsafe { zeroed() };
+
+    sin.sin_family = AF_INET as _;
+    sin.sin_addr.s_addr = u32::from(addr).to_be();
+
+    unsafe { std::mem::transmute(sin) }
+}
@@ -0,0 +1,197 @@
+use crate::digest::sha256;
+use crate::fetch::fetch;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+/// The manifest that is published with the source.
+pub const DEFAULT_MANIFEST: &str =
+    "https://raw.githubusercontent.com/obhq/jailbreak-11/main/manifest.txt";
+
+/// Maximum duration to download the manifest.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Latest versions of the kernel offsets and the known payloads.
+///
+/// Each line is one of the following and the lines that start with `#` are ignored:
+///
+/// - `offsets VERSION`: the latest version of the kernel offsets that are built into the binary.
+/// - `payload SHA256 NOTE`: a payload that should not be used anymore and why (e.g. the newer
+///   release to use instead).
+pub struct Manifest {
+    offsets: Option<u32>,
+    stale: Vec<([u8; 32], String)>,
+}
+
+impl Manifest {
+    /// Download the manifest from `src`, which can also be a local file.
+    pub fn fetch(src: &str) -> Result<Self, ManifestError> {
+        let data = fetch(src, TIMEOUT).map_err(ManifestError::Fetch)?;
+        let data = String::from_utf8(data).map_err(|_| ManifestError::NotText)?;
+
+        Self::parse(&data)
+    }
+
+    pub fn parse(data: &str) -> Result<Self, ManifestError> {
+        let mut offsets = None;
+        let mut stale = Vec::new();
+
+        for (i, line) in data.lines().enumerate() {
+            let line = line.trim();
+            let ln = i + 1;
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // The unknown entries are from the newer manifest so skip them.
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+
+            match key {
+                "offsets" => {
+                    let v = value.parse().map_err(|_| ManifestError::InvalidLine(ln))?;
+
+                    offsets = Some(v);
+                }
+                "payload" => {
+                    let (hash, note) = value.split_once(' ').unwrap_or((value, ""));
+                    let hash = parse_hash(hash).ok_or(ManifestError::InvalidLine(ln))?;
+
+                    stale.push((hash, note.trim().to_owned()));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self { offsets, stale })
+    }
+
+    /// Returns what is outdated. `offsets` is the version of the kernel offsets and `payloads` is
+    /// the name and content of each payload that will be sent.
+    pub fn check(&self, offsets: u32, payloads: &[(&str, Vec<u8>)]) -> Vec<Outdated> {
+        let mut outdated = Vec::new();
+
+        if let Some(latest) = self.offsets.filter(|&v| v > offsets) {
+            outdated.push(Outdated::Offsets {
+                current: offsets,
+                latest,
+            });
+        }
+
+        for (name, data) in payloads {
+            let hash = sha256(data);
+
+            if let Some((_, note)) = self.stale.iter().find(|(h, _)| *h == hash) {
+                outdated.push(Outdated::Payload {
+                    name: (*name).to_owned(),
+                    note: note.clone(),
+                });
+            }
+        }
+
+        outdated
+    }
+}
+
+/// An item that [`Manifest::check()`] found to be outdated.
+pub enum Outdated {
+    Offsets { current: u32, latest: u32 },
+    Payload { name: String, note: String },
+}
+
+impl Display for Outdated {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Offsets { current, latest } => write!(
+                f,
+                "the kernel offsets are version {current} but version {latest} has been published"
+            ),
+            Self::Payload { name, note } if note.is_empty() => {
+                write!(f, "{name} is a known outdated payload")
+            }
+            Self::Payload { name, note } => write!(f, "{name} is a known outdated payload: {note}"),
+        }
+    }
+}
+
+/// Represents an error when [`Manifest`] fails to load.
+#[derive(Debug)]
+pub enum ManifestError {
+    Fetch(std::io::Error),
+    NotText,
+    InvalidLine(usize),
+}
+
+impl Error for ManifestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Fetch(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ManifestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fetch(_) => f.write_str("couldn't fetch the manifest"),
+            Self::NotText => f.write_str("the manifest is not a text"),
+            Self::InvalidLine(l) => write!(f, "invalid entry on line {l}"),
+        }
+    }
+}
+
+/// Parse SHA-256 in hex.
+pub(crate) fn parse_hash(s: &str) -> Option<[u8; 32]> {
+    if s.len() != 64 || !s.is_ascii() {
+        return None;
+    }
+
+    let mut hash = [0; 32];
+
+    for (i, b) in hash.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&s[(i * 2)..(i * 2 + 2)], 16).ok()?;
+    }
+
+    Some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let manifest = Manifest::parse(
+            "# Published manifest.\n\
+            offsets 3\n\
+            payload 2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824 use 1.2\n\
+            firmware 12.00\n",
+        )
+        .unwrap();
+        let payloads = [("stage1", b"hello".to_vec()), ("stage2", b"world".to_vec())];
+        let outdated: Vec<String> = manifest
+            .check(2, &payloads)
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+
+        assert_eq!(
+            outdated,
+            [
+                "the kernel offsets are version 2 but version 3 has been published",
+                "stage1 is a known outdated payload: use 1.2"
+            ]
+        );
+        assert!(manifest.check(3, &payloads[1..]).is_empty());
+        assert!(matches!(
+            Manifest::parse("offsets x"),
+            Err(ManifestError::InvalidLine(1))
+        ));
+        assert!(matches!(
+            Manifest::parse("\npayload 00"),
+            Err(ManifestError::InvalidLine(2))
+        ));
+    }
+}
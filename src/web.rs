@@ -0,0 +1,917 @@
+use crate::attempt::{Attempt, Attempts};
+use crate::console::ConsoleMonitor;
+use crate::cooldown::Cooldown;
+#[cfg(feature = "exploit")]
+use crate::exploit::Exploit;
+use crate::log::{error, info};
+use crate::random::random_bytes;
+use crate::session::Sessions;
+use erdp::ErrorDisplay;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+
+/// Maximum size of the request line, each header and the body of the requests other than the
+/// payload uploads.
+const MAX_LINE: usize = 8192;
+
+/// Maximum duration to receive a whole request. This is long enough for the largest stage2 on a
+/// slow Wi-Fi while a client that trickles the request cannot hold a connection forever.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum duration to send a response.
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `true` while a request body larger than [`MAX_LINE`] is being received. Only one of them is
+/// buffered at a time so the concurrent uploads cannot exhaust the memory.
+static UPLOADING: AtomicBool = AtomicBool::new(false);
+
+/// Number of the most recent finished attempts to show.
+const HISTORY: usize = 20;
+
+/// Maximum number of connections to serve at the same time. The others are refused with 503.
+const MAX_CONNECTIONS: usize = 16;
+
+/// Header that must contain [`WebConfig::token`] on the POST requests.
+pub const TOKEN_HEADER: &str = "X-Jailbreak-Token";
+
+/// Embedded HTTP server to watch and control the jailbreak from a browser or a script.
+///
+/// The page shows the state of the console, the active attempts with their current stage and the
+/// history. It also has buttons to restart the active attempts and to replace the payloads, which
+/// use the same JSON API as the scripts:
+///
+/// - `GET /status` returns the state of the console and the attempts.
+/// - `POST /restart` restarts the active attempts.
+/// - `POST /payloads?name=stage1` or `POST /payloads?name=stage2` replaces a payload with the body.
+///
+/// The errors are returned as `{"error": "..."}` with a 4xx status.
+///
+/// The POST requests must have [`TOKEN_HEADER`] with [`WebConfig::token`] and the `Origin` header,
+/// if any, must be the server itself so other sites cannot use the browser to post. The `Host`
+/// header of any request must be the address of the server or `localhost` so a site cannot rebind
+/// its name to the server and read the responses. The page does not contain the token so the user
+/// must open it as `/#TOKEN` or type the token to use the buttons.
+///
+/// Each connection is served on a dedicated thread with blocking I/O since it does nothing
+/// time-critical and it need to work on all runtimes. No more than 16 connections are served
+/// at the same time.
+pub struct WebServer {
+    listener: TcpListener,
+    local: SocketAddr,
+    config: WebConfig,
+}
+
+impl WebServer {
+    pub fn bind(addr: SocketAddr, config: WebConfig) -> Result<Self, Error> {
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+
+        Ok(Self {
+            listener,
+            local,
+            config,
+        })
+    }
+
+    /// Returns the address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local
+    }
+
+    /// Accept the connections on a dedicated thread for the rest of the process.
+    pub fn start(self) {
+        let config = Arc::new(self.config);
+        let active = Arc::new(AtomicUsize::new(0));
+
+        spawn(move || {
+            for conn in self.listener.incoming() {
+                let conn = match conn {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!("Failed to accept HTTP connection: {}.", e.display());
+                        continue;
+                    }
+                };
+
+                // Refuse the connection instead of spawning an unbounded number of threads.
+                if active.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+                    active.fetch_sub(1, Ordering::Relaxed);
+
+                    let res = Response::error(503, "too many connections");
+
+                    if let Err(e) = conn
+                        .set_write_timeout(Some(IO_TIMEOUT))
+                        .and_then(|_| res.write(&conn))
+                    {
+                        error!("Failed to refuse HTTP connection: {}.", e.display());
+                    }
+
+                    continue;
+                }
+
+                let config = config.clone();
+                let active = active.clone();
+                let local = self.local;
+
+                spawn(move || {
+                    if let Err(e) = serve(&config, local, conn) {
+                        error!("Failed to serve HTTP connection: {}.", e.display());
+                    }
+
+                    active.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+        });
+    }
+}
+
+/// Configurations for [`WebServer`].
+#[derive(Clone)]
+pub struct WebConfig {
+    /// Serve the page on `/` in addition to the API.
+    pub ui: bool,
+    /// Value of [`TOKEN_HEADER`] that is required on the POST requests.
+    pub token: String,
+    pub attempts: Arc<Attempts>,
+    pub sessions: Arc<Sessions>,
+    pub cooldown: Arc<Cooldown>,
+    pub console: Arc<ConsoleMonitor>,
+    /// [`None`] if the exploit is disabled, in which case the payloads cannot be uploaded.
+    #[cfg(feature = "exploit")]
+    pub exploit: Option<Arc<Exploit>>,
+}
+
+/// Serve a single request on `conn`, which was accepted on `local`.
+fn serve(config: &WebConfig, local: SocketAddr, conn: TcpStream) -> Result<(), Error> {
+    conn.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    let mut reader = BufReader::new(Deadline {
+        conn: &conn,
+        until: Instant::now() + REQUEST_TIMEOUT,
+    });
+
+    let res = match read_request(&mut reader) {
+        Ok(req) => route(config, local, req),
+        Err(e) if e.kind() == ErrorKind::InvalidData => Response::error(400, e.to_string()),
+        Err(e) if e.kind() == ErrorKind::ResourceBusy => Response::error(503, e.to_string()),
+        Err(e) => return Err(e),
+    };
+
+    res.write(&conn)
+}
+
+/// [`TcpStream`] that fails all reads with [`ErrorKind::TimedOut`] after `until`.
+struct Deadline<'a> {
+    conn: &'a TcpStream,
+    until: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self.until.saturating_duration_since(Instant::now());
+
+        if remaining.is_zero() {
+            return Err(Error::from(ErrorKind::TimedOut));
+        }
+
+        self.conn.set_read_timeout(Some(remaining))?;
+
+        let mut conn = self.conn;
+
+        conn.read(buf)
+    }
+}
+
+fn route(config: &WebConfig, local: SocketAddr, req: Request) -> Response {
+    // A page on another site can point its own name to us (DNS rebinding) and then read our
+    // responses as its own origin so only our address is accepted. A request without Host is not
+    // from a browser.
+    if req.host.as_deref().is_some_and(|v| !is_local(v, local)) {
+        return Response::error(403, "unknown Host");
+    }
+
+    if req.method == "POST" {
+        if let Err(e) = authorize(&config.token, &req) {
+            return e;
+        }
+    }
+
+    match (req.method.as_str(), req.path.as_str()) {
+        ("GET", "/") if config.ui => Response::html(PAGE.into()),
+        ("GET", "/status") => Response::json(200, status(config)),
+        ("POST", "/restart") => {
+            Response::json(200, format!("{{\"restarted\":{}}}", restart(config)))
+        }
+        ("POST", "/payloads") => match req.param("name") {
+            Some("stage1") => upload(config, "stage1", req.body),
+            Some("stage2") => upload(config, "stage2", req.body),
+            _ => Response::error(400, "name must be stage1 or stage2"),
+        },
+        ("GET", "/") if !config.ui => Response::error(404, "Not Found"),
+        (_, "/" | "/status" | "/restart" | "/payloads") => {
+            Response::error(405, "Method Not Allowed")
+        }
+        _ => Response::error(404, "Not Found"),
+    }
+}
+
+/// Returns `true` if `host` is the address of the server on `local` or `localhost` with its port.
+///
+/// Any IP address is accepted if the server is bound to all addresses since a literal address
+/// cannot be rebound.
+fn is_local(host: &str, local: SocketAddr) -> bool {
+    let (name, port) = match host.rsplit_once(':') {
+        Some((n, p)) if !p.ends_with(']') => (n, p.parse().ok()),
+        _ => (host, Some(80)),
+    };
+
+    if port != Some(local.port()) {
+        return false;
+    }
+
+    if name.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+
+    let ip = name
+        .strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']'))
+        .unwrap_or(name);
+
+    match ip.parse::<IpAddr>() {
+        Ok(v) => local.ip().is_unspecified() || v == local.ip(),
+        Err(_) => false,
+    }
+}
+
+/// Check if a POST request is from our own page or a script that knows `token`.
+fn authorize(token: &str, req: &Request) -> Result<(), Response> {
+    // The browsers always send Origin on a cross-origin POST.
+    if let Some(origin) = &req.origin {
+        let host = origin.strip_prefix("http://");
+
+        if host.is_none() || host != req.host.as_deref() {
+            return Err(Response::error(403, "cross-origin request"));
+        }
+    }
+
+    if req.token.as_deref() != Some(token) {
+        return Err(Response::error(
+            403,
+            format!("missing or invalid {TOKEN_HEADER}"),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns a random token for [`WebConfig::token`].
+pub fn random_token() -> Result<String, Error> {
+    let mut v = [0u8; 16];
+
+    random_bytes(&mut v)?;
+
+    Ok(v.iter().fold(String::new(), |mut s, b| {
+        write!(s, "{b:02x}").unwrap();
+        s
+    }))
+}
+
+/// Restart the sessions of all active attempts and end the cooldown so the consoles can start a
+/// new attempt right away. Returns the number of restarted attempts.
+fn restart(config: &WebConfig) -> usize {
+    let active = config.attempts.actives();
+
+    info!("Restarting the active attempts as requested over HTTP.");
+
+    for a in &active {
+        config.sessions.restart(a.peer());
+    }
+
+    config.cooldown.skip();
+
+    active.len()
+}
+
+/// Render the state of the console and the attempts as JSON.
+fn status(config: &WebConfig) -> String {
+    let mut out = String::new();
+    let (state, since) = config.console.state();
+
+    // Console.
+    write!(
+        out,
+        "{{\"console\":{{\"state\":\"{}\",\"description\":",
+        state.id()
+    )
+    .unwrap();
+    json_string(&mut out, &state.to_string());
+    write!(out, ",\"seconds\":{},\"hint\":", since.as_secs()).unwrap();
+
+    match state.hint() {
+        Some(v) => json_string(&mut out, v),
+        None => out.push_str("null"),
+    }
+
+    out.push_str("},\"cooldown\":");
+
+    match config.cooldown.remaining() {
+        Some(v) => write!(out, "{:.1}", v.as_secs_f64()).unwrap(),
+        None => out.push_str("null"),
+    }
+
+    // Attempts.
+    out.push_str(",\"active\":[");
+
+    for (i, a) in config.attempts.actives().iter().enumerate() {
+        if i != 0 {
+            out.push(',');
+        }
+
+        attempt_json(&mut out, a, true);
+    }
+
+    out.push_str("],\"history\":[");
+
+    let history = config.attempts.history();
+
+    for (i, a) in history.iter().rev().take(HISTORY).enumerate() {
+        if i != 0 {
+            out.push(',');
+        }
+
+        attempt_json(&mut out, a, false);
+    }
+
+    out.push_str("],\"payloads\":");
+
+    // Payloads.
+    #[cfg(feature = "exploit")]
+    if let Some(e) = &config.exploit {
+        let payloads = e.payloads();
+        let list = [("stage1", payloads.stage1()), ("stage2", payloads.stage2())];
+
+        out.push('{');
+
+        for (i, (name, data)) in list.into_iter().enumerate() {
+            if i != 0 {
+                out.push(',');
+            }
+
+            match data {
+                Some(v) => write!(out, "\"{}\":{}", name, v.len()).unwrap(),
+                None => write!(out, "\"{name}\":null").unwrap(),
+            }
+        }
+
+        out.push_str("}}");
+
+        return out;
+    }
+
+    out.push_str("null}");
+    out
+}
+
+/// Append `a` to `out` as JSON. The timeline is included if `timeline` is `true`.
+fn attempt_json(out: &mut String, a: &Attempt, timeline: bool) {
+    write!(
+        out,
+        "{{\"id\":{},\"console\":\"{}\",\"seconds\":{:.1},\"milestone\":",
+        a.id(),
+        a.peer(),
+        a.duration().as_secs_f64()
+    )
+    .unwrap();
+
+    match a.milestone() {
+        Some(v) => json_string(out, &v.to_string()),
+        None => out.push_str("null"),
+    }
+
+    // The result is null while the attempt is active.
+    out.push_str(",\"result\":");
+
+    match a.result() {
+        Some(Ok(_)) => out.push_str("\"SUCCEEDED\""),
+        Some(Err(e)) => write!(out, "\"{}\"", e.id()).unwrap(),
+        None => out.push_str("null"),
+    }
+
+    if timeline {
+        out.push_str(",\"timeline\":[");
+
+        for (i, e) in a.timeline().iter().enumerate() {
+            if i != 0 {
+                out.push(',');
+            }
+
+            write!(
+                out,
+                "{{\"time\":{:.3},\"stage\":\"{}\",\"text\":",
+                e.time.as_secs_f64(),
+                e.stage
+            )
+            .unwrap();
+            json_string(out, &e.text);
+            out.push('}');
+        }
+
+        out.push(']');
+    }
+
+    out.push('}');
+}
+
+#[cfg(feature = "exploit")]
+fn upload(config: &WebConfig, name: &str, data: Vec<u8>) -> Response {
+    let exploit = match &config.exploit {
+        Some(v) => v,
+        None => return Response::error(409, "the exploit is disabled"),
+    };
+
+    let len = data.len();
+    let r = match name {
+        "stage1" => exploit.set_stage1(data),
+        "stage2" => exploit.set_stage2(data),
+        _ => unreachable!(),
+    };
+
+    if let Err(e) = r {
+        return Response::error(400, format!("invalid {name}: {e}"));
+    }
+
+    info!("Replaced {name} with {len} bytes over HTTP.");
+
+    Response::json(200, format!("{{\"name\":\"{name}\",\"size\":{len}}}"))
+}
+
+#[cfg(not(feature = "exploit"))]
+fn upload(_: &WebConfig, _: &str, _: Vec<u8>) -> Response {
+    Response::error(409, "the exploit is disabled")
+}
+
+/// The web UI. It polls `/status` and renders it on the status block so the upload forms are not
+/// reset. The token is not embedded since anyone on the network can load the page, which is why
+/// it is taken from the URL fragment (`/#TOKEN`) or typed by the user.
+const PAGE: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>Jailbreak 11.00</title></head><body>
+<h1>Jailbreak 11.00</h1>
+<div id="status"><p>Loading...</p></div>
+<p>Token: <input id="token" size="32"> <button onclick="restart()">Restart active attempts</button></p>
+<div id="uploads" hidden>
+<p>The new payloads are used from the next attempt.</p>
+<p>stage1: <input type="file" onchange="upload('stage1', this)"></p>
+<p>stage2: <input type="file" onchange="upload('stage2', this)"></p>
+</div>
+<script>
+const token = document.getElementById('token');
+
+token.value = decodeURIComponent(location.hash.slice(1));
+
+function headers() {
+  return { 'X-Jailbreak-Token': token.value };
+}
+
+function esc(s) {
+  return String(s).replace(/[&<>"]/g, c => ({ '&': '&amp;', '<': '&lt;', '>': '&gt;', '"': '&quot;' })[c]);
+}
+
+function render(s) {
+  let h = `<p>The PS4 seems to be ${esc(s.console.description)} for ${s.console.seconds} seconds.`;
+
+  if (s.console.hint) h += ' ' + esc(s.console.hint);
+
+  h += '</p>';
+
+  if (s.cooldown !== null) h += `<p>New attempts will be accepted after ${Math.floor(s.cooldown)} seconds.</p>`;
+
+  h += '<h2>Active attempts</h2>';
+
+  if (!s.active.length) h += '<p>None.</p>';
+
+  for (const a of s.active) {
+    h += `<h3>#${a.id} on ${esc(a.console)} (${a.seconds.toFixed(1)} seconds)</h3>`;
+    h += a.milestone ? `<p>Last milestone: ${esc(a.milestone)}.</p>` : '<p>No milestones yet.</p>';
+    h += '<pre>' + a.timeline.map(e => `+${e.time.toFixed(3)}s [${esc(e.stage)}] ${esc(e.text)}`).join('\n') + '</pre>';
+  }
+
+  h += '<h2>History</h2>';
+
+  if (!s.history.length) h += '<p>None.</p>';
+  else {
+    h += '<table><tr><th>#</th><th>Console</th><th>Seconds</th><th>Result</th></tr>';
+
+    for (const a of s.history) {
+      h += `<tr><td>${a.id}</td><td>${esc(a.console)}</td><td>${a.seconds.toFixed(1)}</td><td>${esc(a.result)}</td></tr>`;
+    }
+
+    h += '</table>';
+  }
+
+  if (s.payloads !== null) {
+    h += '<h2>Payloads</h2><ul>';
+
+    for (const [name, size] of Object.entries(s.payloads)) {
+      h += `<li>${name}: ${size === null ? 'none' : size + ' bytes'}</li>`;
+    }
+
+    h += '</ul>';
+  }
+
+  document.getElementById('status').innerHTML = h;
+  document.getElementById('uploads').hidden = s.payloads === null;
+}
+
+function poll() {
+  fetch('/status')
+    .then(r => r.json())
+    .then(render)
+    .catch(() => {})
+    .finally(() => setTimeout(poll, 2000));
+}
+
+function post(url, body) {
+  return fetch(url, { method: 'POST', headers: headers(), body }).then(r => r.json());
+}
+
+function restart() {
+  post('/restart').then(v => v.error && alert(v.error));
+}
+
+function upload(name, input) {
+  post('/payloads?name=' + name, input.files[0])
+    .then(v => alert(v.error || `Replaced ${v.name} with ${v.size} bytes.`));
+}
+
+poll();
+</script>
+</body></html>
+"#;
+
+/// Append `s` to `out` as a JSON string.
+fn json_string(out: &mut String, s: &str) {
+    out.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c < ' ' => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+}
+
+/// HTTP request. The headers other than `Content-Length`, `Host`, `Origin` and [`TOKEN_HEADER`] are
+/// ignored.
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    host: Option<String>,
+    origin: Option<String>,
+    token: Option<String>,
+    body: Vec<u8>,
+    upload: Option<Upload>,
+}
+
+impl Request {
+    /// Returns the maximum size of the body, which is the size limit of the payload for the
+    /// uploads.
+    fn max_body(&self) -> usize {
+        #[cfg(feature = "exploit")]
+        if self.method == "POST" && self.path == "/payloads" {
+            match self.param("name") {
+                Some("stage1") => return crate::exploit::STAGE1_MAX,
+                Some("stage2") => return crate::exploit::STAGE2_MAX,
+                _ => {}
+            }
+        }
+
+        MAX_LINE
+    }
+
+    /// Returns the value of `name` in the query string. The value is not percent-decoded.
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .split('&')
+            .filter_map(|p| p.split_once('='))
+            .find(|(k, _)| *k == name)
+            .map(|(_, v)| v)
+    }
+}
+
+/// Read a HTTP/1.x request from `r`. Returns [`ErrorKind::InvalidData`] if the request is
+/// malformed.
+fn read_request(r: &mut impl BufRead) -> Result<Request, Error> {
+    let invalid = |m: &str| Error::new(ErrorKind::InvalidData, m);
+
+    // Parse request line.
+    let line = read_line(r)?;
+    let mut parts = line.split(' ');
+    let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(m), Some(t), Some(v)) if v.starts_with("HTTP/1.") => (m, t),
+        _ => return Err(invalid("malformed request line")),
+    };
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    // Parse headers.
+    let mut len = 0;
+    let mut host = None;
+    let mut origin = None;
+    let mut token = None;
+
+    loop {
+        let line = read_line(r)?;
+
+        if line.is_empty() {
+            break;
+        }
+
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid("malformed header"))?;
+
+        if name.eq_ignore_ascii_case("content-length") {
+            len = value
+                .trim()
+                .parse()
+                .map_err(|_| invalid("invalid Content-Length"))?;
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            return Err(invalid("Transfer-Encoding is not supported"));
+        } else if name.eq_ignore_ascii_case("host") {
+            host = Some(value.trim().into());
+        } else if name.eq_ignore_ascii_case("origin") {
+            origin = Some(value.trim().into());
+        } else if name.eq_ignore_ascii_case(TOKEN_HEADER) {
+            token = Some(value.trim().into());
+        }
+    }
+
+    let mut req = Request {
+        method: method.into(),
+        path: path.into(),
+        query: query.into(),
+        host,
+        origin,
+        token,
+        body: Vec::new(),
+        upload: None,
+    };
+
+    if len > req.max_body() {
+        return Err(invalid("the body is too large"));
+    }
+
+    // Only a single large body is buffered at a time.
+    if len > MAX_LINE {
+        req.upload =
+            Some(Upload::acquire().ok_or_else(|| {
+                Error::new(ErrorKind::ResourceBusy, "another upload is in progress")
+            })?);
+    }
+
+    // Read body.
+    req.body = vec![0; len];
+
+    r.read_exact(&mut req.body)?;
+
+    Ok(req)
+}
+
+/// Slot of [`UPLOADING`], which is released when dropped.
+struct Upload(());
+
+impl Upload {
+    fn acquire() -> Option<Self> {
+        UPLOADING
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| Self(()))
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        UPLOADING.store(false, Ordering::Release);
+    }
+}
+
+/// Read a line that is terminated by CRLF or LF without the terminator.
+fn read_line(r: &mut impl BufRead) -> Result<String, Error> {
+    let mut line = Vec::new();
+
+    r.by_ref()
+        .take(MAX_LINE as u64)
+        .read_until(b'\n', &mut line)?;
+
+    if line.pop() != Some(b'\n') {
+        return Err(Error::new(ErrorKind::InvalidData, "truncated request"));
+    }
+
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    String::from_utf8(line).map_err(|_| Error::new(ErrorKind::InvalidData, "non-UTF-8 request"))
+}
+
+/// HTTP response.
+struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn html(body: String) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("Content-Type", "text/html; charset=utf-8".into())],
+            body: body.into_bytes(),
+        }
+    }
+
+    fn json(status: u16, body: String) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type", "application/json".into())],
+            body: body.into_bytes(),
+        }
+    }
+
+    fn error(status: u16, message: impl AsRef<str>) -> Self {
+        let mut body = String::from("{\"error\":");
+
+        json_string(&mut body, message.as_ref());
+        body.push('}');
+
+        Self::json(status, body)
+    }
+
+    fn write(&self, mut w: impl Write) -> Result<(), Error> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            503 => "Service Unavailable",
+            _ => "",
+        };
+
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason);
+
+        for (k, v) in &self.headers {
+            write!(head, "{k}: {v}\r\n").unwrap();
+        }
+
+        write!(
+            head,
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.body.len()
+        )
+        .unwrap();
+
+        w.write_all(head.as_bytes())?;
+        w.write_all(&self.body)?;
+        w.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request() {
+        let mut data: &[u8] =
+            b"PUT /stage1?x=1 HTTP/1.1\r\nHost: a\r\ncontent-length: 3\r\n\r\nabcd";
+        let req = read_request(&mut data).unwrap();
+
+        assert_eq!(req.method, "PUT");
+        assert_eq!(req.path, "/stage1");
+        assert_eq!(req.param("x"), Some("1"));
+        assert_eq!(req.param("y"), None);
+        assert_eq!(req.body, b"abc");
+
+        let mut data: &[u8] = b"GET / HTTP/1.0\n\n";
+        let req = read_request(&mut data).unwrap();
+
+        assert_eq!(req.path, "/");
+        assert!(req.body.is_empty());
+
+        for data in [
+            &b"GET /\r\n\r\n"[..],
+            b"GET / HTTP/1.1\r\nHost\r\n\r\n",
+            b"GET / HTTP/1.1\r\nContent-Length: x\r\n\r\n",
+            b"GET / HTTP/1.1\r\n",
+        ] {
+            let e = read_request(&mut &data[..]).err().unwrap();
+
+            assert_eq!(e.kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn body_limit() {
+        let request = |target: &str, len: usize| {
+            let mut data =
+                format!("POST {target} HTTP/1.1\r\nContent-Length: {len}\r\n\r\n").into_bytes();
+
+            data.resize(data.len() + len, 0);
+            read_request(&mut &data[..])
+        };
+
+        let e = request("/restart", MAX_LINE + 1).err().unwrap();
+
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+        #[cfg(feature = "exploit")]
+        {
+            use crate::exploit::{STAGE1_MAX, STAGE2_MAX};
+
+            let e = request("/payloads?name=stage1", STAGE1_MAX + 1)
+                .err()
+                .unwrap();
+
+            assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+            // Only one large body at a time.
+            let first = request("/payloads?name=stage2", MAX_LINE + 1).unwrap();
+            let e = request("/payloads?name=stage2", MAX_LINE + 1)
+                .err()
+                .unwrap();
+
+            assert_eq!(e.kind(), ErrorKind::ResourceBusy);
+
+            drop(first);
+
+            assert!(request("/payloads?name=stage2", STAGE2_MAX).is_ok());
+        }
+    }
+
+    #[test]
+    fn authorize() {
+        let parse = |data: &str| read_request(&mut data.as_bytes()).unwrap();
+        let token = "0123456789abcdef";
+
+        for data in [
+            "POST /restart HTTP/1.1\r\nX-Jailbreak-Token: 0123456789abcdef\r\n\r\n",
+            "POST /restart HTTP/1.1\r\nHost: 127.0.0.1:8080\r\nOrigin: http://127.0.0.1:8080\r\nx-jailbreak-token: 0123456789abcdef\r\n\r\n",
+        ] {
+            assert!(super::authorize(token, &parse(data)).is_ok(), "{data}");
+        }
+
+        for data in [
+            "POST /restart HTTP/1.1\r\n\r\n",
+            "POST /restart HTTP/1.1\r\nX-Jailbreak-Token: 0123\r\n\r\n",
+            "POST /restart HTTP/1.1\r\nHost: 127.0.0.1:8080\r\nOrigin: http://evil.example\r\nX-Jailbreak-Token: 0123456789abcdef\r\n\r\n",
+            "POST /restart HTTP/1.1\r\nOrigin: http://127.0.0.1:8080\r\nX-Jailbreak-Token: 0123456789abcdef\r\n\r\n",
+        ] {
+            let e = super::authorize(token, &parse(data)).err().unwrap();
+
+            assert_eq!(e.status, 403, "{data}");
+        }
+
+        assert_eq!(random_token().unwrap().len(), 32);
+    }
+
+    #[test]
+    fn host() {
+        let local: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let any: SocketAddr = "0.0.0.0:8080".parse().unwrap();
+        let v6: SocketAddr = "[::1]:8080".parse().unwrap();
+
+        assert!(is_local("127.0.0.1:8080", local));
+        assert!(is_local("localhost:8080", local));
+        assert!(is_local("192.168.1.2:8080", any));
+        assert!(is_local("[::1]:8080", v6));
+        assert!(!is_local("127.0.0.1", local));
+        assert!(!is_local("127.0.0.1:80", local));
+        assert!(!is_local("192.168.1.2:8080", local));
+        assert!(!is_local("evil.example:8080", local));
+        assert!(!is_local("evil.example:8080", any));
+        assert!(!is_local("[::1]", v6));
+    }
+
+    #[test]
+    fn json() {
+        let mut out = String::new();
+
+        json_string(&mut out, "a\"b\\c\n\u{1}");
+
+        assert_eq!(out, r#""a\"b\\c\n\u0001""#);
+    }
+}